@@ -0,0 +1,119 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// El usuario se inscribió en un curso (o reactivó una inscripción abandonada).
+pub const ENROLLED: &str = "enrolled";
+/// El usuario completó una lección.
+pub const LESSON_COMPLETED: &str = "lesson_completed";
+/// El usuario obtuvo el certificado de un curso al completarlo.
+pub const CERTIFICATE_EARNED: &str = "certificate_earned";
+
+/// Entrada de la actividad de un usuario, pendiente de guardar.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    pub user_id: Uuid,
+    /// Tipo de actividad, estable para que los clientes lo interpreten (p. ej. `enrolled`).
+    pub kind: &'static str,
+    pub course_id: Option<Uuid>,
+    pub lesson_id: Option<Uuid>,
+}
+
+/// Guarda varias entradas de actividad con un único `INSERT`. Con una transacción como
+/// `executor`, la actividad se guarda (o se descarta) junto con la acción que la genera.
+pub async fn record<'e>(executor: impl PgExecutor<'e>, activities: &[Activity]) -> Result<u64, sqlx::Error> {
+    if activities.is_empty() {
+        return Ok(0);
+    }
+
+    let user_ids: Vec<Uuid> = activities.iter().map(|a| a.user_id).collect();
+    let kinds: Vec<String> = activities.iter().map(|a| a.kind.to_string()).collect();
+    let course_ids: Vec<Option<Uuid>> = activities.iter().map(|a| a.course_id).collect();
+    let lesson_ids: Vec<Option<Uuid>> = activities.iter().map(|a| a.lesson_id).collect();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO activity (user_id, kind, course_id, lesson_id)
+        SELECT * FROM UNNEST($1::uuid[], $2::varchar[], $3::uuid[], $4::uuid[])
+        "#,
+        &user_ids[..],
+        &kinds[..],
+        &course_ids as &[Option<Uuid>],
+        &lesson_ids as &[Option<Uuid>]
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Guarda la actividad en segundo plano, sin retrasar la respuesta de la acción que la genera.
+///
+/// La actividad es informativa: si falla, se registra un `warn` y la acción sigue siendo válida.
+pub fn record_in_background(db_pool: &PgPool, activities: Vec<Activity>) {
+    if activities.is_empty() {
+        return;
+    }
+    let db_pool = db_pool.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = record(&db_pool, &activities).await {
+            tracing::warn!("Failed to record {} activity entries: {:?}", activities.len(), e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    #[actix_web::test]
+    async fn records_every_entry_in_one_insert() {
+        let db_pool = testing::pool().await;
+        let user_id = testing::insert_user(&db_pool, "student").await;
+        let entry = |kind| Activity {
+            user_id,
+            kind,
+            course_id: None,
+            lesson_id: None,
+        };
+
+        assert_eq!(record(&db_pool, &[]).await.unwrap(), 0);
+        let mut tx = db_pool.begin().await.unwrap();
+        assert_eq!(record(&mut *tx, &[entry(ENROLLED), entry(CERTIFICATE_EARNED)]).await.unwrap(), 2);
+        tx.commit().await.unwrap();
+
+        let kinds: Vec<String> = sqlx::query_scalar("SELECT kind FROM activity WHERE user_id = $1 ORDER BY kind")
+            .bind(user_id)
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+        assert_eq!(kinds, [CERTIFICATE_EARNED, ENROLLED]);
+    }
+
+    #[actix_web::test]
+    async fn records_in_the_background() {
+        let db_pool = testing::pool().await;
+        let user_id = testing::insert_user(&db_pool, "student").await;
+        let entry = Activity {
+            user_id,
+            kind: LESSON_COMPLETED,
+            course_id: None,
+            lesson_id: None,
+        };
+
+        record_in_background(&db_pool, vec![entry]);
+        let mut recorded = 0i64;
+        for _ in 0..50 {
+            recorded = sqlx::query_scalar("SELECT COUNT(*) FROM activity WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&db_pool)
+                .await
+                .unwrap();
+            if recorded > 0 {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(recorded, 1);
+    }
+}
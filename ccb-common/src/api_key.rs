@@ -0,0 +1,74 @@
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+
+/// Cabecera en la que los clientes internos envían la API key.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// API key de los endpoints internos (`INTERNAL_API_KEY`), registrada como `app_data`.
+/// `None` si el servicio no tiene ninguna configurada.
+#[derive(Debug, Clone)]
+pub struct InternalApiKey(pub Option<String>);
+
+/// Extractor que protege los endpoints de uso interno (operaciones, monitorización).
+///
+/// Exige que la cabecera `X-Api-Key` coincida con la `InternalApiKey` de la aplicación. Si el
+/// servicio no tiene API key configurada, los endpoints protegidos quedan deshabilitados.
+pub struct ApiKeyGuard;
+
+impl FromRequest for ApiKeyGuard {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(expected) = req
+            .app_data::<web::Data<InternalApiKey>>()
+            .and_then(|key| key.0.clone())
+        else {
+            return ready(Err(actix_web::error::ErrorNotFound("Not found")));
+        };
+
+        let provided = req.headers().get(API_KEY_HEADER).map(|value| value.as_bytes());
+        match provided {
+            Some(provided) if constant_time_eq(provided, expected.as_bytes()) => ready(Ok(ApiKeyGuard)),
+            _ => ready(Err(actix_web::error::ErrorUnauthorized("Missing or invalid API key"))),
+        }
+    }
+}
+
+/// Compara dos valores en tiempo constante para no filtrar la API key por tiempos de respuesta.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+        App, HttpResponse,
+    };
+
+    async fn status(key: Option<&str>, header: Option<&str>) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(InternalApiKey(key.map(str::to_string))))
+                .route("/health/detail", web::get().to(|_: ApiKeyGuard| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        let mut req = TestRequest::get().uri("/health/detail");
+        if let Some(header) = header {
+            req = req.insert_header((API_KEY_HEADER, header));
+        }
+        call_service(&app, req.to_request()).await.status()
+    }
+
+    #[actix_web::test]
+    async fn requires_the_configured_key() {
+        assert_eq!(status(Some("k3y"), Some("k3y")).await, StatusCode::OK);
+        assert_eq!(status(Some("k3y"), Some("k3y2")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("k3y"), None).await, StatusCode::UNAUTHORIZED);
+        // Sin API key configurada, los endpoints internos no existen.
+        assert_eq!(status(None, Some("k3y")).await, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,79 @@
+use sqlx::PgExecutor;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Un admin empezó a suplantar a un usuario (`POST /admin/impersonate/{user_id}`).
+pub const IMPERSONATION_STARTED: &str = "impersonation_started";
+
+/// Tipo de objeto al que afecta una entrada: un usuario.
+pub const TARGET_USER: &str = "user";
+
+/// Entrada del registro de auditoría, pendiente de guardar.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// Quien hizo la acción (ver `AuthenticatedUser::actor_id`).
+    pub actor_id: Uuid,
+    /// Acción, estable para poder filtrar por ella (p. ej. `impersonation_started`).
+    pub action: &'static str,
+    pub target_type: &'static str,
+    pub target_id: Option<Uuid>,
+    /// Datos adicionales de la acción, como objeto JSON.
+    pub details: serde_json::Value,
+    /// IP desde la que se hizo la acción (ver `ClientIp`), si se conoce.
+    pub ip: Option<IpAddr>,
+}
+
+/// Guarda una entrada de auditoría. A diferencia de la actividad, no se guarda en segundo plano:
+/// si la entrada no se puede registrar, la acción no debe completarse.
+pub async fn record<'e>(executor: impl PgExecutor<'e>, entry: &AuditEntry) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (actor_id, action, target_type, target_id, details, ip)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        entry.actor_id,
+        entry.action,
+        entry.target_type,
+        entry.target_id,
+        entry.details,
+        entry.ip.map(|ip| ip.to_string())
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    #[actix_web::test]
+    async fn records_the_actor_target_and_ip() {
+        let db_pool = testing::pool().await;
+        let admin_id = testing::insert_user(&db_pool, "admin").await;
+        let user_id = testing::insert_user(&db_pool, "student").await;
+
+        let entry = AuditEntry {
+            actor_id: admin_id,
+            action: IMPERSONATION_STARTED,
+            target_type: TARGET_USER,
+            target_id: Some(user_id),
+            details: serde_json::json!({ "expires_in_minutes": 15 }),
+            ip: Some("203.0.113.7".parse().unwrap()),
+        };
+        record(&db_pool, &entry).await.unwrap();
+
+        let (action, target_id, details, ip): (String, Option<Uuid>, serde_json::Value, Option<String>) =
+            sqlx::query_as("SELECT action, target_id, details, ip FROM audit_log WHERE actor_id = $1")
+                .bind(admin_id)
+                .fetch_one(&db_pool)
+                .await
+                .unwrap();
+        assert_eq!(action, IMPERSONATION_STARTED);
+        assert_eq!(target_id, Some(user_id));
+        assert_eq!(details["expires_in_minutes"], 15);
+        assert_eq!(ip.as_deref(), Some("203.0.113.7"));
+    }
+}
@@ -6,4 +6,16 @@ pub struct Claims {
     pub sub: String,
     pub role: UserRole,
     pub exp: usize,
+    /// Token restringido: el usuario debe cambiar su contraseña y solo puede llamar a
+    /// `PASSWORD_CHANGE_PATH` hasta hacerlo.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub password_change_required: bool,
+    /// Permisos de una sesión limitada (ver `scope`). Sin el claim, el token tiene todos los
+    /// permisos de su rol.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// Id del admin que suplanta al usuario (`POST /admin/impersonate/{user_id}`). El token actúa
+    /// como `sub`, pero cada acción queda asociada también al admin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act_as: Option<String>,
 }
\ No newline at end of file
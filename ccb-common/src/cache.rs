@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Caché en memoria de los totales (`COUNT(*)`) de los listados paginados, por filtro.
+///
+/// Cada total se reutiliza durante `ttl` como máximo, así que el total de un paginador puede
+/// estar desfasado como mucho ese tiempo. Las escrituras que afectan al listado deben llamar a
+/// `invalidate` para no esperar a que caduque. Sin `ttl` la caché está desactivada y siempre
+/// se consulta la base de datos.
+pub struct CountCache {
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<String, (Instant, i64)>>,
+}
+
+impl CountCache {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Devuelve el total guardado para `key` si sigue vigente; si no, lo calcula con `compute`
+    /// y lo guarda. Los errores de `compute` no se guardan.
+    pub async fn get_or_compute<F, Fut, E>(&self, key: String, compute: F) -> Result<i64, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<i64, E>>,
+    {
+        let Some(ttl) = self.ttl else {
+            return compute().await;
+        };
+
+        if let Some(&(cached_at, count)) = self.entries.lock().unwrap().get(&key) {
+            if cached_at.elapsed() < ttl {
+                return Ok(count);
+            }
+        }
+
+        let count = compute().await?;
+
+        let mut entries = self.entries.lock().unwrap();
+        // Aprovechamos cada inserción para descartar los totales caducados.
+        entries.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), count));
+        Ok(count)
+    }
+
+    /// Descarta todos los totales guardados. Se llama tras cualquier escritura que cambie el listado.
+    pub fn invalidate(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Devuelve `count` y anota cuántas veces se ha calculado.
+    async fn counted(calls: &Cell<u32>, count: i64) -> Result<i64, ()> {
+        calls.set(calls.get() + 1);
+        Ok(count)
+    }
+
+    #[actix_web::test]
+    async fn computes_once_within_the_ttl() {
+        let cache = CountCache::new(Some(Duration::from_secs(60)));
+        let calls = Cell::new(0);
+
+        for _ in 0..3 {
+            assert_eq!(cache.get_or_compute("status=published".into(), || counted(&calls, 7)).await, Ok(7));
+        }
+        assert_eq!(calls.get(), 1);
+
+        // Cada filtro tiene su propio total.
+        assert_eq!(cache.get_or_compute("status=draft".into(), || counted(&calls, 2)).await, Ok(2));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[actix_web::test]
+    async fn recomputes_after_expiry_or_invalidation() {
+        let cache = CountCache::new(Some(Duration::from_millis(10)));
+        let calls = Cell::new(0);
+
+        cache.get_or_compute("all".into(), || counted(&calls, 1)).await.unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get_or_compute("all".into(), || counted(&calls, 2)).await, Ok(2));
+
+        cache.invalidate();
+        assert_eq!(cache.get_or_compute("all".into(), || counted(&calls, 3)).await, Ok(3));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[actix_web::test]
+    async fn errors_and_disabled_caches_are_not_stored() {
+        let cache = CountCache::new(Some(Duration::from_secs(60)));
+        assert_eq!(cache.get_or_compute("all".into(), || async { Err::<i64, _>("db down") }).await, Err("db down"));
+        assert_eq!(cache.get_or_compute("all".into(), || async { Ok::<_, ()>(5) }).await, Ok(5));
+
+        let disabled = CountCache::new(None);
+        let calls = Cell::new(0);
+        disabled.get_or_compute("all".into(), || counted(&calls, 1)).await.unwrap();
+        disabled.get_or_compute("all".into(), || counted(&calls, 1)).await.unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+}
@@ -0,0 +1,98 @@
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+
+/// Indica si el servicio está detrás de un proxy de confianza (`TRUSTED_PROXY`), registrado como `app_data`.
+///
+/// Solo en ese caso se leen `X-Forwarded-For` y `X-Real-IP`: sin proxy, cualquier cliente
+/// podría enviar esas cabeceras para hacerse pasar por otra IP.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrustedProxy(pub bool);
+
+/// IP del cliente que hizo la petición.
+///
+/// Detrás de un proxy de confianza se toma la última dirección de `X-Forwarded-For` (la que
+/// añadió nuestro proxy, no las que pudiera enviar el cliente) o, si no está, `X-Real-IP`. En
+/// cualquier otro caso, la dirección de la conexión.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let trusted = req.app_data::<web::Data<TrustedProxy>>().is_some_and(|proxy| proxy.0);
+
+    if trusted {
+        let forwarded = req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        let real_ip = || {
+            req.headers()
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|ip| ip.trim().parse().ok())
+        };
+        if let Some(ip) = forwarded.or_else(real_ip) {
+            return Some(ip);
+        }
+    }
+
+    req.peer_addr().map(|addr| addr.ip())
+}
+
+/// Extractor con la IP del cliente según `client_ip`. `None` si no se puede determinar.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequest for ClientIp {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(ClientIp(client_ip(req))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(trusted: Option<bool>) -> TestRequest {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "1.1.1.1, 203.0.113.7"))
+            .insert_header(("x-real-ip", "198.51.100.4"));
+        match trusted {
+            Some(trusted) => req.app_data(web::Data::new(TrustedProxy(trusted))),
+            None => req,
+        }
+    }
+
+    #[test]
+    fn untrusted_mode_ignores_the_forwarding_headers() {
+        for trusted in [None, Some(false)] {
+            let req = request(trusted).to_http_request();
+            assert_eq!(client_ip(&req), Some("10.0.0.2".parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn trusted_mode_uses_the_address_added_by_the_proxy() {
+        let req = request(Some(true)).to_http_request();
+        assert_eq!(client_ip(&req), Some("203.0.113.7".parse().unwrap()));
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:5000".parse().unwrap())
+            .insert_header(("x-real-ip", "198.51.100.4"))
+            .app_data(web::Data::new(TrustedProxy(true)))
+            .to_http_request();
+        assert_eq!(client_ip(&req), Some("198.51.100.4".parse().unwrap()));
+
+        // Sin cabeceras válidas se vuelve a la dirección de la conexión.
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "unknown"))
+            .app_data(web::Data::new(TrustedProxy(true)))
+            .to_http_request();
+        assert_eq!(client_ip(&req), Some("10.0.0.2".parse().unwrap()));
+    }
+}
@@ -0,0 +1,677 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderValue, KeepAlive};
+use jsonwebtoken::Algorithm;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::api_key::InternalApiKey;
+use crate::client_ip::TrustedProxy;
+use crate::cors::CorsPolicy;
+use crate::jwt::JwtKeySet;
+use crate::pagination::{PageLimits, DEFAULT_MAX_PER_PAGE};
+use crate::rate_limit::RateLimitPolicy;
+use crate::security_headers::{SecurityHeaders, DEFAULT_CONTENT_SECURITY_POLICY};
+use crate::throttle::BackoffPolicy;
+use crate::UserRole;
+
+/// URL de verificación por defecto de los captchas (Cloudflare Turnstile).
+pub const DEFAULT_CAPTCHA_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Captcha del registro en auth-service: `CAPTCHA_VERIFY_URL` (por defecto Turnstile; sirve
+/// cualquier API de `siteverify` como la de hCaptcha) y `CAPTCHA_SECRET`.
+#[derive(Debug, Clone)]
+pub struct CaptchaSettings {
+    pub verify_url: String,
+    pub secret: String,
+}
+
+/// Webhook que course-service dispara al publicar un curso (`WEBHOOK_URL` y `WEBHOOK_SECRET`).
+#[derive(Debug, Clone)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub secret: String,
+}
+
+/// Longitud máxima (en caracteres) de los textos de cursos y lecciones: `TITLE_MAX_LENGTH`
+/// (por defecto 200, como mucho 255), `DESCRIPTION_MAX_LENGTH` (por defecto 10000) y
+/// `LESSON_CONTENT_MAX_LENGTH` (por defecto 100000).
+#[derive(Debug, Clone, Copy)]
+pub struct TextLimits {
+    pub title: usize,
+    pub description: usize,
+    pub lesson_content: usize,
+}
+
+/// Configuración común a todos los servicios, cargada y validada una sola vez al arrancar.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// URL de conexión a PostgreSQL (`DATABASE_URL`, obligatoria).
+    pub database_url: String,
+    /// Número máximo de conexiones del pool (`DATABASE_MAX_CONNECTIONS`, por defecto 5).
+    pub database_max_connections: u32,
+    /// Tiempo máximo de cada consulta y de la espera por una conexión del pool
+    /// (`DB_QUERY_TIMEOUT_MS`). `None` (0 o sin definir) significa sin límite.
+    pub db_query_timeout: Option<Duration>,
+    /// Tiempo durante el que se reutilizan los totales de los listados paginados
+    /// (`COUNT_CACHE_TTL_SECONDS`). `None` (0 o sin definir) desactiva la caché.
+    pub count_cache_ttl: Option<Duration>,
+    /// Claves de los JWT: la actual (`JWT_SECRET`, obligatoria, con identificador `JWT_KEY_ID`,
+    /// por defecto `default`) y las anteriores aún aceptadas (`JWT_PREVIOUS_KEYS`, pares
+    /// `kid:secreto` separados por comas). Solo aceptan los algoritmos de `JWT_ALGORITHMS`
+    /// (HMAC, separados por comas, por defecto `HS256`); se firma con el primero.
+    ///
+    /// Con `JWT_ALGORITHMS=RS256` las claves son RSA: la pública actual se lee de
+    /// `JWT_PUBLIC_KEY_FILE` (obligatoria), la privada de `JWT_PRIVATE_KEY_FILE` (solo la necesita
+    /// el servicio que emite tokens) y `JWT_PREVIOUS_KEYS` son pares `kid:ruta` a las públicas anteriores.
+    pub jwt_keys: JwtKeySet,
+    /// Horas de validez de un JWT recién emitido (`JWT_EXPIRATION_HOURS`, por defecto 24).
+    pub jwt_expiration_hours: i64,
+    /// Horas de validez de un JWT emitido en un login con `remember_me` (`JWT_REMEMBER_HOURS`,
+    /// por defecto 720, 30 días). No puede ser menor que `JWT_EXPIRATION_HOURS`.
+    pub jwt_remember_hours: i64,
+    /// Puerto en el que escucha el servidor HTTP (`PORT`, por defecto 8080).
+    pub port: u16,
+    /// Número de workers del servidor HTTP (`HTTP_WORKERS`, por defecto el número de CPUs).
+    pub http_workers: usize,
+    /// Tiempo que se mantiene abierta una conexión inactiva (`HTTP_KEEP_ALIVE_SECONDS`, por
+    /// defecto 5). `None` (0) desactiva el keep-alive.
+    pub http_keep_alive: Option<Duration>,
+    /// Política de CORS del servicio (ver `CorsPolicy::from_lookup`): orígenes, métodos,
+    /// cabeceras y `max-age`, con variables propias del servicio (`COURSE_CORS_ORIGINS`) o
+    /// compartidas (`CORS_ALLOWED_ORIGINS`). Sin configurar se acepta cualquier origen.
+    pub cors: CorsPolicy,
+    /// API key de los endpoints internos (`INTERNAL_API_KEY`). Sin ella, esos endpoints quedan deshabilitados.
+    pub internal_api_key: InternalApiKey,
+    /// El servicio está detrás de un proxy de confianza (`TRUSTED_PROXY`, por defecto `false`):
+    /// la IP del cliente se toma de `X-Forwarded-For`/`X-Real-IP`.
+    pub trusted_proxy: TrustedProxy,
+    /// Arranca el servicio en modo mantenimiento (`MAINTENANCE_MODE`, por defecto `false`).
+    pub maintenance_mode: bool,
+    /// Cabeceras de seguridad de las respuestas: se activan con `SECURITY_HEADERS` (por defecto
+    /// `true`), `X_FRAME_OPTIONS` (`DENY` o `SAMEORIGIN`, por defecto `DENY`),
+    /// `HSTS_MAX_AGE_SECONDS` (por defecto 0, sin HSTS) y `CONTENT_SECURITY_POLICY` (vacía
+    /// para no enviarla).
+    pub security_headers: SecurityHeaders,
+    /// Activa las ediciones de los cursos (`COURSE_OFFERINGS_ENABLED`, por defecto `false`): un
+    /// estudiante puede inscribirse en varias ediciones del mismo curso, una vez en cada una.
+    pub course_offerings_enabled: bool,
+    /// Límites de los listados paginados: `per_page` se recorta a `MAX_PER_PAGE` (por defecto 100).
+    pub pagination: PageLimits,
+    /// Límite de las peticiones de escritura por usuario (o por IP sin sesión):
+    /// `WRITE_RATE_LIMIT_PER_MINUTE` (por defecto 60; 0 lo desactiva), con ráfagas de hasta
+    /// `WRITE_RATE_LIMIT_BURST` (por defecto 20). Con `WRITE_RATE_LIMIT_EXEMPT_ADMINS` (por
+    /// defecto `true`) los admins no tienen límite.
+    pub write_rate_limit: Option<RateLimitPolicy>,
+
+    // --- auth-service ---
+    /// Rol asignado en el registro (`DEFAULT_USER_ROLE`, `student` o `instructor`; por defecto
+    /// `student`). Nunca puede ser `admin`: los admins solo se crean a mano.
+    pub default_user_role: UserRole,
+    /// Minutos de validez de un token de restablecimiento de contraseña (`RESET_TOKEN_MINUTES`, por defecto 30).
+    pub reset_token_minutes: i32,
+    /// Días entre `DELETE /me` y el borrado definitivo de la cuenta (`ACCOUNT_DELETION_GRACE_DAYS`, por defecto 30).
+    pub account_deletion_grace_days: i32,
+    /// Minutos de validez de un token de suplantación (`IMPERSONATION_TOKEN_MINUTES`, entre 1 y
+    /// 60, por defecto 15).
+    pub impersonation_token_minutes: i64,
+    /// Espera exponencial tras fallos de login consecutivos: `LOGIN_THROTTLE_FREE_ATTEMPTS`,
+    /// `LOGIN_THROTTLE_BASE_SECONDS` y `LOGIN_THROTTLE_MAX_SECONDS` (ver `BackoffPolicy`).
+    pub login_throttle: BackoffPolicy,
+    /// Captcha en el registro (`CAPTCHA_ENABLED`, por defecto `false`); `None` si está desactivado.
+    pub captcha: Option<CaptchaSettings>,
+
+    // --- course-service ---
+    /// Webhook de publicación; `None` si `WEBHOOK_URL` no está definida.
+    pub webhook: Option<WebhookSettings>,
+    /// Directorio donde se guardan los ficheros de los materiales (`MATERIALS_DIR`, por defecto `./materials`).
+    pub materials_dir: PathBuf,
+    /// Secreto con el que se firman los enlaces de descarga (`DOWNLOAD_TOKEN_SECRET`,
+    /// obligatorio en course-service).
+    pub download_token_secret: String,
+    /// Si el slug se regenera al cambiar el título (`SLUG_FOLLOWS_TITLE`, por defecto `false`).
+    pub slug_follows_title: bool,
+    /// Número de reportes con el que una reseña se oculta hasta que se modere
+    /// (`REVIEW_REPORT_THRESHOLD`, por defecto 3).
+    pub review_report_threshold: i64,
+    /// Longitud máxima de los textos de cursos y lecciones.
+    pub text_limits: TextLimits,
+
+    // --- enrollment-service ---
+    /// Inscripciones activas por estudiante (`MAX_ACTIVE_ENROLLMENTS_PER_USER`); `None` (0 o sin
+    /// definir) significa sin límite.
+    pub max_active_enrollments: Option<i64>,
+    /// Para inscribirse hace falta el email verificado, no solo indicado
+    /// (`ENROLLMENT_REQUIRES_VERIFIED_EMAIL`, por defecto `false`).
+    pub enrollment_requires_verified_email: bool,
+    /// Endpoint de salud de course-service que comprueba `GET /ready` (`COURSE_SERVICE_HEALTH_URL`).
+    pub course_service_health_url: Option<String>,
+}
+
+/// Error de configuración que reúne todas las variables ausentes o inválidas, no solo la primera.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration ({} problem(s)):", self.problems.len())?;
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Carga la configuración del servicio `service` (el prefijo de sus variables propias, como
+    /// `COURSE`) desde las variables de entorno del proceso.
+    pub fn from_env(service: &str) -> Result<Self, ConfigError> {
+        Self::from_lookup(service, |key| env::var(key).ok())
+    }
+
+    /// Carga la configuración usando una función de búsqueda de variables.
+    /// Permite validar un conjunto de variables sin tocar el entorno real del proceso.
+    pub fn from_lookup<F>(service: &str, lookup: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut problems = Vec::new();
+
+        let database_url = required(&lookup, "DATABASE_URL", &mut problems);
+        let database_max_connections = parsed(&lookup, "DATABASE_MAX_CONNECTIONS", 5u32, &mut problems);
+        let db_query_timeout_ms = parsed(&lookup, "DB_QUERY_TIMEOUT_MS", 0u64, &mut problems);
+        let count_cache_ttl_seconds = parsed(&lookup, "COUNT_CACHE_TTL_SECONDS", 0u64, &mut problems);
+        let jwt_key_id = lookup("JWT_KEY_ID")
+            .map(|kid| kid.trim().to_string())
+            .filter(|kid| !kid.is_empty())
+            .unwrap_or_else(|| "default".to_string());
+        let jwt_previous_keys = previous_keys(&lookup, &jwt_key_id, &mut problems);
+        let jwt_algorithms = jwt_algorithms(&lookup, &mut problems);
+        let jwt_keys = jwt_keys(&lookup, jwt_key_id, jwt_previous_keys, &jwt_algorithms, &mut problems);
+        let jwt_expiration_hours = parsed(&lookup, "JWT_EXPIRATION_HOURS", 24i64, &mut problems);
+        let jwt_remember_hours = parsed(&lookup, "JWT_REMEMBER_HOURS", 720i64, &mut problems);
+        let port = parsed(&lookup, "PORT", 8080u16, &mut problems);
+        let default_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let http_workers = parsed(&lookup, "HTTP_WORKERS", default_workers, &mut problems);
+        let http_keep_alive_seconds = parsed(&lookup, "HTTP_KEEP_ALIVE_SECONDS", 5u64, &mut problems);
+        let maintenance_mode = parsed(&lookup, "MAINTENANCE_MODE", false, &mut problems);
+        let course_offerings_enabled = parsed(&lookup, "COURSE_OFFERINGS_ENABLED", false, &mut problems);
+        let max_per_page = parsed(&lookup, "MAX_PER_PAGE", DEFAULT_MAX_PER_PAGE, &mut problems);
+        let write_rate_limit_per_minute = parsed(&lookup, "WRITE_RATE_LIMIT_PER_MINUTE", 60u32, &mut problems);
+        let write_rate_limit_burst = parsed(&lookup, "WRITE_RATE_LIMIT_BURST", 20u32, &mut problems);
+        let write_rate_limit_exempt_admins = parsed(&lookup, "WRITE_RATE_LIMIT_EXEMPT_ADMINS", true, &mut problems);
+        let trusted_proxy = TrustedProxy(parsed(&lookup, "TRUSTED_PROXY", false, &mut problems));
+        let cors = CorsPolicy::from_lookup(service, &lookup, &mut problems);
+
+        let security_headers = security_headers(&lookup, &mut problems);
+
+        let default_user_role = match lookup("DEFAULT_USER_ROLE").as_deref().map(str::trim) {
+            None | Some("student") => UserRole::Student,
+            Some("instructor") => UserRole::Instructor,
+            Some(other) => {
+                problems.push(format!("DEFAULT_USER_ROLE must be 'student' or 'instructor', got '{}'", other));
+                UserRole::Student
+            }
+        };
+        let reset_token_minutes = parsed(&lookup, "RESET_TOKEN_MINUTES", 30i32, &mut problems);
+        let account_deletion_grace_days = parsed(&lookup, "ACCOUNT_DELETION_GRACE_DAYS", 30i32, &mut problems);
+        let impersonation_token_minutes = parsed(&lookup, "IMPERSONATION_TOKEN_MINUTES", 15i64, &mut problems);
+        let default_backoff = BackoffPolicy::default();
+        let login_throttle_free_attempts =
+            parsed(&lookup, "LOGIN_THROTTLE_FREE_ATTEMPTS", default_backoff.free_attempts, &mut problems);
+        let login_throttle_base_seconds =
+            parsed(&lookup, "LOGIN_THROTTLE_BASE_SECONDS", default_backoff.base_delay.as_secs(), &mut problems);
+        let login_throttle_max_seconds =
+            parsed(&lookup, "LOGIN_THROTTLE_MAX_SECONDS", default_backoff.max_delay.as_secs(), &mut problems);
+        let captcha_enabled = parsed(&lookup, "CAPTCHA_ENABLED", false, &mut problems);
+        let captcha = captcha_enabled.then(|| CaptchaSettings {
+            verify_url: non_empty(&lookup, "CAPTCHA_VERIFY_URL")
+                .unwrap_or_else(|| DEFAULT_CAPTCHA_VERIFY_URL.to_string()),
+            secret: required(&lookup, "CAPTCHA_SECRET", &mut problems),
+        });
+
+        let webhook = non_empty(&lookup, "WEBHOOK_URL").map(|url| WebhookSettings {
+            url,
+            secret: required(&lookup, "WEBHOOK_SECRET", &mut problems),
+        });
+        let materials_dir = PathBuf::from(lookup("MATERIALS_DIR").unwrap_or_else(|| "./materials".to_string()));
+        // Solo course-service firma enlaces de descarga: los demás servicios no necesitan el secreto.
+        let download_token_secret = if service == "COURSE" {
+            required(&lookup, "DOWNLOAD_TOKEN_SECRET", &mut problems)
+        } else {
+            lookup("DOWNLOAD_TOKEN_SECRET").unwrap_or_default()
+        };
+        let slug_follows_title = parsed(&lookup, "SLUG_FOLLOWS_TITLE", false, &mut problems);
+        let review_report_threshold = parsed(&lookup, "REVIEW_REPORT_THRESHOLD", 3i64, &mut problems);
+        // El título no puede pasar de 255 caracteres, el tamaño de su columna.
+        let text_limits = TextLimits {
+            title: text_limit(&lookup, "TITLE_MAX_LENGTH", 200, 255, &mut problems),
+            description: text_limit(&lookup, "DESCRIPTION_MAX_LENGTH", 10_000, usize::MAX, &mut problems),
+            lesson_content: text_limit(&lookup, "LESSON_CONTENT_MAX_LENGTH", 100_000, usize::MAX, &mut problems),
+        };
+
+        let max_active_enrollments = parsed(&lookup, "MAX_ACTIVE_ENROLLMENTS_PER_USER", 0i64, &mut problems);
+        let enrollment_requires_verified_email =
+            parsed(&lookup, "ENROLLMENT_REQUIRES_VERIFIED_EMAIL", false, &mut problems);
+        let course_service_health_url = non_empty(&lookup, "COURSE_SERVICE_HEALTH_URL");
+
+        let internal_api_key = InternalApiKey(lookup("INTERNAL_API_KEY").filter(|key| !key.trim().is_empty()));
+
+        if database_max_connections == 0 {
+            problems.push("DATABASE_MAX_CONNECTIONS must be greater than 0".to_string());
+        }
+        if http_workers == 0 {
+            problems.push("HTTP_WORKERS must be greater than 0".to_string());
+        }
+        if jwt_expiration_hours <= 0 {
+            problems.push("JWT_EXPIRATION_HOURS must be greater than 0".to_string());
+        }
+        if jwt_remember_hours < jwt_expiration_hours {
+            problems.push("JWT_REMEMBER_HOURS must not be shorter than JWT_EXPIRATION_HOURS".to_string());
+        }
+        if max_per_page <= 0 {
+            problems.push("MAX_PER_PAGE must be greater than 0".to_string());
+        }
+        if write_rate_limit_burst == 0 {
+            problems.push("WRITE_RATE_LIMIT_BURST must be greater than 0".to_string());
+        }
+        if reset_token_minutes <= 0 {
+            problems.push("RESET_TOKEN_MINUTES must be greater than 0".to_string());
+        }
+        if account_deletion_grace_days <= 0 {
+            problems.push("ACCOUNT_DELETION_GRACE_DAYS must be greater than 0".to_string());
+        }
+        if !(1..=60).contains(&impersonation_token_minutes) {
+            problems.push("IMPERSONATION_TOKEN_MINUTES must be between 1 and 60".to_string());
+        }
+        if login_throttle_base_seconds == 0 {
+            problems.push("LOGIN_THROTTLE_BASE_SECONDS must be greater than 0".to_string());
+        }
+        if login_throttle_max_seconds < login_throttle_base_seconds {
+            problems.push("LOGIN_THROTTLE_MAX_SECONDS must not be shorter than LOGIN_THROTTLE_BASE_SECONDS".to_string());
+        }
+        if review_report_threshold <= 0 {
+            problems.push("REVIEW_REPORT_THRESHOLD must be greater than 0".to_string());
+        }
+        if max_active_enrollments < 0 {
+            problems.push("MAX_ACTIVE_ENROLLMENTS_PER_USER must not be negative".to_string());
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+
+        Ok(Self {
+            database_url,
+            database_max_connections,
+            db_query_timeout: (db_query_timeout_ms > 0).then(|| Duration::from_millis(db_query_timeout_ms)),
+            count_cache_ttl: (count_cache_ttl_seconds > 0).then(|| Duration::from_secs(count_cache_ttl_seconds)),
+            jwt_keys: jwt_keys.with_algorithms(jwt_algorithms),
+            jwt_expiration_hours,
+            jwt_remember_hours,
+            port,
+            http_workers,
+            http_keep_alive: (http_keep_alive_seconds > 0).then(|| Duration::from_secs(http_keep_alive_seconds)),
+            cors,
+            internal_api_key,
+            trusted_proxy,
+            maintenance_mode,
+            security_headers,
+            course_offerings_enabled,
+            pagination: PageLimits {
+                default_per_page: PageLimits::default().default_per_page.min(max_per_page),
+                max_per_page,
+            },
+            write_rate_limit: (write_rate_limit_per_minute > 0).then_some(RateLimitPolicy {
+                per_minute: write_rate_limit_per_minute,
+                burst: write_rate_limit_burst,
+                exempt_admins: write_rate_limit_exempt_admins,
+            }),
+            default_user_role,
+            reset_token_minutes,
+            account_deletion_grace_days,
+            impersonation_token_minutes,
+            login_throttle: BackoffPolicy {
+                free_attempts: login_throttle_free_attempts,
+                base_delay: Duration::from_secs(login_throttle_base_seconds),
+                max_delay: Duration::from_secs(login_throttle_max_seconds),
+            },
+            captcha,
+            webhook,
+            materials_dir,
+            download_token_secret,
+            slug_follows_title,
+            review_report_threshold,
+            text_limits,
+            max_active_enrollments: (max_active_enrollments > 0).then_some(max_active_enrollments),
+            enrollment_requires_verified_email,
+            course_service_health_url,
+        })
+    }
+
+    /// Keep-alive del servidor HTTP según `HTTP_KEEP_ALIVE_SECONDS`.
+    pub fn keep_alive(&self) -> KeepAlive {
+        match self.http_keep_alive {
+            Some(timeout) => KeepAlive::Timeout(timeout),
+            None => KeepAlive::Disabled,
+        }
+    }
+
+    /// Construye el middleware de CORS con la política del servicio.
+    pub fn cors(&self) -> Cors {
+        self.cors.cors()
+    }
+}
+
+/// Lee una variable obligatoria y no vacía, anotando el problema si falta.
+fn required<F>(lookup: &F, key: &str, problems: &mut Vec<String>) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match lookup(key).filter(|value| !value.trim().is_empty()) {
+        Some(value) => value,
+        None => {
+            problems.push(format!("{} must be set", key));
+            String::new()
+        }
+    }
+}
+
+/// Lee una variable opcional, recortada; una vacía cuenta como no definida.
+fn non_empty<F>(lookup: &F, key: &str) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    lookup(key).map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// Lee una longitud máxima de texto, que debe estar entre 1 y `max` caracteres.
+fn text_limit<F>(lookup: &F, key: &str, default: usize, max: usize, problems: &mut Vec<String>) -> usize
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let limit = parsed(lookup, key, default, problems);
+    if !(1..=max).contains(&limit) {
+        problems.push(format!("{} must be a number of characters between 1 and {}, got '{}'", key, max, limit));
+        return default;
+    }
+    limit
+}
+
+/// Lee `JWT_PREVIOUS_KEYS` (`kid:secreto,kid:secreto`, o `kid:ruta` con RSA), anotando las
+/// entradas mal formadas o que reutilizan el identificador de la clave actual.
+fn previous_keys<F>(lookup: &F, current_kid: &str, problems: &mut Vec<String>) -> Vec<(String, String)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(value) = lookup("JWT_PREVIOUS_KEYS") else {
+        return Vec::new();
+    };
+
+    let mut keys = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        match entry.split_once(':') {
+            Some((kid, secret)) if !kid.trim().is_empty() && !secret.is_empty() => {
+                let kid = kid.trim();
+                if kid == current_kid {
+                    problems.push(format!("JWT_PREVIOUS_KEYS reuses the current key id '{}'", kid));
+                } else {
+                    keys.push((kid.to_string(), secret.to_string()));
+                }
+            }
+            _ => problems.push("JWT_PREVIOUS_KEYS entries must have the form 'kid:secret'".to_string()),
+        }
+    }
+    keys
+}
+
+/// Lee `JWT_ALGORITHMS`: algoritmos HMAC (secretos compartidos) o `RS256` (par de claves RSA),
+/// pero no ambos tipos a la vez.
+fn jwt_algorithms<F>(lookup: &F, problems: &mut Vec<String>) -> Vec<Algorithm>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(value) = lookup("JWT_ALGORITHMS") else {
+        return vec![Algorithm::HS256];
+    };
+
+    let mut algorithms = Vec::new();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match name.to_ascii_uppercase().as_str() {
+            "HS256" => algorithms.push(Algorithm::HS256),
+            "HS384" => algorithms.push(Algorithm::HS384),
+            "HS512" => algorithms.push(Algorithm::HS512),
+            "RS256" => algorithms.push(Algorithm::RS256),
+            _ => problems.push(format!("JWT_ALGORITHMS contains an unsupported algorithm: '{}'", name)),
+        }
+    }
+    if algorithms.is_empty() {
+        problems.push("JWT_ALGORITHMS must list at least one algorithm".to_string());
+    }
+    if algorithms.contains(&Algorithm::RS256) && algorithms.len() > 1 {
+        problems.push("JWT_ALGORITHMS cannot combine RS256 with HMAC algorithms".to_string());
+    }
+    algorithms
+}
+
+/// Construye las claves de los JWT según el tipo de algoritmo: secretos de `JWT_SECRET` y
+/// `JWT_PREVIOUS_KEYS`, o claves RSA leídas de los ficheros PEM.
+fn jwt_keys<F>(
+    lookup: &F,
+    key_id: String,
+    previous: Vec<(String, String)>,
+    algorithms: &[Algorithm],
+    problems: &mut Vec<String>,
+) -> JwtKeySet
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if algorithms != [Algorithm::RS256] {
+        let secret = required(lookup, "JWT_SECRET", problems);
+        return JwtKeySet::new(key_id, secret, previous);
+    }
+
+    let public_path = required(lookup, "JWT_PUBLIC_KEY_FILE", problems);
+    let private_path = lookup("JWT_PRIVATE_KEY_FILE").filter(|path| !path.trim().is_empty());
+
+    let mut unreadable = false;
+    let mut read = |key: &str, path: &str| {
+        let pem = read_pem(key, path, problems);
+        unreadable |= pem.is_none();
+        pem
+    };
+    let mut public_pems: Vec<(String, String)> = previous
+        .into_iter()
+        .filter_map(|(kid, path)| Some((kid, read("JWT_PREVIOUS_KEYS", &path)?)))
+        .collect();
+    if !public_path.is_empty() {
+        if let Some(pem) = read("JWT_PUBLIC_KEY_FILE", &public_path) {
+            public_pems.push((key_id.clone(), pem));
+        }
+    }
+    let private_pem = private_path.and_then(|path| read("JWT_PRIVATE_KEY_FILE", &path));
+
+    match JwtKeySet::rsa(key_id.clone(), private_pem.as_deref(), public_pems) {
+        Ok(keys) => keys,
+        Err(problem) => {
+            // Si falta un fichero ya se anotó; no repetimos el problema como clave inválida.
+            if !unreadable && !public_path.is_empty() {
+                problems.push(format!("Invalid JWT keys: {}", problem));
+            }
+            JwtKeySet::new(key_id, String::new(), Vec::new())
+        }
+    }
+}
+
+/// Lee un fichero PEM indicado en una variable, anotando el problema si no se puede leer.
+fn read_pem(key: &str, path: &str, problems: &mut Vec<String>) -> Option<String> {
+    match std::fs::read_to_string(path.trim()) {
+        Ok(pem) => Some(pem),
+        Err(e) => {
+            problems.push(format!("{} could not be read from '{}': {}", key, path.trim(), e));
+            None
+        }
+    }
+}
+
+/// Lee la configuración de las cabeceras de seguridad, anotando los valores inválidos.
+fn security_headers<F>(lookup: &F, problems: &mut Vec<String>) -> SecurityHeaders
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let enabled = parsed(lookup, "SECURITY_HEADERS", true, problems);
+    let hsts_max_age_seconds = parsed(lookup, "HSTS_MAX_AGE_SECONDS", 0u64, problems);
+
+    let frame_options = match lookup("X_FRAME_OPTIONS").map(|value| value.trim().to_ascii_uppercase()) {
+        None => HeaderValue::from_static("DENY"),
+        Some(value) if value == "DENY" => HeaderValue::from_static("DENY"),
+        Some(value) if value == "SAMEORIGIN" => HeaderValue::from_static("SAMEORIGIN"),
+        Some(value) => {
+            problems.push(format!("X_FRAME_OPTIONS must be DENY or SAMEORIGIN, got '{}'", value));
+            HeaderValue::from_static("DENY")
+        }
+    };
+
+    let csp = lookup("CONTENT_SECURITY_POLICY").unwrap_or_else(|| DEFAULT_CONTENT_SECURITY_POLICY.to_string());
+    let content_security_policy = match csp.trim() {
+        "" => None,
+        csp => match HeaderValue::from_str(csp) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                problems.push("CONTENT_SECURITY_POLICY is not a valid header value".to_string());
+                None
+            }
+        },
+    };
+
+    SecurityHeaders {
+        enabled,
+        frame_options,
+        hsts: SecurityHeaders::hsts_value(hsts_max_age_seconds),
+        content_security_policy,
+    }
+}
+
+/// Lee y convierte una variable opcional, usando `default` si no está definida.
+fn parsed<F, T>(lookup: &F, key: &str, default: T, problems: &mut Vec<String>) -> T
+where
+    F: Fn(&str) -> Option<String>,
+    T: FromStr,
+{
+    match lookup(key) {
+        None => default,
+        Some(value) => match value.trim().parse() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                problems.push(format!("{} has an invalid value: '{}'", key, value));
+                default
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Búsqueda sobre un conjunto fijo de variables.
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    const REQUIRED: &[(&str, &str)] = &[("DATABASE_URL", "postgres://localhost/lms"), ("JWT_SECRET", "secret")];
+
+    fn with(extra: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        vars(&[REQUIRED, extra].concat())
+    }
+
+    #[test]
+    fn loads_the_defaults() {
+        let config = Config::from_lookup("AUTH", with(&[])).unwrap();
+        assert_eq!(config.database_url, "postgres://localhost/lms");
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.jwt_expiration_hours, 24);
+        assert_eq!(config.cors, CorsPolicy::default());
+        assert!(config.internal_api_key.0.is_none());
+    }
+
+    #[test]
+    fn reports_every_missing_variable() {
+        let error = Config::from_lookup("AUTH", vars(&[])).unwrap_err();
+        assert_eq!(error.problems.len(), 2, "{:?}", error.problems);
+        assert!(error.problems.iter().any(|p| p.contains("DATABASE_URL")));
+        assert!(error.problems.iter().any(|p| p.contains("JWT_SECRET")));
+
+        let message = error.to_string();
+        assert!(message.contains("DATABASE_URL") && message.contains("JWT_SECRET"));
+    }
+
+    #[test]
+    fn reports_invalid_values_alongside_missing_ones() {
+        let error = Config::from_lookup("AUTH", vars(&[("JWT_SECRET", "secret"), ("PORT", "eighty")])).unwrap_err();
+        assert_eq!(error.problems.len(), 2, "{:?}", error.problems);
+        assert!(error.problems.iter().any(|p| p.contains("DATABASE_URL")));
+        assert!(error.problems.iter().any(|p| p.contains("PORT")));
+    }
+
+    #[test]
+    fn course_service_requires_the_download_secret() {
+        let error = Config::from_lookup("COURSE", with(&[])).unwrap_err();
+        assert_eq!(error.problems.len(), 1);
+        assert!(error.problems[0].contains("DOWNLOAD_TOKEN_SECRET"));
+        assert!(Config::from_lookup("COURSE", with(&[("DOWNLOAD_TOKEN_SECRET", "s")])).is_ok());
+    }
+
+    #[test]
+    fn reads_the_http_server_settings() {
+        let lookup = with(&[("HTTP_WORKERS", " 3 "), ("HTTP_KEEP_ALIVE_SECONDS", "30")]);
+        let config = Config::from_lookup("AUTH", lookup).unwrap();
+        assert_eq!(config.http_workers, 3);
+        assert!(matches!(config.keep_alive(), KeepAlive::Timeout(timeout) if timeout == Duration::from_secs(30)));
+
+        let config = Config::from_lookup("AUTH", with(&[("HTTP_KEEP_ALIVE_SECONDS", "0")])).unwrap();
+        assert!(config.http_workers >= 1);
+        assert!(matches!(config.keep_alive(), KeepAlive::Disabled));
+    }
+
+    #[test]
+    fn rejects_invalid_http_server_settings() {
+        for (key, value) in [("HTTP_WORKERS", "0"), ("HTTP_WORKERS", "many"), ("HTTP_KEEP_ALIVE_SECONDS", "-1")] {
+            let error = Config::from_lookup("AUTH", with(&[(key, value)])).unwrap_err();
+            assert_eq!(error.problems.len(), 1, "{:?}", error.problems);
+            assert!(error.problems[0].contains(key));
+        }
+    }
+
+    #[test]
+    fn default_user_role_cannot_be_admin() {
+        assert_eq!(Config::from_lookup("AUTH", with(&[])).unwrap().default_user_role, UserRole::Student);
+        let config = Config::from_lookup("AUTH", with(&[("DEFAULT_USER_ROLE", "instructor")])).unwrap();
+        assert_eq!(config.default_user_role, UserRole::Instructor);
+
+        let error = Config::from_lookup("AUTH", with(&[("DEFAULT_USER_ROLE", "admin")])).unwrap_err();
+        assert_eq!(error.problems.len(), 1, "{:?}", error.problems);
+        assert!(error.problems[0].contains("DEFAULT_USER_ROLE"));
+    }
+
+    #[test]
+    fn enrollment_cap_of_zero_means_unlimited() {
+        assert_eq!(Config::from_lookup("ENROLLMENT", with(&[])).unwrap().max_active_enrollments, None);
+        let config = Config::from_lookup("ENROLLMENT", with(&[("MAX_ACTIVE_ENROLLMENTS_PER_USER", "0")])).unwrap();
+        assert_eq!(config.max_active_enrollments, None);
+        let config = Config::from_lookup("ENROLLMENT", with(&[("MAX_ACTIVE_ENROLLMENTS_PER_USER", "3")])).unwrap();
+        assert_eq!(config.max_active_enrollments, Some(3));
+
+        let error = Config::from_lookup("ENROLLMENT", with(&[("MAX_ACTIVE_ENROLLMENTS_PER_USER", "-1")])).unwrap_err();
+        assert_eq!(error.problems.len(), 1, "{:?}", error.problems);
+        assert!(error.problems[0].contains("MAX_ACTIVE_ENROLLMENTS_PER_USER"));
+    }
+}
@@ -0,0 +1,177 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method, Uri};
+use std::str::FromStr;
+
+/// Política de CORS de un servicio. Cada lista vacía significa "cualquiera", que es el
+/// comportamiento permisivo de siempre cuando no se configura nada.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorsPolicy {
+    /// Orígenes permitidos (`https://app.example.com`).
+    pub origins: Vec<String>,
+    /// Métodos permitidos en las peticiones con preflight.
+    pub methods: Vec<Method>,
+    /// Cabeceras que puede enviar el navegador.
+    pub headers: Vec<HeaderName>,
+    /// Segundos que el navegador puede reutilizar la respuesta del preflight. `None` no envía
+    /// `Access-Control-Max-Age`.
+    pub max_age: Option<usize>,
+}
+
+impl CorsPolicy {
+    /// Lee la política del servicio `service` (`AUTH`, `COURSE`, `ENROLLMENT`): cada valor se
+    /// toma de `{service}_CORS_ORIGINS`, `{service}_CORS_METHODS`, `{service}_CORS_HEADERS` y
+    /// `{service}_CORS_MAX_AGE_SECONDS`, y si no está definido, de la variable compartida
+    /// (`CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`, `CORS_ALLOWED_HEADERS` y
+    /// `CORS_MAX_AGE_SECONDS`). Así varios servicios pueden compartir la política y uno concreto
+    /// sobrescribir solo lo que necesita. Los valores inválidos se anotan en `problems`.
+    pub fn from_lookup<F>(service: &str, lookup: &F, problems: &mut Vec<String>) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let setting = |suffix: &str, shared: &str| {
+            let key = format!("{}_CORS_{}", service, suffix);
+            match lookup(&key) {
+                Some(value) => Some((key, value)),
+                None => lookup(shared).map(|value| (shared.to_string(), value)),
+            }
+        };
+
+        let mut origins = Vec::new();
+        if let Some((key, value)) = setting("ORIGINS", "CORS_ALLOWED_ORIGINS") {
+            for origin in list(&value) {
+                match Uri::from_str(origin) {
+                    Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => origins.push(origin.to_string()),
+                    _ => problems.push(format!("{} contains an invalid origin: '{}'", key, origin)),
+                }
+            }
+        }
+
+        let mut methods = Vec::new();
+        if let Some((key, value)) = setting("METHODS", "CORS_ALLOWED_METHODS") {
+            for method in list(&value) {
+                match Method::from_str(&method.to_ascii_uppercase()) {
+                    Ok(method) => methods.push(method),
+                    Err(_) => problems.push(format!("{} contains an invalid method: '{}'", key, method)),
+                }
+            }
+        }
+
+        let mut headers = Vec::new();
+        if let Some((key, value)) = setting("HEADERS", "CORS_ALLOWED_HEADERS") {
+            for header in list(&value) {
+                match HeaderName::from_str(header) {
+                    Ok(header) => headers.push(header),
+                    Err(_) => problems.push(format!("{} contains an invalid header: '{}'", key, header)),
+                }
+            }
+        }
+
+        let max_age = match setting("MAX_AGE_SECONDS", "CORS_MAX_AGE_SECONDS") {
+            None => None,
+            Some((key, value)) => match value.trim().parse::<usize>() {
+                Ok(seconds) => Some(seconds),
+                Err(_) => {
+                    problems.push(format!("{} has an invalid value: '{}'", key, value));
+                    None
+                }
+            },
+        };
+
+        Self {
+            origins,
+            methods,
+            headers,
+            max_age,
+        }
+    }
+
+    /// Construye el middleware de CORS con esta política.
+    pub fn cors(&self) -> Cors {
+        let mut cors = Cors::default().max_age(self.max_age);
+        cors = if self.origins.is_empty() {
+            cors.allow_any_origin()
+        } else {
+            self.origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+        };
+        cors = if self.methods.is_empty() {
+            cors.allow_any_method()
+        } else {
+            cors.allowed_methods(self.methods.clone())
+        };
+        if self.headers.is_empty() {
+            cors.allow_any_header()
+        } else {
+            cors.allowed_headers(self.headers.clone())
+        }
+    }
+}
+
+/// Elementos no vacíos de una lista separada por comas.
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn policy(service: &str, pairs: &[(&str, &str)]) -> (CorsPolicy, Vec<String>) {
+        let vars: HashMap<&str, &str> = pairs.iter().copied().collect();
+        let lookup = |key: &str| vars.get(key).map(|value| value.to_string());
+        let mut problems = Vec::new();
+        (CorsPolicy::from_lookup(service, &lookup, &mut problems), problems)
+    }
+
+    #[test]
+    fn defaults_to_allow_any() {
+        let (policy, problems) = policy("COURSE", &[]);
+        assert_eq!(policy, CorsPolicy::default());
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn service_variables_override_the_shared_ones() {
+        let vars = [
+            ("CORS_ALLOWED_ORIGINS", "https://app.example.com"),
+            ("CORS_ALLOWED_METHODS", "get, post"),
+            ("CORS_MAX_AGE_SECONDS", "600"),
+            ("COURSE_CORS_ORIGINS", "https://admin.example.com, https://app.example.com"),
+            ("COURSE_CORS_HEADERS", "authorization,content-type"),
+        ];
+
+        let (course, problems) = policy("COURSE", &vars);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(course.origins, ["https://admin.example.com", "https://app.example.com"]);
+        assert_eq!(course.methods, [Method::GET, Method::POST]);
+        assert_eq!(
+            course.headers,
+            [HeaderName::from_static("authorization"), HeaderName::from_static("content-type")]
+        );
+        assert_eq!(course.max_age, Some(600));
+
+        let (auth, _) = policy("AUTH", &vars);
+        assert_eq!(auth.origins, ["https://app.example.com"]);
+        assert_eq!(auth.methods, course.methods);
+        assert!(auth.headers.is_empty());
+    }
+
+    #[test]
+    fn reports_each_invalid_value_with_its_variable() {
+        let (policy, problems) = policy(
+            "AUTH",
+            &[
+                ("AUTH_CORS_ORIGINS", "app.example.com,https://ok.example.com"),
+                ("CORS_ALLOWED_METHODS", "GET,NOT A METHOD"),
+                ("AUTH_CORS_HEADERS", "bad header"),
+                ("AUTH_CORS_MAX_AGE_SECONDS", "soon"),
+            ],
+        );
+        assert_eq!(policy.origins, ["https://ok.example.com"]);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("AUTH_CORS_ORIGINS"));
+        assert!(problems[1].starts_with("CORS_ALLOWED_METHODS"));
+        assert!(problems[2].starts_with("AUTH_CORS_HEADERS"));
+        assert!(problems[3].starts_with("AUTH_CORS_MAX_AGE_SECONDS"));
+    }
+}
@@ -0,0 +1,212 @@
+use actix_web::HttpResponse;
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgConnectOptions, PgDatabaseError, PgPoolOptions};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::error::ApiError;
+
+/// Código SQLSTATE con el que PostgreSQL cancela una consulta que superó `statement_timeout`.
+const QUERY_CANCELED: &str = "57014";
+
+/// Espera por una conexión libre a partir de la cual se avisa de que el pool está saturado.
+pub const POOL_ACQUIRE_WARN_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Estado del pool de conexiones, para `GET /health/detail`.
+#[derive(Debug, Serialize)]
+pub struct PoolStats {
+    /// Conexiones abiertas, en uso o libres.
+    pub size: u32,
+    /// Conexiones abiertas y libres.
+    pub num_idle: usize,
+    pub max_connections: u32,
+    /// Esperas por una conexión que superaron `POOL_ACQUIRE_WARN_THRESHOLD` desde el arranque.
+    pub slow_acquisitions: u64,
+}
+
+impl PoolStats {
+    pub fn of(db_pool: &PgPool) -> Self {
+        Self {
+            size: db_pool.size(),
+            num_idle: db_pool.num_idle(),
+            max_connections: db_pool.options().get_max_connections(),
+            slow_acquisitions: SLOW_ACQUISITIONS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Crea el pool de conexiones a partir de la configuración.
+///
+/// Con `DB_QUERY_TIMEOUT_MS` definido, cada conexión fija `statement_timeout` (el servidor
+/// cancela las consultas lentas) y la espera por una conexión libre del pool queda acotada
+/// al mismo tiempo, así que ninguna petición se queda colgada indefinidamente.
+pub async fn connect(config: &Config) -> Result<PgPool, sqlx::Error> {
+    // Las sesiones usan siempre UTC, para que `NOW()`, `date_trunc` y las conversiones de fechas no
+    // dependan de la zona horaria configurada en el servidor de base de datos.
+    let mut connect_options = PgConnectOptions::from_str(&config.database_url)?.options([("timezone", "UTC")]);
+    let mut pool_options = PgPoolOptions::new().max_connections(config.database_max_connections);
+
+    if let Some(timeout) = config.db_query_timeout {
+        connect_options = connect_options.options([("statement_timeout", format!("{}ms", timeout.as_millis()))]);
+        pool_options = pool_options.acquire_timeout(timeout);
+    }
+
+    pool_options.connect_with(connect_options).await
+}
+
+/// Pide una conexión al pool y cronometra la espera (ver `record_acquire_wait`). Los manejadores
+/// y los extractores que necesitan una conexión propia la piden con ella en lugar de
+/// `db_pool.acquire()`.
+pub async fn acquire(db_pool: &PgPool) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let started_at = Instant::now();
+    let conn = db_pool.acquire().await;
+    record_acquire_wait(db_pool, started_at.elapsed());
+    conn
+}
+
+/// Abre una transacción con una conexión de `acquire`, así que su espera también se cronometra.
+/// Uso en un manejador: `let mut tx = match db::begin(&state.db_pool).await { ... };`
+pub async fn begin(db_pool: &PgPool) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    Transaction::begin(acquire(db_pool).await?).await
+}
+
+/// Esperas por una conexión que superaron `POOL_ACQUIRE_WARN_THRESHOLD` desde el arranque.
+static SLOW_ACQUISITIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Si la espera por una conexión superó `POOL_ACQUIRE_WARN_THRESHOLD`, la cuenta y emite un
+/// `warn` con el estado del pool: las peticiones están haciendo cola porque
+/// `DATABASE_MAX_CONNECTIONS` se ha quedado corto.
+fn record_acquire_wait(db_pool: &PgPool, waited: Duration) {
+    if waited < POOL_ACQUIRE_WARN_THRESHOLD {
+        return;
+    }
+    SLOW_ACQUISITIONS.fetch_add(1, Ordering::Relaxed);
+    let stats = PoolStats::of(db_pool);
+    tracing::warn!(
+        waited_ms = waited.as_millis() as u64,
+        size = stats.size,
+        num_idle = stats.num_idle,
+        max_connections = stats.max_connections,
+        "Database pool saturated: acquiring a connection took longer than {:?}",
+        POOL_ACQUIRE_WARN_THRESHOLD
+    );
+}
+
+/// Indica si el error se debe a que una consulta (o la espera por una conexión) superó el tiempo límite.
+pub fn is_timeout(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some(QUERY_CANCELED),
+        _ => false,
+    }
+}
+
+/// Respuesta `503` común para los manejadores cuya consulta superó el tiempo límite.
+///
+/// Uso en un manejador: `Err(e) if db::is_timeout(&e) => db::timeout_response(),`
+pub fn timeout_response() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json(ApiError::new("timeout", "The database query timed out"))
+}
+
+/// Indica si el error es una violación de clave foránea: la fila referenciada no existe.
+pub fn is_foreign_key_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation())
+}
+
+/// Respuesta `400` común para las inserciones que referencian una fila inexistente, con el
+/// código `invalid_reference` y el campo afectado en `field`.
+///
+/// El campo se deduce del nombre de la restricción con la convención de PostgreSQL
+/// (`<tabla>_<columna>_fkey`). Uso en un manejador:
+/// `Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),`
+pub fn invalid_reference_response(err: &sqlx::Error) -> HttpResponse {
+    let field = match err {
+        sqlx::Error::Database(db_err) => db_err.try_downcast_ref::<PgDatabaseError>().and_then(|pg_err| {
+            let table = pg_err.table()?;
+            let constraint = pg_err.constraint()?;
+            constraint.strip_prefix(table)?.strip_prefix('_')?.strip_suffix("_fkey").map(str::to_string)
+        }),
+        _ => None,
+    };
+
+    let error = match field {
+        Some(field) => ApiError::new("invalid_reference", format!("The referenced {} does not exist", field)).with_field(field),
+        None => ApiError::new("invalid_reference", "A referenced record does not exist"),
+    };
+    HttpResponse::BadRequest().json(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, http::StatusCode};
+
+    use crate::testing;
+
+    #[actix_web::test]
+    async fn slow_queries_return_the_timeout_response() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let config = Config::from_lookup("AUTH", |key| match key {
+            "DATABASE_URL" => Some(url.clone()),
+            "JWT_SECRET" => Some("secret".to_string()),
+            "DB_QUERY_TIMEOUT_MS" => Some("100".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let db_pool = connect(&config).await.unwrap();
+
+        let err = sqlx::query("SELECT pg_sleep(2)").execute(&db_pool).await.unwrap_err();
+        assert!(is_timeout(&err), "{:?}", err);
+        let res = timeout_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "timeout");
+
+        // Las consultas rápidas no se ven afectadas.
+        let one: i32 = sqlx::query_scalar("SELECT 1").fetch_one(&db_pool).await.unwrap();
+        assert_eq!(one, 1);
+    }
+
+    #[actix_web::test]
+    async fn foreign_key_violations_name_the_field() {
+        let db_pool = testing::pool().await;
+        let mut conn = db_pool.acquire().await.unwrap();
+        sqlx::query("CREATE TEMPORARY TABLE parents (id INT PRIMARY KEY)").execute(&mut *conn).await.unwrap();
+        sqlx::query("CREATE TEMPORARY TABLE children (parent_id INT REFERENCES parents (id))")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        let err = sqlx::query("INSERT INTO children (parent_id) VALUES (1)").execute(&mut *conn).await.unwrap_err();
+        assert!(is_foreign_key_violation(&err));
+        assert!(!is_timeout(&err));
+
+        let res = invalid_reference_response(&err);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "invalid_reference");
+        assert_eq!(body["field"], "parent_id");
+    }
+
+    #[actix_web::test]
+    async fn slow_acquisitions_are_counted() {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let db_pool = PgPoolOptions::new().max_connections(1).connect(&url).await.unwrap();
+        let before = PoolStats::of(&db_pool).slow_acquisitions;
+
+        // Con la única conexión ocupada, la siguiente espera a que se libere.
+        let busy = acquire(&db_pool).await.unwrap();
+        actix_web::rt::spawn(async move {
+            actix_web::rt::time::sleep(POOL_ACQUIRE_WARN_THRESHOLD * 2).await;
+            drop(busy);
+        });
+        let mut tx = begin(&db_pool).await.unwrap();
+        let one: i32 = sqlx::query_scalar("SELECT 1").fetch_one(&mut *tx).await.unwrap();
+        assert_eq!(one, 1);
+        assert!(PoolStats::of(&db_pool).slow_acquisitions > before);
+    }
+}
@@ -0,0 +1,146 @@
+use actix_web::{
+    error::{InternalError, JsonPayloadError},
+    http::header::{CONTENT_LENGTH, CONTENT_TYPE},
+    web, HttpRequest, HttpResponse,
+};
+use serde::Serialize;
+
+/// Cuerpo JSON de error común a todos los servicios.
+///
+/// `code` es un identificador estable pensado para que los clientes lo interpreten
+/// (por ejemplo, `version_conflict`); `message` es un texto legible para personas. `field`
+/// indica, cuando se conoce, el campo de la petición que causó el error.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+}
+
+/// Configuración de los extractores `web::Json` común a todos los servicios.
+///
+/// Los errores del cuerpo se devuelven como `ApiError`: `415 unsupported_media_type` si el
+/// `Content-Type` no es JSON, `400 empty_body` si falta el cuerpo, `413 payload_too_large` si
+/// excede el límite y `400 invalid_json` si no se puede interpretar.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(json_error_handler)
+}
+
+fn json_error_handler(err: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    // Una petición sin cuerpo ni `Content-Type` es un cuerpo vacío, no un tipo equivocado.
+    let is_empty_request = !req.headers().contains_key(CONTENT_TYPE)
+        && req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .is_none_or(|length| length.as_bytes() == b"0");
+
+    let response = match &err {
+        JsonPayloadError::ContentType if is_empty_request => {
+            HttpResponse::BadRequest().json(ApiError::new("empty_body", "A JSON request body is required"))
+        }
+        JsonPayloadError::ContentType => HttpResponse::UnsupportedMediaType().json(ApiError::new(
+            "unsupported_media_type",
+            "Content-Type must be application/json",
+        )),
+        JsonPayloadError::Deserialize(e) if e.is_eof() && e.line() == 1 && e.column() == 0 => {
+            HttpResponse::BadRequest().json(ApiError::new("empty_body", "A JSON request body is required"))
+        }
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            HttpResponse::PayloadTooLarge().json(ApiError::new("payload_too_large", "The request body is too large"))
+        }
+        _ => HttpResponse::BadRequest().json(ApiError::new("invalid_json", err.to_string())),
+    };
+    InternalError::from_response(err, response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Body {
+        #[allow(dead_code)]
+        title: String,
+    }
+
+    async fn post(req: TestRequest) -> (StatusCode, serde_json::Value) {
+        let app = init_service(
+            App::new()
+                .app_data(json_config().limit(64))
+                .route("/", web::post().to(|_: web::Json<Body>| async { HttpResponse::Created().finish() })),
+        )
+        .await;
+        let res = call_service(&app, req.uri("/").to_request()).await;
+        let status = res.status();
+        let body = if status.is_success() { serde_json::Value::Null } else { read_body_json(res).await };
+        (status, body)
+    }
+
+    #[actix_web::test]
+    async fn accepts_a_json_body() {
+        let (status, _) = post(TestRequest::post().set_json(serde_json::json!({ "title": "Rust" }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn rejects_other_content_types_with_415() {
+        for content_type in ["text/plain", "application/x-www-form-urlencoded"] {
+            let req = TestRequest::post()
+                .insert_header((CONTENT_TYPE, content_type))
+                .set_payload(r#"{"title":"Rust"}"#);
+            let (status, body) = post(req).await;
+            assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            assert_eq!(body["code"], "unsupported_media_type");
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_empty_bodies_with_400() {
+        let (status, body) = post(TestRequest::post()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "empty_body");
+
+        let (status, body) = post(TestRequest::post().insert_header((CONTENT_TYPE, "application/json"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "empty_body");
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_and_oversized_bodies() {
+        let json = |payload: &str| {
+            TestRequest::post()
+                .insert_header((CONTENT_TYPE, "application/json"))
+                .set_payload(payload.to_string())
+        };
+
+        let (status, body) = post(json(r#"{"title":"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_json");
+
+        let (status, body) = post(json(&format!(r#"{{"title":"{}"}}"#, "a".repeat(100)))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "payload_too_large");
+    }
+}
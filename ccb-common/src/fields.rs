@@ -0,0 +1,100 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+/// Campos pedidos en `?fields=id,title` para devolver solo una parte de cada objeto de la
+/// respuesta (sparse fieldsets). Sin el parámetro (o vacío) se devuelven todos.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldSet(Option<Vec<String>>);
+
+impl FieldSet {
+    /// Valida la lista separada por comas contra los campos que admite el endpoint. Un campo
+    /// desconocido responde `400` con el código `unknown_field` y `field: "fields"`.
+    pub fn parse(raw: Option<&str>, known: &[&str]) -> Result<Self, ApiError> {
+        let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+            return Ok(Self(None));
+        };
+
+        let mut fields = Vec::new();
+        for field in raw.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+            if !known.contains(&field) {
+                return Err(ApiError::new(
+                    "unknown_field",
+                    format!("Unknown field '{}'; allowed fields are: {}", field, known.join(", ")),
+                )
+                .with_field("fields"));
+            }
+            if !fields.iter().any(|f| f == field) {
+                fields.push(field.to_string());
+            }
+        }
+        Ok(Self(Some(fields)))
+    }
+
+    /// Indica si la respuesta debe incluir el campo, para no calcular los que no se piden.
+    pub fn includes(&self, field: &str) -> bool {
+        self.0.as_ref().is_none_or(|fields| fields.iter().any(|f| f == field))
+    }
+
+    /// Serializa el valor y se queda solo con los campos pedidos. En una lista se aplica a cada
+    /// elemento. Los campos pedidos que el objeto no tiene (p. ej. los opcionales omitidos) no
+    /// aparecen.
+    pub fn project<T: Serialize>(&self, value: &T) -> Result<Value, serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        Ok(match &self.0 {
+            None => value,
+            Some(fields) => project_value(value, fields),
+        })
+    }
+}
+
+fn project_value(value: Value, fields: &[String]) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.into_iter().map(|item| project_value(item, fields)).collect()),
+        Value::Object(mut object) => {
+            object.retain(|key, _| fields.contains(key));
+            Value::Object(object)
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KNOWN: &[&str] = &["id", "title", "description", "tags"];
+
+    #[test]
+    fn without_fields_keeps_everything() {
+        for raw in [None, Some(""), Some("  ")] {
+            let fields = FieldSet::parse(raw, KNOWN).unwrap();
+            assert_eq!(fields, FieldSet::default());
+            assert!(fields.includes("tags"));
+        }
+    }
+
+    #[test]
+    fn projects_objects_and_lists() {
+        let fields = FieldSet::parse(Some("id, title,id"), KNOWN).unwrap();
+        assert!(fields.includes("title"));
+        assert!(!fields.includes("description"));
+
+        let course = json!({ "id": 1, "title": "Rust", "description": "...", "tags": ["rust"] });
+        assert_eq!(fields.project(&course).unwrap(), json!({ "id": 1, "title": "Rust" }));
+        assert_eq!(
+            fields.project(&json!([course, { "id": 2, "description": "..." }])).unwrap(),
+            json!([{ "id": 1, "title": "Rust" }, { "id": 2 }])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = FieldSet::parse(Some("id,password_hash"), KNOWN).unwrap_err();
+        assert_eq!(error.code, "unknown_field");
+        assert_eq!(error.field.as_deref(), Some("fields"));
+        assert!(error.message.contains("password_hash"));
+    }
+}
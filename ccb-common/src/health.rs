@@ -0,0 +1,97 @@
+use actix_web::HttpResponse;
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::db::PoolStats;
+
+/// Migraciones del repositorio, incrustadas al compilar. Son las que el esquema debería tener aplicadas.
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// Estado detallado de un servicio para `GET /health/detail`.
+#[derive(Serialize)]
+pub struct HealthDetail {
+    pub service: &'static str,
+    pub version: &'static str,
+    pub database_version: String,
+    /// `true` si todas las migraciones conocidas por el servicio están aplicadas en la base de datos.
+    pub migrations_applied: bool,
+    /// Versiones de las migraciones que faltan por aplicar.
+    pub pending_migrations: Vec<i64>,
+    /// Conexiones del pool: si `num_idle` se queda en 0 con `size == max_connections`, las
+    /// peticiones están esperando por una conexión; `slow_acquisitions` cuenta esas esperas.
+    pub pool: PoolStats,
+}
+
+/// Construye la respuesta de `GET /health/detail`: versión del servicio, versión del servidor
+/// de PostgreSQL, migraciones pendientes (según la tabla `_sqlx_migrations` de `sqlx migrate`) y
+/// estado del pool de conexiones.
+pub async fn health_detail(db_pool: &PgPool, service: &'static str, version: &'static str) -> HttpResponse {
+    let database_version = match sqlx::query_scalar::<_, String>("SELECT version()").fetch_one(db_pool).await {
+        Ok(version) => version,
+        Err(e) => {
+            tracing::error!("Health check failed to reach the database: {:?}", e);
+            return HttpResponse::ServiceUnavailable().body("Database unavailable");
+        }
+    };
+
+    // Si la tabla de control no existe, no se ha aplicado ninguna migración con `sqlx migrate`.
+    let applied: HashSet<i64> = sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(db_pool)
+        .await
+        .map(|versions| versions.into_iter().collect())
+        .unwrap_or_default();
+
+    let pending_migrations: Vec<i64> = MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect();
+
+    HttpResponse::Ok().json(HealthDetail {
+        service,
+        version,
+        database_version,
+        migrations_applied: pending_migrations.is_empty(),
+        pending_migrations,
+        pool: PoolStats::of(db_pool),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, http::StatusCode};
+
+    use crate::db::POOL_ACQUIRE_WARN_THRESHOLD;
+    use crate::testing;
+
+    async fn detail() -> serde_json::Value {
+        let db_pool = testing::pool().await;
+        let res = health_detail(&db_pool, "test-service", "1.2.3").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reports_versions_and_migration_status() {
+        let body = detail().await;
+        assert_eq!(body["service"], "test-service");
+        assert_eq!(body["version"], "1.2.3");
+        assert!(body["database_version"].as_str().unwrap().starts_with("PostgreSQL"));
+        assert!(body["migrations_applied"].is_boolean());
+        assert!(body["pending_migrations"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn reports_pool_stats() {
+        let pool = &detail().await["pool"];
+        assert_eq!(pool["max_connections"], 2);
+        assert!(pool["size"].as_u64().unwrap() >= 1);
+        assert!(pool["num_idle"].is_u64());
+        assert!(pool["slow_acquisitions"].is_u64());
+        assert!(POOL_ACQUIRE_WARN_THRESHOLD > std::time::Duration::ZERO);
+    }
+}
+
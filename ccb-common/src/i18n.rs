@@ -0,0 +1,321 @@
+use actix_web::{
+    body::{to_bytes, BoxBody, EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_TYPE},
+    Error,
+};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+/// Idiomas en los que se pueden devolver los mensajes de error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Es,
+}
+
+impl Lang {
+    /// Elige el idioma a partir de una cabecera `Accept-Language` (`es-CL,es;q=0.9,en;q=0.8`).
+    ///
+    /// Se toma el idioma soportado con mayor `q`; si ninguno lo está, se usa inglés.
+    pub fn from_accept_language(header: &str) -> Self {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Orden estable: a igual `q` se respeta el orden de la cabecera.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| {
+                let primary = tag.split('-').next().unwrap_or(tag);
+                if primary.eq_ignore_ascii_case("es") {
+                    Some(Lang::Es)
+                } else if primary.eq_ignore_ascii_case("en") {
+                    Some(Lang::En)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Es => "es",
+        }
+    }
+}
+
+/// Mensaje de un código de error de `ApiError` en el idioma indicado.
+///
+/// Los mensajes en inglés son los que ya escriben los manejadores, así que para `Lang::En`
+/// (y para los códigos sin traducción) se devuelve `None` y se conserva el mensaje original,
+/// que puede incluir detalles concretos de la petición.
+pub fn message(code: &str, lang: Lang) -> Option<&'static str> {
+    match lang {
+        Lang::En => None,
+        Lang::Es => match code {
+            "unsupported_media_type" => Some("El Content-Type debe ser application/json"),
+            "empty_body" => Some("Se requiere un cuerpo JSON en la petición"),
+            "payload_too_large" => Some("El cuerpo de la petición es demasiado grande"),
+            "invalid_json" => Some("El cuerpo de la petición no es un JSON válido"),
+            "timeout" => Some("La consulta a la base de datos superó el tiempo límite"),
+            "maintenance" => Some("El servicio está en mantenimiento; solo se aceptan peticiones de lectura"),
+            "version_conflict" => Some("Otra persona modificó el curso; vuelve a cargarlo e inténtalo de nuevo"),
+            "version_required" => {
+                Some("Envía la versión del curso que editaste en la cabecera If-Match o como expected_version")
+            }
+            "cannot_enroll_own_course" => Some("Los instructores no pueden inscribirse en su propio curso"),
+            "course_full" => Some("El curso está completo; se te ha añadido a su lista de espera"),
+            "enrollment_limit_reached" => Some("Ya tienes el máximo de inscripciones activas permitidas"),
+            "invalid_reference" => Some("El registro referenciado no existe"),
+            "offering_required" => Some("Estás inscrito en varias ediciones de este curso; indica cuál con offering_id"),
+            "invalid_transition" => Some("El curso no puede pasar de su estado actual al pedido"),
+            "account_pending_deletion" => {
+                Some("Esta cuenta tiene el borrado programado; cancela el borrado para volver a iniciar sesión")
+            }
+            "insufficient_scope" => Some("El token no tiene el permiso necesario para esta acción"),
+            "impersonation_forbidden" => Some("No se puede cambiar la cuenta durante una suplantación"),
+            "password_change_required" => Some("Debes cambiar tu contraseña antes de continuar"),
+            "invalid_pagination" => Some("Los parámetros de paginación deben ser enteros positivos"),
+            "too_many_login_attempts" => {
+                Some("Demasiados intentos de inicio de sesión fallidos; espera antes de volver a intentarlo")
+            }
+            "rate_limited" => Some("Demasiadas peticiones de escritura; espera antes de volver a intentarlo"),
+            "field_too_long" => Some("El campo supera la longitud máxima permitida"),
+            "field_required" => Some("El campo no puede estar vacío"),
+            "unknown_field" => Some("El campo pedido no existe"),
+            "captcha_required" => Some("Resuelve el captcha para continuar"),
+            "captcha_invalid" => Some("El captcha no es válido o ha caducado"),
+            "captcha_unavailable" => Some("No se pudo verificar el captcha; inténtalo de nuevo más tarde"),
+            "invalid_username" => Some("El nombre de usuario no puede contener '@'"),
+            "email_taken" => Some("El email ya está en uso por otra cuenta"),
+            "email_already_verified" => Some("El email de la cuenta ya está verificado y no se puede cambiar"),
+            "email_required" => Some("Indica el email de tu cuenta para continuar"),
+            "email_unverified" => Some("Verifica el email de tu cuenta para continuar"),
+            "reviews_disabled" => Some("Las reseñas de este curso están desactivadas"),
+            "course_not_completed" => Some("Solo los estudiantes que completaron el curso pueden reseñarlo"),
+            "internal_error" => Some("Se produjo un error interno; inténtalo de nuevo más tarde"),
+            "unauthorized" => Some("No has iniciado sesión o el token no es válido"),
+            "invalid_credentials" => Some("El nombre de usuario o la contraseña no son correctos"),
+            "incorrect_password" => Some("La contraseña actual no es correcta"),
+            "password_unchanged" => Some("La nueva contraseña debe ser distinta de la actual"),
+            "invalid_verification_token" => Some("El token de verificación no es válido o ha caducado"),
+            "invalid_reset_token" => Some("El token para restablecer la contraseña no es válido o ha caducado"),
+            "invalid_download_token" => Some("El enlace de descarga no es válido o ha caducado"),
+            "username_taken" => Some("El nombre de usuario ya está en uso"),
+            "admin_required" => Some("Solo los administradores pueden realizar esta acción"),
+            "instructor_required" => Some("Solo los instructores y administradores pueden realizar esta acción"),
+            "not_course_owner" => Some("Solo el instructor del curso o un administrador puede realizar esta acción"),
+            "enrollment_required" => Some("Debes estar inscrito en el curso para realizar esta acción"),
+            "cannot_impersonate_admin" => Some("No se puede suplantar a un administrador"),
+            "cannot_impersonate_self" => Some("No puedes suplantarte a ti mismo"),
+            "user_pending_deletion" => Some("La cuenta del usuario tiene el borrado programado"),
+            "invalid_target_instructor" => {
+                Some("Los cursos solo se pueden reasignar a otro usuario que sea instructor o administrador")
+            }
+            "owns_courses" => Some("Elimina o transfiere tus cursos antes de borrar tu cuenta"),
+            "deletion_not_scheduled" => Some("La cuenta no tiene el borrado programado"),
+            "course_not_found" => Some("El curso no existe"),
+            "user_not_found" => Some("El usuario no existe"),
+            "lesson_not_found" => Some("La lección no existe"),
+            "review_not_found" => Some("La reseña no existe"),
+            "material_not_found" => Some("El material no existe"),
+            "token_not_found" => Some("El token no existe"),
+            "revision_not_found" => Some("La revisión no existe"),
+            "category_not_found" => Some("La categoría no existe"),
+            "enrollment_not_found" => Some("No hay una inscripción activa en este curso"),
+            "offerings_disabled" => Some("Las ediciones de cursos están desactivadas"),
+            "already_enrolled" => Some("Ya estás inscrito en este curso"),
+            "already_reviewed" => Some("Ya has reseñado este curso"),
+            "already_reported" => Some("Ya has denunciado esta reseña"),
+            "cannot_report_own_review" => Some("No puedes denunciar tu propia reseña"),
+            "category_exists" => Some("La categoría ya existe"),
+            "offering_exists" => Some("Ya existe una edición con ese nombre en el curso"),
+            "slug_conflict" => Some("Otro curso acaba de tomar la misma URL; inténtalo de nuevo"),
+            "must_be_positive" => Some("El valor debe ser mayor que 0"),
+            "invalid_length" => Some("El campo tiene una longitud no permitida"),
+            "invalid_order" => Some("El orden debe ser 'asc' o 'desc'"),
+            "invalid_sort" => Some("El campo de ordenación no es válido"),
+            "invalid_rating" => Some("La valoración debe estar entre 1 y 5"),
+            "invalid_rating_range" => Some("min_rating no puede ser mayor que max_rating"),
+            "invalid_date_range" => Some("La fecha de inicio debe ser anterior a la de fin"),
+            "invalid_metadata" => Some("metadata debe ser un objeto JSON"),
+            "invalid_material" => Some("Se requieren un nombre de archivo y un contenido no vacío"),
+            "invalid_lesson_order" => Some("lesson_ids debe contener exactamente las lecciones del curso, una vez cada una"),
+            "duplicate_ids" => Some("La lista de identificadores no puede contener duplicados"),
+            "too_many_ids" => Some("La petición incluye demasiados identificadores"),
+            "unknown_scope" => Some("El permiso pedido no existe"),
+            "unknown_notification_kind" => Some("El tipo de notificación no existe"),
+            _ => None,
+        },
+    }
+}
+
+/// Middleware que traduce los mensajes de error según `Accept-Language`.
+///
+/// Solo reescribe las respuestas de error con cuerpo `ApiError` (JSON con `code`) cuyo código
+/// tiene traducción: el `code` no cambia, se sustituye el `message` y se añade
+/// `Content-Language`. Sin cabecera, o si se pide inglés, la respuesta pasa intacta.
+pub struct LocalizedErrors;
+
+impl<S, B> Transform<S, ServiceRequest> for LocalizedErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LocalizedErrorsService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LocalizedErrorsService { service }))
+    }
+}
+
+pub struct LocalizedErrorsService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for LocalizedErrorsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let lang = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Lang::from_accept_language)
+            .unwrap_or_default();
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let is_json_error = (res.status().is_client_error() || res.status().is_server_error())
+                && res
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+            if lang == Lang::En || !is_json_error {
+                return Ok(res.map_into_left_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let bytes = to_bytes(body)
+                .await
+                .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+
+            let localized = serde_json::from_slice::<serde_json::Value>(&bytes).ok().and_then(|mut error| {
+                let translated = message(error.get("code")?.as_str()?, lang)?;
+                error["message"] = translated.into();
+                Some(error)
+            });
+
+            let res = match localized {
+                Some(error) => {
+                    let mut res = res.set_body(BoxBody::new(error.to_string()));
+                    res.headers_mut().insert(CONTENT_LANGUAGE, HeaderValue::from_static(lang.as_str()));
+                    res
+                }
+                None => res.set_body(BoxBody::new(bytes)),
+            };
+            Ok(ServiceResponse::new(req, res).map_into_right_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+    use actix_web::{
+        test::{call_service, init_service, read_body_json, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    #[test]
+    fn picks_the_supported_language_with_the_highest_quality() {
+        assert_eq!(Lang::from_accept_language("es-CL,es;q=0.9,en;q=0.8"), Lang::Es);
+        assert_eq!(Lang::from_accept_language("en-US,es;q=0.5"), Lang::En);
+        assert_eq!(Lang::from_accept_language("fr, es;q=0.3"), Lang::Es);
+        assert_eq!(Lang::from_accept_language("es;q=0, fr"), Lang::En);
+        assert_eq!(Lang::from_accept_language(""), Lang::En);
+    }
+
+    #[actix_web::test]
+    async fn translates_the_message_and_keeps_the_code() {
+        let app = init_service(App::new().wrap(LocalizedErrors).route(
+            "/",
+            web::get().to(|| async {
+                let error = ApiError::new("maintenance", "The service is under maintenance");
+                HttpResponse::ServiceUnavailable().json(error)
+            }),
+        ))
+        .await;
+        let request = |lang: &str| TestRequest::get().uri("/").insert_header((ACCEPT_LANGUAGE, lang)).to_request();
+
+        let res = call_service(&app, request("es")).await;
+        assert_eq!(res.headers().get(CONTENT_LANGUAGE).unwrap(), "es");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "maintenance");
+        assert_eq!(body["message"], message("maintenance", Lang::Es).unwrap());
+
+        let res = call_service(&app, request("en")).await;
+        assert!(res.headers().get(CONTENT_LANGUAGE).is_none());
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "maintenance");
+        assert_eq!(body["message"], "The service is under maintenance");
+    }
+
+    #[actix_web::test]
+    async fn leaves_untranslated_codes_and_successes_alone() {
+        let app = init_service(
+            App::new()
+                .wrap(LocalizedErrors)
+                .route(
+                    "/error",
+                    web::get().to(|| async { HttpResponse::BadRequest().json(ApiError::new("custom", "Custom")) }),
+                )
+                .route(
+                    "/ok",
+                    web::get().to(|| async { HttpResponse::Ok().json(serde_json::json!({ "code": "maintenance" })) }),
+                ),
+        )
+        .await;
+        let request = |uri: &str| TestRequest::get().uri(uri).insert_header((ACCEPT_LANGUAGE, "es")).to_request();
+
+        let res = call_service(&app, request("/error")).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["message"], "Custom");
+
+        let res = call_service(&app, request("/ok")).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(body.get("message").is_none());
+    }
+}
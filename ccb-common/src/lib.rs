@@ -1,29 +1,393 @@
-use actix_web::{dev::Payload, FromRequest, HttpRequest};
-use std::env;
+use actix_web::{dev::Payload, http::{header::AUTHORIZATION, Method}, web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
 use uuid::Uuid;
-use jsonwebtoken::{decode, DecodingKey, Validation};
 
+pub mod activity;
+pub mod api_key;
+pub mod audit;
+pub mod cache;
+pub mod client_ip;
+pub mod config;
+pub mod cors;
+pub mod db;
+pub mod error;
+pub mod fields;
+pub mod health;
+pub mod i18n;
+pub mod jwt;
+pub mod maintenance;
 pub mod middleware;
 pub mod models;
+pub mod notifications;
+pub mod pagination;
+pub mod pat;
+pub mod permissions;
+pub mod rate_limit;
+pub mod scope;
+pub mod security_headers;
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+pub mod throttle;
+pub mod timestamp;
+pub mod transaction;
 
 pub use models::{Claims, User, UserRole};
 
+/// Única ruta a la que da acceso un token con `password_change_required`.
+pub const PASSWORD_CHANGE_PATH: &str = "/me/password";
+
+/// Rutas `POST` que no modifican datos: el login y las consultas por lotes, que usan `POST` solo
+/// para enviar la lista de ids en el cuerpo.
+const READ_ONLY_POSTS: &[&str] = &["/login", "/users/batch", "/courses/batch"];
+
+/// Indica si una petición escribe (`POST`, `PUT`, `PATCH` o `DELETE`, salvo las de
+/// `READ_ONLY_POSTS`). El modo mantenimiento y el límite de escrituras solo afectan a estas.
+pub(crate) fn is_write_request(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::POST => !READ_ONLY_POSTS.contains(&path),
+        Method::PUT | Method::PATCH | Method::DELETE => true,
+        _ => false,
+    }
+}
+
 /// Estructura que representa al usuario autenticado a través del token JWT.
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub id: Uuid,
     pub role: UserRole,
+    /// Permisos del token (ver `scope`). `None` (tokens sin el claim `scopes`) equivale a todo
+    /// lo que permite el rol.
+    pub scopes: Option<Vec<String>>,
+    /// Admin que suplanta al usuario (claim `act_as`). `id` y `role` son los del usuario
+    /// suplantado; este campo identifica a quien hace realmente la petición.
+    pub impersonator: Option<Uuid>,
+}
+
+impl AuthenticatedUser {
+    /// Quien hace realmente la petición: el admin que suplanta al usuario o, si no, el propio usuario.
+    pub fn actor_id(&self) -> Uuid {
+        self.impersonator.unwrap_or(self.id)
+    }
+
+    /// Indica si el token tiene el permiso indicado.
+    pub fn has_scope(&self, required: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|granted| scope::grants(granted, required)))
+    }
+
+    /// Exige un permiso además del rol: devuelve la respuesta `403 insufficient_scope` si el token
+    /// no lo tiene. Uso en un manejador:
+    /// `if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) { return response; }`
+    pub fn missing_scope(&self, required: &str) -> Option<HttpResponse> {
+        if self.has_scope(required) {
+            return None;
+        }
+        Some(HttpResponse::Forbidden().json(error::ApiError::new(
+            "insufficient_scope",
+            format!("The token does not have the '{}' scope", required),
+        )))
+    }
+
+    /// Rechaza con `403 impersonation_forbidden` los cambios de la propia cuenta (perfil, email,
+    /// contraseña, tokens, borrado) durante una suplantación: sirve para ver lo que ve el usuario,
+    /// no para tomar el control de su cuenta. Uso en un manejador, como `missing_scope`.
+    pub fn impersonation_forbidden(&self) -> Option<HttpResponse> {
+        self.impersonator?;
+        Some(HttpResponse::Forbidden().json(error::ApiError::new(
+            "impersonation_forbidden",
+            "Account settings cannot be changed while impersonating a user",
+        )))
+    }
 }
 
 impl FromRequest for AuthenticatedUser {
     type Error = actix_web::Error;
-    type Future = std::future::Ready<Result<Self, Self::Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
-            std::future::ready(Ok(user.clone()))
-        } else {
-            std::future::ready(Err(actix_web::error::ErrorUnauthorized("Not authenticated or invalid token")))
+            return Box::pin(std::future::ready(Ok(user.clone())));
+        }
+
+        let Some(token) = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Box::pin(std::future::ready(Err(unauthorized())));
+        };
+
+        // Los tokens de acceso personal se reconocen por su prefijo y se validan contra la base
+        // de datos (el pool registrado como `app_data`).
+        if token.starts_with(pat::PAT_PREFIX) {
+            let Some(db_pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+                return Box::pin(std::future::ready(Err(unauthorized())));
+            };
+            let token = token.to_string();
+            let req = req.clone();
+            return Box::pin(async move {
+                let owner = match pat::authenticate(&db_pool, &token).await {
+                    Ok(Some(owner)) => owner,
+                    Ok(None) => return Err(unauthorized()),
+                    Err(e) => {
+                        tracing::error!("Failed to validate personal access token: {:?}", e);
+                        return Err(validation_failed());
+                    }
+                };
+                if !owner.allows(req.method()) {
+                    let response = HttpResponse::Forbidden().json(error::ApiError::new(
+                        "insufficient_scope",
+                        "The token does not have the required scope",
+                    ));
+                    return Err(actix_web::error::InternalError::from_response("insufficient scope", response).into());
+                }
+                let user = AuthenticatedUser {
+                    id: owner.user_id,
+                    role: owner.role,
+                    scopes: Some(owner.scopes),
+                    impersonator: None,
+                };
+                req.extensions_mut().insert(user.clone());
+                Ok(user)
+            });
+        }
+
+        // Si no, validamos el JWT con el conjunto de claves registrado en la aplicación (la clave
+        // se elige por el `kid` de la cabecera del JWT).
+        let user = req
+            .app_data::<web::Data<jwt::JwtKeySet>>()
+            .and_then(|keys| keys.decode(token).ok())
+            .and_then(|claims| {
+                let user = AuthenticatedUser {
+                    id: Uuid::parse_str(&claims.sub).ok()?,
+                    role: claims.role,
+                    scopes: claims.scopes,
+                    impersonator: match claims.act_as.as_deref() {
+                        Some(admin_id) => Some(Uuid::parse_str(admin_id).ok()?),
+                        None => None,
+                    },
+                };
+                Some((user, claims.password_change_required))
+            });
+
+        match user {
+            // Un token restringido solo sirve para cambiar la contraseña.
+            Some((_, true)) if req.path() != PASSWORD_CHANGE_PATH => {
+                let response = HttpResponse::Forbidden().json(error::ApiError::new(
+                    "password_change_required",
+                    "You must change your password before continuing",
+                ));
+                Box::pin(std::future::ready(Err(actix_web::error::InternalError::from_response(
+                    "password change required",
+                    response,
+                )
+                .into())))
+            }
+            Some((user, _)) => {
+                let db_pool = req.app_data::<web::Data<PgPool>>().cloned();
+                let req = req.clone();
+                Box::pin(async move {
+                    // Una cuenta con el borrado programado está desactivada: los JWT que ya tenía
+                    // dejan de valer, igual que sus tokens de acceso personal.
+                    if let Some(db_pool) = db_pool {
+                        match pending_deletion(&db_pool, user.id).await {
+                            Ok(Some(false)) => {}
+                            Ok(Some(true)) => {
+                                let response = HttpResponse::Forbidden().json(error::ApiError::new(
+                                    "account_pending_deletion",
+                                    "This account is scheduled for deletion; cancel the deletion to sign in again",
+                                ));
+                                return Err(actix_web::error::InternalError::from_response(
+                                    "account pending deletion",
+                                    response,
+                                )
+                                .into());
+                            }
+                            Ok(None) => return Err(unauthorized()),
+                            Err(e) => {
+                                tracing::error!("Failed to check account status: {:?}", e);
+                                return Err(validation_failed());
+                            }
+                        }
+                    }
+                    req.extensions_mut().insert(user.clone());
+                    Ok(user)
+                })
+            }
+            None => Box::pin(std::future::ready(Err(unauthorized()))),
         }
     }
 }
+
+/// Indica si la cuenta tiene el borrado programado. `None` si la cuenta ya no existe.
+async fn pending_deletion(db_pool: &PgPool, user_id: Uuid) -> Result<Option<bool>, sqlx::Error> {
+    // Casi todas las peticiones pasan por aquí: `db::acquire` mide su espera por una conexión.
+    let mut conn = db::acquire(db_pool).await?;
+    sqlx::query_scalar!(
+        r#"SELECT deletion_scheduled_at IS NOT NULL as "pending!" FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+fn unauthorized() -> actix_web::Error {
+    let response =
+        HttpResponse::Unauthorized().json(error::ApiError::new("unauthorized", "Not authenticated or invalid token"));
+    actix_web::error::InternalError::from_response("not authenticated", response).into()
+}
+
+fn validation_failed() -> actix_web::Error {
+    let response =
+        HttpResponse::InternalServerError().json(error::ApiError::new("internal_error", "Failed to validate token"));
+    actix_web::error::InternalError::from_response("token validation failed", response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App, Responder,
+    };
+
+    /// Responde `200` si el token tiene `courses:write` y `403 insufficient_scope` si no.
+    async fn write_course(auth_user: AuthenticatedUser) -> impl Responder {
+        if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+            return response;
+        }
+        HttpResponse::Ok().json(serde_json::json!({ "id": auth_user.id, "actor": auth_user.actor_id() }))
+    }
+
+    async fn call(claims: &Claims, path: &str) -> (StatusCode, serde_json::Value) {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .route("/courses", web::post().to(write_course))
+                .route(PASSWORD_CHANGE_PATH, web::put().to(write_course)),
+        )
+        .await;
+        let method = if path == PASSWORD_CHANGE_PATH { Method::PUT } else { Method::POST };
+        let req = TestRequest::default()
+            .method(method)
+            .uri(path)
+            .insert_header((AUTHORIZATION, testing::bearer(claims)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        let status = res.status();
+        let body = read_body_json::<serde_json::Value, _>(res).await;
+        (status, body)
+    }
+
+    #[actix_web::test]
+    async fn tokens_without_scopes_keep_every_role_permission() {
+        let id = Uuid::new_v4();
+        let (status, body) = call(&testing::claims(id, UserRole::Instructor), "/courses").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], id.to_string());
+    }
+
+    #[actix_web::test]
+    async fn scoped_tokens_are_allowed_and_denied_per_scope() {
+        let mut claims = testing::claims(Uuid::new_v4(), UserRole::Instructor);
+        claims.scopes = Some(vec![scope::COURSES_WRITE.to_string()]);
+        let (status, _) = call(&claims, "/courses").await;
+        assert_eq!(status, StatusCode::OK);
+
+        claims.scopes = Some(vec![scope::ENROLLMENTS_WRITE.to_string()]);
+        let (status, body) = call(&claims, "/courses").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "insufficient_scope");
+    }
+
+    #[actix_web::test]
+    async fn restricted_tokens_only_reach_the_password_change() {
+        let mut claims = testing::claims(Uuid::new_v4(), UserRole::Student);
+        claims.password_change_required = true;
+
+        let (status, body) = call(&claims, "/courses").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "password_change_required");
+        let (status, _) = call(&claims, PASSWORD_CHANGE_PATH).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn impersonation_tokens_expose_the_user_and_the_admin() {
+        let (user_id, admin_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut claims = testing::claims(user_id, UserRole::Instructor);
+        claims.act_as = Some(admin_id.to_string());
+
+        let (status, body) = call(&claims, "/courses").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], user_id.to_string());
+        assert_eq!(body["actor"], admin_id.to_string());
+
+        let user = AuthenticatedUser {
+            id: user_id,
+            role: UserRole::Instructor,
+            scopes: None,
+            impersonator: Some(admin_id),
+        };
+        assert_eq!(user.impersonation_forbidden().unwrap().status(), StatusCode::FORBIDDEN);
+        assert!(AuthenticatedUser { impersonator: None, ..user }.impersonation_forbidden().is_none());
+
+        // Un `act_as` que no es un id válido invalida el token.
+        claims.act_as = Some("admin".to_string());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .route("/courses", web::post().to(write_course)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/courses")
+            .insert_header((AUTHORIZATION, testing::bearer(&claims)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "unauthorized");
+    }
+
+    #[actix_web::test]
+    async fn accounts_pending_deletion_are_treated_as_deactivated() {
+        let db_pool = testing::pool().await;
+        let user_id = testing::insert_user(&db_pool, "student").await;
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .app_data(web::Data::new(db_pool.clone()))
+                .route("/courses", web::post().to(write_course)),
+        )
+        .await;
+        let request = |user_id| {
+            TestRequest::post()
+                .uri("/courses")
+                .insert_header((AUTHORIZATION, testing::bearer(&testing::claims(user_id, UserRole::Student))))
+                .to_request()
+        };
+
+        assert_eq!(call_service(&app, request(user_id)).await.status(), StatusCode::OK);
+
+        sqlx::query("UPDATE users SET deletion_scheduled_at = NOW() + INTERVAL '30 days' WHERE id = $1")
+            .bind(user_id)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        let res = call_service(&app, request(user_id)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "account_pending_deletion");
+
+        // Un token de una cuenta que ya no existe deja de valer.
+        assert_eq!(call_service(&app, request(Uuid::new_v4())).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
@@ -0,0 +1,180 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::ApiError;
+use crate::{is_write_request, AuthenticatedUser, UserRole};
+
+/// Ruta del endpoint que activa o desactiva el modo mantenimiento. Nunca se bloquea.
+pub const MAINTENANCE_PATH: &str = "/admin/maintenance";
+
+/// Interruptor del modo mantenimiento, compartido entre todos los workers del servicio.
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceFlag(Arc<AtomicBool>);
+
+impl MaintenanceFlag {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Cuerpo de `PUT /admin/maintenance` y de su respuesta.
+#[derive(Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+}
+
+/// Maneja las peticiones PUT a /admin/maintenance (solo administradores)
+/// Activa o desactiva el modo mantenimiento en esta instancia del servicio.
+pub async fn set_maintenance(
+    flag: web::Data<MaintenanceFlag>,
+    auth_user: AuthenticatedUser,
+    state: web::Json<MaintenanceState>,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can toggle maintenance mode"));
+    }
+
+    flag.set(state.enabled);
+    tracing::warn!("Maintenance mode {} by {}", if state.enabled { "enabled" } else { "disabled" }, auth_user.id);
+    HttpResponse::Ok().json(MaintenanceState { enabled: state.enabled })
+}
+
+/// Middleware del modo mantenimiento.
+///
+/// Mientras el interruptor está activo, las peticiones que modifican datos (POST, PUT, PATCH y
+/// DELETE) se rechazan con `503` y el código `maintenance`; las lecturas siguen funcionando.
+pub struct MaintenanceMode {
+    flag: MaintenanceFlag,
+}
+
+impl MaintenanceMode {
+    pub fn new(flag: MaintenanceFlag) -> Self {
+        Self { flag }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for MaintenanceMode
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MaintenanceModeService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MaintenanceModeService {
+            service,
+            flag: self.flag.clone(),
+        }))
+    }
+}
+
+pub struct MaintenanceModeService<S> {
+    service: S,
+    flag: MaintenanceFlag,
+}
+
+impl<S, B> Service<ServiceRequest> for MaintenanceModeService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_write = is_write_request(req.method(), req.path());
+
+        if is_write && self.flag.is_enabled() && req.path() != MAINTENANCE_PATH {
+            let response = HttpResponse::ServiceUnavailable().json(ApiError::new(
+                "maintenance",
+                "The service is under maintenance; only read requests are accepted",
+            ));
+            return Box::pin(ready(Ok(req.into_response(response).map_into_right_body())));
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::{header, StatusCode},
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+    use uuid::Uuid;
+
+    use crate::testing;
+
+    #[actix_web::test]
+    async fn rejects_writes_and_lets_reads_through_while_enabled() {
+        let flag = MaintenanceFlag::new(true);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .app_data(web::Data::new(flag.clone()))
+                .wrap(MaintenanceMode::new(flag.clone()))
+                .route("/courses", web::get().to(HttpResponse::Ok))
+                .route("/courses", web::post().to(HttpResponse::Created))
+                .route("/login", web::post().to(HttpResponse::Ok))
+                .route(MAINTENANCE_PATH, web::put().to(set_maintenance)),
+        )
+        .await;
+
+        let res = call_service(&app, TestRequest::post().uri("/courses").to_request()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "maintenance");
+
+        let res = call_service(&app, TestRequest::get().uri("/courses").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // El propio interruptor nunca se bloquea, pero solo lo pueden usar los admins.
+        let toggle = |role| {
+            TestRequest::put()
+                .uri(MAINTENANCE_PATH)
+                .insert_header((header::AUTHORIZATION, testing::bearer(&testing::claims(Uuid::new_v4(), role))))
+                .set_json(MaintenanceState { enabled: false })
+                .to_request()
+        };
+        let res = call_service(&app, toggle(UserRole::Instructor)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(flag.is_enabled());
+
+        let res = call_service(&app, toggle(UserRole::Admin)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!flag.is_enabled());
+        let res = call_service(&app, TestRequest::post().uri("/courses").to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,141 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Tipos de notificación que existen. Son los que los usuarios pueden desactivar en sus preferencias.
+pub const KINDS: &[&str] = &["enrollment_removed", "course_archived"];
+
+/// Notificación para un usuario, pendiente de guardar.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub user_id: Uuid,
+    /// Tipo de notificación, estable para que los clientes lo interpreten (p. ej. `enrollment_removed`).
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Guarda una notificación dentro de la transacción de quien la genera, de modo que la acción
+/// y su notificación se confirman (o se descartan) juntas.
+///
+/// Si el usuario desactivó ese tipo en sus preferencias, no se guarda nada. Devuelve si la
+/// notificación se creó.
+pub async fn create_notification(
+    tx: &mut Transaction<'_, Postgres>,
+    notification: &Notification,
+) -> Result<bool, sqlx::Error> {
+    create_notifications(tx, std::slice::from_ref(notification))
+        .await
+        .map(|created| created == 1)
+}
+
+/// Guarda varias notificaciones (por ejemplo, un aviso a todos los estudiantes de un curso) con
+/// un único `INSERT` dentro de la transacción de quien las genera: o se guardan todas junto con
+/// la acción que las provoca, o ninguna.
+///
+/// Como en `create_notification`, se omiten las de los usuarios que desactivaron ese tipo.
+/// Devuelve cuántas se crearon.
+pub async fn create_notifications(
+    tx: &mut Transaction<'_, Postgres>,
+    notifications: &[Notification],
+) -> Result<u64, sqlx::Error> {
+    if notifications.is_empty() {
+        return Ok(0);
+    }
+
+    let user_ids: Vec<Uuid> = notifications.iter().map(|n| n.user_id).collect();
+    let kinds: Vec<String> = notifications.iter().map(|n| n.kind.to_string()).collect();
+    let messages: Vec<String> = notifications.iter().map(|n| n.message.clone()).collect();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO notifications (user_id, kind, message)
+        SELECT n.user_id, n.kind, n.message
+        FROM UNNEST($1::uuid[], $2::varchar[], $3::text[]) AS n(user_id, kind, message)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM notification_preferences p
+            WHERE p.user_id = n.user_id AND p.kind = n.kind AND NOT p.enabled
+        )
+        "#,
+        &user_ids[..],
+        &kinds[..],
+        &messages[..]
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+
+    use crate::testing;
+
+    async fn count(db_pool: &PgPool, user_ids: &[Uuid]) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = ANY($1)")
+            .bind(user_ids)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    fn archived(user_ids: &[Uuid]) -> Vec<Notification> {
+        user_ids
+            .iter()
+            .map(|&user_id| Notification {
+                user_id,
+                kind: "course_archived",
+                message: "The course was archived".to_string(),
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn fans_out_to_every_student_and_rolls_back_with_the_parent() {
+        let db_pool = testing::pool().await;
+        let mut students = Vec::new();
+        for _ in 0..3 {
+            students.push(testing::insert_user(&db_pool, "student").await);
+        }
+
+        let mut tx = db_pool.begin().await.unwrap();
+        assert_eq!(create_notifications(&mut tx, &archived(&students)).await.unwrap(), 3);
+        tx.rollback().await.unwrap();
+        assert_eq!(count(&db_pool, &students).await, 0);
+
+        let mut tx = db_pool.begin().await.unwrap();
+        assert_eq!(create_notifications(&mut tx, &archived(&students)).await.unwrap(), 3);
+        assert_eq!(create_notifications(&mut tx, &[]).await.unwrap(), 0);
+        tx.commit().await.unwrap();
+        assert_eq!(count(&db_pool, &students).await, 3);
+    }
+
+    #[actix_web::test]
+    async fn skips_the_types_a_user_disabled() {
+        let db_pool = testing::pool().await;
+        let muted = testing::insert_user(&db_pool, "student").await;
+        let subscribed = testing::insert_user(&db_pool, "student").await;
+        sqlx::query(
+            "INSERT INTO notification_preferences (user_id, kind, enabled) VALUES ($1, 'course_archived', FALSE)",
+        )
+        .bind(muted)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+        let mut tx = db_pool.begin().await.unwrap();
+        assert!(!create_notification(&mut tx, &archived(&[muted])[0]).await.unwrap());
+        assert!(create_notification(&mut tx, &archived(&[subscribed])[0]).await.unwrap());
+        // Los demás tipos siguen llegando.
+        let removed = Notification {
+            kind: "enrollment_removed",
+            ..archived(&[muted])[0].clone()
+        };
+        assert!(create_notification(&mut tx, &removed).await.unwrap());
+        tx.commit().await.unwrap();
+
+        assert_eq!(count(&db_pool, &[muted]).await, 1);
+        assert_eq!(count(&db_pool, &[subscribed]).await, 1);
+    }
+}
+
@@ -0,0 +1,172 @@
+use actix_web::{dev::Payload, error::InternalError, web, FromRequest, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::future::{ready, Ready};
+
+use crate::error::ApiError;
+
+/// Tamaño de página de los listados cuando no se indica `per_page`.
+pub const DEFAULT_PER_PAGE: i64 = 20;
+/// Máximo por defecto de `per_page` (se puede cambiar con `MAX_PER_PAGE`).
+pub const DEFAULT_MAX_PER_PAGE: i64 = 100;
+
+/// Límites de la paginación, registrados como `app_data` (`web::Data<PageLimits>`). Sin
+/// registrar se usan `DEFAULT_PER_PAGE` y `DEFAULT_MAX_PER_PAGE`.
+#[derive(Debug, Clone, Copy)]
+pub struct PageLimits {
+    pub default_per_page: i64,
+    pub max_per_page: i64,
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        Self {
+            default_per_page: DEFAULT_PER_PAGE,
+            max_per_page: DEFAULT_MAX_PER_PAGE,
+        }
+    }
+}
+
+/// Página solicitada en la query string (`?page=1&per_page=20`), ya validada.
+///
+/// `page` empieza en 1. Un `per_page` mayor que el máximo se recorta al máximo; un valor no
+/// numérico o menor que 1 en cualquiera de los dos, o una `page` tan alta que su desplazamiento
+/// no cabe en un `i64`, responde `400` con el código `invalid_pagination` y el campo afectado en
+/// `field`. El resto de parámetros de la query se
+/// ignoran, así que se puede combinar con un `web::Query` propio del listado.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageParams {
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.per_page
+    }
+
+    /// Filas que se saltan antes de la página. `parse` garantiza que el cálculo no desborda.
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+
+    /// Valida `page` y `per_page` tal como llegan en la query string.
+    pub fn parse(page: Option<&str>, per_page: Option<&str>, limits: PageLimits) -> Result<Self, ApiError> {
+        let page = parse_positive("page", page)?.unwrap_or(1);
+        let per_page = parse_positive("per_page", per_page)?
+            .unwrap_or(limits.default_per_page)
+            .min(limits.max_per_page);
+        if (page - 1).checked_mul(per_page).is_none() {
+            return Err(ApiError::new("invalid_pagination", format!("'page' is too large, got '{}'", page)).with_field("page"));
+        }
+        Ok(Self { page, per_page })
+    }
+}
+
+fn parse_positive(field: &'static str, value: Option<&str>) -> Result<Option<i64>, ApiError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    match value.trim().parse::<i64>() {
+        Ok(number) if number >= 1 => Ok(Some(number)),
+        _ => Err(ApiError::new(
+            "invalid_pagination",
+            format!("'{}' must be a positive integer, got '{}'", field, value),
+        )
+        .with_field(field)),
+    }
+}
+
+/// Valores en crudo de la query string; se validan en `PageParams::parse`.
+#[derive(Deserialize)]
+struct RawPageParams {
+    page: Option<String>,
+    per_page: Option<String>,
+}
+
+impl FromRequest for PageParams {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let limits = req
+            .app_data::<web::Data<PageLimits>>()
+            .map(|limits| *limits.get_ref())
+            .unwrap_or_default();
+
+        let result = match web::Query::<RawPageParams>::from_query(req.query_string()) {
+            Ok(raw) => PageParams::parse(raw.page.as_deref(), raw.per_page.as_deref(), limits),
+            Err(e) => Err(ApiError::new("invalid_pagination", e.to_string())),
+        };
+        ready(result.map_err(|error| {
+            let message = error.message.clone();
+            InternalError::from_response(message, HttpResponse::BadRequest().json(error)).into()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        body::to_bytes,
+        http::StatusCode,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App,
+    };
+
+    #[test]
+    fn defaults_when_missing() {
+        let params = PageParams::parse(None, None, PageLimits::default()).unwrap();
+        assert_eq!(params, PageParams { page: 1, per_page: DEFAULT_PER_PAGE });
+        assert_eq!(params.offset(), 0);
+    }
+
+    #[test]
+    fn caps_per_page_at_the_maximum() {
+        let limits = PageLimits { default_per_page: 10, max_per_page: 50 };
+        let params = PageParams::parse(Some("3"), Some("500"), limits).unwrap();
+        assert_eq!(params.per_page, 50);
+        assert_eq!(params.offset(), 100);
+    }
+
+    #[test]
+    fn rejects_non_numeric_and_non_positive_values() {
+        for (page, per_page, field) in [
+            (Some("abc"), None, "page"),
+            (Some("0"), None, "page"),
+            (None, Some("-5"), "per_page"),
+            (None, Some("1.5"), "per_page"),
+        ] {
+            let error = PageParams::parse(page, per_page, PageLimits::default()).unwrap_err();
+            assert_eq!(error.code, "invalid_pagination");
+            assert_eq!(error.field.as_deref(), Some(field));
+        }
+    }
+
+    #[test]
+    fn rejects_pages_whose_offset_overflows() {
+        let page = i64::MAX.to_string();
+        let error = PageParams::parse(Some(&page), Some("100"), PageLimits::default()).unwrap_err();
+        assert_eq!(error.code, "invalid_pagination");
+        assert_eq!(error.field.as_deref(), Some("page"));
+    }
+
+    #[actix_web::test]
+    async fn extractor_responds_400_with_the_error_body() {
+        let app = init_service(App::new().route(
+            "/",
+            web::get().to(|page: PageParams| async move { HttpResponse::Ok().body(page.page.to_string()) }),
+        ))
+        .await;
+
+        let res = call_service(&app, TestRequest::get().uri("/?page=2&other=x").to_request()).await;
+        assert!(res.status().is_success());
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "2");
+
+        let res = call_service(&app, TestRequest::get().uri("/?per_page=abc").to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "invalid_pagination");
+        assert_eq!(body["field"], "per_page");
+    }
+}
@@ -0,0 +1,142 @@
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{db, UserRole};
+
+/// Prefijo de los tokens de acceso personal. Permite distinguirlos de un JWT en `Authorization: Bearer`.
+pub const PAT_PREFIX: &str = "ccb_pat_";
+
+/// Permisos que puede tener un token de acceso personal: `read` da acceso a las peticiones de
+/// solo lectura (`GET`, `HEAD`, `OPTIONS`) y `write` al resto.
+pub const SCOPES: &[&str] = &["read", "write"];
+
+/// Usuario al que pertenece un token de acceso personal válido.
+#[derive(Debug, Clone)]
+pub struct PatOwner {
+    pub user_id: Uuid,
+    pub role: UserRole,
+    pub scopes: Vec<String>,
+}
+
+impl PatOwner {
+    /// Indica si el token tiene el permiso necesario para una petición con este método.
+    pub fn allows(&self, method: &actix_web::http::Method) -> bool {
+        let scope = if method.is_safe() { "read" } else { "write" };
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Genera un token nuevo, con el prefijo `PAT_PREFIX`.
+pub fn generate_token() -> String {
+    format!("{}{}{}", PAT_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Hash SHA-256 (hex) de un token. En la base de datos nunca se guarda el token en claro.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Busca el dueño de un token vigente (sin caducar y de una cuenta sin borrado programado) y
+/// anota su último uso.
+pub async fn authenticate(db_pool: &PgPool, token: &str) -> Result<Option<PatOwner>, sqlx::Error> {
+    let mut conn = db::acquire(db_pool).await?;
+    sqlx::query_as!(
+        PatOwner,
+        r#"
+        UPDATE personal_access_tokens p
+        SET last_used_at = NOW()
+        FROM users u
+        WHERE p.token_hash = $1
+          AND u.id = p.user_id
+          AND (p.expires_at IS NULL OR p.expires_at > NOW())
+          AND u.deletion_scheduled_at IS NULL
+        RETURNING p.user_id, u.role as "role: UserRole", p.scopes
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::{header::AUTHORIZATION, StatusCode},
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use crate::{testing, AuthenticatedUser};
+
+    /// Guarda un token del usuario con los permisos y la caducidad (en segundos desde ahora) indicados.
+    async fn insert_token(
+        db_pool: &PgPool,
+        user_id: Uuid,
+        scopes: &[&str],
+        expires_in: Option<i64>,
+    ) -> (Uuid, String) {
+        let token = generate_token();
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO personal_access_tokens (user_id, name, token_hash, scopes, expires_at)
+            VALUES ($1, 'ci', $2, $3, NOW() + make_interval(secs => $4))
+            RETURNING id
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(scopes.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        .bind(expires_in.map(|secs| secs as f64))
+        .fetch_one(db_pool)
+        .await
+        .unwrap();
+        (id, token)
+    }
+
+    #[test]
+    fn tokens_are_prefixed_and_stored_hashed() {
+        let token = generate_token();
+        assert!(token.starts_with(PAT_PREFIX));
+        assert_ne!(generate_token(), token);
+        assert_eq!(hash_token(&token).len(), 64);
+        assert_ne!(hash_token(&token), token);
+    }
+
+    #[actix_web::test]
+    async fn authenticates_until_revoked_or_expired() {
+        let db_pool = testing::pool().await;
+        let user_id = testing::insert_user(&db_pool, "instructor").await;
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db_pool.clone()))
+                .route("/me", web::get().to(|_: AuthenticatedUser| async { HttpResponse::Ok().finish() }))
+                .route("/courses", web::post().to(|_: AuthenticatedUser| async { HttpResponse::Created().finish() })),
+        )
+        .await;
+        let call = |method: &str, token: &str| {
+            let req = match method {
+                "GET" => TestRequest::get().uri("/me"),
+                _ => TestRequest::post().uri("/courses"),
+            };
+            req.insert_header((AUTHORIZATION, format!("Bearer {}", token))).to_request()
+        };
+
+        let (id, token) = insert_token(&db_pool, user_id, &["read"], None).await;
+        assert_eq!(call_service(&app, call("GET", &token)).await.status(), StatusCode::OK);
+        let owner = authenticate(&db_pool, &token).await.unwrap().unwrap();
+        assert_eq!(owner.user_id, user_id);
+        assert_eq!(owner.role, UserRole::Instructor);
+        // Un token de solo lectura no sirve para escribir.
+        assert_eq!(call_service(&app, call("POST", &token)).await.status(), StatusCode::FORBIDDEN);
+
+        sqlx::query("DELETE FROM personal_access_tokens WHERE id = $1").bind(id).execute(&db_pool).await.unwrap();
+        assert_eq!(call_service(&app, call("GET", &token)).await.status(), StatusCode::UNAUTHORIZED);
+
+        let (_, token) = insert_token(&db_pool, user_id, &["read", "write"], Some(-1)).await;
+        assert_eq!(call_service(&app, call("GET", &token)).await.status(), StatusCode::UNAUTHORIZED);
+        let (_, token) = insert_token(&db_pool, user_id, &["read", "write"], Some(3600)).await;
+        assert_eq!(call_service(&app, call("POST", &token)).await.status(), StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{scope, AuthenticatedUser, UserRole};
+
+/// Crear cursos (instructores y admins, con `courses:write`).
+pub const CREATE_COURSE: &str = "create_course";
+/// Inscribirse en cursos (con `enrollments:write`).
+pub const ENROLL: &str = "enroll";
+/// Editar el perfil, la contraseña y la cuenta (con `profile:write` y sin suplantación).
+pub const EDIT_PROFILE: &str = "edit_profile";
+/// Revisar las reseñas reportadas (instructores y admins).
+pub const MODERATE_REVIEWS: &str = "moderate_reviews";
+/// Crear categorías y recategorizar cursos (admins, con `courses:write`).
+pub const MANAGE_CATEGORIES: &str = "manage_categories";
+/// Exportar el catálogo completo (admins).
+pub const EXPORT_COURSES: &str = "export_courses";
+/// Listar, crear y suplantar usuarios y transferir sus cursos (admins).
+pub const ADMIN_USERS: &str = "admin_users";
+/// Activar o desactivar el modo mantenimiento (admins).
+pub const TOGGLE_MAINTENANCE: &str = "toggle_maintenance";
+
+/// Acciones globales que puede hacer el usuario según su rol y los permisos de su token, con
+/// las mismas reglas que aplican los manejadores. Es lo que devuelve `GET /me/permissions`, para
+/// que las interfaces no dupliquen esta lógica. Las acciones sobre un curso concreto (editarlo,
+/// ver sus estudiantes) dependen además de quién es su propietario y no se incluyen.
+pub fn capabilities(user: &AuthenticatedUser) -> Vec<&'static str> {
+    let staff = matches!(user.role, UserRole::Instructor | UserRole::Admin);
+    let admin = user.role == UserRole::Admin;
+    // Un token de acceso personal de solo lectura no puede hacer peticiones de escritura.
+    let can_write = user.scopes.as_ref().is_none_or(|scopes| scopes.iter().any(|s| s != "read"));
+
+    [
+        (CREATE_COURSE, staff && user.has_scope(scope::COURSES_WRITE)),
+        (ENROLL, user.has_scope(scope::ENROLLMENTS_WRITE)),
+        (EDIT_PROFILE, user.has_scope(scope::PROFILE_WRITE) && user.impersonator.is_none()),
+        (MODERATE_REVIEWS, staff),
+        (MANAGE_CATEGORIES, admin && user.has_scope(scope::COURSES_WRITE)),
+        (EXPORT_COURSES, admin),
+        (ADMIN_USERS, admin && can_write),
+        (TOGGLE_MAINTENANCE, admin && can_write),
+    ]
+    .into_iter()
+    .filter_map(|(capability, allowed)| allowed.then_some(capability))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn user(role: UserRole, scopes: Option<&[&str]>) -> AuthenticatedUser {
+        AuthenticatedUser {
+            id: Uuid::new_v4(),
+            role,
+            scopes: scopes.map(|scopes| scopes.iter().map(|s| s.to_string()).collect()),
+            impersonator: None,
+        }
+    }
+
+    #[test]
+    fn each_role_gets_its_capability_set() {
+        assert_eq!(capabilities(&user(UserRole::Student, None)), [ENROLL, EDIT_PROFILE]);
+        assert_eq!(
+            capabilities(&user(UserRole::Instructor, None)),
+            [CREATE_COURSE, ENROLL, EDIT_PROFILE, MODERATE_REVIEWS]
+        );
+        assert_eq!(
+            capabilities(&user(UserRole::Admin, None)),
+            [
+                CREATE_COURSE,
+                ENROLL,
+                EDIT_PROFILE,
+                MODERATE_REVIEWS,
+                MANAGE_CATEGORIES,
+                EXPORT_COURSES,
+                ADMIN_USERS,
+                TOGGLE_MAINTENANCE
+            ]
+        );
+    }
+
+    #[test]
+    fn scopes_and_impersonation_narrow_the_set() {
+        let limited = user(UserRole::Instructor, Some(&[scope::ENROLLMENTS_WRITE]));
+        assert_eq!(capabilities(&limited), [ENROLL, MODERATE_REVIEWS]);
+
+        let read_only = user(UserRole::Admin, Some(&["read"]));
+        assert_eq!(capabilities(&read_only), [MODERATE_REVIEWS, EXPORT_COURSES]);
+
+        let impersonated = AuthenticatedUser {
+            impersonator: Some(Uuid::new_v4()),
+            ..user(UserRole::Student, None)
+        };
+        assert_eq!(capabilities(&impersonated), [ENROLL]);
+    }
+}
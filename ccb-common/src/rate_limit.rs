@@ -0,0 +1,291 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error, FromRequest, HttpResponse,
+};
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::client_ip::client_ip;
+use crate::error::ApiError;
+use crate::{is_write_request, AuthenticatedUser, UserRole};
+
+/// Límite de las peticiones de escritura: cubo de `burst` fichas que se rellena a razón de
+/// `per_minute` fichas por minuto. Cada escritura gasta una ficha; sin fichas, se rechaza.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitPolicy {
+    pub per_minute: u32,
+    pub burst: u32,
+    /// Los admins no tienen límite.
+    pub exempt_admins: bool,
+}
+
+/// Cada cuánto se recorren los cubos para olvidar los que ya están llenos.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fichas disponibles de una clave y cuándo se calcularon.
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Cubos de todas las claves y cuándo se limpiaron por última vez.
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    pruned_at: Instant,
+}
+
+/// Cubos de fichas por clave (usuario autenticado o IP), compartidos entre todos los workers.
+#[derive(Clone)]
+pub struct RateLimiter {
+    policy: RateLimitPolicy,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(policy: RateLimitPolicy) -> Self {
+        Self {
+            policy,
+            buckets: Arc::new(Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                pruned_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Gasta una ficha de la clave. Si no quedan, devuelve el tiempo hasta la siguiente.
+    pub fn acquire(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = f64::from(self.policy.burst);
+        let per_second = f64::from(self.policy.per_minute) / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Un cubo que ya se ha rellenado del todo equivale a no tenerlo: lo olvidamos. Recorrer
+        // todos los cubos cuesta tanto como claves haya, así que se hace como mucho una vez por
+        // `PRUNE_INTERVAL` y no en cada petición.
+        if now.duration_since(buckets.pruned_at) >= PRUNE_INTERVAL {
+            buckets.by_key.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * per_second < burst
+            });
+            buckets.pruned_at = now;
+        }
+        let bucket = buckets.by_key.entry(key.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+/// Middleware que limita las peticiones que modifican datos (POST, PUT, PATCH y DELETE).
+///
+/// Cada usuario autenticado tiene su propio límite; las peticiones sin sesión válida comparten
+/// el de su IP (ver `client_ip`). Al superarlo se responde `429` con el código `rate_limited` y
+/// la cabecera `Retry-After`. Las lecturas nunca se limitan. Sin limitador (`None`, límite
+/// desactivado) deja pasar todas las peticiones.
+pub struct WriteRateLimit {
+    limiter: Option<RateLimiter>,
+}
+
+impl WriteRateLimit {
+    pub fn new(limiter: Option<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for WriteRateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = WriteRateLimitService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(WriteRateLimitService {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct WriteRateLimitService<S> {
+    service: Rc<S>,
+    limiter: Option<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for WriteRateLimitService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_write = is_write_request(req.method(), req.path());
+        let service = Rc::clone(&self.service);
+        let Some(limiter) = self.limiter.clone().filter(|_| is_write) else {
+            return Box::pin(async move { service.call(req).await.map(ServiceResponse::map_into_left_body) });
+        };
+
+        Box::pin(async move {
+            // El usuario queda guardado en la petición, así que el manejador no vuelve a validar el token.
+            let user = AuthenticatedUser::extract(req.request()).await.ok();
+            let key = match (&user, client_ip(req.request())) {
+                (Some(user), _) => Some(format!("user:{}", user.id)),
+                (None, Some(ip)) => Some(format!("ip:{}", ip)),
+                (None, None) => None,
+            };
+            let exempt = limiter.policy.exempt_admins && user.is_some_and(|user| user.role == UserRole::Admin);
+
+            if let Some(key) = key.filter(|_| !exempt) {
+                if let Err(retry_after) = limiter.acquire(&key) {
+                    // Redondeamos hacia arriba: reintentar antes de tiempo volvería a fallar.
+                    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                        .json(ApiError::new(
+                            "rate_limited",
+                            format!("Too many write requests; try again in {} seconds", seconds),
+                        ));
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+            }
+
+            service.call(req).await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::{Method, StatusCode},
+        test::{call_service, init_service, TestRequest},
+        web, App,
+    };
+    use uuid::Uuid;
+
+    use crate::testing;
+
+    fn limiter(per_minute: u32, burst: u32, exempt_admins: bool) -> RateLimiter {
+        RateLimiter::new(RateLimitPolicy {
+            per_minute,
+            burst,
+            exempt_admins,
+        })
+    }
+
+    #[test]
+    fn acquire_spends_the_burst_then_waits_for_a_refill() {
+        let limiter = limiter(60, 3, false);
+        for _ in 0..3 {
+            assert!(limiter.acquire("user:a").is_ok());
+        }
+        let retry_after = limiter.acquire("user:a").unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+
+        // Cada clave tiene su propio cubo.
+        assert!(limiter.acquire("user:b").is_ok());
+    }
+
+    #[test]
+    fn acquire_refills_over_time() {
+        let limiter = limiter(6000, 1, false);
+        assert!(limiter.acquire("ip:1").is_ok());
+        assert!(limiter.acquire("ip:1").is_err());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.acquire("ip:1").is_ok());
+    }
+
+    fn request(method: Method, token: Option<&str>) -> TestRequest {
+        let req = TestRequest::default()
+            .method(method)
+            .uri("/items")
+            .peer_addr("10.0.0.1:4000".parse().unwrap());
+        match token {
+            Some(token) => req.insert_header((header::AUTHORIZATION, token)),
+            None => req,
+        }
+    }
+
+    #[actix_web::test]
+    async fn rapid_writes_trip_the_limit_while_reads_pass() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .wrap(WriteRateLimit::new(Some(limiter(1, 2, false))))
+                .route("/items", web::get().to(HttpResponse::Ok))
+                .route("/items", web::post().to(HttpResponse::Created)),
+        )
+        .await;
+        let token = testing::bearer(&testing::claims(Uuid::new_v4(), UserRole::Student));
+
+        for _ in 0..2 {
+            let res = call_service(&app, request(Method::POST, Some(&token)).to_request()).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+        let res = call_service(&app, request(Method::POST, Some(&token)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        for _ in 0..5 {
+            let res = call_service(&app, request(Method::GET, Some(&token)).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        // Otro usuario desde la misma IP tiene su propio límite, y sin sesión se limita por IP.
+        let other = testing::bearer(&testing::claims(Uuid::new_v4(), UserRole::Student));
+        let res = call_service(&app, request(Method::POST, Some(&other)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        for _ in 0..2 {
+            let res = call_service(&app, request(Method::POST, None).to_request()).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+        let res = call_service(&app, request(Method::POST, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn admins_can_be_exempt() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .wrap(WriteRateLimit::new(Some(limiter(1, 1, true))))
+                .route("/items", web::post().to(HttpResponse::Created)),
+        )
+        .await;
+        let token = testing::bearer(&testing::claims(Uuid::new_v4(), UserRole::Admin));
+
+        for _ in 0..3 {
+            let res = call_service(&app, request(Method::POST, Some(&token)).to_request()).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/// Crear, modificar y borrar cursos (y su contenido), y el resto de escrituras de course-service:
+/// reseñas, favoritos, categorías y destacados.
+pub const COURSES_WRITE: &str = "courses:write";
+/// Inscribirse en cursos y abandonarlos, y gestionar los estudiantes de los cursos propios.
+pub const ENROLLMENTS_WRITE: &str = "enrollments:write";
+/// Modificar el perfil y la cuenta del usuario.
+pub const PROFILE_WRITE: &str = "profile:write";
+
+/// Permisos que se pueden pedir para una sesión limitada.
+pub const KNOWN: &[&str] = &[COURSES_WRITE, ENROLLMENTS_WRITE, PROFILE_WRITE];
+
+/// Indica si un permiso concedido cubre el requerido: el mismo permiso o, para los permisos de
+/// los tokens de acceso personal (`read`, `write`), la misma acción en cualquier recurso.
+pub fn grants(granted: &str, required: &str) -> bool {
+    granted == required || required.rsplit_once(':').is_some_and(|(_, action)| granted == action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_the_same_scope_or_the_generic_action() {
+        assert!(grants(COURSES_WRITE, COURSES_WRITE));
+        assert!(!grants(ENROLLMENTS_WRITE, COURSES_WRITE));
+        assert!(grants("write", COURSES_WRITE));
+        assert!(!grants("read", COURSES_WRITE));
+        assert!(!grants("courses", COURSES_WRITE));
+    }
+}
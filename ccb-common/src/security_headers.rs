@@ -0,0 +1,171 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{
+        HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    Error,
+};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+/// `Content-Security-Policy` por defecto: los servicios solo devuelven JSON, así que no se
+/// permite cargar ningún recurso ni incrustar las respuestas en otra página.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; frame-ancestors 'none'";
+
+/// Middleware que añade cabeceras de seguridad a todas las respuestas:
+/// `X-Content-Type-Options: nosniff`, `X-Frame-Options`, `Strict-Transport-Security` y
+/// `Content-Security-Policy`. No sustituye las que un manejador ya haya fijado.
+///
+/// Se configura con `SECURITY_HEADERS`, `X_FRAME_OPTIONS`, `HSTS_MAX_AGE_SECONDS` y
+/// `CONTENT_SECURITY_POLICY` (ver `Config`).
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    /// Con `false` el middleware no añade ninguna cabecera.
+    pub enabled: bool,
+    /// Valor de `X-Frame-Options` (`DENY` o `SAMEORIGIN`).
+    pub frame_options: HeaderValue,
+    /// Valor de `Strict-Transport-Security`. `None` no envía la cabecera (servicios sin TLS).
+    pub hsts: Option<HeaderValue>,
+    /// Valor de `Content-Security-Policy`. `None` no envía la cabecera.
+    pub content_security_policy: Option<HeaderValue>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frame_options: HeaderValue::from_static("DENY"),
+            hsts: None,
+            content_security_policy: Some(HeaderValue::from_static(DEFAULT_CONTENT_SECURITY_POLICY)),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// Valor de `Strict-Transport-Security` para la duración indicada (0 lo desactiva).
+    pub fn hsts_value(max_age_seconds: u64) -> Option<HeaderValue> {
+        (max_age_seconds > 0).then(|| {
+            HeaderValue::from_str(&format!("max-age={}; includeSubDomains", max_age_seconds))
+                .expect("HSTS header value is always valid")
+        })
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityHeadersService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersService {
+            service,
+            headers: self.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersService<S> {
+    service: S,
+    headers: SecurityHeaders,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let headers = self.headers.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if !headers.enabled {
+                return Ok(res);
+            }
+
+            let response_headers = res.headers_mut();
+            let mut set_default = |name: HeaderName, value: HeaderValue| {
+                if !response_headers.contains_key(&name) {
+                    response_headers.insert(name, value);
+                }
+            };
+            set_default(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+            set_default(X_FRAME_OPTIONS, headers.frame_options);
+            if let Some(hsts) = headers.hsts {
+                set_default(STRICT_TRANSPORT_SECURITY, hsts);
+            }
+            if let Some(csp) = headers.content_security_policy {
+                set_default(CONTENT_SECURITY_POLICY, csp);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    #[actix_web::test]
+    async fn adds_the_headers_without_replacing_the_handler_ones() {
+        let headers = SecurityHeaders {
+            frame_options: HeaderValue::from_static("SAMEORIGIN"),
+            hsts: SecurityHeaders::hsts_value(3600),
+            ..SecurityHeaders::default()
+        };
+        let app = init_service(
+            App::new()
+                .wrap(headers)
+                .route("/", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/embeddable",
+                    web::get().to(|| async {
+                        HttpResponse::Ok().insert_header((X_FRAME_OPTIONS, "ALLOWALL")).finish()
+                    }),
+                ),
+        )
+        .await;
+
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        let headers = res.headers();
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(X_FRAME_OPTIONS).unwrap(), "SAMEORIGIN");
+        assert_eq!(headers.get(STRICT_TRANSPORT_SECURITY).unwrap(), "max-age=3600; includeSubDomains");
+        assert_eq!(headers.get(CONTENT_SECURITY_POLICY).unwrap(), DEFAULT_CONTENT_SECURITY_POLICY);
+
+        let res = call_service(&app, TestRequest::get().uri("/embeddable").to_request()).await;
+        assert_eq!(res.headers().get(X_FRAME_OPTIONS).unwrap(), "ALLOWALL");
+    }
+
+    #[actix_web::test]
+    async fn disabled_adds_nothing() {
+        let headers = SecurityHeaders {
+            enabled: false,
+            ..SecurityHeaders::default()
+        };
+        let app = init_service(App::new().wrap(headers).route("/", web::get().to(HttpResponse::Ok))).await;
+
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        assert!(res.headers().get(X_CONTENT_TYPE_OPTIONS).is_none());
+        assert!(res.headers().get(CONTENT_SECURITY_POLICY).is_none());
+        assert_eq!(SecurityHeaders::hsts_value(0), None);
+    }
+}
@@ -0,0 +1,221 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage,
+};
+use std::env;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::time::Instant;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+use crate::AuthenticatedUser;
+
+/// Cabecera con la que se propaga (o se genera) el identificador de cada petición.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Inicializa el subscriber de `tracing` de un servicio.
+///
+/// Con `LOG_FORMAT=json` los eventos se emiten como JSON (una línea por evento), listos para
+/// enviarlos a un agregador de logs; en cualquier otro caso se usa el formato de texto legible.
+/// El nivel se controla con `RUST_LOG` (por defecto `info`). Los registros emitidos con `log`
+/// (por ejemplo, los de sqlx) también se redirigen a `tracing`.
+pub fn init_logging() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = env::var("LOG_FORMAT").map(|format| format.eq_ignore_ascii_case("json")).unwrap_or(false);
+
+    if json {
+        tracing_subscriber::fmt()
+            .json()
+            .with_current_span(false)
+            .with_env_filter(filter)
+            .init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
+}
+
+/// Middleware de logs de acceso estructurados.
+///
+/// Por cada petición emite un evento `access_log` con `method`, `path`, `status`, `latency_ms`,
+/// `request_id`, `client_ip` y, si la petición estaba autenticada, `user_id` (y `act_as` con el
+/// admin que suplanta al usuario, si lo hay). El `request_id` se
+/// toma de la cabecera `X-Request-Id` o se genera uno nuevo, y se devuelve en la respuesta. La IP
+/// solo se toma de las cabeceras del proxy con `TRUSTED_PROXY` (ver `client_ip`).
+pub struct AccessLog;
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessLogService { service }))
+    }
+}
+
+pub struct AccessLogService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started_at = Instant::now();
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let client_ip = client_ip(req.request()).map(|ip| ip.to_string());
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            let latency_ms = started_at.elapsed().as_millis() as u64;
+
+            match result {
+                Ok(mut res) => {
+                    let (user_id, act_as) = res
+                        .request()
+                        .extensions()
+                        .get::<AuthenticatedUser>()
+                        .map(|user| (Some(user.id.to_string()), user.impersonator.map(|id| id.to_string())))
+                        .unwrap_or_default();
+                    tracing::info!(
+                        target: "access_log",
+                        method = %method,
+                        path = %path,
+                        status = res.status().as_u16(),
+                        latency_ms,
+                        request_id = %request_id,
+                        client_ip = client_ip.as_deref(),
+                        user_id = user_id.as_deref(),
+                        act_as = act_as.as_deref(),
+                        "request completed"
+                    );
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(res)
+                }
+                Err(e) => {
+                    tracing::info!(
+                        target: "access_log",
+                        method = %method,
+                        path = %path,
+                        status = e.as_response_error().status_code().as_u16(),
+                        latency_ms,
+                        request_id = %request_id,
+                        client_ip = client_ip.as_deref(),
+                        "request failed"
+                    );
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::header::AUTHORIZATION,
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use crate::{testing, UserRole};
+
+    /// Destino de los logs que guarda todo lo escrito en memoria.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn emits_a_json_access_log_per_request() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_current_span(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(testing::keys()))
+                .wrap(AccessLog)
+                .route("/me", web::get().to(|_: AuthenticatedUser| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        let user_id = Uuid::new_v4();
+        let req = TestRequest::get()
+            .uri("/me")
+            .peer_addr("10.0.0.3:6000".parse().unwrap())
+            .insert_header((REQUEST_ID_HEADER, "req-42"))
+            .insert_header((AUTHORIZATION, testing::bearer(&testing::claims(user_id, UserRole::Student))))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "req-42");
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let event: serde_json::Value = output
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .find(|event| event["target"] == "access_log")
+            .expect("an access_log event");
+        let fields = &event["fields"];
+        assert_eq!(fields["method"], "GET");
+        assert_eq!(fields["path"], "/me");
+        assert_eq!(fields["status"], 200);
+        assert!(fields["latency_ms"].is_u64());
+        assert_eq!(fields["request_id"], "req-42");
+        assert_eq!(fields["client_ip"], "10.0.0.3");
+        assert_eq!(fields["user_id"], user_id.to_string());
+    }
+
+    #[actix_web::test]
+    async fn generates_a_request_id_when_missing() {
+        let app = init_service(App::new().wrap(AccessLog).route("/", web::get().to(HttpResponse::Ok))).await;
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        let request_id = res.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+        assert!(Uuid::parse_str(request_id).is_ok());
+    }
+}
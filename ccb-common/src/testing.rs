@@ -0,0 +1,53 @@
+use chrono::{Duration, Utc};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::jwt::JwtKeySet;
+use crate::{Claims, UserRole};
+
+/// Conjunto de claves HMAC de los tests, con la clave actual `current`.
+pub(crate) fn keys() -> JwtKeySet {
+    JwtKeySet::new("current".to_string(), "test-secret".to_string(), Vec::new())
+}
+
+/// Claims de un token normal que caduca dentro de una hora.
+pub(crate) fn claims(id: Uuid, role: UserRole) -> Claims {
+    Claims {
+        sub: id.to_string(),
+        role,
+        exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+        password_change_required: false,
+        scopes: None,
+        act_as: None,
+    }
+}
+
+/// Valor de la cabecera `Authorization` con los claims firmados por `keys()`.
+pub(crate) fn bearer(claims: &Claims) -> String {
+    format!("Bearer {}", keys().encode(claims).unwrap())
+}
+
+/// Pool contra la base de datos de los tests (`DATABASE_URL`, con las migraciones aplicadas).
+pub(crate) async fn pool() -> PgPool {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point to the test database");
+    PgPoolOptions::new().max_connections(2).connect(&url).await.unwrap()
+}
+
+/// Crea un usuario con un nombre único y el rol indicado (`student`, `instructor`, `admin`) y
+/// devuelve su id.
+pub(crate) async fn insert_user(db_pool: &PgPool, role: &str) -> Uuid {
+    let username = format!("test_{}", Uuid::new_v4().simple());
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO users (username, display_username, password_hash, first_name, last_name, role)
+        VALUES ($1, $1, 'not-a-hash', 'Test', 'User', $2::user_role)
+        RETURNING id
+        "#,
+    )
+    .bind(username)
+    .bind(role)
+    .fetch_one(db_pool)
+    .await
+    .unwrap()
+}
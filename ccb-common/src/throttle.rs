@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Política de espera tras fallos consecutivos de login.
+///
+/// Los primeros `free_attempts` fallos no bloquean. A partir de ahí, cada fallo bloquea la clave
+/// durante `base_delay * 2^(n - 1)`, siendo `n` el número de fallos por encima de los gratuitos,
+/// con un máximo de `max_delay`. Con los valores por defecto (5, 1 s, 15 min): 1 s, 2 s, 4 s,
+/// 8 s, ... hasta 15 min.
+#[derive(Debug, Clone, Copy)]
+pub struct BackoffPolicy {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            free_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Espera que corresponde tras `failures` fallos consecutivos (`None` si aún no hay bloqueo).
+    pub fn delay_after(&self, failures: u32) -> Option<Duration> {
+        let exponent = failures.checked_sub(self.free_attempts)?.checked_sub(1)?;
+        let factor = 2u32.checked_pow(exponent.min(31)).unwrap_or(u32::MAX);
+        Some(self.base_delay.saturating_mul(factor).min(self.max_delay))
+    }
+}
+
+/// Cada cuánto se recorren las claves para olvidar las que ya no tienen fallos recientes.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fallos consecutivos de una clave y hasta cuándo está bloqueada.
+struct FailureState {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Option<Instant>,
+}
+
+/// Limitador en memoria de los intentos de login fallidos, por clave (IP del cliente e
+/// identificador de la cuenta).
+///
+/// Mientras una clave está bloqueada, `check` devuelve el tiempo que falta; los intentos
+/// rechazados no cuentan como fallos. Un login correcto (`record_success`) borra el historial.
+/// Los contadores se olvidan si pasa `max_delay` sin fallos nuevos después del último bloqueo.
+pub struct LoginThrottle {
+    policy: BackoffPolicy,
+    entries: Mutex<FailureEntries>,
+}
+
+/// Fallos de todas las claves y cuándo se limpiaron por última vez.
+struct FailureEntries {
+    by_key: HashMap<String, FailureState>,
+    pruned_at: Instant,
+}
+
+impl LoginThrottle {
+    pub fn new(policy: BackoffPolicy) -> Self {
+        Self {
+            policy,
+            entries: Mutex::new(FailureEntries {
+                by_key: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Tiempo que falta para poder volver a intentarlo, si la clave está bloqueada.
+    pub fn check(&self, key: &str) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap();
+        let blocked_until = entries.by_key.get(key)?.blocked_until?;
+        blocked_until.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Anota un fallo y devuelve el bloqueo que empieza con él, si lo hay.
+    pub fn record_failure(&self, key: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        // Aprovechamos los fallos para descartar las claves que ya se pueden olvidar, como mucho
+        // una vez por `PRUNE_INTERVAL`: recorrerlas todas en cada fallo sería caro bajo un ataque.
+        if now.duration_since(entries.pruned_at) >= PRUNE_INTERVAL {
+            entries.by_key.retain(|_, state| !self.is_stale(state, now));
+            entries.pruned_at = now;
+        }
+
+        let state = entries.by_key.entry(key.to_string()).or_insert(FailureState {
+            failures: 0,
+            last_failure: now,
+            blocked_until: None,
+        });
+        // La limpieza no es inmediata: una clave que ya se podía olvidar empieza de cero.
+        if self.is_stale(state, now) {
+            state.failures = 0;
+        }
+        state.failures = state.failures.saturating_add(1);
+        state.last_failure = now;
+        let delay = self.policy.delay_after(state.failures);
+        state.blocked_until = delay.map(|delay| now + delay);
+        delay
+    }
+
+    /// Olvida los fallos de la clave tras un login correcto.
+    pub fn record_success(&self, key: &str) {
+        self.entries.lock().unwrap().by_key.remove(key);
+    }
+
+    fn is_stale(&self, state: &FailureState, now: Instant) -> bool {
+        let since = state.blocked_until.unwrap_or(state.last_failure);
+        now.checked_duration_since(since)
+            .is_some_and(|elapsed| elapsed >= self.policy.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(free_attempts: u32, base_ms: u64, max_ms: u64) -> BackoffPolicy {
+        BackoffPolicy {
+            free_attempts,
+            base_delay: Duration::from_millis(base_ms),
+            max_delay: Duration::from_millis(max_ms),
+        }
+    }
+
+    #[test]
+    fn delays_grow_monotonically_up_to_the_maximum() {
+        let policy = BackoffPolicy::default();
+        for failures in 0..=policy.free_attempts {
+            assert_eq!(policy.delay_after(failures), None);
+        }
+
+        let delays: Vec<Duration> = (policy.free_attempts + 1..200)
+            .map(|failures| policy.delay_after(failures).unwrap())
+            .collect();
+        assert_eq!(&delays[..4], [1, 2, 4, 8].map(Duration::from_secs));
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*delays.last().unwrap(), policy.max_delay);
+        assert_eq!(policy.delay_after(u32::MAX), Some(policy.max_delay));
+    }
+
+    #[test]
+    fn blocks_after_the_free_attempts() {
+        let throttle = LoginThrottle::new(policy(2, 60_000, 600_000));
+        assert_eq!(throttle.record_failure("ip:1"), None);
+        assert_eq!(throttle.record_failure("ip:1"), None);
+        assert_eq!(throttle.check("ip:1"), None);
+
+        assert_eq!(throttle.record_failure("ip:1"), Some(Duration::from_secs(60)));
+        let remaining = throttle.check("ip:1").unwrap();
+        assert!(remaining > Duration::from_secs(59) && remaining <= Duration::from_secs(60));
+        assert_eq!(throttle.record_failure("ip:1"), Some(Duration::from_secs(120)));
+
+        // Las claves son independientes.
+        assert_eq!(throttle.check("ip:2"), None);
+    }
+
+    #[test]
+    fn success_clears_the_history() {
+        let throttle = LoginThrottle::new(policy(0, 60_000, 600_000));
+        assert!(throttle.record_failure("user:ana").is_some());
+        throttle.record_success("user:ana");
+        assert_eq!(throttle.check("user:ana"), None);
+        assert_eq!(throttle.record_failure("user:ana"), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn stale_failures_start_over() {
+        let throttle = LoginThrottle::new(policy(1, 1, 5));
+        throttle.record_failure("ip:1");
+        assert_eq!(throttle.record_failure("ip:1"), Some(Duration::from_millis(1)));
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(throttle.check("ip:1"), None);
+        assert_eq!(throttle.record_failure("ip:1"), None);
+    }
+}
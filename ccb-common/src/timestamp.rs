@@ -0,0 +1,69 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serializer;
+
+/// Formato único de las fechas en las respuestas: RFC 3339 en UTC, con milisegundos y `Z`
+/// explícita (`2025-01-31T09:05:00.000Z`).
+///
+/// Sin este helper, serde usa el formato por defecto de chrono, cuya precisión varía según el
+/// valor (sin decimales, microsegundos...). Se aplica a cada campo con
+/// `#[serde(serialize_with = "timestamp::serialize")]` (o `serialize_option` si es opcional).
+pub fn format(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Serializa una fecha con `format`.
+pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(value))
+}
+
+/// Serializa una fecha opcional con `format` (`null` si no hay fecha).
+pub fn serialize_option<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.serialize_str(&format(value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Row {
+        #[serde(serialize_with = "serialize")]
+        created_at: DateTime<Utc>,
+        #[serde(serialize_with = "serialize_option")]
+        updated_at: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn serializes_as_utc_with_milliseconds_and_z() {
+        let created_at = Utc.with_ymd_and_hms(2025, 1, 31, 9, 5, 0).unwrap();
+        let row = Row {
+            created_at,
+            updated_at: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&row).unwrap(),
+            serde_json::json!({ "created_at": "2025-01-31T09:05:00.000Z", "updated_at": null })
+        );
+
+        // La precisión es siempre de milisegundos, aunque el valor tenga más o menos decimales.
+        let row = Row {
+            created_at: created_at + chrono::Duration::microseconds(123_456),
+            updated_at: Some(created_at),
+        };
+        let value = serde_json::to_value(&row).unwrap();
+        assert_eq!(value["created_at"], "2025-01-31T09:05:00.123Z");
+        assert_eq!(value["updated_at"], "2025-01-31T09:05:00.000Z");
+    }
+
+    #[test]
+    fn converts_other_offsets_to_utc() {
+        let santiago = FixedOffset::west_opt(3 * 3600).unwrap();
+        let local = santiago.with_ymd_and_hms(2025, 1, 31, 6, 5, 0).unwrap();
+        assert_eq!(format(&local.with_timezone(&Utc)), "2025-01-31T09:05:00.000Z");
+    }
+}
@@ -0,0 +1,263 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::cell::RefCell;
+use std::future::{ready, Future, Ready};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use crate::db;
+use crate::error::ApiError;
+
+/// Hueco de la petición donde `Tx` deja su transacción al terminar el manejador, para que
+/// `RequestTransactions` la confirme o la descarte.
+#[derive(Clone, Default)]
+struct TransactionSlot(Rc<RefCell<Option<Transaction<'static, Postgres>>>>);
+
+/// Extractor con una transacción de la base de datos para toda la petición.
+///
+/// Se abre al extraerlo (con el pool registrado como `web::Data<PgPool>`) y el manejador la usa
+/// como una `Transaction` normal (`.fetch_one(&mut **tx)`, o `&mut tx` en las funciones que
+/// reciben `&mut Transaction`). No hace falta confirmarla: `RequestTransactions` la confirma si
+/// la respuesta es `2xx` y la descarta en cualquier otro caso, también si el manejador devuelve
+/// un error o entra en pánico. Así un manejador que falla a mitad no deja escrituras parciales.
+/// Si algo debe hacerse solo con los cambios ya guardados (enviar un webhook), el manejador puede
+/// confirmarla antes con `Tx::commit`.
+///
+/// Requiere el middleware `RequestTransactions`; sin él, la extracción falla con `500`. Solo
+/// puede haber un `Tx` por petición.
+pub struct Tx {
+    tx: Option<Transaction<'static, Postgres>>,
+    slot: TransactionSlot,
+}
+
+impl Tx {
+    /// Confirma la transacción ya, sin esperar a la respuesta.
+    pub async fn commit(mut self) -> Result<(), sqlx::Error> {
+        match self.tx.take() {
+            Some(tx) => tx.commit().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for Tx {
+    type Target = Transaction<'static, Postgres>;
+
+    fn deref(&self) -> &Self::Target {
+        self.tx.as_ref().expect("the transaction is only taken on drop")
+    }
+}
+
+impl DerefMut for Tx {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.tx.as_mut().expect("the transaction is only taken on drop")
+    }
+}
+
+impl Drop for Tx {
+    fn drop(&mut self) {
+        // Tras un pánico no la devolvemos: al soltarla aquí, sqlx hace el rollback.
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(tx) = self.tx.take() {
+            *self.slot.0.borrow_mut() = Some(tx);
+        }
+    }
+}
+
+impl FromRequest for Tx {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let slot = req.extensions().get::<TransactionSlot>().cloned();
+        let db_pool = req.app_data::<web::Data<PgPool>>().cloned();
+
+        Box::pin(async move {
+            let (Some(slot), Some(db_pool)) = (slot, db_pool) else {
+                tracing::error!("Tx requires the RequestTransactions middleware and a web::Data<PgPool>");
+                return Err(start_failed());
+            };
+            match db::begin(&db_pool).await {
+                Ok(tx) => Ok(Tx { tx: Some(tx), slot }),
+                Err(e) if db::is_timeout(&e) => Err(InternalError::from_response(e, db::timeout_response()).into()),
+                Err(e) => {
+                    tracing::error!("Failed to start transaction: {:?}", e);
+                    Err(start_failed())
+                }
+            }
+        })
+    }
+}
+
+fn start_failed() -> Error {
+    let response = HttpResponse::InternalServerError()
+        .json(ApiError::new("internal_error", "Failed to start transaction"));
+    InternalError::from_response("failed to start transaction", response).into()
+}
+
+/// Middleware que cierra la transacción de `Tx` al terminar cada petición: la confirma si la
+/// respuesta es `2xx` y la descarta (rollback) si no. Si la confirmación falla, la respuesta
+/// pasa a ser `500`. Las peticiones que no usan `Tx` no abren ninguna transacción.
+pub struct RequestTransactions;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTransactions
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestTransactionsService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTransactionsService { service }))
+    }
+}
+
+pub struct RequestTransactionsService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTransactionsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let slot = TransactionSlot::default();
+        req.extensions_mut().insert(slot.clone());
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            // Si el manejador falla, la transacción se suelta sin confirmar junto con `slot`.
+            let res = fut.await?;
+            let tx = slot.0.borrow_mut().take();
+            let Some(tx) = tx.filter(|_| res.status().is_success()) else {
+                return Ok(res.map_into_left_body());
+            };
+
+            match tx.commit().await {
+                Ok(_) => Ok(res.map_into_left_body()),
+                Err(e) => {
+                    tracing::error!("Failed to commit request transaction: {:?}", e);
+                    let response = HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to commit changes"));
+                    Ok(res.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+        App,
+    };
+    use uuid::Uuid;
+
+    use crate::testing;
+
+    /// Escritura de prueba dentro de la transacción de la petición.
+    async fn insert_notification(tx: &mut Transaction<'_, Postgres>, user_id: Uuid) {
+        sqlx::query("INSERT INTO notifications (user_id, kind, message) VALUES ($1, 'test', 'partial write')")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .unwrap();
+    }
+
+    async fn notifications(db_pool: &PgPool, user_id: Uuid) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn commits_on_success_and_rolls_back_otherwise() {
+        let db_pool = testing::pool().await;
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db_pool.clone()))
+                .wrap(RequestTransactions)
+                .route(
+                    "/ok/{user_id}",
+                    web::post().to(|mut tx: Tx, path: web::Path<Uuid>| async move {
+                        insert_notification(&mut tx, *path).await;
+                        HttpResponse::Created().finish()
+                    }),
+                )
+                .route(
+                    "/conflict/{user_id}",
+                    web::post().to(|mut tx: Tx, path: web::Path<Uuid>| async move {
+                        insert_notification(&mut tx, *path).await;
+                        HttpResponse::Conflict().finish()
+                    }),
+                )
+                .route(
+                    "/error/{user_id}",
+                    web::post().to(|mut tx: Tx, path: web::Path<Uuid>| async move {
+                        insert_notification(&mut tx, *path).await;
+                        Err::<HttpResponse, _>(actix_web::error::ErrorInternalServerError("failed mid-handler"))
+                    }),
+                )
+                .route(
+                    "/commit-early/{user_id}",
+                    web::post().to(|mut tx: Tx, path: web::Path<Uuid>| async move {
+                        insert_notification(&mut tx, *path).await;
+                        tx.commit().await.unwrap();
+                        HttpResponse::BadGateway().finish()
+                    }),
+                ),
+        )
+        .await;
+
+        for (path, status, kept) in [
+            ("ok", StatusCode::CREATED, 1),
+            ("conflict", StatusCode::CONFLICT, 0),
+            ("error", StatusCode::INTERNAL_SERVER_ERROR, 0),
+            ("commit-early", StatusCode::BAD_GATEWAY, 1),
+        ] {
+            let user_id = testing::insert_user(&db_pool, "student").await;
+            let req = TestRequest::post().uri(&format!("/{}/{}", path, user_id)).to_request();
+            assert_eq!(call_service(&app, req).await.status(), status, "{}", path);
+            assert_eq!(notifications(&db_pool, user_id).await, kept, "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn fails_without_the_middleware() {
+        let db_pool = testing::pool().await;
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db_pool))
+                .route("/", web::post().to(|_: Tx| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::post().uri("/").to_request()).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- 1. Crear un tipo ENUM para el estado de publicación de los cursos.
+CREATE TYPE course_status AS ENUM ('draft', 'published', 'archived');
+
+-- 2. Añadir las columnas de estado y fecha de publicación a la tabla 'courses'.
+-- Los cursos nuevos empiezan como borrador.
+ALTER TABLE courses ADD COLUMN status course_status NOT NULL DEFAULT 'draft';
+ALTER TABLE courses ADD COLUMN published_at TIMESTAMPTZ;
+
+-- 3. Los cursos existentes ya eran visibles públicamente, así que los marcamos como publicados.
+UPDATE courses SET status = 'published', published_at = created_at;
+
+-- 4. Índices para los feeds de cursos recientes y en tendencia.
+CREATE INDEX idx_courses_status_published_at ON courses (status, published_at DESC);
+CREATE INDEX idx_enrollments_course_date ON enrollments (course_id, enrollment_date);
+-- Fin del script de migración
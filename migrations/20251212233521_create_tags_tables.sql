@@ -0,0 +1,19 @@
+-- Add migration script here
+-- 1. Crea la tabla de etiquetas (tags). Los nombres se guardan normalizados en minúsculas.
+CREATE TABLE tags (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(50) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 2. Tabla intermedia que relaciona cursos con etiquetas.
+CREATE TABLE course_tags (
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (course_id, tag_id)
+);
+
+-- 3. Índices para la búsqueda por prefijo y el conteo de uso de cada etiqueta.
+CREATE INDEX idx_tags_name_prefix ON tags (name text_pattern_ops);
+CREATE INDEX idx_course_tags_tag_id ON course_tags (tag_id);
+-- Fin del script de migración
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Crea la tabla de marcadores (cursos guardados por los estudiantes).
+-- La clave primaria compuesta evita marcadores duplicados.
+CREATE TABLE bookmarks (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, course_id)
+);
+-- Fin del script de migración
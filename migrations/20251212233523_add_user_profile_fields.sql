@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Añade los campos opcionales del perfil público de los usuarios.
+ALTER TABLE users ADD COLUMN bio TEXT;
+ALTER TABLE users ADD COLUMN avatar_url VARCHAR(2048);
+-- Fin del script de migración
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Crea la tabla de materiales privados de los cursos (PDFs, vídeos, etc.).
+-- El fichero se guarda en disco con el ID del material como nombre; aquí solo guardamos sus metadatos.
+CREATE TABLE course_materials (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_course_materials_course_id ON course_materials (course_id);
+-- Fin del script de migración
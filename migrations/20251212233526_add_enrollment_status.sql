@@ -0,0 +1,10 @@
+-- Add migration script here
+-- 1. Crear un tipo ENUM para el estado de una inscripción.
+CREATE TYPE enrollment_status AS ENUM ('pending', 'active', 'completed', 'dropped');
+
+-- 2. Añadir la columna 'status' a la tabla 'enrollments'.
+-- Las inscripciones existentes y nuevas quedan activas por defecto.
+ALTER TABLE enrollments ADD COLUMN status enrollment_status NOT NULL DEFAULT 'active';
+
+CREATE INDEX idx_enrollments_user_status ON enrollments (user_id, status);
+-- Fin del script de migración
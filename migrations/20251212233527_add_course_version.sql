@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Añade un número de versión a los cursos para el control de concurrencia optimista.
+-- Cada actualización lo incrementa; una edición basada en una versión antigua se rechaza.
+ALTER TABLE courses ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+-- Fin del script de migración
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- 1. Crea la tabla de categorías del catálogo.
+CREATE TABLE categories (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(100) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 2. Cada curso puede pertenecer a una categoría. Si la categoría se elimina, el curso queda sin categoría.
+ALTER TABLE courses ADD COLUMN category_id UUID REFERENCES categories(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_courses_category_id ON courses (category_id);
+-- Fin del script de migración
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Añade la fecha de publicación programada. Una tarea en segundo plano publica los borradores
+-- cuya `publish_at` ya ha pasado.
+ALTER TABLE courses ADD COLUMN publish_at TIMESTAMPTZ;
+
+CREATE INDEX idx_courses_scheduled_publish ON courses (publish_at) WHERE status = 'draft' AND publish_at IS NOT NULL;
+-- Fin del script de migración
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Registra la última vez que cada usuario vio cada curso.
+-- La clave primaria compuesta deduplica por curso: una nueva visita solo actualiza `viewed_at`.
+CREATE TABLE course_views (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    viewed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, course_id)
+);
+
+CREATE INDEX idx_course_views_user_viewed_at ON course_views (user_id, viewed_at DESC);
+-- Fin del script de migración
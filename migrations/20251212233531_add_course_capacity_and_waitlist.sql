@@ -0,0 +1,14 @@
+-- Add migration script here
+-- 1. Cupo máximo de estudiantes activos por curso. NULL significa sin límite.
+ALTER TABLE courses ADD COLUMN max_students INTEGER CHECK (max_students > 0);
+
+-- 2. Lista de espera de los cursos completos, en orden de llegada.
+CREATE TABLE course_waitlist (
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (course_id, user_id)
+);
+
+CREATE INDEX idx_course_waitlist_course_created_at ON course_waitlist (course_id, created_at);
+-- Fin del script de migración
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Marca las cuentas (creadas por un admin) que deben cambiar la contraseña en su primer login.
+ALTER TABLE users ADD COLUMN must_reset_password BOOLEAN NOT NULL DEFAULT FALSE;
+-- Fin del script de migración
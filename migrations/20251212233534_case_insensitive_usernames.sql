@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Los nombres de usuario pasan a ser únicos sin distinguir mayúsculas.
+-- `username` se guarda en minúsculas y `display_username` conserva el formato original.
+-- Requisito: no debe haber usuarios que solo se diferencien en mayúsculas (si los hay, la migración falla).
+ALTER TABLE users ADD COLUMN display_username VARCHAR(255);
+UPDATE users SET display_username = username;
+ALTER TABLE users ALTER COLUMN display_username SET NOT NULL;
+
+UPDATE users SET username = LOWER(username) WHERE username <> LOWER(username);
+
+CREATE UNIQUE INDEX idx_users_username_lower ON users (LOWER(username));
+-- Fin del script de migración
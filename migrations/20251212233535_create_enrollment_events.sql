@@ -0,0 +1,31 @@
+-- Add migration script here
+-- 1. Historial de cambios de estado de las inscripciones.
+CREATE TABLE enrollment_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    course_id UUID NOT NULL,
+    status enrollment_status NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (user_id, course_id) REFERENCES enrollments(user_id, course_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_enrollment_events_user_occurred_at ON enrollment_events (user_id, occurred_at);
+
+-- 2. Un trigger registra cada alta y cada cambio de estado, venga de donde venga.
+CREATE FUNCTION record_enrollment_event() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO enrollment_events (user_id, course_id, status) VALUES (NEW.user_id, NEW.course_id, NEW.status);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER enrollment_created AFTER INSERT ON enrollments
+    FOR EACH ROW EXECUTE FUNCTION record_enrollment_event();
+
+CREATE TRIGGER enrollment_status_changed AFTER UPDATE OF status ON enrollments
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status) EXECUTE FUNCTION record_enrollment_event();
+
+-- 3. Las inscripciones existentes empiezan su historial con su estado actual.
+INSERT INTO enrollment_events (user_id, course_id, status, occurred_at)
+SELECT user_id, course_id, status, enrollment_date FROM enrollments;
+-- Fin del script de migración
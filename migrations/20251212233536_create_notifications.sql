@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Notificaciones para los usuarios. `kind` identifica el tipo (p. ej. `enrollment_removed`).
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(50) NOT NULL,
+    message TEXT NOT NULL,
+    read_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_notifications_user_created_at ON notifications (user_id, created_at DESC);
+-- Fin del script de migración
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- 1. Tokens de restablecimiento de contraseña. Como en la verificación de email, solo se guarda
+--    el hash SHA-256 del token enviado. Cada token se borra al usarse (un solo uso).
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_password_reset_tokens_user_id ON password_reset_tokens (user_id);
+-- Fin del script de migración
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Preferencias de notificación por usuario y tipo. Sin fila, el tipo está activado: solo se
+-- guardan los tipos que el usuario ha configurado explícitamente.
+CREATE TABLE notification_preferences (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(50) NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, kind)
+);
+-- Fin del script de migración
@@ -0,0 +1,17 @@
+-- Add migration script here
+-- 1. Permite al instructor desactivar las reseñas de un curso. Por defecto están permitidas.
+ALTER TABLE courses ADD COLUMN allow_reviews BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- 2. Reseñas de los cursos: una por estudiante y curso, con una valoración de 1 a 5.
+CREATE TABLE course_reviews (
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (course_id, user_id)
+);
+
+CREATE INDEX idx_course_reviews_course_created_at ON course_reviews (course_id, created_at DESC);
+-- Fin del script de migración
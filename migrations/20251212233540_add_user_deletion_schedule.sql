@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Borrado diferido de cuentas: mientras `deletion_scheduled_at` no sea NULL la cuenta está
+-- desactivada, y se borra definitivamente al llegar esa fecha.
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TIMESTAMPTZ;
+
+CREATE INDEX idx_users_deletion_scheduled_at ON users (deletion_scheduled_at) WHERE deletion_scheduled_at IS NOT NULL;
+-- Fin del script de migración
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- Tokens de acceso personal: credenciales de larga duración para scripts e integraciones.
+-- Solo se guarda el hash SHA-256 del token; el token en claro se muestra una única vez al crearlo.
+CREATE TABLE personal_access_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    -- Permisos del token (`read`, `write`).
+    scopes TEXT[] NOT NULL,
+    -- NULL significa que el token no caduca.
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_personal_access_tokens_user_id ON personal_access_tokens (user_id);
+-- Fin del script de migración
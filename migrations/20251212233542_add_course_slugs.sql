@@ -0,0 +1,25 @@
+-- Add migration script here
+-- Slug único de cada curso, para URLs legibles (`/courses/intro-to-rust`).
+ALTER TABLE courses ADD COLUMN slug VARCHAR(100);
+
+-- Los cursos existentes reciben un slug derivado del título; los repetidos se numeran
+-- (`-2`, `-3`, ...) por orden de creación, igual que al crear un curso.
+WITH base AS (
+    SELECT id, created_at,
+           COALESCE(NULLIF(trim(BOTH '-' FROM left(regexp_replace(
+               translate(lower(title), 'áàäâãéèëêíìïîóòöôõúùüûñç', 'aaaaaeeeeiiiiooooouuuunc'),
+               '[^a-z0-9]+', '-', 'g'), 80)), ''), 'course') AS slug
+    FROM courses
+),
+numbered AS (
+    SELECT id, slug, ROW_NUMBER() OVER (PARTITION BY slug ORDER BY created_at, id) AS n
+    FROM base
+)
+UPDATE courses c
+SET slug = CASE WHEN numbered.n = 1 THEN numbered.slug ELSE numbered.slug || '-' || numbered.n END
+FROM numbered
+WHERE numbered.id = c.id;
+
+ALTER TABLE courses ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE courses ADD CONSTRAINT courses_slug_key UNIQUE (slug);
+-- Fin del script de migración
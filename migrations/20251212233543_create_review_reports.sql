@@ -0,0 +1,17 @@
+-- Add migration script here
+-- 1. Identificador propio de cada reseña, para poder reportarla y moderarla individualmente.
+--    `hidden_at` se fija cuando la reseña acumula suficientes reportes y queda oculta hasta que
+--    se modere.
+ALTER TABLE course_reviews ADD COLUMN id UUID NOT NULL DEFAULT uuid_generate_v4();
+ALTER TABLE course_reviews ADD CONSTRAINT course_reviews_id_key UNIQUE (id);
+ALTER TABLE course_reviews ADD COLUMN hidden_at TIMESTAMPTZ;
+
+-- 2. Reportes de reseñas inapropiadas: uno por usuario y reseña, con el motivo.
+CREATE TABLE review_reports (
+    review_id UUID NOT NULL REFERENCES course_reviews(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (review_id, user_id)
+);
+-- Fin del script de migración
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Con `reserve_pending_slots`, las inscripciones pendientes de aprobación ocupan plaza: el cupo
+-- (`max_students`) se compara con las inscripciones `pending` + `active` en lugar de solo las `active`.
+ALTER TABLE courses ADD COLUMN reserve_pending_slots BOOLEAN NOT NULL DEFAULT FALSE;
+-- Fin del script de migración
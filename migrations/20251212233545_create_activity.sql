@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Actividad de cada usuario (se inscribió, completó una lección, ...), para su línea de tiempo
+-- en `GET /me/activity`. `course_id` y `lesson_id` indican sobre qué se hizo, si aplica.
+CREATE TABLE activity (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(50) NOT NULL,
+    course_id UUID REFERENCES courses(id) ON DELETE CASCADE,
+    lesson_id UUID REFERENCES lessons(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_activity_user_created_at ON activity (user_id, created_at DESC);
+-- Fin del script de migración
@@ -0,0 +1,46 @@
+-- Add migration script here
+-- 1. Ediciones de un curso (por ejemplo, una por semestre). Con `COURSE_OFFERINGS_ENABLED` un
+--    estudiante puede inscribirse en varias ediciones del mismo curso.
+CREATE TABLE course_offerings (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    starts_at TIMESTAMPTZ,
+    ends_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (course_id, name)
+);
+
+-- 2. Cada inscripción pasa a tener su propio identificador y, opcionalmente, una edición.
+ALTER TABLE enrollments ADD COLUMN id UUID NOT NULL DEFAULT uuid_generate_v4();
+ALTER TABLE enrollments ADD COLUMN offering_id UUID REFERENCES course_offerings(id) ON DELETE CASCADE;
+
+-- 3. El historial de estados apunta a la inscripción por su identificador, ya que
+--    `(user_id, course_id)` deja de ser único.
+ALTER TABLE enrollment_events ADD COLUMN enrollment_id UUID;
+UPDATE enrollment_events ev
+SET enrollment_id = e.id
+FROM enrollments e
+WHERE e.user_id = ev.user_id AND e.course_id = ev.course_id;
+ALTER TABLE enrollment_events DROP CONSTRAINT enrollment_events_user_id_course_id_fkey;
+
+ALTER TABLE enrollments DROP CONSTRAINT enrollments_pkey;
+ALTER TABLE enrollments ADD PRIMARY KEY (id);
+
+ALTER TABLE enrollment_events ALTER COLUMN enrollment_id SET NOT NULL;
+ALTER TABLE enrollment_events
+    ADD FOREIGN KEY (enrollment_id) REFERENCES enrollments(id) ON DELETE CASCADE;
+
+CREATE OR REPLACE FUNCTION record_enrollment_event() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO enrollment_events (enrollment_id, user_id, course_id, status)
+    VALUES (NEW.id, NEW.user_id, NEW.course_id, NEW.status);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- 4. Unicidad: una inscripción por estudiante y curso sin edición, y una por estudiante y edición.
+CREATE UNIQUE INDEX enrollments_user_course_key ON enrollments (user_id, course_id) WHERE offering_id IS NULL;
+CREATE UNIQUE INDEX enrollments_user_offering_key ON enrollments (user_id, offering_id) WHERE offering_id IS NOT NULL;
+CREATE INDEX idx_enrollments_user_course ON enrollments (user_id, course_id);
+-- Fin del script de migración
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- El login acepta el email sin distinguir mayúsculas (`LOWER(email) = LOWER($1)`).
+CREATE INDEX idx_users_email_lower ON users (LOWER(email));
+-- Fin del script de migración
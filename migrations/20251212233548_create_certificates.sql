@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Los cursos con `issues_certificate` emiten un certificado al estudiante que completa todas sus
+-- lecciones (su inscripción pasa a `completed`). Un certificado por estudiante y curso.
+ALTER TABLE courses ADD COLUMN issues_certificate BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE certificates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    issued_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, course_id)
+);
+-- Fin del script de migración
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Borrado lógico de cursos: un curso con `deleted_at` deja de aparecer en los listados y en el
+-- detalle (salvo para los admins con `include_deleted=true`), pero conserva sus inscripciones,
+-- reseñas y certificados.
+ALTER TABLE courses ADD COLUMN deleted_at TIMESTAMPTZ;
+-- Fin del script de migración
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Nota privada y metadatos libres (un objeto JSON) que el instructor puede asociar a cada
+-- inscripción. Solo se muestran en el listado de estudiantes del curso, nunca al estudiante.
+ALTER TABLE enrollments
+    ADD COLUMN notes TEXT,
+    ADD COLUMN metadata JSONB NOT NULL DEFAULT '{}'::jsonb;
+-- Fin del script de migración
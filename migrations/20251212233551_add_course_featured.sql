@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Cursos destacados por los admins para la portada, en el orden de `featured_rank` (1 primero).
+-- Un curso tiene posición si y solo si está destacado.
+ALTER TABLE courses
+    ADD COLUMN is_featured BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN featured_rank INTEGER,
+    ADD CONSTRAINT courses_featured_rank_check CHECK (is_featured = (featured_rank IS NOT NULL));
+
+CREATE INDEX idx_courses_featured_rank ON courses (featured_rank) WHERE is_featured;
+-- Fin del script de migración
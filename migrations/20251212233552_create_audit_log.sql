@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Registro de auditoría de las acciones sensibles de los admins (p. ej. suplantar a un usuario).
+-- `target_type` y `target_id` indican sobre qué se hizo; las entradas se conservan aunque se
+-- borre la cuenta del admin.
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    action VARCHAR(50) NOT NULL,
+    target_type VARCHAR(50) NOT NULL,
+    target_id UUID,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_log_created_at ON audit_log (created_at DESC);
+-- Fin del script de migración
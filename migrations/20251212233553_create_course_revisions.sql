@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Historial del contenido de los cursos: antes de cada cambio del título, la descripción o las
+-- lecciones se guarda una copia de cómo estaban, para poder consultarla y volver a ella.
+-- `lessons` es la lista de lecciones (`id`, `title`, `content`, `position`) en orden.
+CREATE TABLE course_revisions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    author_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    lessons JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_course_revisions_course_created_at ON course_revisions (course_id, created_at DESC);
+-- Fin del script de migración
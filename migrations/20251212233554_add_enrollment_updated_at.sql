@@ -0,0 +1,20 @@
+-- Add migration script here
+-- 1. Fecha del último cambio de cada inscripción (estado, nota, metadatos...). Las existentes
+--    empiezan con su fecha de inscripción.
+ALTER TABLE enrollments ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+UPDATE enrollments SET updated_at = enrollment_date;
+
+-- 2. Un trigger la actualiza en cada cambio, venga de donde venga. `enrollment_date` la fija
+--    siempre la base de datos (`DEFAULT NOW()`, o `NOW()` al reactivar una inscripción).
+CREATE FUNCTION touch_enrollment_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER enrollment_updated BEFORE UPDATE ON enrollments
+    FOR EACH ROW WHEN (OLD IS DISTINCT FROM NEW) EXECUTE FUNCTION touch_enrollment_updated_at();
+
+CREATE INDEX idx_enrollments_user_updated_at ON enrollments (user_id, updated_at);
+-- Fin del script de migración
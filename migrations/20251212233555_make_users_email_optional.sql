@@ -0,0 +1,6 @@
+-- Add migration script here
+-- 1. El email deja de ser obligatorio: algunas altas se hacen antes de conocerlo y el usuario lo
+--    completa después con `PUT /me/email`. La restricción UNIQUE se mantiene (admite varios NULL),
+--    así que dos cuentas no pueden tener el mismo email.
+ALTER TABLE users ALTER COLUMN email DROP NOT NULL;
+-- Fin del script de migración
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- 1. Con `review_requires_completion`, solo los estudiantes que completaron el curso pueden
+--    reseñarlo; sin él basta con estar inscrito, como hasta ahora.
+ALTER TABLE courses ADD COLUMN review_requires_completion BOOLEAN NOT NULL DEFAULT FALSE;
+-- Fin del script de migración
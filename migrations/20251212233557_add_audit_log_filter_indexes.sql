@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Índices para los filtros de `GET /audit-log` (por quién y por acción), que siempre ordenan por
+-- fecha. El de `actor_id` también sirve al `ON DELETE SET NULL` al borrar una cuenta.
+CREATE INDEX idx_audit_log_actor_id_created_at ON audit_log (actor_id, created_at DESC);
+CREATE INDEX idx_audit_log_action_created_at ON audit_log (action, created_at DESC);
+-- Fin del script de migración
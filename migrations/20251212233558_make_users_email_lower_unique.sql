@@ -0,0 +1,7 @@
+-- Add migration script here
+-- El login y la recuperación de contraseña buscan el email sin distinguir mayúsculas, así que la
+-- unicidad también debe ignorarlas: `Ana@x.com` y `ana@x.com` no pueden ser dos cuentas. Sustituye
+-- al índice normal de `LOWER(email)`; como la restricción UNIQUE, admite varios NULL.
+DROP INDEX idx_users_email_lower;
+CREATE UNIQUE INDEX idx_users_email_lower ON users (LOWER(email));
+-- Fin del script de migración
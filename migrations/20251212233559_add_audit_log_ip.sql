@@ -0,0 +1,6 @@
+-- Add migration script here
+-- IP desde la que se hizo cada acción auditada (la de `ClientIp`, que respeta `TRUSTED_PROXY`).
+-- Se guarda como texto (IPv4 o IPv6); las entradas anteriores y las acciones sin IP conocida
+-- quedan en NULL.
+ALTER TABLE audit_log ADD COLUMN ip VARCHAR(45);
+-- Fin del script de migración
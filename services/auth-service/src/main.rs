@@ -1,52 +1,421 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use ccb_common::{AuthenticatedUser, Claims, UserRole};
-use actix_cors::Cors;
+use actix_web::{http::header, web, App, HttpResponse, HttpServer, Responder};
+use ccb_common::{api_key::ApiKeyGuard, audit::{self, AuditEntry}, client_ip::ClientIp, i18n::LocalizedErrors, notifications, pat, maintenance::{self, MaintenanceFlag, MaintenanceMode}, rate_limit::{RateLimiter, WriteRateLimit}, health, cache::CountCache, config::Config, db, error::ApiError, jwt::JwtKeySet, pagination::PageParams, permissions, scope, telemetry::{self, AccessLog}, throttle::LoginThrottle, timestamp, AuthenticatedUser, Claims, UserRole};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
-use sqlx::{postgres::PgPoolOptions, FromRow, PgPool};
-use std::env;
+use tracing::{debug, error, info};
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{encode, Header, EncodingKey};
 use chrono::{Utc, Duration, DateTime};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
 use uuid::Uuid;
 
 // --- Modelos de Datos ---
 
-/// Estructura para recibir los datos de registro.
+/// Datos comunes para crear una cuenta, tanto en el registro como desde un admin.
 #[derive(Deserialize)]
-struct RegisterUser {
+struct NewUser {
     username: String,
     password: String,
-    email: String,
+    /// Opcional: la cuenta puede crearse sin email y completarlo después con `PUT /me/email`.
+    #[serde(default)]
+    email: Option<String>,
     first_name: String,
     last_name: String,
 }
 
+/// Estructura para recibir los datos de registro.
+#[derive(Deserialize)]
+struct RegisterUser {
+    #[serde(flatten)]
+    user: NewUser,
+    /// Rol solicitado. Solo se respeta si quien registra es un admin; si no, se usa `DEFAULT_USER_ROLE`.
+    role: Option<UserRole>,
+    /// Token del captcha resuelto por el cliente. Obligatorio con `CAPTCHA_ENABLED`, salvo para admins.
+    captcha_token: Option<String>,
+}
+
+/// Estructura para que un admin cree una cuenta con un rol concreto (`POST /users`).
+#[derive(Deserialize)]
+struct AdminCreateUser {
+    #[serde(flatten)]
+    user: NewUser,
+    role: UserRole,
+    /// Obliga al usuario a cambiar la contraseña en su primer login.
+    #[serde(default)]
+    require_password_reset: bool,
+}
+
 /// Estructura para recibir los datos de login.
 #[derive(Deserialize)]
 struct LoginUser {
-    username: String,
+    /// Nombre de usuario o email, sin distinguir mayúsculas. También se acepta como `username`,
+    /// el nombre del campo antes de admitir el email.
+    #[serde(alias = "username")]
+    identifier: String,
     password: String,
+    /// Permisos de una sesión limitada (ver `scope::KNOWN`). Sin ellos, el token tiene todos los
+    /// permisos del rol.
+    #[serde(default)]
+    scopes: Option<Vec<String>>,
+    /// Pide una sesión larga: el token dura `JWT_REMEMBER_HOURS` en vez de `JWT_EXPIRATION_HOURS`.
+    #[serde(default)]
+    remember_me: bool,
 }
 
 /// Estructura para representar un usuario en la base de datos y en las respuestas API.
 #[derive(Serialize, FromRow)]
 struct User {
     id: Uuid,
+    /// Nombre de usuario normalizado a minúsculas; es el que se usa para el login.
     username: String,
-    email: String,
+    /// Nombre de usuario tal como se registró, para mostrarlo.
+    display_username: String,
+    /// `None` si la cuenta se creó sin email y aún no lo ha indicado.
+    email: Option<String>,
     first_name: String,
     last_name: String,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+    /// `None` mientras el usuario no haya verificado su email.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    email_verified_at: Option<DateTime<Utc>>,
+    /// `true` si el usuario debe cambiar la contraseña (cuentas creadas por un admin).
+    must_reset_password: bool,
+    /// Fecha en la que se borrará la cuenta; mientras tanto está desactivada.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "timestamp::serialize_option")]
+    deletion_scheduled_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing)] // Nunca enviar el hash de la contraseña al cliente
     password_hash: String,
     role: UserRole,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Estructura para recibir los cambios del perfil del usuario autenticado. Los campos son opcionales.
+#[derive(Deserialize)]
+struct UpdateProfile {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+}
+
+/// Perfil público de un usuario. Nunca incluye el email ni el hash de la contraseña.
+#[derive(Serialize, FromRow)]
+struct PublicProfile {
+    id: Uuid,
+    username: String,
+    display_username: String,
+    first_name: String,
+    last_name: String,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+    role: UserRole,
+    published_course_count: i64,
+}
+
+/// Cuerpo de `POST /users/batch`: los ids de los usuarios a resolver.
+#[derive(Deserialize)]
+struct BatchUsersRequest {
+    ids: Vec<Uuid>,
+}
+
+/// Datos públicos de un usuario para mostrarlo en otros servicios (`POST /users/batch`).
+#[derive(Serialize, FromRow)]
+struct UserSummary {
+    id: Uuid,
+    username: String,
+    display_username: String,
+    first_name: String,
+    last_name: String,
+    avatar_url: Option<String>,
+}
+
+/// Respuesta de `GET /stats/platform`: el estado general de la plataforma para los admins.
+#[derive(Serialize)]
+struct PlatformStats {
+    users: UserStats,
+    courses: CourseStats,
+    /// Inscripciones `active` en todos los cursos.
+    active_enrollments: i64,
+    signups: SignupStats,
+}
+
+#[derive(Serialize)]
+struct UserStats {
+    total: i64,
+    /// Usuarios por rol, con todos los roles (los que no tienen usuarios aparecen con 0).
+    by_role: BTreeMap<String, i64>,
+}
+
+#[derive(Serialize)]
+struct CourseStats {
+    /// Cursos sin borrar.
+    total: i64,
+    /// Cursos sin borrar por estado, con todos los estados.
+    by_status: BTreeMap<String, i64>,
+    /// Cursos con borrado lógico (`DELETE /courses/{id}?soft=true`), que no cuentan en el resto.
+    deleted: i64,
+}
+
+/// Cuentas creadas en los últimos 7 y 30 días.
+#[derive(Serialize)]
+struct SignupStats {
+    last_7_days: i64,
+    last_30_days: i64,
+}
+
+/// Parámetros del listado de usuarios para administradores: rol y búsqueda (la paginación va en `PageParams`).
+#[derive(Deserialize)]
+struct UsersQuery {
+    role: Option<UserRole>,
+    /// Texto a buscar (parcialmente) en el nombre de usuario o el email.
+    q: Option<String>,
+}
+
+/// Respuesta paginada del listado de usuarios, con el total de resultados del filtro.
+#[derive(Serialize)]
+struct UserListResponse {
+    users: Vec<User>,
+    total: i64,
+    page: i64,
+    per_page: i64,
+}
+
+/// Filtros del registro de auditoría (la paginación va en `PageParams`). `from` es inclusivo y
+/// `to` exclusivo.
+#[derive(Deserialize)]
+struct AuditLogQuery {
+    actor_id: Option<Uuid>,
+    action: Option<String>,
+    target_type: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+/// Entrada del registro de auditoría tal como la ven los admins.
+#[derive(Serialize, FromRow)]
+struct AuditLogEntry {
+    id: Uuid,
+    /// `None` si la cuenta de quien hizo la acción ya se borró.
+    actor_id: Option<Uuid>,
+    action: String,
+    target_type: String,
+    target_id: Option<Uuid>,
+    details: serde_json::Value,
+    /// IP desde la que se hizo la acción; `None` en las entradas sin IP conocida.
+    ip: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Respuesta paginada del registro de auditoría, con el total de entradas del filtro.
+#[derive(Serialize)]
+struct AuditLogResponse {
+    entries: Vec<AuditLogEntry>,
+    total: i64,
+    page: i64,
+    per_page: i64,
+}
+
+/// Respuesta de `POST /notifications/read-all`: número de notificaciones marcadas como leídas.
+#[derive(Serialize)]
+struct MarkedReadResponse {
+    updated: u64,
+}
+
+/// Notificación del usuario autenticado.
+#[derive(Serialize, FromRow)]
+struct UserNotification {
+    id: Uuid,
+    kind: String,
+    message: String,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    read_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize")]
     created_at: DateTime<Utc>,
 }
 
+/// Parámetros opcionales del login (`?include_user=true`).
+#[derive(Deserialize)]
+struct LoginQuery {
+    #[serde(default)]
+    include_user: bool,
+}
+
+/// Respuesta de `GET /me/permissions`: el rol, los permisos del token (`None` si tiene todos los
+/// de su rol) y las acciones que puede hacer el usuario (ver `permissions::capabilities`).
+#[derive(Serialize)]
+struct PermissionsResponse {
+    role: UserRole,
+    scopes: Option<Vec<String>>,
+    capabilities: Vec<&'static str>,
+}
+
 /// Estructura para la respuesta del login, que contiene el token.
+/// Si se pide con `include_user=true`, incluye también el perfil del usuario (sin el hash de la contraseña).
 #[derive(Serialize)]
 struct TokenResponse {
     token: String,
+    /// Presente (y `true`) cuando el usuario debe cambiar su contraseña antes de continuar.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    password_reset_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<User>,
+}
+
+/// Estructura para confirmar el email con el token recibido.
+#[derive(Deserialize)]
+struct VerifyEmail {
+    token: String,
+}
+
+/// Estructura para indicar (o corregir, mientras no esté verificado) el email de la cuenta.
+#[derive(Deserialize)]
+struct SetEmail {
+    email: String,
+}
+
+/// Estructura para pedir el reenvío del token de verificación sin sesión iniciada.
+#[derive(Deserialize)]
+struct ResendVerification {
+    email: String,
+}
+
+/// Estructura para pedir un token de restablecimiento de contraseña.
+#[derive(Deserialize)]
+struct RequestPasswordReset {
+    email: String,
+}
+
+/// Estructura para fijar una nueva contraseña con el token recibido.
+#[derive(Deserialize)]
+struct ConfirmPasswordReset {
+    token: String,
+    new_password: String,
+}
+
+/// Estructura para que el usuario autenticado cambie su contraseña.
+#[derive(Deserialize)]
+struct ChangePassword {
+    current_password: String,
+    new_password: String,
+}
+
+/// Estructura para crear un token de acceso personal. Sin `scopes` el token tiene todos los
+/// permisos (`pat::SCOPES`); sin `expires_in_days` no caduca.
+#[derive(Deserialize)]
+struct CreatePersonalAccessToken {
+    name: String,
+    scopes: Option<Vec<String>>,
+    expires_in_days: Option<i32>,
+}
+
+/// Token de acceso personal tal como se lista (nunca incluye el token ni su hash).
+#[derive(Serialize, FromRow)]
+struct PersonalAccessToken {
+    id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    last_used_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Respuesta de `POST /me/tokens`: el único momento en el que se devuelve el token en claro.
+#[derive(Serialize)]
+struct CreatedPersonalAccessToken {
+    #[serde(flatten)]
+    info: PersonalAccessToken,
+    token: String,
+}
+
+/// Respuesta de `DELETE /me`: fecha en la que se borrará la cuenta.
+#[derive(Serialize)]
+struct DeletionScheduled {
+    #[serde(serialize_with = "timestamp::serialize")]
+    deletion_scheduled_at: DateTime<Utc>,
+}
+
+/// Cuerpo de `POST /users/{id}/reassign-courses`: el instructor que recibe los cursos.
+#[derive(Deserialize)]
+struct ReassignCourses {
+    instructor_id: Uuid,
+}
+
+/// Respuesta de `POST /users/{id}/reassign-courses`: los cursos transferidos.
+#[derive(Serialize)]
+struct ReassignedCourses {
+    from_user_id: Uuid,
+    to_user_id: Uuid,
+    course_ids: Vec<Uuid>,
+}
+
+/// Respuesta de `POST /admin/impersonate/{user_id}`: el token con el que el admin actúa como el
+/// usuario y cuándo caduca.
+#[derive(Serialize)]
+struct ImpersonationToken {
+    token: String,
+    #[serde(serialize_with = "timestamp::serialize")]
+    expires_at: DateTime<Utc>,
+    user_id: Uuid,
+    act_as: Uuid,
+}
+
+/// Máximo de ids por petición en `POST /users/batch`.
+const MAX_BATCH_USER_IDS: usize = 100;
+
+/// Tiempo máximo de espera de la verificación de un captcha.
+const CAPTCHA_VERIFY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Horas de validez de un token de verificación de email.
+const EMAIL_VERIFICATION_TOKEN_HOURS: i64 = 24;
+
+/// Tiempo mínimo entre dos envíos del token de verificación a un mismo usuario.
+const VERIFICATION_RESEND_COOLDOWN_SECONDS: f64 = 60.0;
+
+/// Cada cuánto se borran las cuentas cuyo periodo de gracia ha terminado.
+const ACCOUNT_DELETION_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// Respuesta del servicio de captchas. Turnstile y hCaptcha comparten este formato.
+#[derive(Deserialize)]
+struct CaptchaVerification {
+    success: bool,
+}
+
+/// Verifica los captchas del registro contra la API del proveedor (`CAPTCHA_VERIFY_URL`). Sirve
+/// cualquier servicio con la API de `siteverify` de Turnstile o hCaptcha.
+#[derive(Clone)]
+struct CaptchaVerifier {
+    client: reqwest::Client,
+    verify_url: String,
+    secret: String,
+}
+
+impl CaptchaVerifier {
+    /// Indica si el proveedor acepta el token. Un error significa que no se pudo comprobar.
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, reqwest::Error> {
+        let mut form = vec![("secret", self.secret.clone()), ("response", token.to_string())];
+        if let Some(ip) = remote_ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+
+        let verification = self
+            .client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CaptchaVerification>()
+            .await?;
+        Ok(verification.success)
+    }
 }
 
 // --- Estado de la Aplicación ---
@@ -54,176 +423,3681 @@ struct TokenResponse {
 /// Contiene los datos compartidos entre los hilos del servidor, como el pool de conexiones a la BD.
 struct AppState {
     db_pool: PgPool,
+    jwt_keys: JwtKeySet,
+    jwt_expiration_hours: i64,
+    /// Validez de los tokens de las sesiones con `remember_me` (`JWT_REMEMBER_HOURS`).
+    jwt_remember_hours: i64,
+    /// Rol asignado en el registro (`DEFAULT_USER_ROLE`, por defecto `student`).
+    default_user_role: UserRole,
+    /// Totales del listado de usuarios por filtro, compartidos entre todos los workers.
+    user_counts: Arc<CountCache>,
+    /// Minutos de validez de un token de restablecimiento de contraseña (`RESET_TOKEN_MINUTES`, por defecto 30).
+    reset_token_minutes: i32,
+    /// Días entre `DELETE /me` y el borrado definitivo de la cuenta (`ACCOUNT_DELETION_GRACE_DAYS`, por defecto 30).
+    account_deletion_grace_days: i32,
+    /// Verificación del captcha en el registro (`CAPTCHA_ENABLED`); `None` si está desactivada.
+    captcha: Option<CaptchaVerifier>,
+    /// Minutos de validez de un token de suplantación (`IMPERSONATION_TOKEN_MINUTES`, por defecto 15).
+    impersonation_token_minutes: i64,
+    /// Fallos de login consecutivos por IP e identificador, compartidos entre todos los workers.
+    login_throttle: Arc<LoginThrottle>,
 }
 
-// --- Manejadores de Endpoints (Handlers) ---
-
-/// Maneja las peticiones POST a /register
-async fn register(
-    state: web::Data<AppState>,
-    user_data: web::Json<RegisterUser>,
-) -> impl Responder {
-    // Extraemos los datos antes de mover la contraseña a un hilo bloqueante.
-    let username = user_data.username.clone();
-    let password = user_data.password.clone();
-    let email = user_data.email.clone();
-    let first_name = user_data.first_name.clone();
-    let last_name = user_data.last_name.clone();
+// --- Funciones Auxiliares ---
 
-    // Hashear la contraseña del usuario. Es un proceso que consume CPU,
-    // por lo que lo ejecutamos en un hilo bloqueante para no detener el event loop.
-    let password_hash = match web::block(move || hash(&password, DEFAULT_COST)).await {
-        Ok(Ok(hash)) => hash,
-        _ => return HttpResponse::InternalServerError().body("Error hashing password"),
-    };
+/// Busca al usuario cuyo nombre de usuario o email coincide con `identifier`, sin distinguir
+/// mayúsculas. Un identificador con `@` solo se busca como email: así nadie puede registrar como
+/// nombre de usuario el email de otra cuenta para quedarse con su login.
+async fn find_user_by_identifier(db_pool: &PgPool, identifier: &str) -> Result<Option<User>, sqlx::Error> {
+    let identifier = identifier.trim();
+    if identifier.contains('@') {
+        return sqlx::query_as!(
+            User,
+            r#"
+            SELECT id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+            FROM users
+            WHERE LOWER(email) = LOWER($1)
+            "#,
+            identifier
+        )
+        .fetch_optional(db_pool)
+        .await;
+    }
 
-    // Insertar el nuevo usuario en la base de datos.
-    // Usamos `query_as` para que sqlx mapee automáticamente el resultado a nuestra struct `User`.
-    let new_user: Result<User, sqlx::Error> = sqlx::query_as!(
+    sqlx::query_as!(
         User,
         r#"
-        INSERT INTO users (username, password_hash, email, first_name, last_name) 
-        VALUES ($1, $2, $3, $4, $5) 
-        RETURNING id, username, password_hash, email, first_name, last_name, role, created_at
+        SELECT id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+        FROM users
+        WHERE username = LOWER($1)
         "#,
-        username,
-        password_hash,
-        email,
-        first_name,
-        last_name
+        identifier
     )
-    .fetch_one(&state.db_pool)
-    .await;
+    .fetch_optional(db_pool)
+    .await
+}
 
-    match new_user {
-        Ok(user) => HttpResponse::Created().json(user),
-        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
-            HttpResponse::Conflict().body("Username already exists")
-        }
-        Err(e) => {
-            error!("Failed to create user: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to create user")
-        }
+/// Clave del limitador de login: la IP del cliente y el identificador normalizado, para que un
+/// atacante no pueda bloquear la cuenta de otra persona desde su propia IP.
+fn login_throttle_key(client_ip: ClientIp, identifier: &str) -> String {
+    let ip = client_ip.0.map(|ip| ip.to_string()).unwrap_or_default();
+    format!("{}|{}", ip, identifier.trim().to_lowercase())
+}
+
+/// Respuesta `429` para un login bloqueado, con los segundos de espera en `Retry-After`.
+fn login_throttled_response(retry_after: std::time::Duration) -> HttpResponse {
+    // Redondeamos hacia arriba: reintentar antes de tiempo volvería a fallar.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, seconds.to_string()))
+        .json(ApiError::new(
+            "too_many_login_attempts",
+            format!("Too many failed login attempts; try again in {} seconds", seconds),
+        ))
+}
+
+/// Anota un intento de login fallido y devuelve la respuesta: `401` con el mensaje genérico o,
+/// si este fallo bloquea la clave, `429` con el tiempo de espera.
+fn login_failed_response(state: &AppState, throttle_key: &str) -> HttpResponse {
+    match state.login_throttle.record_failure(throttle_key) {
+        Some(retry_after) => login_throttled_response(retry_after),
+        None => HttpResponse::Unauthorized().json(ApiError::new("invalid_credentials", "Invalid username or password")),
     }
 }
 
-/// Maneja las peticiones POST a /login
-async fn login(
-    state: web::Data<AppState>,
-    user_data: web::Json<LoginUser>,
-) -> impl Responder {
-    // 1. Buscar al usuario por su nombre de usuario.
-    // Usamos `fetch_optional` porque el usuario puede no existir.
-    let user = match sqlx::query_as!(
-        User,
-        "SELECT id, username, password_hash, email, first_name, last_name, role, created_at FROM users WHERE username = $1",
-        user_data.username
-    )
-    .fetch_optional(&state.db_pool)
-    .await
-    {
-        Ok(Some(user)) => user, // Si se encuentra, `user` es de tipo `User`
-        Ok(None) => return HttpResponse::Unauthorized().body("Invalid username or password"),
-        Err(_) => return HttpResponse::InternalServerError().body("Something went wrong"),
-    };
+/// Escapa los comodines de `LIKE` (`%`, `_` y `\`) para buscar un texto literal.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
 
-    // 2. Verificar que la contraseña proporcionada coincide con el hash almacenado.
-    let is_password_valid = match verify(&user_data.password, &user.password_hash) {
-        Ok(valid) => valid,
-        Err(_) => return HttpResponse::InternalServerError().body("Error verifying password"),
-    };
+/// Añade al `QueryBuilder` las condiciones de filtrado del listado de usuarios.
+/// Todos los valores se enlazan como parámetros, nunca se concatenan en el SQL.
+fn push_user_filters(builder: &mut QueryBuilder<'_, Postgres>, query: &UsersQuery) {
+    builder.push(" WHERE TRUE");
 
-    if !is_password_valid {
-        return HttpResponse::Unauthorized().body("Invalid username or password");
+    if let Some(role) = &query.role {
+        builder.push(" AND role = ").push_bind(role.clone());
+    }
+
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", escape_like(q));
+        builder
+            .push(" AND (username ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR email ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+}
+
+/// Añade a la consulta del registro de auditoría las condiciones de los filtros, con parámetros enlazados.
+fn push_audit_log_filters(builder: &mut QueryBuilder<'_, Postgres>, query: &AuditLogQuery) {
+    builder.push(" WHERE TRUE");
+
+    if let Some(actor_id) = query.actor_id {
+        builder.push(" AND actor_id = ").push_bind(actor_id);
     }
+    if let Some(action) = query.action.as_deref().map(str::trim).filter(|action| !action.is_empty()) {
+        builder.push(" AND action = ").push_bind(action.to_string());
+    }
+    if let Some(target_type) = query.target_type.as_deref().map(str::trim).filter(|target_type| !target_type.is_empty()) {
+        builder.push(" AND target_type = ").push_bind(target_type.to_string());
+    }
+    if let Some(from) = query.from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND created_at < ").push_bind(to);
+    }
+}
+
+/// Hash SHA-256 (hex) de un token. En la base de datos nunca se guarda el token en claro.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
 
-    // 3. Generar el JWT.
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+/// Firma el JWT de sesión del usuario. Con `password_change_required` el token queda restringido
+/// a `PUT /me/password`, y con `scopes` limitado a esos permisos. Con `remember_me` dura
+/// `JWT_REMEMBER_HOURS` en vez de `JWT_EXPIRATION_HOURS`. Lleva en su cabecera el `kid` de la
+/// clave actual, para poder rotarla sin invalidar sesiones.
+fn issue_token(
+    state: &AppState,
+    user: &User,
+    password_change_required: bool,
+    scopes: Option<Vec<String>>,
+    remember_me: bool,
+) -> jsonwebtoken::errors::Result<String> {
+    let hours = if remember_me { state.jwt_remember_hours } else { state.jwt_expiration_hours };
     let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24)) // El token expira en 24 horas
+        .checked_add_signed(Duration::hours(hours)) // Por defecto, el token expira en 24 horas (30 días con `remember_me`)
         .expect("Failed to calculate expiration")
         .timestamp();
 
     let claims = Claims {
         sub: user.id.to_string(),
-        role: user.role,
+        role: user.role.clone(),
         exp: expiration as usize,
+        password_change_required,
+        scopes,
+        act_as: None,
     };
+    state.jwt_keys.encode(&claims)
+}
 
-    let token = match encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_ref())) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().body("Failed to create token"),
-    };
+/// Genera un nuevo token de verificación para el usuario, invalidando los anteriores.
+async fn issue_email_verification_token(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+    sqlx::query!("DELETE FROM email_verification_tokens WHERE user_id = $1", user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO email_verification_tokens (token_hash, user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(hours => $3))
+        "#,
+        hash_token(&token),
+        user_id,
+        EMAIL_VERIFICATION_TOKEN_HOURS as i32
+    )
+    .execute(&mut **tx)
+    .await?;
 
-    // 4. Devolver el token al cliente.
-    HttpResponse::Ok().json(TokenResponse { token })
+    Ok(token)
 }
 
-/// Endpoint protegido que devuelve los datos del usuario autenticado.
-async fn get_me(
-    state: web::Data<AppState>,
-    auth_user: AuthenticatedUser, // El middleware se ejecuta aquí. Si falla, este handler nunca se llama.
-) -> impl Responder {
-    // El ID del usuario viene del token validado por el middleware.
-    let user_id = auth_user.id;
+/// Envía el token de verificación al email del usuario.
+/// Todavía no hay un proveedor de correo integrado: el envío se registra en el log y el token
+/// solo se muestra con nivel `debug`, para poder probar el flujo en desarrollo.
+fn send_verification_email(user_id: Uuid, email: &str, token: &str) {
+    info!("Verification email queued for user {}", user_id);
+    debug!("Verification token for {}: {}", email, token);
+}
 
-    match sqlx::query_as!(
-        User,
-        "SELECT id, username, password_hash, email, first_name, last_name, role, created_at FROM users WHERE id = $1",
-        user_id
+/// Genera un nuevo token de restablecimiento de contraseña, invalidando los anteriores.
+async fn issue_password_reset_token(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    minutes: i32,
+) -> Result<String, sqlx::Error> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+    sqlx::query!("DELETE FROM password_reset_tokens WHERE user_id = $1", user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(mins => $3))
+        "#,
+        hash_token(&token),
+        user_id,
+        minutes
     )
-    .fetch_one(&state.db_pool)
-    .await {
-        Ok(user) => HttpResponse::Ok().json(user),
-        Err(_) => HttpResponse::NotFound().body("User not found"),
-    }
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(token)
 }
 
-// --- Función Principal ---
+/// Envía el token de restablecimiento al email del usuario (por ahora, solo al log; ver `send_verification_email`).
+fn send_password_reset_email(user_id: Uuid, email: &str, token: &str) {
+    info!("Password reset email queued for user {}", user_id);
+    debug!("Password reset token for {}: {}", email, token);
+}
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    // Carga las variables de entorno desde un archivo .env si existe.
-    // Y configura el logger.
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    dotenvy::dotenv().ok();
+/// Restricciones de unicidad del email de los usuarios (la exacta y la que no distingue
+/// mayúsculas), para distinguir su violación de la del nombre de usuario.
+const USERS_EMAIL_CONSTRAINTS: &[&str] = &["users_email_key", "idx_users_email_lower"];
 
-    // Lee la URL de la base de datos desde las variables de entorno.
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+/// Indica si el error es una violación de la unicidad del email.
+fn is_email_taken(db_err: &dyn sqlx::error::DatabaseError) -> bool {
+    db_err.constraint().is_some_and(|constraint| USERS_EMAIL_CONSTRAINTS.contains(&constraint))
+}
 
-    // Crea el pool de conexiones a la base de datos.
-    // Este pool se compartirá de forma segura entre todos los hilos del servidor.
-    let db_pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to create database pool.");
+/// Respuesta `409` cuando el email ya pertenece a otra cuenta.
+fn email_taken_response() -> HttpResponse {
+    HttpResponse::Conflict()
+        .json(ApiError::new("email_taken", "The email is already used by another account").with_field("email"))
+}
 
-    info!("🚀 Servidor de autenticación iniciado en http://127.0.0.1:8081");
+/// Hashea la contraseña, crea el usuario con su token de verificación de email en una
+/// transacción y devuelve la respuesta HTTP. Lo comparten `register` y `create_user`.
+async fn create_user_account(
+    state: &AppState,
+    user_data: &NewUser,
+    role: UserRole,
+    must_reset_password: bool,
+) -> HttpResponse {
+    // El login distingue el email del nombre de usuario por la `@`.
+    let username = user_data.username.trim();
+    if username.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "username must not be empty").with_field("username"));
+    }
+    if username.contains('@') {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_username", "username must not contain '@'").with_field("username"));
+    }
 
-    HttpServer::new(move || {
-        App::new()
-            // Middleware de CORS: permite peticiones desde cualquier origen.
-            // ¡IMPORTANTE! En producción, esto debería restringirse a dominios específicos.
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header(),
-            )
-            .wrap(actix_web::middleware::Logger::default())
-            // Comparte el estado (el pool de BD) con todos los handlers.
-            .app_data(web::Data::new(AppState {
-                db_pool: db_pool.clone(),
-            }))
-            // Define la ruta y el método para el endpoint de registro.
-            .route("/register", web::post().to(register))
-            // Define la ruta para el endpoint de login.
-            .route("/login", web::post().to(login))
-            // Define una ruta protegida.
-            .route("/me", web::get().to(get_me))
+    // Un email vacío equivale a no indicarlo.
+    let email = user_data.email.as_deref().map(str::trim).filter(|email| !email.is_empty());
+
+    // Extraemos la contraseña antes de moverla a un hilo bloqueante.
+    let password = user_data.password.clone();
+
+    // Hashear la contraseña del usuario. Es un proceso que consume CPU,
+    // por lo que lo ejecutamos en un hilo bloqueante para no detener el event loop.
+    let password_hash = match web::block(move || hash(&password, DEFAULT_COST)).await {
+        Ok(Ok(hash)) => hash,
+        _ => return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error hashing password")),
+    };
+
+    // El usuario y su token de verificación de email se crean en la misma transacción.
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create user"));
+        }
+    };
+
+    // Insertar el nuevo usuario en la base de datos.
+    // Usamos `query_as` para que sqlx mapee automáticamente el resultado a nuestra struct `User`.
+    let new_user: Result<User, sqlx::Error> = sqlx::query_as!(
+        User,
+        r#"
+        INSERT INTO users (username, display_username, password_hash, email, first_name, last_name, role, must_reset_password) 
+        VALUES (LOWER($1), $1, $2, $3, $4, $5, $6, $7) 
+        RETURNING id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+        "#,
+        username,
+        password_hash,
+        email,
+        user_data.first_name,
+        user_data.last_name,
+        role as UserRole,
+        must_reset_password
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let user = match new_user {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(db_err)) if is_email_taken(db_err.as_ref()) => {
+            return email_taken_response();
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("username_taken", "Username already exists").with_field("username"));
+        }
+        Err(e) => {
+            error!("Failed to create user: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create user"));
+        }
+    };
+
+    // Sin email no hay nada que verificar: el token se emite cuando el usuario lo indique.
+    let token = match &user.email {
+        Some(_) => match issue_email_verification_token(&mut tx, user.id).await {
+            Ok(token) => Some(token),
+            Err(e) => {
+                error!("Failed to issue email verification token: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to create user"));
+            }
+        },
+        None => None,
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            state.user_counts.invalidate();
+            if let (Some(email), Some(token)) = (&user.email, &token) {
+                send_verification_email(user.id, email, token);
+            }
+            HttpResponse::Created().json(user)
+        }
+        Err(e) => {
+            error!("Failed to commit user creation: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create user"))
+        }
+    }
+}
+
+// --- Borrado Diferido de Cuentas ---
+
+/// Borra las cuentas cuyo periodo de gracia ha terminado. Las que aún tienen cursos se saltan
+/// (los cursos no se borran en cascada) y se vuelven a intentar en la siguiente pasada.
+async fn delete_expired_accounts(db_pool: &PgPool) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        DELETE FROM users u
+        WHERE u.deletion_scheduled_at <= NOW()
+          AND NOT EXISTS (SELECT 1 FROM courses c WHERE c.instructor_id = u.id)
+        RETURNING u.id
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Ejecuta `delete_expired_accounts` cada `ACCOUNT_DELETION_SWEEP_INTERVAL`.
+async fn run_account_deletion_sweeper(db_pool: PgPool, user_counts: Arc<CountCache>) {
+    let mut interval = tokio::time::interval(ACCOUNT_DELETION_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match delete_expired_accounts(&db_pool).await {
+            Ok(deleted) if deleted.is_empty() => {}
+            Ok(deleted) => {
+                for user_id in &deleted {
+                    info!("Account {} deleted after its grace period", user_id);
+                }
+                user_counts.invalidate();
+            }
+            Err(e) => error!("Failed to delete expired accounts: {:?}", e),
+        }
+    }
+}
+
+// --- Manejadores de Endpoints (Handlers) ---
+
+/// Maneja las peticiones POST a /register
+async fn register(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    client_ip: ClientIp,
+    user_data: web::Json<RegisterUser>,
+) -> impl Responder {
+    let is_admin = auth_user.as_ref().is_some_and(|user| user.role == UserRole::Admin);
+
+    // Con el captcha activado, los registros anónimos deben traer un token válido.
+    if let Some(captcha) = state.captcha.as_ref().filter(|_| !is_admin) {
+        let Some(token) = user_data.captcha_token.as_deref().map(str::trim).filter(|token| !token.is_empty()) else {
+            return HttpResponse::BadRequest().json(
+                ApiError::new("captcha_required", "Solve the captcha and send its token in captcha_token")
+                    .with_field("captcha_token"),
+            );
+        };
+        match captcha.verify(token, client_ip.0).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::BadRequest().json(
+                    ApiError::new("captcha_invalid", "The captcha token is invalid or has expired")
+                        .with_field("captcha_token"),
+                );
+            }
+            Err(e) => {
+                error!("Failed to verify captcha: {:?}", e);
+                return HttpResponse::ServiceUnavailable().json(ApiError::new(
+                    "captcha_unavailable",
+                    "The captcha could not be verified; try again later",
+                ));
+            }
+        }
+    }
+
+    // Un rol enviado por el cliente solo se respeta con un token de admin; en otro caso se ignora.
+    let role = match &user_data.role {
+        Some(role) if is_admin => role.clone(),
+        _ => state.default_user_role.clone(),
+    };
+
+    create_user_account(&state, &user_data.user, role, false).await
+}
+
+/// Maneja las peticiones POST a /users (solo administradores)
+/// Crea una cuenta con el rol indicado, opcionalmente obligando a cambiar la contraseña en el primer login.
+async fn create_user(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    user_data: web::Json<AdminCreateUser>,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can create users"));
+    }
+
+    create_user_account(&state, &user_data.user, user_data.role.clone(), user_data.require_password_reset).await
+}
+
+/// Maneja las peticiones POST a /users/{id}/reassign-courses (solo administradores)
+/// Transfiere todos los cursos del usuario (también los borrados) al instructor indicado, en una
+/// transacción. Así se puede borrar la cuenta de un instructor, que no puede tener cursos.
+async fn reassign_user_courses(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    reassign_data: web::Json<ReassignCourses>,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can reassign courses"));
+    }
+    let from_user_id = path.into_inner();
+    let to_user_id = reassign_data.instructor_id;
+    if from_user_id == to_user_id {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_target_instructor", "The target instructor must be a different user"));
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reassign courses"));
+        }
+    };
+
+    match sqlx::query_scalar!(r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1) as "exists!""#, from_user_id)
+        .fetch_one(&mut *tx)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) => {
+            error!("Failed to fetch user: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reassign courses"));
+        }
+    }
+
+    // 1. El destino debe ser un instructor o admin activo. Lo bloqueamos para que no cambie de
+    //    rol ni programe su borrado mientras recibe los cursos.
+    let target = sqlx::query!(
+        r#"
+        SELECT role as "role: UserRole", deletion_scheduled_at
+        FROM users
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        to_user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    match target {
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(
+                ApiError::new("invalid_reference", "The referenced instructor_id does not exist").with_field("instructor_id"),
+            );
+        }
+        Ok(Some(target)) if !matches!(target.role, UserRole::Instructor | UserRole::Admin) => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("invalid_target_instructor", "The target user must be an instructor or an admin"));
+        }
+        Ok(Some(target)) if target.deletion_scheduled_at.is_some() => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("user_pending_deletion", "The target user is pending deletion"));
+        }
+        Ok(Some(_)) => {}
+        Err(e) => {
+            error!("Failed to fetch target instructor: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reassign courses"));
+        }
+    }
+
+    // 2. Transferir todos los cursos de una vez.
+    let course_ids = sqlx::query_scalar!(
+        r#"
+        UPDATE courses SET instructor_id = $2, version = version + 1, updated_at = NOW()
+        WHERE instructor_id = $1
+        RETURNING id
+        "#,
+        from_user_id,
+        to_user_id
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let course_ids = match course_ids {
+        Ok(course_ids) => course_ids,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to reassign courses: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reassign courses"));
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit course reassignment: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reassign courses"));
+    }
+
+    info!(
+        "{} course(s) reassigned from {} to {} by {}",
+        course_ids.len(),
+        from_user_id,
+        to_user_id,
+        auth_user.id
+    );
+    HttpResponse::Ok().json(ReassignedCourses {
+        from_user_id,
+        to_user_id,
+        course_ids,
     })
-    .bind(("0.0.0.0", 8080))? // Escucha en todas las interfaces dentro del contenedor.
-    .run()
+}
+
+/// Maneja las peticiones POST a /admin/impersonate/{user_id} (solo administradores)
+/// Emite un token de corta duración con el id y el rol del usuario, para reproducir lo que ve.
+/// El token lleva el claim `act_as` con el admin, y el inicio de la suplantación se guarda en el
+/// registro de auditoría. Los permisos del token son los de la sesión del admin.
+async fn impersonate_user(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    client_ip: ClientIp,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can impersonate users"));
+    }
+    let user_id = path.into_inner();
+    if user_id == auth_user.id {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("cannot_impersonate_self", "You cannot impersonate yourself"));
+    }
+
+    let user = sqlx::query!(
+        r#"SELECT role as "role: UserRole", deletion_scheduled_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    let role = match user {
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        // Suplantar a otro admin no permite reproducir nada que el admin no vea ya, y le daría
+        // sus mismos permisos en nombre de otro.
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("cannot_impersonate_admin", "Admins cannot be impersonated"));
+        }
+        Ok(Some(user)) if user.deletion_scheduled_at.is_some() => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("user_pending_deletion", "The user is pending deletion"));
+        }
+        Ok(Some(user)) => user.role,
+        Err(e) => {
+            error!("Failed to fetch user: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to impersonate user"));
+        }
+    };
+
+    let expires_at = Utc::now() + Duration::minutes(state.impersonation_token_minutes);
+    let claims = Claims {
+        sub: user_id.to_string(),
+        role,
+        exp: expires_at.timestamp() as usize,
+        password_change_required: false,
+        scopes: auth_user.scopes.clone(),
+        act_as: Some(auth_user.id.to_string()),
+    };
+    let token = match state.jwt_keys.encode(&claims) {
+        Ok(token) => token,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create token"))
+        }
+    };
+
+    // Sin la entrada de auditoría no se entrega el token.
+    let entry = AuditEntry {
+        actor_id: auth_user.id,
+        action: audit::IMPERSONATION_STARTED,
+        target_type: audit::TARGET_USER,
+        target_id: Some(user_id),
+        details: serde_json::json!({ "expires_at": timestamp::format(&expires_at) }),
+        ip: client_ip.0,
+    };
+    if let Err(e) = audit::record(&state.db_pool, &entry).await {
+        error!("Failed to record impersonation in the audit log: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to impersonate user"));
+    }
+
+    info!("Admin {} started impersonating user {}", auth_user.id, user_id);
+    HttpResponse::Ok().json(ImpersonationToken {
+        token,
+        expires_at,
+        user_id,
+        act_as: auth_user.id,
+    })
+}
+
+/// Maneja las peticiones POST a /login
+async fn login(
+    state: web::Data<AppState>,
+    query: web::Query<LoginQuery>,
+    client_ip: ClientIp,
+    user_data: web::Json<LoginUser>,
+) -> impl Responder {
+    // Una sesión limitada solo puede pedir permisos conocidos.
+    let mut scopes = user_data.scopes.clone();
+    if let Some(scopes) = scopes.as_mut() {
+        if let Some(unknown) = scopes.iter().find(|s| !scope::KNOWN.contains(&s.as_str())) {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("unknown_scope", format!("Unknown scope: '{}'", unknown)).with_field("scopes"));
+        }
+        scopes.sort();
+        scopes.dedup();
+    }
+
+    // Tras varios fallos seguidos, los intentos se rechazan sin comprobar la contraseña.
+    let throttle_key = login_throttle_key(client_ip, &user_data.identifier);
+    if let Some(retry_after) = state.login_throttle.check(&throttle_key) {
+        return login_throttled_response(retry_after);
+    }
+
+    // 1. Buscar al usuario por su nombre de usuario o su email (sin distinguir mayúsculas).
+    // El usuario puede no existir; la respuesta es la misma que con una contraseña incorrecta.
+    let user = match find_user_by_identifier(&state.db_pool, &user_data.identifier).await {
+        Ok(Some(user)) => user, // Si se encuentra, `user` es de tipo `User`
+        Ok(None) => return login_failed_response(&state, &throttle_key),
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Something went wrong"))
+        }
+    };
+
+    // 2. Verificar que la contraseña proporcionada coincide con el hash almacenado.
+    let is_password_valid = match verify(&user_data.password, &user.password_hash) {
+        Ok(valid) => valid,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error verifying password"))
+        }
+    };
+
+    if !is_password_valid {
+        return login_failed_response(&state, &throttle_key);
+    }
+    state.login_throttle.record_success(&throttle_key);
+
+    // Una cuenta con el borrado programado está desactivada hasta que se cancele el borrado.
+    if user.deletion_scheduled_at.is_some() {
+        return HttpResponse::Forbidden().json(ApiError::new(
+            "account_pending_deletion",
+            "This account is scheduled for deletion; cancel the deletion to sign in again",
+        ));
+    }
+
+    // 3. Generar el JWT. Si el usuario debe cambiar su contraseña, el token queda restringido
+    //    a `PUT /me/password` hasta que lo haga; ese token restringido nunca es de sesión larga.
+    let remember_me = user_data.remember_me && !user.must_reset_password;
+    let token = match issue_token(&state, &user, user.must_reset_password, scopes, remember_me) {
+        Ok(t) => t,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create token"))
+        }
+    };
+
+    // 4. Devolver el token al cliente, junto con el usuario si se ha solicitado.
+    let password_reset_required = user.must_reset_password;
+    let user = query.include_user.then_some(user);
+    HttpResponse::Ok().json(TokenResponse { token, password_reset_required, user })
+}
+
+/// Maneja las peticiones GET a /me/permissions
+/// Devuelve lo que puede hacer el usuario autenticado, para que las interfaces muestren u oculten
+/// acciones sin duplicar las reglas de roles y permisos.
+async fn get_my_permissions(auth_user: AuthenticatedUser) -> impl Responder {
+    HttpResponse::Ok().json(PermissionsResponse {
+        capabilities: permissions::capabilities(&auth_user),
+        role: auth_user.role,
+        scopes: auth_user.scopes,
+    })
+}
+
+/// Endpoint protegido que devuelve los datos del usuario autenticado.
+async fn get_me(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser, // El middleware se ejecuta aquí. Si falla, este handler nunca se llama.
+) -> impl Responder {
+    // El ID del usuario viene del token validado por el middleware.
+    let user_id = auth_user.id;
+
+    match sqlx::query_as!(
+        User,
+        r#"SELECT id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.db_pool)
+    .await {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(_) => HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+    }
+}
+
+/// Maneja las peticiones PUT a /me/profile
+/// Los campos omitidos conservan su valor; un texto vacío en `bio` o `avatar_url` los borra.
+async fn update_my_profile(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    profile_data: web::Json<UpdateProfile>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    let updated_user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users SET
+            first_name = COALESCE($1, first_name),
+            last_name = COALESCE($2, last_name),
+            bio = NULLIF(COALESCE($3, bio), ''),
+            avatar_url = NULLIF(COALESCE($4, avatar_url), '')
+        WHERE id = $5
+        RETURNING id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+        "#,
+        profile_data.first_name,
+        profile_data.last_name,
+        profile_data.bio,
+        profile_data.avatar_url,
+        auth_user.id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match updated_user {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Ok(None) => HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) => {
+            error!("Failed to update profile: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update profile"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /me/email
+/// Indica el email de una cuenta creada sin él, o lo corrige mientras no esté verificado, y envía
+/// un token de verificación nuevo (los anteriores dejan de valer). Un email ya verificado no se
+/// puede cambiar por aquí.
+async fn set_my_email(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    email_data: web::Json<SetEmail>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    let email = email_data.email.trim();
+    if email.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "email must not be empty").with_field("email"));
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to set email"));
+        }
+    };
+
+    // Bloqueamos la fila para que una verificación simultánea no quede asociada al email anterior.
+    let verified = sqlx::query_scalar!(
+        r#"SELECT email_verified_at IS NOT NULL as "verified!" FROM users WHERE id = $1 FOR UPDATE"#,
+        auth_user.id
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    match verified {
+        Ok(Some(false)) => {}
+        Ok(Some(true)) => {
+            return HttpResponse::Conflict().json(ApiError::new(
+                "email_already_verified",
+                "The account email is already verified and cannot be changed",
+            ));
+        }
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) => {
+            error!("Failed to fetch user for email change: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to set email"));
+        }
+    }
+
+    let user = match sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users SET email = $2, email_verified_at = NULL
+        WHERE id = $1
+        RETURNING id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+        "#,
+        auth_user.id,
+        email
+    )
+    .fetch_one(&mut *tx)
     .await
+    {
+        Ok(user) => user,
+        Err(sqlx::Error::Database(db_err)) if is_email_taken(db_err.as_ref()) => {
+            return email_taken_response();
+        }
+        Err(e) => {
+            error!("Failed to set email: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to set email"));
+        }
+    };
+
+    let token = match issue_email_verification_token(&mut tx, user.id).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to issue email verification token: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to set email"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            send_verification_email(user.id, email, &token);
+            HttpResponse::Ok().json(user)
+        }
+        Err(e) => {
+            error!("Failed to commit email change: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to set email"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /verify-email
+/// Consume el token (es de un solo uso) y marca el email del usuario como verificado.
+async fn verify_email(
+    state: web::Data<AppState>,
+    verify_data: web::Json<VerifyEmail>,
+) -> impl Responder {
+    // El token se borra aunque haya caducado; solo se verifica el email si seguía vigente.
+    let verified = sqlx::query_scalar!(
+        r#"
+        WITH consumed AS (
+            DELETE FROM email_verification_tokens WHERE token_hash = $1
+            RETURNING user_id, expires_at
+        )
+        UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW())
+        FROM consumed
+        WHERE users.id = consumed.user_id AND consumed.expires_at > NOW()
+        RETURNING users.id
+        "#,
+        hash_token(&verify_data.token)
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match verified {
+        Ok(Some(_)) => HttpResponse::Ok().body("Email verified"),
+        Ok(None) => {
+            HttpResponse::BadRequest().json(
+                ApiError::new("invalid_verification_token", "Invalid or expired verification token")
+                    .with_field("token"),
+            )
+        }
+        Err(e) => {
+            error!("Failed to verify email: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to verify email"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /verify-email/resend
+/// Identifica la cuenta por la sesión o, sin sesión, por el `email` del cuerpo. Siempre responde
+/// 200 para no revelar qué emails existen; solo se envía un token nuevo a cuentas sin verificar
+/// y como mucho uno por minuto.
+async fn resend_verification_email(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    resend_data: Option<web::Json<ResendVerification>>,
+) -> impl Responder {
+    let accepted = HttpResponse::Ok().body("If the account exists and is not verified, a new verification email has been sent");
+
+    let user_id = auth_user.map(|user| user.id);
+    let email = resend_data.map(|data| data.into_inner().email);
+    if user_id.is_none() && email.is_none() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("email_required", "Provide an email or an authenticated session").with_field("email"));
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to resend verification email"));
+        }
+    };
+
+    // Bloqueamos la fila del usuario para que dos reenvíos simultáneos no se salten la espera mínima.
+    let candidate = sqlx::query!(
+        r#"
+        SELECT
+            u.id,
+            u.email as "email!",
+            u.email_verified_at IS NOT NULL as "verified!",
+            EXISTS(
+                SELECT 1 FROM email_verification_tokens t
+                WHERE t.user_id = u.id AND t.created_at > NOW() - make_interval(secs => $3)
+            ) as "recently_sent!"
+        FROM users u
+        WHERE (u.id = $1 OR ($1 IS NULL AND LOWER(u.email) = LOWER($2))) AND u.email IS NOT NULL
+        FOR UPDATE OF u
+        "#,
+        user_id,
+        email,
+        VERIFICATION_RESEND_COOLDOWN_SECONDS
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let user = match candidate {
+        Ok(Some(user)) if !user.verified && !user.recently_sent => user,
+        Ok(_) => return accepted,
+        Err(e) => {
+            error!("Failed to look up user for verification resend: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to resend verification email"));
+        }
+    };
+
+    let token = match issue_email_verification_token(&mut tx, user.id).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to issue email verification token: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to resend verification email"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            send_verification_email(user.id, &user.email, &token);
+            accepted
+        }
+        Err(e) => {
+            error!("Failed to commit verification resend: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to resend verification email"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /password-reset
+/// Envía un token de restablecimiento al email indicado. Siempre responde 200 para no revelar
+/// qué emails existen.
+async fn request_password_reset(
+    state: web::Data<AppState>,
+    reset_data: web::Json<RequestPasswordReset>,
+) -> impl Responder {
+    let accepted = HttpResponse::Ok().body("If the account exists, a password reset email has been sent");
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to request password reset"));
+        }
+    };
+
+    let user = match sqlx::query!(r#"SELECT id, email as "email!" FROM users WHERE LOWER(email) = LOWER($1)"#, reset_data.email.trim())
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return accepted,
+        Err(e) => {
+            error!("Failed to look up user for password reset: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to request password reset"));
+        }
+    };
+
+    let token = match issue_password_reset_token(&mut tx, user.id, state.reset_token_minutes).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to issue password reset token: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to request password reset"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            send_password_reset_email(user.id, &user.email, &token);
+            accepted
+        }
+        Err(e) => {
+            error!("Failed to commit password reset request: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to request password reset"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /password-reset/confirm
+/// Fija la nueva contraseña si el token sigue vigente. El token se consume en la misma transacción
+/// que actualiza la contraseña: con dos envíos simultáneos del mismo token, el segundo espera al
+/// bloqueo de la fila y ya no la encuentra, así que solo uno tiene éxito.
+async fn confirm_password_reset(
+    state: web::Data<AppState>,
+    reset_data: web::Json<ConfirmPasswordReset>,
+) -> impl Responder {
+    let reset_data = reset_data.into_inner();
+    if reset_data.new_password.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "new_password must not be empty").with_field("new_password"));
+    }
+
+    let password = reset_data.new_password;
+    let password_hash = match web::block(move || hash(&password, DEFAULT_COST)).await {
+        Ok(Ok(hash)) => hash,
+        _ => return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error hashing password")),
+    };
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reset password"));
+        }
+    };
+
+    // El token se borra aunque haya caducado; solo se cambia la contraseña si seguía vigente.
+    let reset = sqlx::query_scalar!(
+        r#"
+        WITH consumed AS (
+            DELETE FROM password_reset_tokens WHERE token_hash = $1
+            RETURNING user_id, expires_at
+        )
+        UPDATE users SET password_hash = $2, must_reset_password = FALSE
+        FROM consumed
+        WHERE users.id = consumed.user_id AND consumed.expires_at > NOW()
+        RETURNING users.id
+        "#,
+        hash_token(&reset_data.token),
+        password_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let user_id = match reset {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            // Confirmamos el borrado de un token caducado para que no se pueda volver a intentar.
+            if let Err(e) = tx.commit().await {
+                error!("Failed to commit expired password reset token removal: {:?}", e);
+            }
+            return HttpResponse::BadRequest().json(
+                ApiError::new("invalid_reset_token", "Invalid or expired password reset token")
+                    .with_field("token"),
+            );
+        }
+        Err(e) => {
+            error!("Failed to reset password: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reset password"));
+        }
+    };
+
+    // Cualquier otro token pendiente del usuario deja de ser válido.
+    if let Err(e) = sqlx::query!("DELETE FROM password_reset_tokens WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await
+    {
+        error!("Failed to revoke password reset tokens: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reset password"));
+    }
+
+    match tx.commit().await {
+        Ok(_) => HttpResponse::Ok().body("Password updated"),
+        Err(e) => {
+            error!("Failed to commit password reset: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reset password"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /me/password
+/// Cambia la contraseña del usuario autenticado tras comprobar la actual. Es el único endpoint al
+/// que da acceso el token restringido del primer login; devuelve un token nuevo sin restricciones.
+async fn change_my_password(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    password_data: web::Json<ChangePassword>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    let password_data = password_data.into_inner();
+    if password_data.new_password.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "new_password must not be empty").with_field("new_password"));
+    }
+    if password_data.new_password == password_data.current_password {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("password_unchanged", "new_password must be different from the current password")
+                .with_field("new_password"),
+        );
+    }
+
+    let user = match sqlx::query_as!(
+        User,
+        r#"SELECT id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at FROM users WHERE id = $1"#,
+        auth_user.id
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) => {
+            error!("Failed to fetch user for password change: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to change password"));
+        }
+    };
+
+    let current_password = password_data.current_password;
+    let current_hash = user.password_hash.clone();
+    match web::block(move || verify(&current_password, &current_hash)).await {
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => {
+            return HttpResponse::Unauthorized().json(
+                ApiError::new("incorrect_password", "Current password is incorrect")
+                    .with_field("current_password"),
+            )
+        }
+        _ => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error verifying password"))
+        }
+    }
+
+    let new_password = password_data.new_password;
+    let password_hash = match web::block(move || hash(&new_password, DEFAULT_COST)).await {
+        Ok(Ok(hash)) => hash,
+        _ => return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error hashing password")),
+    };
+
+    let user = match sqlx::query_as!(
+        User,
+        r#"
+        UPDATE users SET password_hash = $2, must_reset_password = FALSE
+        WHERE id = $1
+        RETURNING id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role as "role: UserRole", created_at
+        "#,
+        auth_user.id,
+        password_hash
+    )
+    .fetch_one(&state.db_pool)
+    .await
+    {
+        Ok(user) => user,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to change password: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to change password"));
+        }
+    };
+
+    // El token nuevo conserva los permisos de la sesión con la que se cambió la contraseña.
+    match issue_token(&state, &user, false, auth_user.scopes, false) {
+        Ok(token) => HttpResponse::Ok().json(TokenResponse {
+            token,
+            password_reset_required: false,
+            user: None,
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create token")),
+    }
+}
+
+/// Maneja las peticiones DELETE a /me
+/// Programa el borrado de la cuenta tras el periodo de gracia (`ACCOUNT_DELETION_GRACE_DAYS`).
+/// Mientras tanto la cuenta está desactivada y el borrado se puede cancelar con
+/// `POST /me/cancel-deletion`. Repetir la petición no aplaza la fecha ya programada.
+async fn schedule_account_deletion(state: web::Data<AppState>, auth_user: AuthenticatedUser) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    // Los cursos no se borran en cascada con su instructor: hay que transferirlos o borrarlos antes.
+    match sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM courses WHERE instructor_id = $1) as "exists!""#,
+        auth_user.id
+    )
+    .fetch_one(&state.db_pool)
+    .await
+    {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("owns_courses", "Delete or transfer your courses before deleting your account"))
+        }
+        Err(e) => {
+            error!("Failed to check courses before account deletion: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to schedule account deletion"));
+        }
+    }
+
+    let scheduled = sqlx::query_scalar!(
+        r#"
+        UPDATE users
+        SET deletion_scheduled_at = COALESCE(deletion_scheduled_at, NOW() + make_interval(days => $2))
+        WHERE id = $1
+        RETURNING deletion_scheduled_at as "deletion_scheduled_at!"
+        "#,
+        auth_user.id,
+        state.account_deletion_grace_days
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match scheduled {
+        Ok(Some(deletion_scheduled_at)) => {
+            info!("Account {} scheduled for deletion at {}", auth_user.id, deletion_scheduled_at);
+            HttpResponse::Accepted().json(DeletionScheduled { deletion_scheduled_at })
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to schedule account deletion: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to schedule account deletion"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /me/cancel-deletion
+/// Como el login está bloqueado durante el periodo de gracia, el usuario se identifica con sus
+/// credenciales (el mismo cuerpo que `/login`). Cancela el borrado y devuelve un token como el login.
+async fn cancel_account_deletion(
+    state: web::Data<AppState>,
+    client_ip: ClientIp,
+    user_data: web::Json<LoginUser>,
+) -> impl Responder {
+    // Comparte el limitador con el login: son las mismas credenciales.
+    let throttle_key = login_throttle_key(client_ip, &user_data.identifier);
+    if let Some(retry_after) = state.login_throttle.check(&throttle_key) {
+        return login_throttled_response(retry_after);
+    }
+
+    let user = match find_user_by_identifier(&state.db_pool, &user_data.identifier).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return login_failed_response(&state, &throttle_key),
+        Err(e) => {
+            error!("Failed to fetch user to cancel deletion: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to cancel account deletion"));
+        }
+    };
+
+    let password = user_data.into_inner().password;
+    let password_hash = user.password_hash.clone();
+    match web::block(move || verify(&password, &password_hash)).await {
+        Ok(Ok(true)) => state.login_throttle.record_success(&throttle_key),
+        Ok(Ok(false)) => return login_failed_response(&state, &throttle_key),
+        _ => {
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Error verifying password"))
+        }
+    }
+
+    match sqlx::query!(
+        "UPDATE users SET deletion_scheduled_at = NULL WHERE id = $1 AND deletion_scheduled_at IS NOT NULL",
+        user.id
+    )
+    .execute(&state.db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("deletion_not_scheduled", "Account is not scheduled for deletion"))
+        }
+        Ok(_) => info!("Account deletion cancelled for user {}", user.id),
+        Err(e) => {
+            error!("Failed to cancel account deletion: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to cancel account deletion"));
+        }
+    }
+
+    match issue_token(&state, &user, user.must_reset_password, None, false) {
+        Ok(token) => HttpResponse::Ok().json(TokenResponse {
+            token,
+            password_reset_required: user.must_reset_password,
+            user: None,
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create token")),
+    }
+}
+
+/// Maneja las peticiones POST a /me/tokens
+/// Crea un token de acceso personal para scripts e integraciones. El token se devuelve una sola
+/// vez; en la base de datos solo se guarda su hash.
+async fn create_personal_access_token(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    token_data: web::Json<CreatePersonalAccessToken>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    // Un token con permisos limitados (una sesión con `scopes` u otro token de acceso personal)
+    // no puede crear un token con más permisos que los suyos, que además no caduca.
+    if auth_user.scopes.is_some() {
+        return HttpResponse::Forbidden().json(ApiError::new(
+            "insufficient_scope",
+            "Personal access tokens can only be created from a session without scope limits",
+        ));
+    }
+    let token_data = token_data.into_inner();
+    let name = token_data.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 100 {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_length", "name must be between 1 and 100 characters").with_field("name"));
+    }
+
+    let mut scopes = match token_data.scopes {
+        Some(scopes) => scopes,
+        None => pat::SCOPES.iter().map(|scope| scope.to_string()).collect(),
+    };
+    if let Some(unknown) = scopes.iter().find(|scope| !pat::SCOPES.contains(&scope.as_str())) {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("unknown_scope", format!("Unknown scope: '{}'", unknown)).with_field("scopes"));
+    }
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "scopes must not be empty").with_field("scopes"));
+    }
+
+    if token_data.expires_in_days.is_some_and(|days| days <= 0) {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("must_be_positive", "expires_in_days must be greater than 0")
+                .with_field("expires_in_days"),
+        );
+    }
+
+    let token = pat::generate_token();
+    let created = sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+        INSERT INTO personal_access_tokens (user_id, name, token_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, NOW() + make_interval(days => $5))
+        RETURNING id, name, scopes, expires_at, last_used_at, created_at
+        "#,
+        auth_user.id,
+        name,
+        pat::hash_token(&token),
+        &scopes,
+        token_data.expires_in_days
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match created {
+        Ok(info) => HttpResponse::Created().json(CreatedPersonalAccessToken { info, token }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to create personal access token: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create token"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /me/tokens
+/// Lista los tokens de acceso personal del usuario, del más reciente al más antiguo.
+async fn get_personal_access_tokens(state: web::Data<AppState>, auth_user: AuthenticatedUser) -> impl Responder {
+    let tokens = sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+        SELECT id, name, scopes, expires_at, last_used_at, created_at
+        FROM personal_access_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id
+        "#,
+        auth_user.id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match tokens {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch personal access tokens: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch tokens"))
+        }
+    }
+}
+
+/// Maneja las peticiones DELETE a /me/tokens/{id}
+/// Revoca un token de acceso personal del usuario; deja de aceptarse de inmediato.
+async fn revoke_personal_access_token(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    let result = sqlx::query!(
+        "DELETE FROM personal_access_tokens WHERE id = $1 AND user_id = $2",
+        path.into_inner(),
+        auth_user.id
+    )
+    .execute(&state.db_pool)
+    .await;
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(ApiError::new("token_not_found", "Token not found"))
+        }
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Failed to revoke personal access token: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revoke token"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /notifications
+/// Devuelve las notificaciones del usuario autenticado, de la más reciente a la más antigua.
+async fn get_my_notifications(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    pagination: PageParams,
+) -> impl Responder {
+    let notifications = sqlx::query_as!(
+        UserNotification,
+        r#"
+        SELECT id, kind, message, read_at, created_at
+        FROM notifications
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        auth_user.id,
+        pagination.limit(),
+        pagination.offset()
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match notifications {
+        Ok(notifications) => HttpResponse::Ok().json(notifications),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch notifications: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch notifications"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /notifications/read-all
+/// Marca como leídas todas las notificaciones pendientes del usuario; las ya leídas conservan su `read_at`.
+async fn mark_all_notifications_read(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    let result = sqlx::query!(
+        "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+        auth_user.id
+    )
+    .execute(&state.db_pool)
+    .await;
+
+    match result {
+        Ok(res) => HttpResponse::Ok().json(MarkedReadResponse { updated: res.rows_affected() }),
+        Err(e) => {
+            error!("Failed to mark notifications as read: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to mark notifications as read"))
+        }
+    }
+}
+
+/// Preferencias de notificación del usuario: cada tipo conocido con su estado. Los tipos que el
+/// usuario nunca ha configurado están activados.
+async fn load_notification_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<BTreeMap<&'static str, bool>, sqlx::Error> {
+    let stored = sqlx::query!(
+        "SELECT kind, enabled FROM notification_preferences WHERE user_id = $1",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(notifications::KINDS
+        .iter()
+        .map(|&kind| {
+            let enabled = stored.iter().find(|row| row.kind == kind).is_none_or(|row| row.enabled);
+            (kind, enabled)
+        })
+        .collect())
+}
+
+/// Maneja las peticiones GET a /me/notification-preferences
+async fn get_notification_preferences(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+) -> impl Responder {
+    match load_notification_preferences(&state.db_pool, auth_user.id).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(e) => {
+            error!("Failed to fetch notification preferences: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch notification preferences"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /me/notification-preferences
+/// Recibe `{ "tipo": true|false }`; los tipos omitidos conservan su estado.
+async fn update_notification_preferences(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    preferences: web::Json<BTreeMap<String, bool>>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::PROFILE_WRITE) {
+        return response;
+    }
+    if let Some(response) = auth_user.impersonation_forbidden() {
+        return response;
+    }
+    if let Some(unknown) = preferences.keys().find(|kind| !notifications::KINDS.contains(&kind.as_str())) {
+        return HttpResponse::BadRequest().json(ApiError::new(
+            "unknown_notification_kind",
+            format!("Unknown notification kind: '{}'", unknown),
+        ));
+    }
+
+    let (kinds, enabled): (Vec<String>, Vec<bool>) = preferences.into_inner().into_iter().unzip();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO notification_preferences (user_id, kind, enabled)
+        SELECT $1, kind, enabled FROM UNNEST($2::varchar[], $3::bool[]) AS p(kind, enabled)
+        ON CONFLICT (user_id, kind) DO UPDATE SET enabled = EXCLUDED.enabled, updated_at = NOW()
+        "#,
+        auth_user.id,
+        &kinds[..],
+        &enabled[..]
+    )
+    .execute(&state.db_pool)
+    .await;
+
+    if let Err(e) = result {
+        error!("Failed to update notification preferences: {:?}", e);
+        return HttpResponse::InternalServerError()
+            .json(ApiError::new("internal_error", "Failed to update notification preferences"));
+    }
+
+    match load_notification_preferences(&state.db_pool, auth_user.id).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(e) => {
+            error!("Failed to fetch notification preferences: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update notification preferences"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /users/{id}/profile
+/// Ruta pública: devuelve solo los datos del perfil público y el número de cursos publicados.
+async fn get_public_profile(
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let profile = sqlx::query_as!(
+        PublicProfile,
+        r#"
+        SELECT
+            u.id, u.username, u.display_username, u.first_name, u.last_name, u.bio, u.avatar_url,
+            u.role as "role: UserRole",
+            (SELECT COUNT(*) FROM courses c WHERE c.instructor_id = u.id AND c.status = 'published') as "published_course_count!"
+        FROM users u
+        WHERE u.id = $1 AND u.deletion_scheduled_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match profile {
+        Ok(Some(profile)) => HttpResponse::Ok().json(profile),
+        Ok(None) => HttpResponse::NotFound().json(ApiError::new("user_not_found", "User not found")),
+        Err(e) => {
+            error!("Failed to fetch public profile: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch profile"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /users/batch (uso interno, requiere `X-Api-Key`)
+/// Resuelve varios ids de usuario a sus datos públicos en una sola consulta, para los listados de
+/// otros servicios. Los ids desconocidos se omiten.
+async fn get_users_batch(
+    state: web::Data<AppState>,
+    _api_key: ApiKeyGuard,
+    batch_data: web::Json<BatchUsersRequest>,
+) -> impl Responder {
+    if batch_data.ids.len() > MAX_BATCH_USER_IDS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new(
+                "too_many_ids",
+                format!("Too many ids: at most {} are allowed per request", MAX_BATCH_USER_IDS),
+            )
+            .with_field("ids"),
+        );
+    }
+
+    let users = sqlx::query_as!(
+        UserSummary,
+        r#"
+        SELECT id, username, display_username, first_name, last_name, avatar_url
+        FROM users
+        WHERE id = ANY($1)
+        ORDER BY username
+        "#,
+        &batch_data.ids[..]
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match users {
+        Ok(users) => HttpResponse::Ok().json(users),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch users batch: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch users"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /audit-log (solo administradores)
+/// Lista el registro de auditoría, de la entrada más reciente a la más antigua, filtrable por
+/// quién hizo la acción, la acción, el tipo de objeto y un rango de fechas.
+async fn get_audit_log(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    query: web::Query<AuditLogQuery>,
+    pagination: PageParams,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can view the audit log"));
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return HttpResponse::BadRequest().json(ApiError::new("invalid_date_range", "from must be earlier than to"));
+        }
+    }
+
+    let PageParams { page, per_page } = pagination;
+
+    // 1. Contar el total de entradas que cumplen el filtro.
+    let mut count_builder = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM audit_log");
+    push_audit_log_filters(&mut count_builder, &query);
+    let total: i64 = match count_builder.build_query_scalar().fetch_one(&state.db_pool).await {
+        Ok(total) => total,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to count audit log entries: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch audit log"));
+        }
+    };
+
+    // 2. Obtener la página solicitada con el mismo filtro.
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "SELECT id, actor_id, action, target_type, target_id, details, ip, created_at FROM audit_log",
+    );
+    push_audit_log_filters(&mut builder, &query);
+    builder
+        .push(" ORDER BY created_at DESC, id LIMIT ")
+        .push_bind(per_page)
+        .push(" OFFSET ")
+        .push_bind(pagination.offset());
+
+    match builder.build_query_as::<AuditLogEntry>().fetch_all(&state.db_pool).await {
+        Ok(entries) => HttpResponse::Ok().json(AuditLogResponse { entries, total, page, per_page }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch audit log: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch audit log"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /stats/platform (solo administradores)
+/// Resume el estado de la plataforma: usuarios por rol, cursos por estado, inscripciones activas
+/// y registros recientes, con una consulta de agregados por tabla.
+async fn get_platform_stats(state: web::Data<AppState>, auth_user: AuthenticatedUser) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can view platform stats"));
+    }
+
+    let stats = async {
+        // Partimos de los valores del enum para que los roles y estados sin filas salgan con 0.
+        let users_by_role = sqlx::query!(
+            r#"
+            SELECT r.role::text as "role!", COUNT(u.id) as "count!"
+            FROM UNNEST(enum_range(NULL::user_role)) AS r(role)
+            LEFT JOIN users u ON u.role = r.role
+            GROUP BY r.role
+            "#
+        )
+        .fetch_all(&state.db_pool)
+        .await?;
+
+        let signups = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE created_at >= NOW() - INTERVAL '7 days') as "last_7_days!",
+                COUNT(*) FILTER (WHERE created_at >= NOW() - INTERVAL '30 days') as "last_30_days!"
+            FROM users
+            "#
+        )
+        .fetch_one(&state.db_pool)
+        .await?;
+
+        let courses_by_status = sqlx::query!(
+            r#"
+            SELECT s.status::text as "status!", COUNT(c.id) as "count!"
+            FROM UNNEST(enum_range(NULL::course_status)) AS s(status)
+            LEFT JOIN courses c ON c.status = s.status AND c.deleted_at IS NULL
+            GROUP BY s.status
+            "#
+        )
+        .fetch_all(&state.db_pool)
+        .await?;
+
+        let deleted_courses = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM courses WHERE deleted_at IS NOT NULL"#
+        )
+        .fetch_one(&state.db_pool)
+        .await?;
+
+        let active_enrollments = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM enrollments WHERE status = 'active'"#
+        )
+        .fetch_one(&state.db_pool)
+        .await?;
+
+        let by_role: BTreeMap<String, i64> = users_by_role.into_iter().map(|row| (row.role, row.count)).collect();
+        let by_status: BTreeMap<String, i64> =
+            courses_by_status.into_iter().map(|row| (row.status, row.count)).collect();
+        Ok::<_, sqlx::Error>(PlatformStats {
+            users: UserStats {
+                total: by_role.values().sum(),
+                by_role,
+            },
+            courses: CourseStats {
+                total: by_status.values().sum(),
+                by_status,
+                deleted: deleted_courses,
+            },
+            active_enrollments,
+            signups: SignupStats {
+                last_7_days: signups.last_7_days,
+                last_30_days: signups.last_30_days,
+            },
+        })
+    }
+    .await;
+
+    match stats {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to compute platform stats: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch platform stats"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /users (solo administradores)
+/// Admite filtrar por `role` y buscar con `q`, y devuelve el total de usuarios que cumplen el filtro.
+async fn get_users(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    query: web::Query<UsersQuery>,
+    pagination: PageParams,
+) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can list users"));
+    }
+
+    let PageParams { page, per_page } = pagination;
+
+    // 1. Contar el total de usuarios que cumplen el filtro.
+    // Con `COUNT_CACHE_TTL_SECONDS` se reutiliza el total de un mismo filtro durante ese tiempo.
+    let count_key = format!(
+        "role={:?}&q={}",
+        query.role,
+        query.q.as_deref().map(str::trim).unwrap_or_default().to_lowercase()
+    );
+    let total = state
+        .user_counts
+        .get_or_compute(count_key, || async {
+            let mut count_builder = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM users");
+            push_user_filters(&mut count_builder, &query);
+            count_builder.build_query_scalar().fetch_one(&state.db_pool).await
+        })
+        .await;
+    let total: i64 = match total {
+        Ok(total) => total,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to count users: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch users"));
+        }
+    };
+
+    // 2. Obtener la página solicitada con el mismo filtro.
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "SELECT id, username, display_username, password_hash, email, first_name, last_name, bio, avatar_url, email_verified_at, must_reset_password, deletion_scheduled_at, role, created_at FROM users",
+    );
+    push_user_filters(&mut builder, &query);
+    builder
+        .push(" ORDER BY created_at DESC LIMIT ")
+        .push_bind(per_page)
+        .push(" OFFSET ")
+        .push_bind(pagination.offset());
+
+    match builder.build_query_as::<User>().fetch_all(&state.db_pool).await {
+        Ok(users) => HttpResponse::Ok().json(UserListResponse { users, total, page, per_page }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch users: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch users"))
+        }
+    }
+}
+
+/// Tiempo que los clientes pueden guardar en caché el JWKS. Tras rotar la clave, la anterior
+/// sigue publicada (en `JWT_PREVIOUS_KEYS`), así que una copia algo desfasada sigue sirviendo.
+const JWKS_CACHE_MAX_AGE_SECONDS: u32 = 300;
+
+/// Maneja las peticiones GET a /.well-known/jwks.json
+/// Ruta pública: devuelve las claves públicas RSA con las que se verifican los JWT (la actual y,
+/// durante una rotación, las anteriores), identificadas por `kid`. Con claves HMAC está vacío.
+async fn get_jwks(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![
+            header::CacheDirective::Public,
+            header::CacheDirective::MaxAge(JWKS_CACHE_MAX_AGE_SECONDS),
+        ]))
+        .json(state.jwt_keys.jwks())
+}
+
+/// Maneja las peticiones GET a /health/detail (solo uso interno, requiere `X-Api-Key`)
+/// Devuelve la versión del servicio, la de PostgreSQL y si el esquema tiene todas las migraciones.
+async fn get_health_detail(state: web::Data<AppState>, _api_key: ApiKeyGuard) -> impl Responder {
+    health::health_detail(&state.db_pool, "auth-service", env!("CARGO_PKG_VERSION")).await
+}
+
+/// Registra las rutas del servicio. Lo usan `main` y las pruebas, para montar la misma aplicación.
+fn routes(cfg: &mut web::ServiceConfig) {
+    // Define la ruta y el método para el endpoint de registro.
+    cfg.route("/register", web::post().to(register))
+        // Define la ruta para el endpoint de login.
+        .route("/login", web::post().to(login))
+        // Define una ruta protegida.
+        .route("/me", web::get().to(get_me))
+        .route("/me", web::delete().to(schedule_account_deletion))
+        .route("/me/profile", web::put().to(update_my_profile))
+        // Email de la cuenta, si se creó sin él o aún no está verificado.
+        .route("/me/email", web::put().to(set_my_email))
+        // Acciones que puede hacer el usuario, según su rol y los permisos del token.
+        .route("/me/permissions", web::get().to(get_my_permissions))
+        // Tokens de acceso personal del usuario autenticado.
+        .route("/me/tokens", web::get().to(get_personal_access_tokens))
+        .route("/me/tokens", web::post().to(create_personal_access_token))
+        .route("/me/tokens/{id}", web::delete().to(revoke_personal_access_token))
+        // Cancela el borrado programado de la cuenta (con las credenciales, ya que el login está bloqueado).
+        .route("/me/cancel-deletion", web::post().to(cancel_account_deletion))
+        // Cambio de contraseña; también accesible con el token restringido del primer login.
+        .route(ccb_common::PASSWORD_CHANGE_PATH, web::put().to(change_my_password))
+        // Verificación del email y reenvío del token.
+        .route("/verify-email", web::post().to(verify_email))
+        .route("/verify-email/resend", web::post().to(resend_verification_email))
+        // Restablecimiento de contraseña con un token de un solo uso.
+        .route("/password-reset", web::post().to(request_password_reset))
+        .route("/password-reset/confirm", web::post().to(confirm_password_reset))
+        // Listado y alta de usuarios y traspaso de sus cursos (solo administradores).
+        .route("/users", web::get().to(get_users))
+        .route("/users", web::post().to(create_user))
+        // Registro de auditoría de las acciones sensibles (solo admins).
+        .route("/audit-log", web::get().to(get_audit_log))
+        // Resumen de la plataforma para los admins.
+        .route("/stats/platform", web::get().to(get_platform_stats))
+        // Resuelve varios usuarios a sus datos públicos (uso interno, requiere `X-Api-Key`).
+        .route("/users/batch", web::post().to(get_users_batch))
+        .route("/users/{id}/reassign-courses", web::post().to(reassign_user_courses))
+        .route("/admin/impersonate/{user_id}", web::post().to(impersonate_user))
+        // Notificaciones del usuario autenticado.
+        .route("/notifications", web::get().to(get_my_notifications))
+        .route("/notifications/read-all", web::post().to(mark_all_notifications_read))
+        .route("/me/notification-preferences", web::get().to(get_notification_preferences))
+        .route("/me/notification-preferences", web::put().to(update_notification_preferences))
+        // Claves públicas para verificar los JWT (no requiere autenticación).
+        .route("/.well-known/jwks.json", web::get().to(get_jwks))
+        // Perfil público de un usuario (no requiere autenticación).
+        .route("/users/{id}/profile", web::get().to(get_public_profile))
+        // Activa o desactiva el modo mantenimiento (solo administradores).
+        .route(maintenance::MAINTENANCE_PATH, web::put().to(maintenance::set_maintenance))
+        // Estado detallado para operaciones (requiere `X-Api-Key`).
+        .route("/health/detail", web::get().to(get_health_detail));
+}
+
+// --- Función Principal ---
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // Carga las variables de entorno desde un archivo .env si existe.
+    // Y configura el logger (texto o JSON según `LOG_FORMAT`).
+    dotenvy::dotenv().ok();
+    telemetry::init_logging();
+
+    // Carga y valida toda la configuración de una vez; si falta algo, se informa de todos los problemas.
+    let config = Config::from_env("AUTH").unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
+    // Este servicio emite los tokens: con RS256 necesita también la clave privada.
+    if !config.jwt_keys.can_sign() {
+        error!("JWT_PRIVATE_KEY_FILE must be set in auth-service to sign tokens with RS256");
+        std::process::exit(1);
+    }
+
+    // Crea el pool de conexiones a la base de datos.
+    // Este pool se compartirá de forma segura entre todos los hilos del servidor.
+    let db_pool = db::connect(&config)
+        .await
+        .expect("Failed to create database pool.");
+
+    // Espera exponencial tras fallos de login consecutivos (por IP e identificador).
+    let login_throttle = Arc::new(LoginThrottle::new(config.login_throttle));
+
+    // Captcha opcional en el registro, contra la API de Turnstile (por defecto) o de hCaptcha.
+    let captcha = config.captcha.clone().map(|settings| CaptchaVerifier {
+        client: reqwest::Client::builder()
+            .timeout(CAPTCHA_VERIFY_TIMEOUT)
+            .build()
+            .expect("Failed to create HTTP client."),
+        verify_url: settings.verify_url,
+        secret: settings.secret,
+    });
+
+    let user_counts = Arc::new(CountCache::new(config.count_cache_ttl));
+
+    // Tarea en segundo plano que borra las cuentas cuyo periodo de gracia ha terminado.
+    actix_web::rt::spawn(run_account_deletion_sweeper(db_pool.clone(), user_counts.clone()));
+
+    info!("🚀 Servidor de autenticación iniciado en el puerto {}", config.port);
+    let port = config.port;
+    let workers = config.http_workers;
+    let keep_alive = config.keep_alive();
+    info!("HTTP workers: {}, keep-alive: {:?}", workers, config.http_keep_alive);
+    // El modo mantenimiento se comparte entre todos los workers y se puede cambiar en caliente.
+    let maintenance_flag = MaintenanceFlag::new(config.maintenance_mode);
+    // Los cubos del límite de escrituras también se comparten entre los workers.
+    let write_limiter = config.write_rate_limit.map(RateLimiter::new);
+
+    HttpServer::new(move || {
+        App::new()
+            // Limita las escrituras por usuario (o por IP) con `WRITE_RATE_LIMIT_*`; responde 429.
+            .wrap(WriteRateLimit::new(write_limiter.clone()))
+            // Con el modo mantenimiento activo, rechaza las escrituras con 503 y deja pasar las lecturas.
+            .wrap(MaintenanceMode::new(maintenance_flag.clone()))
+            // Traduce los mensajes de `ApiError` según `Accept-Language` (por defecto, inglés).
+            .wrap(LocalizedErrors)
+            // Middleware de CORS con la política de `AUTH_CORS_*` (o `CORS_*`); sin configurar permite cualquier origen.
+            // ¡IMPORTANTE! En producción, esto debería restringirse a dominios específicos.
+            .wrap(config.cors())
+            // Cabeceras de seguridad (`nosniff`, `X-Frame-Options`, HSTS y CSP) en todas las respuestas.
+            .wrap(config.security_headers.clone())
+            // Logs de acceso estructurados (método, ruta, estado, latencia, request_id y usuario).
+            .wrap(AccessLog)
+            // Claves para verificar los JWT de `AuthenticatedUser` (admite varias durante una rotación).
+            .app_data(web::Data::new(config.jwt_keys.clone()))
+            // Pool con el que `AuthenticatedUser` valida los tokens de acceso personal.
+            .app_data(web::Data::new(db_pool.clone()))
+            // Errores claros (415/400) para cuerpos JSON con otro `Content-Type`, vacíos o inválidos.
+            .app_data(ccb_common::error::json_config())
+            // Máximo de `per_page` de los listados paginados (`PageParams`).
+            .app_data(web::Data::new(config.pagination))
+            // API key de los endpoints internos como `/health/detail` (si está configurada).
+            .app_data(web::Data::new(config.internal_api_key.clone()))
+            // Si hay un proxy de confianza delante, la IP del cliente se toma de sus cabeceras.
+            .app_data(web::Data::new(config.trusted_proxy))
+            .app_data(web::Data::new(maintenance_flag.clone()))
+            // Comparte el estado (el pool de BD) con todos los handlers.
+            .app_data(web::Data::new(AppState {
+                db_pool: db_pool.clone(),
+                jwt_keys: config.jwt_keys.clone(),
+                jwt_expiration_hours: config.jwt_expiration_hours,
+                jwt_remember_hours: config.jwt_remember_hours,
+                default_user_role: config.default_user_role.clone(),
+                user_counts: user_counts.clone(),
+                reset_token_minutes: config.reset_token_minutes,
+                account_deletion_grace_days: config.account_deletion_grace_days,
+                captcha: captcha.clone(),
+                impersonation_token_minutes: config.impersonation_token_minutes,
+                login_throttle: login_throttle.clone(),
+            }))
+            // Rutas del servicio.
+            .configure(routes)
+    })
+    .workers(workers)
+    .keep_alive(keep_alive)
+    .bind(("0.0.0.0", port))? // Escucha en todas las interfaces dentro del contenedor.
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use ccb_common::{api_key::InternalApiKey, throttle::BackoffPolicy};
+    use sqlx::postgres::PgPoolOptions;
+
+    /// Contraseña de todos los usuarios de los tests.
+    const PASSWORD: &str = "correct-password";
+
+    /// API key de los endpoints internos.
+    const API_KEY: &str = "internal-key";
+
+    /// Monta la aplicación con las rutas del servicio, la traducción de errores y el estado indicado
+    /// (`web::Data<AppState>`).
+    macro_rules! app {
+        ($state:expr) => {
+            init_service(
+                App::new()
+                    .wrap(LocalizedErrors)
+                    .app_data(web::Data::new($state.jwt_keys.clone()))
+                    .app_data(web::Data::new($state.db_pool.clone()))
+                    .app_data(ccb_common::error::json_config())
+                    .app_data(web::Data::new(InternalApiKey(Some(API_KEY.to_string()))))
+                    .app_data($state.clone())
+                    .configure(routes),
+            )
+            .await
+        };
+    }
+
+    /// Estado del servicio contra la base de datos de los tests (`DATABASE_URL`), con claves HMAC,
+    /// sesiones de 24 horas (720 con `remember_me`) y sin captcha. Los tests lo envuelven en
+    /// `web::Data`, tras cambiar los campos que necesiten.
+    async fn state() -> AppState {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point to the test database");
+        AppState {
+            db_pool: PgPoolOptions::new().max_connections(4).connect(&url).await.unwrap(),
+            jwt_keys: JwtKeySet::new("current".to_string(), "test-secret".to_string(), Vec::new()),
+            jwt_expiration_hours: 24,
+            jwt_remember_hours: 720,
+            default_user_role: UserRole::Student,
+            user_counts: Arc::new(CountCache::new(None)),
+            reset_token_minutes: 30,
+            account_deletion_grace_days: 30,
+            captcha: None,
+            impersonation_token_minutes: 15,
+            login_throttle: Arc::new(LoginThrottle::new(BackoffPolicy::default())),
+        }
+    }
+
+    /// Crea un usuario con un nombre único, el rol indicado y la contraseña `PASSWORD`, y devuelve
+    /// su id y su nombre de usuario.
+    async fn insert_user(db_pool: &PgPool, role: &str) -> (Uuid, String) {
+        let username = format!("test_{}", Uuid::new_v4().simple());
+        // El coste mínimo de bcrypt basta para los tests y los hace mucho más rápidos.
+        let password_hash = hash(PASSWORD, 4).unwrap();
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO users (username, display_username, password_hash, first_name, last_name, role)
+            VALUES ($1, $1, $2, 'Test', 'User', $3::user_role)
+            RETURNING id
+            "#,
+        )
+        .bind(&username)
+        .bind(password_hash)
+        .bind(role)
+        .fetch_one(db_pool)
+        .await
+        .unwrap();
+        (id, username)
+    }
+
+    /// Valor de la cabecera `Authorization` con un token de sesión del usuario.
+    fn bearer(state: &AppState, id: Uuid, role: UserRole) -> String {
+        let claims = Claims {
+            sub: id.to_string(),
+            role,
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            password_change_required: false,
+            scopes: None,
+            act_as: None,
+        };
+        format!("Bearer {}", state.jwt_keys.encode(&claims).unwrap())
+    }
+
+    /// Petición de login con la contraseña `PASSWORD` y el resto de campos de `extra`.
+    fn login_request(username: &str, extra: serde_json::Value) -> TestRequest {
+        let mut body = serde_json::json!({ "identifier": username, "password": PASSWORD });
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        TestRequest::post().uri("/login").set_json(body)
+    }
+
+    /// Horas que faltan para que caduque el token de una respuesta de login, redondeadas.
+    fn token_hours(state: &AppState, body: &serde_json::Value) -> i64 {
+        let claims = state.jwt_keys.decode(body["token"].as_str().unwrap()).unwrap();
+        (claims.exp as i64 - Utc::now().timestamp() + 1800) / 3600
+    }
+
+    #[actix_web::test]
+    async fn remember_me_issues_a_longer_token() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (_, username) = insert_user(&state.db_pool, "student").await;
+
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(token_hours(&state, &body), 24);
+
+        let req = login_request(&username, serde_json::json!({ "remember_me": true })).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(token_hours(&state, &body), 720);
+    }
+
+    #[actix_web::test]
+    async fn remember_me_is_ignored_for_the_password_change_token() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        sqlx::query("UPDATE users SET must_reset_password = TRUE WHERE id = $1")
+            .bind(id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let req = login_request(&username, serde_json::json!({ "remember_me": true })).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["password_reset_required"], true);
+        assert_eq!(token_hours(&state, &body), 24);
+    }
+
+    /// Crea un token de restablecimiento para el usuario que caduca dentro de `minutes` minutos
+    /// (ya caducado si es negativo) y lo devuelve.
+    async fn insert_reset_token(db_pool: &PgPool, user_id: Uuid, minutes: i32) -> String {
+        let mut tx = db_pool.begin().await.unwrap();
+        let token = issue_password_reset_token(&mut tx, user_id, minutes).await.unwrap();
+        tx.commit().await.unwrap();
+        token
+    }
+
+    /// Petición que confirma el restablecimiento con el token y la contraseña `new-password`.
+    fn confirm_reset_request(token: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/password-reset/confirm")
+            .set_json(serde_json::json!({ "token": token, "new_password": "new-password" }))
+    }
+
+    #[actix_web::test]
+    async fn password_reset_token_lasts_reset_token_minutes() {
+        let state = web::Data::new(AppState { reset_token_minutes: 5, ..state().await });
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        let email = format!("{}@example.com", username);
+        sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&email)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let req = TestRequest::post()
+            .uri("/password-reset")
+            .set_json(serde_json::json!({ "email": email }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+
+        let minutes: f64 = sqlx::query_scalar(
+            "SELECT EXTRACT(EPOCH FROM expires_at - NOW())::float8 / 60 FROM password_reset_tokens WHERE user_id = $1",
+        )
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await
+        .unwrap();
+        assert!((4.0..=5.0).contains(&minutes), "{}", minutes);
+    }
+
+    #[actix_web::test]
+    async fn expired_password_reset_token_is_rejected_and_consumed() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        let token = insert_reset_token(&state.db_pool, id, -1).await;
+
+        let res = call_service(&app, confirm_reset_request(&token).to_request()).await;
+        assert_eq!(res.status(), 400);
+
+        // La contraseña no cambia y el token caducado ya no existe.
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM password_reset_tokens WHERE user_id = $1")
+            .bind(id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[actix_web::test]
+    async fn concurrent_password_resets_with_the_same_token_succeed_once() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        let token = insert_reset_token(&state.db_pool, id, 30).await;
+
+        let (first, second) = tokio::join!(
+            call_service(&app, confirm_reset_request(&token).to_request()),
+            call_service(&app, confirm_reset_request(&token).to_request()),
+        );
+        let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+        statuses.sort();
+        assert_eq!(statuses, [200, 400]);
+
+        let login = serde_json::json!({ "identifier": username, "password": "new-password" });
+        let req = TestRequest::post().uri("/login").set_json(login).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn scheduled_deletion_blocks_login_until_cancelled() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+
+        let req = TestRequest::delete()
+            .uri("/me")
+            .insert_header((header::AUTHORIZATION, bearer(&state, id, UserRole::Student)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 202);
+        let body: serde_json::Value = read_body_json(res).await;
+        let scheduled_at: DateTime<Utc> = body["deletion_scheduled_at"].as_str().unwrap().parse().unwrap();
+        assert_eq!((scheduled_at - Utc::now() + Duration::hours(1)).num_days(), 30);
+
+        // Durante el periodo de gracia la cuenta está desactivada.
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "account_pending_deletion");
+
+        let cancel = || {
+            TestRequest::post()
+                .uri("/me/cancel-deletion")
+                .set_json(serde_json::json!({ "identifier": username, "password": PASSWORD }))
+                .to_request()
+        };
+        let res = call_service(&app, cancel()).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(body["token"].is_string());
+        assert_eq!(call_service(&app, cancel()).await.status(), 409);
+
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn cancel_deletion_requires_the_password() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        sqlx::query("UPDATE users SET deletion_scheduled_at = NOW() + INTERVAL '1 day' WHERE id = $1")
+            .bind(id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let req = TestRequest::post()
+            .uri("/me/cancel-deletion")
+            .set_json(serde_json::json!({ "identifier": username, "password": "wrong-password" }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn sweeper_deletes_only_accounts_past_their_grace_period() {
+        let state = state().await;
+        let (expired, _) = insert_user(&state.db_pool, "student").await;
+        let (pending, _) = insert_user(&state.db_pool, "student").await;
+        for (id, offset) in [(expired, "-1 minute"), (pending, "1 day")] {
+            sqlx::query("UPDATE users SET deletion_scheduled_at = NOW() + $2::interval WHERE id = $1")
+                .bind(id)
+                .bind(offset)
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+        }
+
+        let deleted = delete_expired_accounts(&state.db_pool).await.unwrap();
+        assert!(deleted.contains(&expired));
+        assert!(!deleted.contains(&pending));
+    }
+
+    #[actix_web::test]
+    async fn personal_access_tokens_authenticate_until_revoked() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, _) = insert_user(&state.db_pool, "student").await;
+        let session = bearer(&state, id, UserRole::Student);
+
+        let req = TestRequest::post()
+            .uri("/me/tokens")
+            .insert_header((header::AUTHORIZATION, session.clone()))
+            .set_json(serde_json::json!({ "name": "ci", "scopes": ["read", "read"], "expires_in_days": 7 }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let created: serde_json::Value = read_body_json(res).await;
+        assert_eq!(created["scopes"], serde_json::json!(["read"]));
+        let pat = format!("Bearer {}", created["token"].as_str().unwrap());
+        let token_id = created["id"].as_str().unwrap().to_string();
+
+        // El listado nunca incluye el token.
+        let req = TestRequest::get()
+            .uri("/me/tokens")
+            .insert_header((header::AUTHORIZATION, session.clone()))
+            .to_request();
+        let listed: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert!(listed[0].get("token").is_none());
+
+        let me = || TestRequest::get().uri("/me").insert_header((header::AUTHORIZATION, pat.clone())).to_request();
+        assert_eq!(call_service(&app, me()).await.status(), 200);
+
+        // Un token de acceso personal no puede crear otros.
+        let req = TestRequest::post()
+            .uri("/me/tokens")
+            .insert_header((header::AUTHORIZATION, pat.clone()))
+            .set_json(serde_json::json!({ "name": "copy" }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+
+        let revoke = || {
+            TestRequest::delete()
+                .uri(&format!("/me/tokens/{}", token_id))
+                .insert_header((header::AUTHORIZATION, session.clone()))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, revoke()).await.status(), 204);
+        assert_eq!(call_service(&app, me()).await.status(), 401);
+        assert_eq!(call_service(&app, revoke()).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn personal_access_tokens_reject_invalid_requests() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, _) = insert_user(&state.db_pool, "student").await;
+
+        for body in [
+            serde_json::json!({ "name": " " }),
+            serde_json::json!({ "name": "ci", "scopes": ["admin"] }),
+            serde_json::json!({ "name": "ci", "scopes": [] }),
+            serde_json::json!({ "name": "ci", "expires_in_days": 0 }),
+        ] {
+            let req = TestRequest::post()
+                .uri("/me/tokens")
+                .insert_header((header::AUTHORIZATION, bearer(&state, id, UserRole::Student)))
+                .set_json(&body)
+                .to_request();
+            assert_eq!(call_service(&app, req).await.status(), 400, "{}", body);
+        }
+    }
+
+    /// Petición de `admin` para suplantar a `user_id`.
+    fn impersonate_request(state: &AppState, admin: Uuid, user_id: Uuid) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/admin/impersonate/{}", user_id))
+            .insert_header((header::AUTHORIZATION, bearer(state, admin, UserRole::Admin)))
+    }
+
+    #[actix_web::test]
+    async fn impersonation_issues_a_short_token_and_an_audit_entry() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let (student, _) = insert_user(&state.db_pool, "student").await;
+
+        let res = call_service(&app, impersonate_request(&state, admin, student).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let claims = state.jwt_keys.decode(body["token"].as_str().unwrap()).unwrap();
+        assert_eq!(claims.sub, student.to_string());
+        assert_eq!(claims.role, UserRole::Student);
+        assert_eq!(claims.act_as, Some(admin.to_string()));
+        let minutes = (claims.exp as i64 - Utc::now().timestamp() + 30) / 60;
+        assert_eq!(minutes, 15);
+
+        let (action, target_id): (String, Option<Uuid>) =
+            sqlx::query_as("SELECT action, target_id FROM audit_log WHERE actor_id = $1")
+                .bind(admin)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(action, audit::IMPERSONATION_STARTED);
+        assert_eq!(target_id, Some(student));
+
+        // Con el token de suplantación no se puede cambiar la contraseña del usuario.
+        let req = TestRequest::put()
+            .uri(ccb_common::PASSWORD_CHANGE_PATH)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", body["token"].as_str().unwrap())))
+            .set_json(serde_json::json!({ "current_password": PASSWORD, "new_password": "new-password" }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn impersonation_is_limited_to_admins_and_non_admin_targets() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let (other_admin, _) = insert_user(&state.db_pool, "admin").await;
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+        let (student, _) = insert_user(&state.db_pool, "student").await;
+
+        let req = TestRequest::post()
+            .uri(&format!("/admin/impersonate/{}", student))
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+
+        let res = call_service(&app, impersonate_request(&state, admin, other_admin).to_request()).await;
+        assert_eq!(res.status(), 403);
+        let res = call_service(&app, impersonate_request(&state, admin, admin).to_request()).await;
+        assert_eq!(res.status(), 400);
+        let res = call_service(&app, impersonate_request(&state, admin, Uuid::new_v4()).to_request()).await;
+        assert_eq!(res.status(), 404);
+    }
+
+    /// Petición de `admin` al registro de auditoría con los filtros de `query`.
+    fn audit_log_request(state: &AppState, admin: Uuid, query: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(&format!("/audit-log?{}", query))
+            .insert_header((header::AUTHORIZATION, bearer(state, admin, UserRole::Admin)))
+    }
+
+    #[actix_web::test]
+    async fn audit_log_filters_by_action_and_date_range() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        for (action, created_at) in [
+            ("impersonation_started", "2020-01-01T10:00:00Z"),
+            ("impersonation_started", "2020-01-03T10:00:00Z"),
+            ("course_reassigned", "2020-01-02T10:00:00Z"),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log (actor_id, action, target_type, details, created_at)
+                VALUES ($1, $2, 'user', '{}', $3::timestamptz)
+                "#,
+            )
+            .bind(admin)
+            .bind(action)
+            .bind(created_at)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        }
+
+        let query = format!("actor_id={}&action=impersonation_started", admin);
+        let body: serde_json::Value =
+            read_body_json(call_service(&app, audit_log_request(&state, admin, &query).to_request()).await).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["entries"][0]["created_at"], "2020-01-03T10:00:00.000Z");
+
+        let query = format!("actor_id={}&from=2020-01-02T00:00:00Z&to=2020-01-03T00:00:00Z", admin);
+        let body: serde_json::Value =
+            read_body_json(call_service(&app, audit_log_request(&state, admin, &query).to_request()).await).await;
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["entries"][0]["action"], "course_reassigned");
+
+        let query = format!("actor_id={}&per_page=1&page=2", admin);
+        let body: serde_json::Value =
+            read_body_json(call_service(&app, audit_log_request(&state, admin, &query).to_request()).await).await;
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(body["entries"][0]["action"], "course_reassigned");
+
+        let query = "from=2020-01-03T00:00:00Z&to=2020-01-02T00:00:00Z";
+        let res = call_service(&app, audit_log_request(&state, admin, query).to_request()).await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn audit_log_is_forbidden_to_non_admins() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let req = TestRequest::get()
+            .uri("/audit-log")
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    /// Petición de registro de un usuario nuevo con el cuerpo de `extra` añadido.
+    fn register_request(extra: serde_json::Value) -> TestRequest {
+        let mut body = serde_json::json!({
+            "username": format!("test_{}", Uuid::new_v4().simple()),
+            "password": PASSWORD,
+            "first_name": "Test",
+            "last_name": "User",
+        });
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        TestRequest::post().uri("/register").set_json(body)
+    }
+
+    /// Arranca un proveedor de captchas falso en un puerto libre que solo acepta el token `valid`,
+    /// y devuelve un verificador que lo usa.
+    fn mock_captcha() -> CaptchaVerifier {
+        async fn siteverify(form: web::Form<BTreeMap<String, String>>) -> HttpResponse {
+            let success = form.get("secret").map(String::as_str) == Some("captcha-secret")
+                && form.get("response").map(String::as_str) == Some("valid");
+            HttpResponse::Ok().json(serde_json::json!({ "success": success }))
+        }
+
+        let server = HttpServer::new(|| App::new().route("/siteverify", web::post().to(siteverify)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        CaptchaVerifier {
+            client: reqwest::Client::new(),
+            verify_url: format!("http://{}/siteverify", addr),
+            secret: "captcha-secret".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn registration_needs_no_captcha_when_disabled() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let res = call_service(&app, register_request(serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 201);
+    }
+
+    #[actix_web::test]
+    async fn registration_verifies_the_captcha_when_enabled() {
+        let state = web::Data::new(AppState { captcha: Some(mock_captcha()), ..state().await });
+        let app = app!(state);
+
+        let res = call_service(&app, register_request(serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 400);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "captcha_required");
+
+        let req = register_request(serde_json::json!({ "captcha_token": "invalid" })).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 400);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "captcha_invalid");
+
+        let req = register_request(serde_json::json!({ "captcha_token": "valid" })).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 201);
+
+        // Los admins registran cuentas sin captcha.
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let req = register_request(serde_json::json!({}))
+            .insert_header((header::AUTHORIZATION, bearer(&state, admin, UserRole::Admin)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 201);
+    }
+
+    #[actix_web::test]
+    async fn registration_fails_when_the_captcha_cannot_be_verified() {
+        // Nada escucha en el puerto 1: la verificación falla por la conexión.
+        let captcha = CaptchaVerifier {
+            client: reqwest::Client::new(),
+            verify_url: "http://127.0.0.1:1/siteverify".to_string(),
+            secret: "captcha-secret".to_string(),
+        };
+        let state = web::Data::new(AppState { captcha: Some(captcha), ..state().await });
+        let app = app!(state);
+
+        let req = register_request(serde_json::json!({ "captcha_token": "valid" })).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 503);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "captcha_unavailable");
+    }
+
+    /// Estado con un limitador de login que bloquea desde el segundo fallo: 1 s, 2 s y 4 s como máximo.
+    async fn throttled_state() -> web::Data<AppState> {
+        let policy = BackoffPolicy {
+            free_attempts: 1,
+            base_delay: std::time::Duration::from_secs(1),
+            max_delay: std::time::Duration::from_secs(4),
+        };
+        web::Data::new(AppState { login_throttle: Arc::new(LoginThrottle::new(policy)), ..state().await })
+    }
+
+    /// Petición de login con una contraseña incorrecta.
+    fn wrong_login_request(username: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({ "identifier": username, "password": "wrong-password" }))
+    }
+
+    #[actix_web::test]
+    async fn consecutive_login_failures_increase_retry_after() {
+        let state = throttled_state().await;
+        let app = app!(state);
+        let (_, username) = insert_user(&state.db_pool, "student").await;
+
+        assert_eq!(call_service(&app, wrong_login_request(&username).to_request()).await.status(), 401);
+
+        let mut retry_afters = Vec::new();
+        for _ in 0..3 {
+            let res = call_service(&app, wrong_login_request(&username).to_request()).await;
+            assert_eq!(res.status(), 429);
+            let retry_after = res.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap();
+            let retry_after: u64 = retry_after.parse().unwrap();
+            let body: serde_json::Value = read_body_json(res).await;
+            assert_eq!(body["code"], "too_many_login_attempts");
+            retry_afters.push(retry_after);
+
+            // Mientras dura el bloqueo, ni la contraseña correcta entra.
+            let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+            assert_eq!(res.status(), 429);
+            if retry_afters.len() < 3 {
+                actix_web::rt::time::sleep(std::time::Duration::from_secs(retry_after)).await;
+            }
+        }
+        assert_eq!(retry_afters, [1, 2, 4]);
+    }
+
+    #[actix_web::test]
+    async fn successful_login_resets_the_failures() {
+        let state = throttled_state().await;
+        let app = app!(state);
+        let (_, username) = insert_user(&state.db_pool, "student").await;
+
+        assert_eq!(call_service(&app, wrong_login_request(&username).to_request()).await.status(), 401);
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(call_service(&app, wrong_login_request(&username).to_request()).await.status(), 401);
+    }
+
+    /// Crea un curso del instructor con el estado indicado (`draft`, `published`, `archived`) y
+    /// devuelve su id.
+    async fn insert_course(db_pool: &PgPool, instructor_id: Uuid, status: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO courses (title, slug, instructor_id, status)
+            VALUES ('Test course', $1, $2, $3::course_status)
+            RETURNING id
+            "#,
+        )
+        .bind(format!("test-{}", Uuid::new_v4().simple()))
+        .bind(instructor_id)
+        .bind(status)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn public_profile_counts_published_courses_and_hides_private_fields() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, username) = insert_user(&state.db_pool, "instructor").await;
+        insert_course(&state.db_pool, instructor, "published").await;
+        insert_course(&state.db_pool, instructor, "published").await;
+        insert_course(&state.db_pool, instructor, "draft").await;
+
+        let req = TestRequest::get().uri(&format!("/users/{}/profile", instructor)).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["username"], username);
+        assert_eq!(body["role"], "Instructor");
+        assert_eq!(body["published_course_count"], 2);
+        assert!(body.get("email").is_none());
+        assert!(body.get("password_hash").is_none());
+    }
+
+    #[actix_web::test]
+    async fn public_profile_is_not_found_for_unknown_or_pending_deletion_users() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (user, _) = insert_user(&state.db_pool, "student").await;
+        sqlx::query("UPDATE users SET deletion_scheduled_at = NOW() + INTERVAL '1 day' WHERE id = $1")
+            .bind(user)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        for id in [user, Uuid::new_v4()] {
+            let req = TestRequest::get().uri(&format!("/users/{}/profile", id)).to_request();
+            assert_eq!(call_service(&app, req).await.status(), 404);
+        }
+    }
+
+
+    /// Petición de `admin` al listado de usuarios con los filtros de `query`.
+    fn users_request(state: &AppState, admin: Uuid, query: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(&format!("/users?{}", query))
+            .insert_header((header::AUTHORIZATION, bearer(state, admin, UserRole::Admin)))
+    }
+
+    #[actix_web::test]
+    async fn user_list_filters_by_role_and_search() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        // Una marca única en el email agrupa a los usuarios de este test.
+        let marker = Uuid::new_v4().simple().to_string();
+        for role in ["student", "student", "instructor"] {
+            let (id, _) = insert_user(&state.db_pool, role).await;
+            sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+                .bind(id)
+                .bind(format!("{}_{}@example.com", marker, id.simple()))
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+        }
+
+        let req = users_request(&state, admin, &format!("q={}", marker.to_uppercase())).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["total"], 3);
+
+        let req = users_request(&state, admin, &format!("q={}&role=Student&per_page=1", marker)).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["per_page"], 1);
+        assert_eq!(body["users"].as_array().unwrap().len(), 1);
+        assert_eq!(body["users"][0]["role"], "Student");
+
+        // Los comodines de `LIKE` se buscan literalmente.
+        let req = users_request(&state, admin, &format!("q={}%25", marker)).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["total"], 0);
+    }
+
+    #[actix_web::test]
+    async fn user_list_is_forbidden_to_non_admins() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let req = TestRequest::get()
+            .uri("/users")
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn login_includes_the_user_only_when_asked() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(body["token"].is_string());
+        assert!(body.get("user").is_none());
+
+        let req = login_request(&username, serde_json::json!({})).uri("/login?include_user=true").to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert!(body["token"].is_string());
+        assert_eq!(body["user"]["id"], id.to_string());
+        assert_eq!(body["user"]["username"], username);
+        assert!(body["user"].get("password_hash").is_none());
+    }
+
+    /// Hashes de los tokens de verificación pendientes del usuario.
+    async fn verification_token_hashes(db_pool: &PgPool, user_id: Uuid) -> Vec<String> {
+        sqlx::query_scalar("SELECT token_hash FROM email_verification_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn verification_resend_respects_the_cooldown() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        let email = format!("{}@example.com", username);
+        sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&email)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let resend = || {
+            TestRequest::post()
+                .uri("/verify-email/resend")
+                .set_json(serde_json::json!({ "email": email.to_uppercase() }))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, resend()).await.status(), 200);
+        let sent = verification_token_hashes(&state.db_pool, id).await;
+        assert_eq!(sent.len(), 1);
+
+        // Un segundo reenvío dentro del minuto no emite otro token.
+        assert_eq!(call_service(&app, resend()).await.status(), 200);
+        assert_eq!(verification_token_hashes(&state.db_pool, id).await, sent);
+    }
+
+    #[actix_web::test]
+    async fn verified_accounts_get_no_new_verification_token() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+            .bind(id)
+            .bind(format!("{}@example.com", username))
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let mut tx = state.db_pool.begin().await.unwrap();
+        let token = issue_email_verification_token(&mut tx, id).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let req = TestRequest::post()
+            .uri("/verify-email")
+            .set_json(serde_json::json!({ "token": token }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+
+        let req = TestRequest::post()
+            .uri("/verify-email/resend")
+            .insert_header((header::AUTHORIZATION, bearer(&state, id, UserRole::Student)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        assert!(verification_token_hashes(&state.db_pool, id).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn verification_resend_needs_an_email_or_a_session() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let req = TestRequest::post().uri("/verify-email/resend").to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+
+        // Un email desconocido recibe la misma respuesta que uno existente.
+        let req = TestRequest::post()
+            .uri("/verify-email/resend")
+            .set_json(serde_json::json!({ "email": format!("{}@example.com", Uuid::new_v4()) }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn registration_uses_the_default_role_unless_an_admin_sets_one() {
+        let state = web::Data::new(AppState { default_user_role: UserRole::Instructor, ..state().await });
+        let app = app!(state);
+
+        let res = call_service(&app, register_request(serde_json::json!({})).to_request()).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["role"], "Instructor");
+
+        // Sin token de admin, el rol pedido se ignora.
+        let req = register_request(serde_json::json!({ "role": "Admin" })).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["role"], "Instructor");
+
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let req = register_request(serde_json::json!({ "role": "Student" }))
+            .insert_header((header::AUTHORIZATION, bearer(&state, admin, UserRole::Admin)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["role"], "Student");
+    }
+
+    #[actix_web::test]
+    async fn admins_create_users_that_must_change_their_password() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let username = format!("test_{}", Uuid::new_v4().simple());
+
+        let req = TestRequest::post()
+            .uri("/users")
+            .insert_header((header::AUTHORIZATION, bearer(&state, admin, UserRole::Admin)))
+            .set_json(serde_json::json!({
+                "username": username,
+                "password": PASSWORD,
+                "first_name": "Test",
+                "last_name": "User",
+                "role": "Instructor",
+                "require_password_reset": true,
+            }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["role"], "Instructor");
+        assert_eq!(body["must_reset_password"], true);
+
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["password_reset_required"], true);
+        assert!(state.jwt_keys.decode(body["token"].as_str().unwrap()).unwrap().password_change_required);
+    }
+
+    #[actix_web::test]
+    async fn only_admins_create_users() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let req = TestRequest::post()
+            .uri("/users")
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({
+                "username": format!("test_{}", Uuid::new_v4().simple()),
+                "password": PASSWORD,
+                "first_name": "Test",
+                "last_name": "User",
+                "role": "Admin",
+            }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn usernames_are_unique_regardless_of_case() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let username = format!("Test_{}", Uuid::new_v4().simple().to_string().to_uppercase());
+
+        let req = register_request(serde_json::json!({ "username": username })).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["username"], username.to_lowercase());
+        assert_eq!(body["display_username"], username);
+
+        let req = register_request(serde_json::json!({ "username": username.to_lowercase() })).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 409);
+
+        let req = login_request(&username.to_uppercase(), serde_json::json!({})).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn usernames_cannot_look_like_emails() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let req = register_request(serde_json::json!({ "username": "someone@example.com" })).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 400);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "invalid_username");
+    }
+
+    /// Crea una notificación del usuario, leída en `read_at` si se indica.
+    async fn insert_notification(db_pool: &PgPool, user_id: Uuid, read_at: Option<&str>) {
+        sqlx::query(
+            r#"
+            INSERT INTO notifications (user_id, kind, message, read_at)
+            VALUES ($1, 'course_archived', 'A course was archived', $2::timestamptz)
+            "#,
+        )
+        .bind(user_id)
+        .bind(read_at)
+        .execute(db_pool)
+        .await
+        .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn mark_all_read_updates_only_the_pending_notifications_of_the_user() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (user, _) = insert_user(&state.db_pool, "student").await;
+        let (other, _) = insert_user(&state.db_pool, "student").await;
+        insert_notification(&state.db_pool, user, None).await;
+        insert_notification(&state.db_pool, user, None).await;
+        insert_notification(&state.db_pool, user, Some("2020-01-01T00:00:00Z")).await;
+        insert_notification(&state.db_pool, other, None).await;
+
+        let read_all = || {
+            TestRequest::post()
+                .uri("/notifications/read-all")
+                .insert_header((header::AUTHORIZATION, bearer(&state, user, UserRole::Student)))
+                .to_request()
+        };
+        let body: serde_json::Value = read_body_json(call_service(&app, read_all()).await).await;
+        assert_eq!(body["updated"], 2);
+        let body: serde_json::Value = read_body_json(call_service(&app, read_all()).await).await;
+        assert_eq!(body["updated"], 0);
+
+        // La notificación ya leída conserva su fecha y las de otros usuarios siguen pendientes.
+        let req = TestRequest::get()
+            .uri("/notifications")
+            .insert_header((header::AUTHORIZATION, bearer(&state, user, UserRole::Student)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let read_ats: Vec<&str> = body.as_array().unwrap().iter().map(|n| n["read_at"].as_str().unwrap()).collect();
+        assert_eq!(read_ats.len(), 3);
+        assert_eq!(read_ats.iter().filter(|read_at| read_at.starts_with("2020-01-01")).count(), 1);
+        let pending: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL")
+                .bind(other)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(pending, 1);
+    }
+
+    #[actix_web::test]
+    async fn mark_all_read_needs_profile_write_and_no_impersonation() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (user, _) = insert_user(&state.db_pool, "student").await;
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        insert_notification(&state.db_pool, user, None).await;
+        let read_all = |scopes: Option<Vec<String>>, act_as: Option<Uuid>| {
+            let claims = Claims {
+                sub: user.to_string(),
+                role: UserRole::Student,
+                exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+                password_change_required: false,
+                scopes,
+                act_as: act_as.map(|id| id.to_string()),
+            };
+            TestRequest::post()
+                .uri("/notifications/read-all")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", state.jwt_keys.encode(&claims).unwrap())))
+                .to_request()
+        };
+
+        // Un token sin `profile:write` (solo lectura) no cambia las notificaciones.
+        let res = call_service(&app, read_all(Some(Vec::new()), None)).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "insufficient_scope");
+        let res = call_service(&app, read_all(Some(vec![scope::ENROLLMENTS_WRITE.to_string()]), None)).await;
+        assert_eq!(res.status(), 403);
+
+        // Un admin que suplanta al usuario tampoco.
+        let res = call_service(&app, read_all(None, Some(admin))).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "impersonation_forbidden");
+
+        let pending: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL")
+                .bind(user)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(pending, 1);
+
+        let res = call_service(&app, read_all(Some(vec![scope::PROFILE_WRITE.to_string()]), None)).await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn notification_preferences_default_to_enabled_and_keep_omitted_kinds() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (user, _) = insert_user(&state.db_pool, "student").await;
+        let authorization = bearer(&state, user, UserRole::Student);
+
+        let req = TestRequest::get()
+            .uri("/me/notification-preferences")
+            .insert_header((header::AUTHORIZATION, authorization.clone()))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body, serde_json::json!({ "course_archived": true, "enrollment_removed": true }));
+
+        let update = |preferences: serde_json::Value| {
+            TestRequest::put()
+                .uri("/me/notification-preferences")
+                .insert_header((header::AUTHORIZATION, authorization.clone()))
+                .set_json(preferences)
+                .to_request()
+        };
+        let res = call_service(&app, update(serde_json::json!({ "course_archived": false }))).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body, serde_json::json!({ "course_archived": false, "enrollment_removed": true }));
+
+        let res = call_service(&app, update(serde_json::json!({ "enrollment_removed": false }))).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body, serde_json::json!({ "course_archived": false, "enrollment_removed": false }));
+
+        let res = call_service(&app, update(serde_json::json!({ "newsletter": false }))).await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn first_login_requires_a_password_change() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        sqlx::query("UPDATE users SET must_reset_password = TRUE WHERE id = $1")
+            .bind(id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let res = call_service(&app, login_request(&username, serde_json::json!({})).to_request()).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        let restricted = format!("Bearer {}", body["token"].as_str().unwrap());
+
+        // El token restringido solo sirve para cambiar la contraseña.
+        let req = TestRequest::get()
+            .uri("/me")
+            .insert_header((header::AUTHORIZATION, restricted.clone()))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "password_change_required");
+
+        let change = |current_password: &str| {
+            TestRequest::put()
+                .uri(ccb_common::PASSWORD_CHANGE_PATH)
+                .insert_header((header::AUTHORIZATION, restricted.clone()))
+                .set_json(serde_json::json!({ "current_password": current_password, "new_password": "new-password" }))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, change("wrong-password")).await.status(), 401);
+        let res = call_service(&app, change(PASSWORD)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(body.get("password_reset_required").is_none());
+
+        let req = TestRequest::get()
+            .uri("/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", body["token"].as_str().unwrap())))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["must_reset_password"], false);
+    }
+
+    #[actix_web::test]
+    async fn login_accepts_the_email_or_the_username() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (id, username) = insert_user(&state.db_pool, "student").await;
+        let email = format!("{}@Example.com", username);
+        sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&email)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        for identifier in [email.to_lowercase(), format!(" {} ", email.to_uppercase()), username.clone()] {
+            let res = call_service(&app, login_request(&identifier, serde_json::json!({})).to_request()).await;
+            assert_eq!(res.status(), 200, "{}", identifier);
+        }
+
+        // El nombre anterior del campo sigue funcionando.
+        let req = TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({ "username": username, "password": PASSWORD }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+
+        let res = call_service(&app, wrong_login_request(&email).to_request()).await;
+        assert_eq!(res.status(), 401);
+    }
+
+    /// Clave pública RSA de 2048 bits, solo para verificar (la misma que en los tests de `ccb_common::jwt`).
+    const TEST_PUBLIC_PEM: &str = "-----BEGIN PUBLIC KEY-----\n\
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAtWtDRH3Cu/OxQ3LexIfF\n\
+U/AAtZFK7Z/SF5IQYQq1OTY1oDJC+xcfHleS84k/pktZesLh1B/EvX29k/fuUk/3\n\
+1lqHTtaXcyCKns2l6VbcuXPP/qinMUupaqopHE08dv5ayRpd/1Yr+koM+bbodKGL\n\
+9l2TBduNh2VzR7acDcXnKJg27gbw8dz/E0cji0yaDYHo9TFNZFQ6bjGo4ZsJ9Pz5\n\
+v+NWwFjecPNTLSetRCxHKDllgLxDl73cVwF7hvYKLXRNBmAtZjcPhflMalz2j7ax\n\
+hoGf1NeBJRHaN9+BrZQK+GKEVNWmcz0YmwluvHXwJjBNkDLpk88i3u4c35Cwvxwt\n\
+1QIDAQAB\n\
+-----END PUBLIC KEY-----";
+
+    #[actix_web::test]
+    async fn jwks_publishes_the_rsa_public_keys() {
+        let jwt_keys =
+            JwtKeySet::rsa("rsa-1".to_string(), None, vec![("rsa-1".to_string(), TEST_PUBLIC_PEM.to_string())])
+                .unwrap();
+        let state = web::Data::new(AppState { jwt_keys, ..state().await });
+        let app = app!(state);
+
+        let res = call_service(&app, TestRequest::get().uri("/.well-known/jwks.json").to_request()).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::CACHE_CONTROL).unwrap(), "public, max-age=300");
+        let body: serde_json::Value = read_body_json(res).await;
+        let keys = body["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0]["kid"], "rsa-1");
+        assert_eq!(keys[0]["kty"], "RSA");
+        assert!(keys[0].get("d").is_none());
+    }
+
+    #[actix_web::test]
+    async fn jwks_is_empty_with_hmac_keys() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let res = call_service(&app, TestRequest::get().uri("/.well-known/jwks.json").to_request()).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body, serde_json::json!({ "keys": [] }));
+    }
+
+    #[actix_web::test]
+    async fn permissions_reflect_the_role_and_the_token_scopes() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let req = TestRequest::get()
+            .uri("/me/permissions")
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["role"], "Instructor");
+        assert_eq!(body["scopes"], serde_json::Value::Null);
+        let capabilities = [
+            permissions::CREATE_COURSE,
+            permissions::ENROLL,
+            permissions::EDIT_PROFILE,
+            permissions::MODERATE_REVIEWS,
+        ];
+        assert_eq!(body["capabilities"], serde_json::json!(capabilities));
+
+        // Una sesión limitada a `profile:write` ya no puede crear cursos ni inscribirse.
+        let claims = Claims {
+            sub: instructor.to_string(),
+            role: UserRole::Instructor,
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            password_change_required: false,
+            scopes: Some(vec![scope::PROFILE_WRITE.to_string()]),
+            act_as: None,
+        };
+        let req = TestRequest::get()
+            .uri("/me/permissions")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", state.jwt_keys.encode(&claims).unwrap())))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["scopes"], serde_json::json!([scope::PROFILE_WRITE]));
+        let capabilities = [permissions::EDIT_PROFILE, permissions::MODERATE_REVIEWS];
+        assert_eq!(body["capabilities"], serde_json::json!(capabilities));
+    }
+
+    /// Petición de `admin` para transferir los cursos de `from` al instructor `to`.
+    fn reassign_request(state: &AppState, admin: Uuid, from: Uuid, to: Uuid) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/users/{}/reassign-courses", from))
+            .insert_header((header::AUTHORIZATION, bearer(state, admin, UserRole::Admin)))
+            .set_json(serde_json::json!({ "instructor_id": to }))
+    }
+
+    #[actix_web::test]
+    async fn reassigning_courses_lets_the_instructor_delete_the_account() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let (from, _) = insert_user(&state.db_pool, "instructor").await;
+        let (to, _) = insert_user(&state.db_pool, "instructor").await;
+        let mut courses = vec![
+            insert_course(&state.db_pool, from, "published").await,
+            insert_course(&state.db_pool, from, "archived").await,
+        ];
+
+        let delete_account = || {
+            TestRequest::delete()
+                .uri("/me")
+                .insert_header((header::AUTHORIZATION, bearer(&state, from, UserRole::Instructor)))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, delete_account()).await.status(), 409);
+
+        let res = call_service(&app, reassign_request(&state, admin, from, to).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let mut reassigned: Vec<Uuid> = serde_json::from_value(body["course_ids"].clone()).unwrap();
+        reassigned.sort();
+        courses.sort();
+        assert_eq!(reassigned, courses);
+
+        let owned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM courses WHERE instructor_id = $1")
+            .bind(to)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(owned, 2);
+        assert_eq!(call_service(&app, delete_account()).await.status(), 202);
+    }
+
+    #[actix_web::test]
+    async fn courses_are_only_reassigned_to_another_active_instructor() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let (from, _) = insert_user(&state.db_pool, "instructor").await;
+        let (student, _) = insert_user(&state.db_pool, "student").await;
+        let (leaving, _) = insert_user(&state.db_pool, "instructor").await;
+        sqlx::query("UPDATE users SET deletion_scheduled_at = NOW() + INTERVAL '1 day' WHERE id = $1")
+            .bind(leaving)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        for to in [from, student, leaving, Uuid::new_v4()] {
+            let res = call_service(&app, reassign_request(&state, admin, from, to).to_request()).await;
+            assert_eq!(res.status(), 400, "{}", to);
+        }
+        let res = call_service(&app, reassign_request(&state, admin, Uuid::new_v4(), from).to_request()).await;
+        assert_eq!(res.status(), 404);
+
+        let req = TestRequest::post()
+            .uri(&format!("/users/{}/reassign-courses", from))
+            .insert_header((header::AUTHORIZATION, bearer(&state, from, UserRole::Instructor)))
+            .set_json(serde_json::json!({ "instructor_id": from }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    /// Petición interna a `POST /users/batch` con la API key `api_key`.
+    fn batch_request(api_key: &str, ids: &[Uuid]) -> TestRequest {
+        TestRequest::post()
+            .uri("/users/batch")
+            .insert_header(("X-Api-Key", api_key))
+            .set_json(serde_json::json!({ "ids": ids }))
+    }
+
+    #[actix_web::test]
+    async fn batch_lookup_resolves_the_existing_users() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (first, _) = insert_user(&state.db_pool, "student").await;
+        let (second, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let res = call_service(&app, batch_request(API_KEY, &[first, second, Uuid::new_v4()]).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let mut ids: Vec<Uuid> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["id"].as_str().unwrap().parse().unwrap())
+            .collect();
+        ids.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert!(body[0].get("email").is_none());
+    }
+
+    #[actix_web::test]
+    async fn batch_lookup_requires_the_api_key_and_limits_the_ids() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let res = call_service(&app, batch_request("wrong-key", &[Uuid::new_v4()]).to_request()).await;
+        assert_eq!(res.status(), 401);
+
+        let ids: Vec<Uuid> = (0..=MAX_BATCH_USER_IDS).map(|_| Uuid::new_v4()).collect();
+        let res = call_service(&app, batch_request(API_KEY, &ids).to_request()).await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn platform_stats_count_every_role_and_status() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let stats = || {
+            TestRequest::get()
+                .uri("/stats/platform")
+                .insert_header((header::AUTHORIZATION, bearer(&state, admin, UserRole::Admin)))
+                .to_request()
+        };
+
+        let before: serde_json::Value = read_body_json(call_service(&app, stats()).await).await;
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+        insert_course(&state.db_pool, instructor, "published").await;
+        let after: serde_json::Value = read_body_json(call_service(&app, stats()).await).await;
+
+        // Otros tests crean usuarios y cursos a la vez: solo sabemos que hay al menos uno más.
+        let count = |stats: &serde_json::Value, pointer: &str| stats.pointer(pointer).unwrap().as_i64().unwrap();
+        assert!(count(&after, "/users/by_role/instructor") > count(&before, "/users/by_role/instructor"));
+        assert!(count(&after, "/courses/by_status/published") > count(&before, "/courses/by_status/published"));
+        assert!(count(&after, "/signups/last_7_days") >= 2);
+
+        let by_role = after["users"]["by_role"].as_object().unwrap();
+        assert_eq!(by_role.keys().collect::<Vec<_>>(), ["admin", "instructor", "student"]);
+        let by_status = after["courses"]["by_status"].as_object().unwrap();
+        assert_eq!(by_status.keys().collect::<Vec<_>>(), ["archived", "draft", "published"]);
+    }
+
+    #[actix_web::test]
+    async fn platform_stats_are_forbidden_to_non_admins() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (instructor, _) = insert_user(&state.db_pool, "instructor").await;
+
+        let req = TestRequest::get()
+            .uri("/stats/platform")
+            .insert_header((header::AUTHORIZATION, bearer(&state, instructor, UserRole::Instructor)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn email_can_be_completed_after_registering_without_it() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let res = call_service(&app, register_request(serde_json::json!({ "email": " " })).to_request()).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["email"], serde_json::Value::Null);
+        let id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+        assert!(verification_token_hashes(&state.db_pool, id).await.is_empty());
+
+        let set_email = |email: &str| {
+            TestRequest::put()
+                .uri("/me/email")
+                .insert_header((header::AUTHORIZATION, bearer(&state, id, UserRole::Student)))
+                .set_json(serde_json::json!({ "email": email }))
+                .to_request()
+        };
+        let email = format!("{}@example.com", Uuid::new_v4().simple());
+        let res = call_service(&app, set_email(&email)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["email"], email);
+        assert_eq!(verification_token_hashes(&state.db_pool, id).await.len(), 1);
+
+        // Otra cuenta no puede usar el mismo email, aunque cambien las mayúsculas.
+        let (other, _) = insert_user(&state.db_pool, "student").await;
+        let req = TestRequest::put()
+            .uri("/me/email")
+            .insert_header((header::AUTHORIZATION, bearer(&state, other, UserRole::Student)))
+            .set_json(serde_json::json!({ "email": email.to_uppercase() }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "email_taken");
+
+        // Una vez verificado, el email ya no se puede cambiar.
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let res = call_service(&app, set_email("changed@example.com")).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "email_already_verified");
+    }
+
+    #[actix_web::test]
+    async fn user_list_reuses_the_cached_total_until_a_user_is_created() {
+        let ttl = std::time::Duration::from_secs(60);
+        let state = web::Data::new(AppState { user_counts: Arc::new(CountCache::new(Some(ttl))), ..state().await });
+        let app = app!(state);
+        let (admin, _) = insert_user(&state.db_pool, "admin").await;
+        let marker = Uuid::new_v4().simple().to_string();
+        let insert_marked_user = || async {
+            let (id, _) = insert_user(&state.db_pool, "student").await;
+            sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+                .bind(id)
+                .bind(format!("{}_{}@example.com", marker, id.simple()))
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+        };
+        let total = || async {
+            let req = users_request(&state, admin, &format!("q={}", marker)).to_request();
+            let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+            body["total"].as_i64().unwrap()
+        };
+
+        insert_marked_user().await;
+        assert_eq!(total().await, 1);
+        // Un alta directa en la base de datos no invalida la caché: el total sigue siendo el anterior.
+        insert_marked_user().await;
+        assert_eq!(total().await, 1);
+
+        let req = TestRequest::post()
+            .uri("/users")
+            .insert_header((header::AUTHORIZATION, bearer(&state, admin, UserRole::Admin)))
+            .set_json(serde_json::json!({
+                "username": format!("test_{}", Uuid::new_v4().simple()),
+                "password": PASSWORD,
+                "email": format!("{}_created@example.com", marker),
+                "first_name": "Test",
+                "last_name": "User",
+                "role": "Student",
+            }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 201);
+        assert_eq!(total().await, 3);
+    }
+
+    #[actix_web::test]
+    async fn scoped_sessions_only_allow_their_scopes() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (_, username) = insert_user(&state.db_pool, "student").await;
+
+        let req = login_request(&username, serde_json::json!({ "scopes": ["admin:everything"] })).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+
+        let scopes = serde_json::json!([scope::PROFILE_WRITE, scope::PROFILE_WRITE]);
+        let req = login_request(&username, serde_json::json!({ "scopes": scopes })).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let token = body["token"].as_str().unwrap();
+        assert_eq!(state.jwt_keys.decode(token).unwrap().scopes, Some(vec![scope::PROFILE_WRITE.to_string()]));
+
+        let update_profile = |token: &str| {
+            TestRequest::put()
+                .uri("/me/profile")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .set_json(serde_json::json!({ "bio": "Hola" }))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, update_profile(token)).await.status(), 200);
+
+        let scopes = serde_json::json!([scope::ENROLLMENTS_WRITE]);
+        let req = login_request(&username, serde_json::json!({ "scopes": scopes })).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let res = call_service(&app, update_profile(body["token"].as_str().unwrap())).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "insufficient_scope");
+    }
+
+    #[actix_web::test]
+    async fn errors_carry_a_code_and_follow_accept_language() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let (_, username) = insert_user(&state.db_pool, "student").await;
+
+        let wrong_password = |lang: &str| {
+            TestRequest::post()
+                .uri("/login")
+                .insert_header((header::ACCEPT_LANGUAGE, lang))
+                .set_json(serde_json::json!({ "identifier": username, "password": "wrong-password" }))
+                .to_request()
+        };
+        let res = call_service(&app, wrong_password("en")).await;
+        assert_eq!(res.status(), 401);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "invalid_credentials");
+        assert_eq!(body["message"], "Invalid username or password");
+
+        let res = call_service(&app, wrong_password("es-CL,es;q=0.9")).await;
+        assert_eq!(res.status(), 401);
+        assert_eq!(res.headers().get(header::CONTENT_LANGUAGE).unwrap(), "es");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "invalid_credentials");
+        assert_eq!(body["message"], "El nombre de usuario o la contraseña no son correctos");
+
+        // Los errores del extractor de autenticación también son `ApiError`.
+        let req = TestRequest::get().uri("/me").insert_header((header::ACCEPT_LANGUAGE, "es")).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 401);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "unauthorized");
+        assert_eq!(body["message"], "No has iniciado sesión o el token no es válido");
+    }
 }
@@ -1,12 +1,16 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use ccb_common::{AuthenticatedUser, middleware::jwt_auth::{JwtMiddleware, Role as AuthRole}, UserRole};
-use actix_cors::Cors;
+use actix_web::{http::header, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use ccb_common::{activity::{self, Activity}, api_key::ApiKeyGuard, i18n::LocalizedErrors, maintenance::{self, MaintenanceFlag, MaintenanceMode}, notifications::{self, Notification}, rate_limit::{RateLimiter, WriteRateLimit}, health, config::{Config, TextLimits, WebhookSettings}, db, error::ApiError, fields::FieldSet, pagination::PageParams, scope, telemetry::{self, AccessLog}, timestamp, transaction::{RequestTransactions, Tx}, AuthenticatedUser, middleware::jwt_auth::{JwtMiddleware, Role as AuthRole}, UserRole};
 use serde::{Deserialize, Serialize}; 
-use sqlx::{postgres::PgPoolOptions, FromRow, PgPool};
-use std::env;
-use tracing::info;
+use sqlx::{FromRow, PgExecutor, PgPool, Postgres, QueryBuilder, Transaction};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 // --- Modelos de Datos ---
 
 /// Estructura para recibir los datos para crear un curso.
@@ -14,13 +18,504 @@ use chrono::{DateTime, Utc};
 struct CreateCourse {
     title: String,
     description: Option<String>,
+    tags: Option<Vec<String>>,
+    /// Fecha en la que el borrador se publicará automáticamente.
+    publish_at: Option<DateTime<Utc>>,
+    /// Cupo de estudiantes activos; sin definir, el curso no tiene límite.
+    max_students: Option<i32>,
+    /// Si se aceptan reseñas del curso (por defecto, sí).
+    allow_reviews: Option<bool>,
+    /// Si solo pueden reseñar los estudiantes que completaron el curso (por defecto, no).
+    review_requires_completion: Option<bool>,
+    /// Si las inscripciones pendientes de aprobación ocupan plaza (por defecto, no).
+    reserve_pending_slots: Option<bool>,
+    /// Si se emite un certificado al completar el curso (por defecto, no).
+    issues_certificate: Option<bool>,
 }
 
-/// Estructura para recibir los datos para actualizar un curso. Los campos son opcionales.
+/// Estructura para recibir los datos para actualizar un curso. Los campos son opcionales: uno
+/// ausente conserva su valor. En los campos anulables (`description`, `publish_at`,
+/// `max_students` y `category_id`), `null` lo borra.
 #[derive(Deserialize)]
 struct UpdateCourse {
     title: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    description: Option<Option<String>>,
+    status: Option<CourseStatus>,
+    #[serde(default, deserialize_with = "present")]
+    publish_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "present")]
+    max_students: Option<Option<i32>>,
+    allow_reviews: Option<bool>,
+    review_requires_completion: Option<bool>,
+    reserve_pending_slots: Option<bool>,
+    issues_certificate: Option<bool>,
+    /// Nueva categoría del curso; debe existir.
+    #[serde(default, deserialize_with = "present")]
+    category_id: Option<Option<Uuid>>,
+    /// Conjunto completo de etiquetas: sustituye al actual (`[]` las quita todas).
+    tags: Option<Vec<String>>,
+    /// Versión del curso sobre la que se hizo la edición. Alternativa a la cabecera `If-Match`.
+    expected_version: Option<i32>,
+}
+
+/// Estructura para recibir una lista de IDs en la consulta por lotes.
+#[derive(Deserialize)]
+struct BatchCoursesRequest {
+    ids: Vec<Uuid>,
+}
+
+/// Parámetros del feed de tendencias: la ventana de días a considerar (la paginación va en `PageParams`).
+#[derive(Deserialize)]
+struct TrendingQuery {
+    days: Option<i32>,
+}
+
+/// Parámetros de la búsqueda de etiquetas por prefijo (`?q=ru`).
+#[derive(Deserialize)]
+struct TagSuggestQuery {
+    q: Option<String>,
+}
+
+/// Etiqueta sugerida junto con el número de cursos que la usan.
+#[derive(Serialize, FromRow)]
+struct TagSuggestion {
+    name: String,
+    usage_count: i64,
+}
+
+/// Estructura para devolver un curso guardado en los marcadores del usuario.
+#[derive(Serialize, FromRow)]
+struct BookmarkedCourse {
+    course_id: Uuid,
+    title: String,
+    description: Option<String>,
+    instructor_id: Uuid,
+    status: CourseStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    bookmarked_at: DateTime<Utc>,
+}
+
+/// Cupo de un curso y estado de su lista de espera.
+#[derive(Serialize)]
+struct CourseCapacity {
+    /// `None` si el curso no tiene límite de estudiantes.
+    max_students: Option<i32>,
+    active_count: i64,
+    /// Inscripciones pendientes de aprobación; con `reserve_pending_slots` también ocupan plaza.
+    pending_count: i64,
+    reserve_pending_slots: bool,
+    waitlist_count: i64,
+    /// Posición (desde 1) del usuario autenticado en la lista de espera, si está en ella.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    my_waitlist_position: Option<i64>,
+}
+
+/// Parámetros de `GET /courses`.
+#[derive(Deserialize)]
+struct CoursesQuery {
+    /// Con `true`, omite los cursos en los que el usuario autenticado ya está inscrito.
+    #[serde(default)]
+    exclude_enrolled: bool,
+    /// Con `true`, incluye los cursos borrados (solo admins; para el resto se ignora).
+    #[serde(default)]
+    include_deleted: bool,
+    /// Campos de cada curso a devolver, separados por comas (ver `COURSE_FIELDS`).
+    fields: Option<String>,
+}
+
+/// Parámetros de `GET /courses/{id}` y `GET /courses/by-slug/{slug}`.
+#[derive(Deserialize)]
+struct CourseDetailQuery {
+    /// Con `true`, un admin también puede ver un curso borrado (para el resto se ignora).
+    #[serde(default)]
+    include_deleted: bool,
+    /// Campos a devolver, separados por comas: los de `COURSE_FIELDS` más `lessons` y `materials`.
+    fields: Option<String>,
+}
+
+/// Parámetros de `DELETE /courses/{id}` (`?dry_run=true` solo informa, no borra; con
+/// `?soft=true` el curso se marca como borrado en lugar de eliminarse).
+#[derive(Deserialize)]
+struct DeleteCourseQuery {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    soft: bool,
+}
+
+/// Filas que se borrarían junto con el curso, devueltas por `DELETE /courses/{id}?dry_run=true`.
+/// Cubre todas las tablas a las que llega el `ON DELETE CASCADE` desde `courses`.
+#[derive(Serialize)]
+struct CourseDeletionSummary {
+    course_id: Uuid,
+    enrollments: i64,
+    /// Historial de cambios de estado de esas inscripciones.
+    enrollment_events: i64,
+    offerings: i64,
+    lessons: i64,
+    lesson_progress: i64,
+    materials: i64,
+    bookmarks: i64,
+    waitlist: i64,
+    reviews: i64,
+    review_reports: i64,
+    certificates: i64,
+    revisions: i64,
+    tags: i64,
+    views: i64,
+    /// Entradas del feed de actividad del curso o de sus lecciones.
+    activity: i64,
+}
+
+/// Parámetros de `GET /courses/{id}/similar`.
+#[derive(Deserialize)]
+struct SimilarCoursesQuery {
+    limit: Option<i64>,
+}
+
+/// Parámetros de `GET /me/recently-viewed`.
+#[derive(Deserialize)]
+struct RecentlyViewedQuery {
+    limit: Option<i64>,
+}
+
+/// Curso visto recientemente por el usuario.
+#[derive(Serialize, FromRow)]
+struct RecentlyViewedCourse {
+    course_id: Uuid,
+    title: String,
+    description: Option<String>,
+    instructor_id: Uuid,
+    status: CourseStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    viewed_at: DateTime<Utc>,
+}
+
+/// Parámetros de la subida de un material (`?file_name=apuntes.pdf`).
+#[derive(Deserialize)]
+struct UploadMaterialQuery {
+    file_name: String,
+}
+
+/// Metadatos de un material privado de un curso.
+#[derive(Serialize, FromRow)]
+struct CourseMaterial {
+    id: Uuid,
+    course_id: Uuid,
+    file_name: String,
+    content_type: String,
+    size_bytes: i64,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Respuesta con un enlace de descarga firmado y de corta duración.
+#[derive(Serialize)]
+struct DownloadTokenResponse {
+    token: String,
+    url: String,
+    #[serde(serialize_with = "timestamp::serialize")]
+    expires_at: DateTime<Utc>,
+}
+
+/// Parámetros de la descarga de un material (`?token=...`).
+#[derive(Deserialize)]
+struct DownloadQuery {
+    token: String,
+}
+
+/// Respuesta con el número de estudiantes inscritos en un curso.
+#[derive(Serialize)]
+struct StudentCountResponse {
+    count: i64,
+}
+
+/// Tamaño de los intervalos de la serie de inscripciones.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Granularity {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// Unidad de `date_trunc` (y del intervalo de `generate_series`) correspondiente.
+    fn as_str(self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+}
+
+/// Parámetros de `GET /courses/{id}/analytics/enrollments` (`?granularity=day|week|month`).
+#[derive(Deserialize)]
+struct EnrollmentAnalyticsQuery {
+    #[serde(default)]
+    granularity: Granularity,
+}
+
+/// Número de inscripciones que empezaron dentro de un intervalo.
+#[derive(Serialize, FromRow)]
+struct EnrollmentBucket {
+    #[serde(serialize_with = "timestamp::serialize")]
+    bucket: DateTime<Utc>,
+    count: i64,
+}
+
+/// Estructura para recibir los datos para crear una lección.
+#[derive(Deserialize)]
+struct CreateLesson {
+    title: String,
+    content: Option<String>,
+}
+
+/// Estructura para recibir el nuevo orden de las lecciones de un curso.
+#[derive(Deserialize)]
+struct ReorderLessons {
+    lesson_ids: Vec<Uuid>,
+}
+
+/// Estructura para representar una lección en la base de datos.
+#[derive(Serialize, FromRow)]
+struct Lesson {
+    id: Uuid,
+    course_id: Uuid,
+    title: String,
+    content: Option<String>,
+    position: i32,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    updated_at: DateTime<Utc>,
+}
+
+/// Copia del contenido de un curso antes de un cambio (ver `record_course_revision`).
+#[derive(Serialize, FromRow)]
+struct CourseRevision {
+    id: Uuid,
+    course_id: Uuid,
+    /// Quien hizo el cambio que motivó la copia (`None` si su cuenta ya no existe).
+    author_id: Option<Uuid>,
+    title: String,
     description: Option<String>,
+    /// Lecciones en orden, cada una con `id`, `title`, `content` y `position`.
+    lessons: serde_json::Value,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Respuesta de `POST /lessons/{id}/complete`.
+#[derive(Serialize)]
+struct LessonCompletion {
+    lesson_id: Uuid,
+    #[serde(serialize_with = "timestamp::serialize")]
+    completed_at: DateTime<Utc>,
+    /// `true` si con esta lección el estudiante completó el curso (su inscripción pasó a `completed`).
+    course_completed: bool,
+    /// Certificado emitido al completar el curso, si el curso tiene `issues_certificate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate_id: Option<Uuid>,
+}
+
+/// Estructura para recibir los datos de una nueva edición de un curso.
+#[derive(Deserialize)]
+struct CreateOffering {
+    /// Nombre de la edición, único dentro del curso (p. ej. `2025-1`).
+    name: String,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+}
+
+/// Edición de un curso (por ejemplo, un semestre). Los estudiantes se inscriben en cada una por separado.
+#[derive(Serialize, FromRow)]
+struct Offering {
+    id: Uuid,
+    course_id: Uuid,
+    name: String,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    starts_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    ends_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Estructura para recibir una reseña de un curso.
+#[derive(Deserialize)]
+struct CreateReview {
+    /// Valoración de 1 a 5.
+    rating: i32,
+    comment: Option<String>,
+}
+
+/// Reseña de un curso escrita por uno de sus estudiantes.
+#[derive(Serialize, FromRow)]
+struct Review {
+    id: Uuid,
+    course_id: Uuid,
+    user_id: Uuid,
+    rating: i32,
+    comment: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    updated_at: DateTime<Utc>,
+}
+
+/// Reseña del listado público de un curso, con el nombre y el avatar de quien la escribió.
+#[derive(Serialize, FromRow)]
+struct CourseReviewEntry {
+    id: Uuid,
+    course_id: Uuid,
+    user_id: Uuid,
+    reviewer_name: String,
+    reviewer_avatar_url: Option<String>,
+    rating: i32,
+    comment: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    updated_at: DateTime<Utc>,
+}
+
+/// Parámetros de `GET /courses/{id}/reviews`: filtro por puntuación y ordenación (la paginación va en `PageParams`).
+#[derive(Deserialize)]
+struct ReviewsQuery {
+    /// Puntuación mínima incluida (1-5).
+    min_rating: Option<i32>,
+    /// Puntuación máxima incluida (1-5).
+    max_rating: Option<i32>,
+    /// Campo de ordenación: `recent` (por defecto) o `rating`.
+    sort: Option<String>,
+    /// Dirección de ordenación: `asc` o `desc` (por defecto).
+    order: Option<String>,
+}
+
+/// Estructura para recibir el reporte de una reseña inapropiada.
+#[derive(Deserialize)]
+struct ReportReview {
+    reason: String,
+}
+
+/// Respuesta de `POST /reviews/{id}/report`.
+#[derive(Serialize)]
+struct ReviewReportResult {
+    review_id: Uuid,
+    report_count: i64,
+    /// La reseña ha quedado oculta a la espera de moderación.
+    hidden: bool,
+}
+
+/// Reseña reportada, tal como la ve quien la modera.
+#[derive(Serialize, FromRow)]
+struct ReportedReview {
+    id: Uuid,
+    course_id: Uuid,
+    user_id: Uuid,
+    rating: i32,
+    comment: Option<String>,
+    /// Momento en el que se ocultó por superar el umbral de reportes.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    hidden_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+    report_count: i64,
+    /// Motivos de los reportes, del más antiguo al más reciente.
+    reasons: Vec<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    last_reported_at: DateTime<Utc>,
+}
+
+/// Estructura para recibir los datos para crear una categoría.
+#[derive(Deserialize)]
+struct CreateCategory {
+    name: String,
+}
+
+/// Estructura para representar una categoría del catálogo.
+#[derive(Serialize, FromRow)]
+struct Category {
+    id: Uuid,
+    name: String,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Cuerpo de `PUT /courses/featured` y de su respuesta: la lista completa de cursos destacados, en orden.
+#[derive(Serialize, Deserialize)]
+struct SetFeaturedCourses {
+    course_ids: Vec<Uuid>,
+}
+
+/// Estructura para asignar una categoría a varios cursos a la vez.
+#[derive(Deserialize)]
+struct CategorizeCourses {
+    category_id: Uuid,
+    course_ids: Vec<Uuid>,
+}
+
+/// Resultado de la asignación masiva de categoría.
+#[derive(Serialize)]
+struct CategorizeResult {
+    updated: Vec<Uuid>,
+    not_found: Vec<Uuid>,
+}
+
+/// Estructura para cambiar el estado de varios cursos a la vez.
+#[derive(Deserialize)]
+struct BulkStatusRequest {
+    status: CourseStatus,
+    course_ids: Vec<Uuid>,
+}
+
+/// Resultado del cambio de estado de un curso dentro de una petición masiva.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BulkStatusOutcome {
+    Updated,
+    /// El curso ya tenía el estado pedido.
+    Unchanged,
+    NotFound,
+    /// El curso no pertenece a quien hace la petición (y no es admin); no se modifica.
+    Forbidden,
+    /// El estado actual no admite pasar al estado pedido.
+    InvalidTransition,
+}
+
+#[derive(Serialize)]
+struct BulkStatusResult {
+    course_id: Uuid,
+    result: BulkStatusOutcome,
+}
+
+/// Estado de publicación de un curso. Solo los cursos `published` son públicos.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "course_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+enum CourseStatus {
+    Draft,
+    Published,
+    Archived,
+}
+
+impl CourseStatus {
+    /// Transiciones de estado admitidas, tanto al editar un curso como en los cambios masivos. Un
+    /// curso archivado debe volver a `draft` antes de publicarse de nuevo.
+    fn can_transition_to(self, to: CourseStatus) -> bool {
+        matches!(
+            (self, to),
+            (CourseStatus::Draft, CourseStatus::Published)
+                | (CourseStatus::Draft, CourseStatus::Archived)
+                | (CourseStatus::Published, CourseStatus::Draft)
+                | (CourseStatus::Published, CourseStatus::Archived)
+                | (CourseStatus::Archived, CourseStatus::Draft)
+        )
+    }
 }
 
 /// Estructura para representar un curso en la base de datos.
@@ -30,220 +525,5825 @@ struct Course {
     title: String,
     description: Option<String>,
     instructor_id: Uuid,
+    category_id: Option<Uuid>,
+    status: CourseStatus,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    published_at: Option<DateTime<Utc>>,
+    /// Publicación programada: si el curso sigue en `draft` al llegar esta fecha, se publica solo.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    publish_at: Option<DateTime<Utc>>,
+    /// Cupo de estudiantes activos. Con el curso completo, las nuevas inscripciones van a la lista de espera.
+    max_students: Option<i32>,
+    /// Si los estudiantes pueden publicar reseñas del curso.
+    allow_reviews: bool,
+    /// Si para reseñar hace falta haber completado el curso, no solo estar inscrito.
+    review_requires_completion: bool,
+    /// Si las inscripciones `pending` (pendientes de aprobación) cuentan para `max_students`,
+    /// para que el instructor no apruebe más estudiantes de los que caben.
+    reserve_pending_slots: bool,
+    /// Si el estudiante recibe un certificado al completar todas las lecciones del curso.
+    issues_certificate: bool,
+    /// Fecha del borrado lógico. Solo la ven los admins que piden `include_deleted=true`.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "timestamp::serialize_option")]
+    deleted_at: Option<DateTime<Utc>>,
+    /// Si un admin lo destacó en la portada (`GET /courses/featured`).
+    is_featured: bool,
+    /// Posición entre los destacados (1 primero); solo la tienen los cursos destacados.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    featured_rank: Option<i32>,
+    /// Identificador legible y único derivado del título (`intro-to-rust`), para URLs limpias.
+    slug: String,
+    /// Se incrementa en cada actualización (control de concurrencia optimista).
+    version: i32,
+    #[serde(serialize_with = "timestamp::serialize")]
     created_at: DateTime<Utc>,
+    #[serde(serialize_with = "timestamp::serialize")]
     updated_at: DateTime<Utc>,
 }
 
+/// Respuesta de `GET /courses/{id}`: el resumen del curso y, solo para estudiantes inscritos,
+/// su propietario o un admin, las lecciones y los materiales.
+#[derive(Serialize)]
+struct CourseDetail {
+    #[serde(flatten)]
+    course: Course,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lessons: Option<Vec<Lesson>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    materials: Option<Vec<CourseMaterial>>,
+}
+
+/// Número de líneas de `GET /courses/export` que se preparan por adelantado mientras el cliente lee.
+const EXPORT_BUFFER_LINES: usize = 64;
+
+/// Campos de `Course` que se pueden pedir con `?fields=` en el listado y el detalle de cursos.
+const COURSE_FIELDS: &[&str] = &[
+    "id",
+    "title",
+    "description",
+    "instructor_id",
+    "category_id",
+    "status",
+    "published_at",
+    "publish_at",
+    "max_students",
+    "allow_reviews",
+    "review_requires_completion",
+    "reserve_pending_slots",
+    "issues_certificate",
+    "deleted_at",
+    "is_featured",
+    "featured_rank",
+    "slug",
+    "version",
+    "created_at",
+    "updated_at",
+];
+
+/// Número máximo de IDs aceptados en una sola petición a `POST /courses/batch`.
+const MAX_BATCH_IDS: usize = 100;
+
+/// Número de cursos similares devueltos por defecto y como máximo.
+const DEFAULT_SIMILAR_COURSES: i64 = 6;
+const MAX_SIMILAR_COURSES: i64 = 20;
+
+/// Número de cursos vistos recientemente devueltos por defecto y como máximo.
+const DEFAULT_RECENTLY_VIEWED: i64 = 10;
+const MAX_RECENTLY_VIEWED: i64 = 50;
+
+/// Cada cuánto se buscan borradores con publicación programada vencida.
+const SCHEDULED_PUBLISH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Número máximo de sugerencias devueltas por `GET /tags/suggest`.
+const MAX_TAG_SUGGESTIONS: i64 = 10;
+
+/// Ventana (en días) usada por defecto para calcular los cursos en tendencia.
+const DEFAULT_TRENDING_DAYS: i32 = 7;
+
+/// Número máximo de intentos de entrega de un webhook y espera inicial entre intentos.
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+const WEBHOOK_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Tamaño máximo (en bytes) de un material subido.
+const MAX_MATERIAL_BYTES: usize = 50 * 1024 * 1024;
+
+/// Tiempo de validez (en segundos) de un enlace de descarga firmado.
+const DOWNLOAD_TOKEN_TTL_SECONDS: i64 = 15 * 60;
+
 // --- Estado de la Aplicación ---
 
 struct AppState {
     db_pool: PgPool,
+    http_client: reqwest::Client,
+    webhook: Option<WebhookSettings>,
+    /// Directorio donde se guardan los ficheros de los materiales (`MATERIALS_DIR`).
+    materials_dir: PathBuf,
+    /// Secreto con el que se firman los enlaces de descarga (`DOWNLOAD_TOKEN_SECRET`).
+    download_token_secret: String,
+    /// Si el slug se regenera al cambiar el título (`SLUG_FOLLOWS_TITLE`, por defecto `false`).
+    /// Sin ella, el slug se mantiene estable para no romper los enlaces ya compartidos.
+    slug_follows_title: bool,
+    /// Número de reportes con el que una reseña se oculta hasta que se modere
+    /// (`REVIEW_REPORT_THRESHOLD`, por defecto 3).
+    review_report_threshold: i64,
+    /// Ediciones de los cursos activadas (`COURSE_OFFERINGS_ENABLED`).
+    offerings_enabled: bool,
+    /// Longitud máxima de los títulos y descripciones de cursos y lecciones.
+    text_limits: TextLimits,
 }
 
-// --- Manejadores de Endpoints ---
+/// Cuerpo del webhook enviado cuando un curso pasa a `published`.
+#[derive(Serialize)]
+struct CoursePublishedEvent {
+    event: &'static str,
+    course_id: Uuid,
+    title: String,
+    instructor_id: Uuid,
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    published_at: Option<DateTime<Utc>>,
+}
 
-async fn create_course(
-    state: web::Data<AppState>,
-    auth_user: AuthenticatedUser,
-    course_data: web::Json<CreateCourse>,
-) -> impl Responder {
-    let new_course = sqlx::query_as!(
-        Course,
-        r#"
-        INSERT INTO courses (title, description, instructor_id)
-        VALUES ($1, $2, $3)
-        RETURNING id, title, description, instructor_id, created_at, updated_at
-        "#,
-        course_data.title,
-        course_data.description,
-        auth_user.id, // Usamos el ID del token validado
-    )
-    .fetch_one(&state.db_pool)
-    .await;
+// --- Funciones Auxiliares ---
 
-    match new_course {
-        Ok(course) => HttpResponse::Created().json(course),
-        Err(e) => {
-            tracing::error!("Failed to create course: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to create course")
-        }
+/// Deserializa un campo anulable distinguiendo si venía en el cuerpo: con `#[serde(default)]`,
+/// un campo ausente queda en `None`, `null` en `Some(None)` y un valor en `Some(Some(valor))`.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Recorta los espacios alrededor de un texto recibido y comprueba que no supera `max`
+/// caracteres. Si los supera, devuelve el error `field_too_long` (para responder `400`).
+fn trimmed_text(field: &'static str, value: &str, max: usize) -> Result<String, ApiError> {
+    let value = value.trim();
+    if value.chars().count() > max {
+        return Err(
+            ApiError::new("field_too_long", format!("'{}' must be at most {} characters long", field, max))
+                .with_field(field),
+        );
     }
+    Ok(value.to_string())
 }
 
-async fn get_courses(state: web::Data<AppState>) -> impl Responder {
-    let courses = sqlx::query_as!(
-        Course,
-        r#"
-        SELECT id, title, description, instructor_id, created_at, updated_at 
-        FROM courses
-        ORDER BY created_at DESC
-        "#
-    )
-    .fetch_all(&state.db_pool)
-    .await;
+/// Como `trimmed_text`, pero además el texto no puede quedar vacío (error `field_required`).
+fn required_text(field: &'static str, value: &str, max: usize) -> Result<String, ApiError> {
+    let value = trimmed_text(field, value, max)?;
+    if value.is_empty() {
+        return Err(ApiError::new("field_required", format!("'{}' must not be empty", field)).with_field(field));
+    }
+    Ok(value)
+}
 
-    match courses {
-        Ok(courses) => HttpResponse::Ok().json(courses),
-        Err(e) => {
-            tracing::error!("Failed to fetch courses: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to fetch courses")
-        }
+/// Valida un texto opcional con `trimmed_text`; uno vacío tras recortarlo cuenta como ausente.
+fn optional_text(field: &'static str, value: Option<&str>, max: usize) -> Result<Option<String>, ApiError> {
+    match value {
+        Some(value) => Ok(Some(trimmed_text(field, value, max)?).filter(|value| !value.is_empty())),
+        None => Ok(None),
     }
 }
 
-async fn get_course_by_id(
-    state: web::Data<AppState>,
-    path: web::Path<Uuid>,
-) -> impl Responder {
-    let course_id = path.into_inner();
+/// Normaliza una lista de etiquetas: recorta espacios, pasa a minúsculas y elimina vacías y duplicadas.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
 
-    let course = sqlx::query_as!(
-        Course,
+/// Longitud máxima del slug generado a partir del título (sin contar el sufijo de colisión).
+const MAX_SLUG_LENGTH: usize = 80;
+
+/// Convierte un título en slug: minúsculas, sin tildes y con guiones entre palabras
+/// (`"Introducción a Rust"` → `"introduccion-a-rust"`). Si no queda nada, devuelve `"course"`.
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.to_lowercase().chars() {
+        let c = match c {
+            'á' | 'à' | 'ä' | 'â' | 'ã' => 'a',
+            'é' | 'è' | 'ë' | 'ê' => 'e',
+            'í' | 'ì' | 'ï' | 'î' => 'i',
+            'ó' | 'ò' | 'ö' | 'ô' | 'õ' => 'o',
+            'ú' | 'ù' | 'ü' | 'û' => 'u',
+            'ñ' => 'n',
+            'ç' => 'c',
+            c => c,
+        };
+        if c.is_ascii_alphanumeric() {
+            if slug.len() == MAX_SLUG_LENGTH {
+                break;
+            }
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "course".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Devuelve un slug libre a partir de `base`: el propio `base` o, si ya lo usa otro curso,
+/// `base-2`, `base-3`, ... `exclude` es el curso que se está editando, cuyo slug actual no cuenta.
+async fn unique_slug(
+    executor: impl sqlx::PgExecutor<'_>,
+    base: &str,
+    exclude: Option<Uuid>,
+) -> Result<String, sqlx::Error> {
+    let taken: Vec<String> = sqlx::query_scalar!(
         r#"
-        SELECT id, title, description, instructor_id, created_at, updated_at 
-        FROM courses
-        WHERE id = $1
+        SELECT slug FROM courses
+        WHERE (slug = $1 OR slug LIKE $2)
+          AND ($3::uuid IS NULL OR id <> $3)
         "#,
-        course_id
+        base,
+        format!("{}-%", escape_like(base)),
+        exclude
     )
-    .fetch_one(&state.db_pool)
-    .await;
+    .fetch_all(executor)
+    .await?;
 
-    match course {
-        Ok(course) => HttpResponse::Ok().json(course),
-        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().body("Course not found"),
-        Err(e) => {
-            tracing::error!("Failed to fetch course: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to fetch course")
-        }
+    if !taken.iter().any(|slug| slug == base) {
+        return Ok(base.to_string());
     }
+    let suffix = (2..)
+        .find(|n| !taken.iter().any(|slug| *slug == format!("{}-{}", base, n)))
+        .unwrap_or(2);
+    Ok(format!("{}-{}", base, suffix))
 }
 
-async fn update_course_by_id(
-    state: web::Data<AppState>,
-    auth_user: AuthenticatedUser,
-    path: web::Path<Uuid>,
-    update_data: web::Json<UpdateCourse>,
-) -> impl Responder {
-    let course_id = path.into_inner();
-    let instructor_id = auth_user.id;
-
-    // 1. Verificar que el curso existe.
-    let course = match sqlx::query_as!(Course, "SELECT * FROM courses WHERE id = $1", course_id)
-        .fetch_optional(&state.db_pool)
-        .await
-    {
-        Ok(Some(course)) => course,
-        Ok(None) => return HttpResponse::NotFound().body("Course not found"),
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+/// Escapa los comodines de `LIKE` (`%`, `_` y `\`) para usar un texto como prefijo literal.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
 
-    // 2. Verificar permisos: solo el instructor que creó el curso o un admin pueden modificarlo.
-    if course.instructor_id != instructor_id && auth_user.role != UserRole::Admin {
-        return HttpResponse::Forbidden().body("You are not authorized to update this course");
+/// Asocia las etiquetas indicadas a un curso, creando las que todavía no existen.
+async fn attach_course_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    if tags.is_empty() {
+        return Ok(());
     }
 
-    // 2. Preparar los nuevos datos. Si un campo es None en la petición, se mantiene el valor antiguo.
-    let title = update_data.title.clone().unwrap_or(course.title);
-    let description = update_data.description.clone();
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT * FROM UNNEST($1::text[]) ON CONFLICT (name) DO NOTHING",
+        tags
+    )
+    .execute(&mut **tx)
+    .await?;
 
-    // 4. Ejecutar la actualización.
-    let updated_course = sqlx::query_as!(
-        Course,
+    sqlx::query!(
         r#"
-        UPDATE courses SET title = $1, description = $2, updated_at = NOW()
-        WHERE id = $3
-        RETURNING id, title, description, instructor_id, created_at, updated_at
+        INSERT INTO course_tags (course_id, tag_id)
+        SELECT $1, id FROM tags WHERE name = ANY($2)
+        ON CONFLICT DO NOTHING
         "#,
-        title,
-        description,
-        course_id
+        course_id,
+        tags
     )
-    .fetch_one(&state.db_pool)
-    .await;
+    .execute(&mut **tx)
+    .await?;
 
-    match updated_course {
-        Ok(course) => HttpResponse::Ok().json(course),
-        Err(e) => {
-            tracing::error!("Failed to update course: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to update course")
-        }
-    }
+    Ok(())
 }
 
-async fn delete_course_by_id(
+/// Si el estudiante ya completó todas las lecciones del curso, pasa sus inscripciones activas a
+/// `completed` y, si el curso tiene `issues_certificate`, le emite el certificado, todo en la
+/// misma transacción. Devuelve si el curso quedó completado y el id del certificado emitido.
+async fn complete_course_if_finished(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    course_id: Uuid,
+) -> Result<(bool, Option<Uuid>), sqlx::Error> {
+    let finished = sqlx::query_scalar!(
+        r#"
+        SELECT NOT EXISTS (
+            SELECT 1 FROM lessons l
+            WHERE l.course_id = $1
+              AND NOT EXISTS (SELECT 1 FROM lesson_progress p WHERE p.lesson_id = l.id AND p.user_id = $2)
+        ) as "finished!"
+        "#,
+        course_id,
+        user_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    if !finished {
+        return Ok((false, None));
+    }
+
+    let completed = sqlx::query!(
+        "UPDATE enrollments SET status = 'completed' WHERE user_id = $1 AND course_id = $2 AND status = 'active'",
+        user_id,
+        course_id
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if completed == 0 {
+        return Ok((false, None));
+    }
+
+    let certificate_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO certificates (user_id, course_id)
+        SELECT $1, id FROM courses WHERE id = $2 AND issues_certificate
+        ON CONFLICT (user_id, course_id) DO NOTHING
+        RETURNING id
+        "#,
+        user_id,
+        course_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok((true, certificate_id))
+}
+
+/// Sustituye las etiquetas de un curso por `tags`: quita las que ya no están y añade las nuevas,
+/// sin tocar las que se mantienen.
+async fn replace_course_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM course_tags ct
+        USING tags t
+        WHERE ct.tag_id = t.id AND ct.course_id = $1 AND t.name <> ALL($2)
+        "#,
+        course_id,
+        tags
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    attach_course_tags(tx, course_id, tags).await
+}
+
+/// Relación de un usuario con un curso, según la política de visibilidad: un curso publicado es
+/// público, y uno en borrador o archivado solo lo ven su propietario, los admins y sus
+/// estudiantes inscritos. A quien no puede ver un curso se le responde 404, como si no existiera;
+/// 403 solo cuando ya puede saber que el curso existe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CourseAccess {
+    /// El curso no existe o el usuario no puede verlo.
+    Hidden,
+    /// Curso publicado en el que el usuario no está inscrito.
+    Public,
+    /// El usuario está inscrito (inscripción activa o completada).
+    Enrolled,
+    /// Propietario del curso o admin.
+    Owner,
+}
+
+impl CourseAccess {
+    /// Indica si el usuario puede ver el contenido del curso (lecciones y materiales).
+    fn can_read_content(self) -> bool {
+        matches!(self, CourseAccess::Enrolled | CourseAccess::Owner)
+    }
+
+    /// Respuesta de error para quien no puede gestionar el curso: 404 si ni siquiera puede verlo
+    /// y 403 (con `forbidden_message`) si lo ve.
+    fn owner_error(self, forbidden_message: &'static str) -> Option<HttpResponse> {
+        match self {
+            CourseAccess::Owner => None,
+            CourseAccess::Hidden => {
+                Some(HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")))
+            }
+            CourseAccess::Public | CourseAccess::Enrolled => {
+                Some(HttpResponse::Forbidden().json(ApiError::new("not_course_owner", forbidden_message)))
+            }
+        }
+    }
+}
+
+/// Calcula el `CourseAccess` de un usuario (o de un visitante anónimo) sobre un curso. Acepta el
+/// pool o una transacción (`&mut **tx`).
+async fn course_access<'e>(
+    executor: impl PgExecutor<'e>,
+    user: Option<&AuthenticatedUser>,
+    course_id: Uuid,
+) -> Result<CourseAccess, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            c.instructor_id,
+            c.status as "status: CourseStatus",
+            c.deleted_at,
+            EXISTS (
+                SELECT 1 FROM enrollments e
+                WHERE e.course_id = c.id AND e.user_id = $2 AND e.status IN ('active', 'completed')
+            ) as "is_enrolled!"
+        FROM courses c
+        WHERE c.id = $1
+        "#,
+        course_id,
+        user.map(|user| user.id)
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    // Un curso borrado no existe para nadie, ni siquiera para su propietario.
+    let Some(row) = row.filter(|row| row.deleted_at.is_none()) else {
+        return Ok(CourseAccess::Hidden);
+    };
+    Ok(match user {
+        Some(user) if row.instructor_id == user.id || user.role == UserRole::Admin => CourseAccess::Owner,
+        _ if row.is_enrolled => CourseAccess::Enrolled,
+        _ if row.status == CourseStatus::Published => CourseAccess::Public,
+        _ => CourseAccess::Hidden,
+    })
+}
+
+/// Exige que el usuario sea el propietario del curso o un admin, siguiendo la política de
+/// `CourseAccess`. Uso en un manejador:
+/// `if let Some(response) = require_course_owner(&state.db_pool, &auth_user, course_id, "...").await { return response; }`
+async fn require_course_owner<'e>(
+    executor: impl PgExecutor<'e>,
+    user: &AuthenticatedUser,
+    course_id: Uuid,
+    forbidden_message: &'static str,
+) -> Option<HttpResponse> {
+    match course_access(executor, Some(user), course_id).await {
+        Ok(access) => access.owner_error(forbidden_message),
+        Err(e) if db::is_timeout(&e) => Some(db::timeout_response()),
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            Some(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/// Avisa a los estudiantes con inscripción activa o pendiente de que su curso se archivó, con
+/// una notificación `course_archived` por inscripción guardada en la misma transacción que el
+/// cambio de estado. Devuelve cuántas se crearon.
+async fn notify_course_archived(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    course_ids: &[Uuid],
+) -> Result<u64, sqlx::Error> {
+    let students = sqlx::query!(
+        r#"
+        SELECT e.user_id, c.title
+        FROM enrollments e
+        JOIN courses c ON c.id = e.course_id
+        WHERE e.course_id = ANY($1) AND e.status IN ('active', 'pending')
+        "#,
+        course_ids
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let notifications: Vec<Notification> = students
+        .into_iter()
+        .map(|student| Notification {
+            user_id: student.user_id,
+            kind: "course_archived",
+            message: format!("The course \"{}\" has been archived", student.title),
+        })
+        .collect();
+    notifications::create_notifications(tx, &notifications).await
+}
+
+/// Guarda en `course_revisions` el contenido actual del curso (título, descripción y lecciones).
+/// Se llama dentro de la transacción que lo va a modificar, antes del cambio: si el cambio no se
+/// confirma, tampoco queda la revisión.
+async fn record_course_revision(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    course_id: Uuid,
+    author_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO course_revisions (course_id, author_id, title, description, lessons)
+        SELECT c.id, $2, c.title, c.description, COALESCE(
+            (
+                SELECT jsonb_agg(
+                    jsonb_build_object('id', l.id, 'title', l.title, 'content', l.content, 'position', l.position)
+                    ORDER BY l.position, l.created_at
+                )
+                FROM lessons l
+                WHERE l.course_id = c.id
+            ),
+            '[]'::jsonb
+        )
+        FROM courses c
+        WHERE c.id = $1
+        "#,
+        course_id,
+        author_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Lecciones (en orden) y materiales de un curso, para quienes pueden ver su contenido.
+async fn fetch_course_content(
+    pool: &PgPool,
+    course_id: Uuid,
+) -> Result<(Vec<Lesson>, Vec<CourseMaterial>), sqlx::Error> {
+    let lessons = sqlx::query_as!(
+        Lesson,
+        r#"
+        SELECT id, course_id, title, content, position, created_at, updated_at
+        FROM lessons
+        WHERE course_id = $1
+        ORDER BY position, created_at
+        "#,
+        course_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let materials = sqlx::query_as!(
+        CourseMaterial,
+        r#"
+        SELECT id, course_id, file_name, content_type, size_bytes, created_at
+        FROM course_materials
+        WHERE course_id = $1
+        ORDER BY created_at
+        "#,
+        course_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok((lessons, materials))
+}
+
+// --- Firmas HMAC ---
+
+/// Calcula la firma HMAC-SHA256 (en hexadecimal) de unos datos con el secreto indicado.
+fn hmac_sha256_hex(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verifica en tiempo constante una firma HMAC-SHA256 en hexadecimal.
+fn verify_hmac_sha256_hex(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Genera un token de descarga con el formato `<material_id>.<expira_unix>.<firma>`.
+fn sign_download_token(secret: &str, material_id: Uuid, expires_at: DateTime<Utc>) -> String {
+    let payload = format!("{}.{}", material_id, expires_at.timestamp());
+    let signature = hmac_sha256_hex(secret, payload.as_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// Comprueba la firma y la caducidad de un token de descarga y devuelve el ID del material.
+fn verify_download_token(secret: &str, token: &str) -> Option<Uuid> {
+    let mut parts = token.splitn(3, '.');
+    let (material_id, expires_at, signature) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let payload = format!("{}.{}", material_id, expires_at);
+    if !verify_hmac_sha256_hex(secret, payload.as_bytes(), signature) {
+        return None;
+    }
+
+    let expires_at: i64 = expires_at.parse().ok()?;
+    if Utc::now().timestamp() > expires_at {
+        return None;
+    }
+
+    material_id.parse().ok()
+}
+
+/// Lee la versión esperada de la cabecera `If-Match` (por ejemplo `"3"` o `W/"3"`).
+fn if_match_version(req: &HttpRequest) -> Option<i32> {
+    let value = req.headers().get(header::IF_MATCH)?.to_str().ok()?;
+    value.trim().trim_start_matches("W/").trim_matches('"').parse().ok()
+}
+
+/// Construye el valor de la cabecera `ETag` de un curso a partir de su versión.
+fn course_etag(course: &Course) -> (header::HeaderName, String) {
+    (header::ETAG, format!("\"{}\"", course.version))
+}
+
+// --- Webhooks ---
+
+/// Envía el webhook, reintentando con espera exponencial si el destino falla.
+/// El receptor puede verificar la cabecera `X-CCB-Signature` (`sha256=<hex>`) con el secreto compartido.
+async fn deliver_webhook(client: reqwest::Client, config: WebhookSettings, body: Vec<u8>) {
+    let signature = format!("sha256={}", hmac_sha256_hex(&config.secret, &body));
+    let mut backoff = WEBHOOK_INITIAL_BACKOFF;
+
+    for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+        let result = client
+            .post(&config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-CCB-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        match result {
+            Ok(res) if res.status().is_success() => {
+                info!("Webhook delivered to {} on attempt {}", config.url, attempt);
+                return;
+            }
+            Ok(res) => warn!("Webhook attempt {} failed with status {}", attempt, res.status()),
+            Err(e) => warn!("Webhook attempt {} failed: {:?}", attempt, e),
+        }
+
+        if attempt < WEBHOOK_MAX_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    tracing::error!("Giving up on webhook to {} after {} attempts", config.url, WEBHOOK_MAX_ATTEMPTS);
+}
+
+/// Programa en segundo plano el webhook de publicación, sin bloquear la respuesta al cliente.
+fn notify_course_published(state: &AppState, course: &Course) {
+    let Some(config) = state.webhook.clone() else {
+        return;
+    };
+
+    let event = CoursePublishedEvent {
+        event: "course.published",
+        course_id: course.id,
+        title: course.title.clone(),
+        instructor_id: course.instructor_id,
+        published_at: course.published_at,
+    };
+
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to serialize webhook payload: {:?}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(deliver_webhook(state.http_client.clone(), config, body));
+}
+
+/// Registra (en segundo plano) que el usuario ha visto el curso. Un fallo solo se registra
+/// en el log: nunca debe afectar a la respuesta del detalle del curso.
+fn record_course_view(db_pool: &PgPool, user_id: Uuid, course_id: Uuid) {
+    let db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO course_views (user_id, course_id) VALUES ($1, $2)
+            ON CONFLICT (user_id, course_id) DO UPDATE SET viewed_at = NOW()
+            "#,
+            user_id,
+            course_id
+        )
+        .execute(&db_pool)
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to record view of course {}: {:?}", course_id, e);
+        }
+    });
+}
+
+// --- Publicación Programada ---
+
+/// Publica los borradores cuya `publish_at` ya ha pasado y devuelve los cursos publicados.
+///
+/// Es seguro ejecutarla desde varias réplicas a la vez: el `UPDATE` vuelve a comprobar
+/// `status = 'draft'` fila a fila, así que cada curso solo lo publica una de ellas.
+async fn publish_scheduled_courses(db_pool: &PgPool) -> Result<Vec<Course>, sqlx::Error> {
+    sqlx::query_as!(
+        Course,
+        r#"
+        UPDATE courses SET
+            status = 'published',
+            published_at = COALESCE(published_at, NOW()),
+            version = version + 1,
+            updated_at = NOW()
+        WHERE status = 'draft' AND publish_at IS NOT NULL AND publish_at <= NOW() AND deleted_at IS NULL
+        RETURNING id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Tarea en segundo plano que revisa periódicamente las publicaciones programadas.
+async fn run_scheduled_publisher(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(SCHEDULED_PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match publish_scheduled_courses(&state.db_pool).await {
+            Ok(courses) => {
+                for course in &courses {
+                    info!("Scheduled publish: course {} is now published", course.id);
+                    notify_course_published(&state, course);
+                }
+            }
+            Err(e) => tracing::error!("Failed to publish scheduled courses: {:?}", e),
+        }
+    }
+}
+
+// --- Manejadores de Endpoints ---
+
+async fn create_course(
     state: web::Data<AppState>,
     auth_user: AuthenticatedUser,
+    course_data: web::Json<CreateCourse>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    if course_data.max_students.is_some_and(|max| max <= 0) {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("must_be_positive", "max_students must be greater than 0").with_field("max_students"));
+    }
+    let limits = state.text_limits;
+    let title = match required_text("title", &course_data.title, limits.title) {
+        Ok(title) => title,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+    let description = match optional_text("description", course_data.description.as_deref(), limits.description) {
+        Ok(description) => description,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    let tags = normalize_tags(course_data.tags.as_deref().unwrap_or_default());
+
+    // El curso y sus etiquetas se guardan en una misma transacción.
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create course"));
+        }
+    };
+
+    let slug = match unique_slug(&mut *tx, &slugify(&title), None).await {
+        Ok(slug) => slug,
+        Err(e) => {
+            tracing::error!("Failed to generate course slug: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create course"));
+        }
+    };
+
+    let new_course = sqlx::query_as!(
+        Course,
+        r#"
+        INSERT INTO courses (title, description, instructor_id, publish_at, max_students, allow_reviews, slug, reserve_pending_slots, issues_certificate, review_requires_completion)
+        VALUES ($1, $2, $3, $4, $5, COALESCE($6, TRUE), $7, COALESCE($8, FALSE), COALESCE($9, FALSE), COALESCE($10, FALSE))
+        RETURNING id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        "#,
+        title,
+        description,
+        auth_user.id, // Usamos el ID del token validado
+        course_data.publish_at,
+        course_data.max_students,
+        course_data.allow_reviews,
+        slug,
+        course_data.reserve_pending_slots,
+        course_data.issues_certificate,
+        course_data.review_requires_completion,
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let course = match new_course {
+        Ok(course) => course,
+        // Otra petición concurrente se quedó con el mismo slug.
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("slug_conflict", "Another course with the same slug was just created; try again"))
+        }
+        // El instructor del token ya no existe.
+        Err(e) if db::is_foreign_key_violation(&e) => return db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to create course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create course"));
+        }
+    };
+
+    if let Err(e) = attach_course_tags(&mut tx, course.id, &tags).await {
+        tracing::error!("Failed to attach course tags: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create course"));
+    }
+
+    match tx.commit().await {
+        Ok(_) => HttpResponse::Created().json(course),
+        Err(e) => {
+            tracing::error!("Failed to commit course creation: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create course"))
+        }
+    }
+}
+
+async fn get_courses(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    query: web::Query<CoursesQuery>,
+) -> impl Responder {
+    let fields = match FieldSet::parse(query.fields.as_deref(), COURSE_FIELDS) {
+        Ok(fields) => fields,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+    // Sin usuario (o sin `exclude_enrolled`) el LEFT JOIN no encuentra filas y no filtra nada.
+    let exclude_user_id = auth_user.as_ref().filter(|_| query.exclude_enrolled).map(|user| user.id);
+    let is_admin = auth_user.as_ref().is_some_and(|user| user.role == UserRole::Admin);
+    let include_deleted = query.include_deleted && is_admin;
+
+    // Solo los cursos que el usuario puede ver, con el criterio de `course_access`.
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT c.id, c.title, c.description, c.instructor_id, c.category_id, c.status as "status: CourseStatus", c.published_at, c.publish_at, c.max_students, c.allow_reviews, c.review_requires_completion, c.reserve_pending_slots, c.issues_certificate, c.deleted_at, c.is_featured, c.featured_rank, c.slug, c.version, c.created_at, c.updated_at
+        FROM courses c
+        LEFT JOIN enrollments e ON e.course_id = c.id AND e.user_id = $1 AND e.status <> 'dropped'
+        WHERE e.user_id IS NULL AND (c.deleted_at IS NULL OR $2)
+          AND (c.status = 'published' OR $4 OR c.instructor_id = $3 OR EXISTS (
+              SELECT 1 FROM enrollments v
+              WHERE v.course_id = c.id AND v.user_id = $3 AND v.status IN ('active', 'completed')
+          ))
+        ORDER BY c.created_at DESC
+        "#,
+        exclude_user_id,
+        include_deleted,
+        auth_user.as_ref().map(|user| user.id),
+        is_admin
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    let courses = match courses {
+        Ok(courses) => courses,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch courses: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"));
+        }
+    };
+
+    match fields.project(&courses) {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) => {
+            tracing::error!("Failed to serialize courses: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/{id}
+/// Cualquiera que pueda ver el curso (ver `CourseAccess`) recibe su resumen; los estudiantes
+/// inscritos, el propietario y los admins reciben además sus lecciones y materiales.
+async fn get_course_by_id(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
     path: web::Path<Uuid>,
+    query: web::Query<CourseDetailQuery>,
 ) -> impl Responder {
     let course_id = path.into_inner();
-    let instructor_id = auth_user.id;
 
-    // Para eliminar, requerimos que sea el instructor propietario o un admin.
-    // La consulta SQL se simplifica si lo manejamos en el código.
-    let query = if auth_user.role == UserRole::Admin {
-        sqlx::query!("DELETE FROM courses WHERE id = $1", course_id)
-    } else {
-        sqlx::query!(
-            "DELETE FROM courses WHERE id = $1 AND instructor_id = $2",
-            course_id,
-            instructor_id
-        )
+    let course = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at 
+        FROM courses
+        WHERE id = $1
+        "#,
+        course_id
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    course_detail_response(&state, auth_user, &query, course).await
+}
+
+/// Maneja las peticiones GET a /courses/by-slug/{slug}
+/// Igual que `GET /courses/{id}`, pero buscando el curso por su slug.
+async fn get_course_by_slug(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    path: web::Path<String>,
+    query: web::Query<CourseDetailQuery>,
+) -> impl Responder {
+    let slug = path.into_inner();
+
+    let course = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        FROM courses
+        WHERE slug = $1
+        "#,
+        slug
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    course_detail_response(&state, auth_user, &query, course).await
+}
+
+/// Construye la respuesta de detalle de un curso ya consultado: 404 si no existe o el usuario
+/// no puede verlo; si no, su resumen y, según el acceso, sus lecciones y materiales. Un curso
+/// borrado solo lo ve un admin que lo pide con `include_deleted`.
+async fn course_detail_response(
+    state: &AppState,
+    auth_user: Option<AuthenticatedUser>,
+    query: &CourseDetailQuery,
+    course: Result<Course, sqlx::Error>,
+) -> HttpResponse {
+    let known_fields = [COURSE_FIELDS, &["lessons", "materials"]].concat();
+    let fields = match FieldSet::parse(query.fields.as_deref(), &known_fields) {
+        Ok(fields) => fields,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    let course = match course {
+        Ok(course) => course,
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch course"));
+        }
     };
 
-    // Usamos `execute` para borrar, que devuelve el número de filas afectadas.
-    let result = query.execute(&state.db_pool).await;
+    let is_admin = auth_user.as_ref().is_some_and(|user| user.role == UserRole::Admin);
+    if course.deleted_at.is_some() && !(query.include_deleted && is_admin) {
+        return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"));
+    }
 
-    match result {
-        Ok(res) if res.rows_affected() == 1 => HttpResponse::NoContent().finish(),
-        Ok(_) => HttpResponse::NotFound().body("Course not found or you are not the owner"),
+    let access = match course_access(&state.db_pool, auth_user.as_ref(), course.id).await {
+        // `course_access` oculta los cursos borrados; aquí solo llega uno si lo pide un admin.
+        Ok(_) if course.deleted_at.is_some() => CourseAccess::Owner,
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(access) => access,
         Err(e) => {
-            tracing::error!("Failed to delete course: {:?}", e);
-            HttpResponse::InternalServerError().finish()
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch course"));
         }
+    };
+
+    if let Some(user) = auth_user.as_ref().filter(|_| course.deleted_at.is_none()) {
+        record_course_view(&state.db_pool, user.id, course.id);
     }
-}
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    dotenvy::dotenv().ok();
+    let wants_content = fields.includes("lessons") || fields.includes("materials");
+    let (lessons, materials) = if access.can_read_content() && wants_content {
+        match fetch_course_content(&state.db_pool, course.id).await {
+            Ok((lessons, materials)) => (Some(lessons), Some(materials)),
+            Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+            Err(e) => {
+                tracing::error!("Failed to fetch course content: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to fetch course"));
+            }
+        }
+    } else {
+        (None, None)
+    };
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to create database pool.");
+    let etag = course_etag(&course);
+    match fields.project(&CourseDetail { course, lessons, materials }) {
+        Ok(detail) => HttpResponse::Ok().insert_header(etag).json(detail),
+        Err(e) => {
+            tracing::error!("Failed to serialize course: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch course"))
+        }
+    }
+}
 
-    info!("🚀 Servidor de cursos iniciado en http://localhost:8082");
+/// Maneja las peticiones GET a /courses/newest
+/// Devuelve los cursos publicados más recientes, paginados.
+async fn get_newest_courses(
+    state: web::Data<AppState>,
+    page: PageParams,
+) -> impl Responder {
+    let (limit, offset) = (page.limit(), page.offset());
 
-    HttpServer::new(move || {
-        App::new()
-            // Middleware de CORS: permite peticiones desde cualquier origen.
-            // ¡IMPORTANTE! En producción, esto debería restringirse a dominios específicos.
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header(),
-            )
-            .wrap(actix_web::middleware::Logger::default())
-            .app_data(web::Data::new(AppState { db_pool: db_pool.clone() }))
-            // Agrupamos las rutas bajo el scope "/courses"
-            .service(
-                web::scope("/courses")
-                    .route("", web::get().to(get_courses))
-                    .route("", web::post().to(create_course).wrap(JwtMiddleware::new(vec![
-                        AuthRole::Instructor,
-                        AuthRole::Admin,
-                    ])))
-                    .route("/{id}", web::get().to(get_course_by_id)) // GET /courses/{id}
-                    .route("/{id}", web::put().to(update_course_by_id)) // PUT /courses/{id}
-                    .route("/{id}", web::delete().to(delete_course_by_id)), // DELETE /courses/{id}
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        FROM courses
+        WHERE status = 'published' AND deleted_at IS NULL
+        ORDER BY published_at DESC NULLS LAST, created_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch newest courses: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/{id}/similar
+/// Devuelve cursos publicados ordenados por afinidad con el curso indicado: cada etiqueta en
+/// común suma un punto y compartir categoría suma otro. A igual afinidad (también si el curso
+/// no tiene etiquetas ni categoría) se prefieren los publicados más recientemente.
+async fn get_similar_courses(
+    state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    query: web::Query<SimilarCoursesQuery>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_SIMILAR_COURSES).clamp(1, MAX_SIMILAR_COURSES);
+
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT c.id, c.title, c.description, c.instructor_id, c.category_id, c.status as "status: CourseStatus", c.published_at, c.publish_at, c.max_students, c.allow_reviews, c.review_requires_completion, c.reserve_pending_slots, c.issues_certificate, c.deleted_at, c.is_featured, c.featured_rank, c.slug, c.version, c.created_at, c.updated_at
+        FROM courses base
+        JOIN courses c ON c.id <> base.id AND c.status = 'published' AND c.deleted_at IS NULL
+        LEFT JOIN course_tags ct ON ct.course_id = c.id
+            AND ct.tag_id IN (SELECT bt.tag_id FROM course_tags bt WHERE bt.course_id = base.id)
+        WHERE base.id = $1
+        GROUP BY c.id, base.category_id
+        ORDER BY
+            COUNT(ct.tag_id) + CASE WHEN c.category_id = base.category_id THEN 1 ELSE 0 END DESC,
+            c.published_at DESC NULLS LAST,
+            c.created_at DESC
+        LIMIT $2
+        "#,
+        course_id,
+        limit
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        // Sin resultados puede que el curso no exista: solo entonces hacemos la comprobación.
+        Ok(courses) if courses.is_empty() => {
+            match sqlx::query_scalar!(r#"SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1 AND deleted_at IS NULL) as "exists!""#, course_id)
+                .fetch_one(&state.db_pool)
+                .await
+            {
+                Ok(true) => HttpResponse::Ok().json(courses),
+                Ok(false) => HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+                Err(e) => {
+                    tracing::error!("Failed to check course: {:?}", e);
+                    HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to fetch similar courses"))
+                }
+            }
+        }
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch similar courses: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch similar courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/trending
+/// Ordena los cursos publicados por el número de inscripciones recibidas en los últimos `days` días.
+async fn get_trending_courses(
+    state: web::Data<AppState>,
+    query: web::Query<TrendingQuery>,
+    page: PageParams,
+) -> impl Responder {
+    let (limit, offset) = (page.limit(), page.offset());
+    let days = query.days.unwrap_or(DEFAULT_TRENDING_DAYS).clamp(1, 365);
+
+    // Usamos un LEFT JOIN para que los cursos sin inscripciones recientes sigan apareciendo al final.
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT
+            c.id, c.title, c.description, c.instructor_id, c.category_id, c.status as "status: CourseStatus",
+            c.published_at, c.publish_at, c.max_students, c.allow_reviews, c.review_requires_completion, c.reserve_pending_slots, c.issues_certificate, c.deleted_at, c.is_featured, c.featured_rank, c.slug, c.version, c.created_at, c.updated_at
+        FROM courses c
+        LEFT JOIN enrollments e
+            ON e.course_id = c.id
+            AND e.enrollment_date >= NOW() - make_interval(days => $1)
+        WHERE c.status = 'published' AND c.deleted_at IS NULL
+        GROUP BY c.id
+        ORDER BY COUNT(e.user_id) DESC, MAX(e.enrollment_date) DESC NULLS LAST, c.published_at DESC NULLS LAST
+        LIMIT $2 OFFSET $3
+        "#,
+        days,
+        limit,
+        offset
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch trending courses: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/featured
+/// Devuelve los cursos publicados que los admins destacaron, en el orden de `featured_rank`.
+async fn get_featured_courses(state: web::Data<AppState>) -> impl Responder {
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        FROM courses
+        WHERE is_featured AND status = 'published' AND deleted_at IS NULL
+        ORDER BY featured_rank
+        "#
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch featured courses: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /courses/featured (solo administradores)
+/// Sustituye la lista de destacados: los cursos indicados pasan a estar destacados en ese orden
+/// y el resto deja de estarlo (`[]` los quita todos). Se pueden destacar cursos aún sin publicar;
+/// aparecerán en el feed cuando se publiquen.
+async fn set_featured_courses(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    featured_data: web::Json<SetFeaturedCourses>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can feature courses"));
+    }
+
+    let course_ids = &featured_data.course_ids;
+    if course_ids.len() > MAX_BATCH_IDS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("too_many_ids", format!("Too many ids: at most {} are allowed per request", MAX_BATCH_IDS))
+                .with_field("course_ids"),
+        );
+    }
+    if course_ids.iter().collect::<HashSet<_>>().len() != course_ids.len() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("duplicate_ids", "course_ids must not contain duplicates").with_field("course_ids"));
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // 1. Quitar de los destacados los cursos que ya no están en la lista.
+    let unfeatured = sqlx::query!(
+        "UPDATE courses SET is_featured = FALSE, featured_rank = NULL WHERE is_featured AND id <> ALL($1)",
+        &course_ids[..]
+    )
+    .execute(&mut *tx)
+    .await;
+    if let Err(e) = unfeatured {
+        tracing::error!("Failed to unfeature courses: {:?}", e);
+        return HttpResponse::InternalServerError()
+            .json(ApiError::new("internal_error", "Failed to update featured courses"));
+    }
+
+    // 2. Destacar los de la lista con su posición; si falta alguno, no se aplica ningún cambio.
+    let featured = sqlx::query_scalar!(
+        r#"
+        UPDATE courses SET is_featured = TRUE, featured_rank = ordered.rank::int
+        FROM UNNEST($1::uuid[]) WITH ORDINALITY AS ordered(id, rank)
+        WHERE courses.id = ordered.id AND courses.deleted_at IS NULL
+        RETURNING courses.id
+        "#,
+        &course_ids[..]
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let featured = match featured {
+        Ok(featured) => featured,
+        Err(e) => {
+            tracing::error!("Failed to feature courses: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update featured courses"));
+        }
+    };
+    let not_found: Vec<String> = course_ids
+        .iter()
+        .filter(|id| !featured.contains(id))
+        .map(Uuid::to_string)
+        .collect();
+    if !not_found.is_empty() {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("course_not_found", format!("Courses not found: {}", not_found.join(", ")))
+                .with_field("course_ids"),
+        );
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit featured courses: {:?}", e);
+        return HttpResponse::InternalServerError()
+            .json(ApiError::new("internal_error", "Failed to update featured courses"));
+    }
+
+    info!("Featured courses set to {:?} by {}", course_ids, auth_user.id);
+    HttpResponse::Ok().json(SetFeaturedCourses {
+        course_ids: course_ids.clone(),
+    })
+}
+
+/// Maneja las peticiones GET a /courses/export (solo administradores)
+/// Devuelve todo el catálogo como JSON delimitado por saltos de línea (un curso por línea).
+/// Las filas se leen de la base de datos a medida que el cliente consume la respuesta, sin
+/// cargar el catálogo entero en memoria.
+async fn export_courses(state: web::Data<AppState>, auth_user: AuthenticatedUser) -> impl Responder {
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can export the catalog"));
+    }
+
+    // La consulta se ejecuta en su propia tarea y envía cada línea por un canal acotado: si el
+    // cliente lee despacio, la lectura de filas se detiene hasta que haya hueco.
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Result<web::Bytes, actix_web::Error>>(EXPORT_BUFFER_LINES);
+    let db_pool = state.db_pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut courses = sqlx::query_as!(
+            Course,
+            r#"
+            SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+            FROM courses
+            ORDER BY created_at, id
+            "#
+        )
+        .fetch(&db_pool);
+
+        loop {
+            let line = match courses.try_next().await {
+                Ok(Some(course)) => serde_json::to_vec(&course)
+                    .map(|mut line| {
+                        line.push(b'\n');
+                        web::Bytes::from(line)
+                    })
+                    .map_err(actix_web::error::ErrorInternalServerError),
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Failed to export courses: {:?}", e);
+                    Err(actix_web::error::ErrorInternalServerError("Failed to export courses"))
+                }
+            };
+            let failed = line.is_err();
+            // Si el cliente cerró la conexión, dejamos de leer filas.
+            if sender.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (line, receiver))
+    });
+    HttpResponse::Ok().content_type("application/x-ndjson").streaming(body)
+}
+
+/// Maneja las peticiones POST a /courses/batch
+/// Devuelve los cursos cuyos IDs existen, omitiendo silenciosamente los desconocidos, los borrados
+/// y los que el usuario no puede ver (ver `CourseAccess`).
+async fn get_courses_batch(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    batch_data: web::Json<BatchCoursesRequest>,
+) -> impl Responder {
+    if batch_data.ids.len() > MAX_BATCH_IDS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("too_many_ids", format!("Too many ids: at most {} are allowed per request", MAX_BATCH_IDS))
+                .with_field("ids"),
+        );
+    }
+
+    let courses = sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        FROM courses c
+        WHERE id = ANY($1) AND deleted_at IS NULL
+          AND (status = 'published' OR $3 OR instructor_id = $2 OR EXISTS (
+              SELECT 1 FROM enrollments e
+              WHERE e.course_id = c.id AND e.user_id = $2 AND e.status IN ('active', 'completed')
+          ))
+        ORDER BY created_at DESC
+        "#,
+        &batch_data.ids[..],
+        auth_user.as_ref().map(|user| user.id),
+        auth_user.as_ref().is_some_and(|user| user.role == UserRole::Admin)
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch courses batch: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /tags/suggest
+/// Devuelve las etiquetas que empiezan por `q`, ordenadas por número de cursos que las usan.
+async fn suggest_tags(
+    state: web::Data<AppState>,
+    query: web::Query<TagSuggestQuery>,
+) -> impl Responder {
+    let prefix = query.q.as_deref().unwrap_or_default().trim().to_lowercase();
+    let pattern = format!("{}%", escape_like(&prefix));
+
+    let suggestions = sqlx::query_as!(
+        TagSuggestion,
+        r#"
+        SELECT t.name, COUNT(ct.course_id) as "usage_count!"
+        FROM tags t
+        LEFT JOIN course_tags ct ON ct.tag_id = t.id
+        WHERE t.name LIKE $1
+        GROUP BY t.id, t.name
+        ORDER BY COUNT(ct.course_id) DESC, t.name ASC
+        LIMIT $2
+        "#,
+        pattern,
+        MAX_TAG_SUGGESTIONS
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match suggestions {
+        Ok(suggestions) => HttpResponse::Ok().json(suggestions),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch tag suggestions: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch tag suggestions"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /courses/{id}/bookmark
+/// Es idempotente: guardar dos veces el mismo curso no genera un duplicado.
+async fn bookmark_course(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "INSERT INTO bookmarks (user_id, course_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        auth_user.id,
+        course_id
+    )
+    .execute(&state.db_pool)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to bookmark course: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to bookmark course"))
+        }
+    }
+}
+
+/// Maneja las peticiones DELETE a /courses/{id}/bookmark
+async fn remove_bookmark(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "DELETE FROM bookmarks WHERE user_id = $1 AND course_id = $2",
+        auth_user.id,
+        course_id
+    )
+    .execute(&state.db_pool)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to remove bookmark: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to remove bookmark"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /me/bookmarks
+async fn get_my_bookmarks(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+) -> impl Responder {
+    let bookmarks = sqlx::query_as!(
+        BookmarkedCourse,
+        r#"
+        SELECT
+            c.id as course_id,
+            c.title,
+            c.description,
+            c.instructor_id,
+            c.status as "status: CourseStatus",
+            b.created_at as bookmarked_at
+        FROM bookmarks b
+        JOIN courses c ON b.course_id = c.id
+        WHERE b.user_id = $1 AND c.deleted_at IS NULL
+        ORDER BY b.created_at DESC
+        "#,
+        auth_user.id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match bookmarks {
+        Ok(bookmarks) => HttpResponse::Ok().json(bookmarks),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch bookmarks: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch bookmarks"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /me/recently-viewed
+/// Devuelve los últimos cursos distintos que vio el usuario, del más reciente al más antiguo.
+async fn get_recently_viewed(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    query: web::Query<RecentlyViewedQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_RECENTLY_VIEWED).clamp(1, MAX_RECENTLY_VIEWED);
+
+    let courses = sqlx::query_as!(
+        RecentlyViewedCourse,
+        r#"
+        SELECT
+            c.id as course_id,
+            c.title,
+            c.description,
+            c.instructor_id,
+            c.status as "status: CourseStatus",
+            v.viewed_at
+        FROM course_views v
+        JOIN courses c ON v.course_id = c.id
+        WHERE v.user_id = $1 AND c.deleted_at IS NULL
+        ORDER BY v.viewed_at DESC
+        LIMIT $2
+        "#,
+        auth_user.id,
+        limit
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch recently viewed courses: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch recently viewed courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/{id}/capacity
+/// Devuelve el cupo del curso, las inscripciones activas y la lista de espera. Si la petición
+/// está autenticada, incluye además la posición del usuario en la lista de espera.
+async fn get_course_capacity(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            c.max_students,
+            c.reserve_pending_slots,
+            (SELECT COUNT(*) FROM enrollments e WHERE e.course_id = c.id AND e.status = 'active') as "active_count!",
+            (SELECT COUNT(*) FROM enrollments e WHERE e.course_id = c.id AND e.status = 'pending') as "pending_count!",
+            (SELECT COUNT(*) FROM course_waitlist w WHERE w.course_id = c.id) as "waitlist_count!"
+        FROM courses c
+        WHERE c.id = $1
+        "#,
+        course_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    let totals = match totals {
+        Ok(Some(totals)) => totals,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch course capacity: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch course capacity"));
+        }
+    };
+
+    // La posición se calcula por orden de llegada a la lista de espera.
+    let my_waitlist_position = match auth_user {
+        Some(user) => {
+            let position = sqlx::query_scalar!(
+                r#"
+                SELECT position as "position!"
+                FROM (
+                    SELECT user_id, ROW_NUMBER() OVER (ORDER BY created_at, user_id) as position
+                    FROM course_waitlist
+                    WHERE course_id = $1
+                ) w
+                WHERE w.user_id = $2
+                "#,
+                course_id,
+                user.id
             )
+            .fetch_optional(&state.db_pool)
+            .await;
+
+            match position {
+                Ok(position) => position,
+                Err(e) => {
+                    tracing::error!("Failed to fetch waitlist position: {:?}", e);
+                    return HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to fetch course capacity"));
+                }
+            }
+        }
+        None => None,
+    };
+
+    HttpResponse::Ok().json(CourseCapacity {
+        max_students: totals.max_students,
+        active_count: totals.active_count,
+        pending_count: totals.pending_count,
+        reserve_pending_slots: totals.reserve_pending_slots,
+        waitlist_count: totals.waitlist_count,
+        my_waitlist_position,
     })
-    .bind(("0.0.0.0", 8080))?
-    .run()
+}
+
+/// Maneja las peticiones GET a /courses/{id}/students/count
+/// Solo el propietario del curso o un admin pueden consultar el número de inscritos.
+async fn get_student_count(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to view this course's students",
+    )
     .await
+    {
+        return response;
+    }
+
+    let result = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM enrollments WHERE course_id = $1 AND status = 'active'"#,
+        course_id
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match result {
+        Ok(count) => HttpResponse::Ok().json(StudentCountResponse { count }),
+        Err(e) => {
+            tracing::error!("Failed to count course students: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /courses/{id}/analytics/enrollments
+/// Devuelve el número de inscripciones por día, semana o mes, desde la creación del curso hasta
+/// el intervalo actual. Los intervalos sin inscripciones aparecen con `count` 0, para que la
+/// gráfica no se salte días. Cuenta todas las inscripciones, también las que luego se abandonaron.
+/// Solo el propietario del curso o un admin pueden consultarla.
+async fn get_enrollment_analytics(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    query: web::Query<EnrollmentAnalyticsQuery>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to view this course's analytics",
+    )
+    .await
+    {
+        return response;
+    }
+
+    // La serie empieza en el intervalo de la creación del curso (o de la primera inscripción, si
+    // fuese anterior) y `generate_series` rellena los intervalos vacíos.
+    let buckets = sqlx::query_as!(
+        EnrollmentBucket,
+        r#"
+        WITH bounds AS (
+            SELECT
+                date_trunc($2::text, LEAST(c.created_at, MIN(e.enrollment_date))) AS start_at,
+                date_trunc($2::text, NOW()) AS end_at
+            FROM courses c
+            LEFT JOIN enrollments e ON e.course_id = c.id
+            WHERE c.id = $1
+            GROUP BY c.created_at
+        ),
+        buckets AS (
+            SELECT generate_series(start_at, end_at, ('1 ' || $2::text)::interval) AS bucket
+            FROM bounds
+        )
+        SELECT b.bucket as "bucket!", COUNT(e.user_id) as "count!"
+        FROM buckets b
+        LEFT JOIN enrollments e
+            ON e.course_id = $1 AND date_trunc($2::text, e.enrollment_date) = b.bucket
+        GROUP BY b.bucket
+        ORDER BY b.bucket
+        "#,
+        course_id,
+        query.granularity.as_str()
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match buckets {
+        Ok(buckets) => HttpResponse::Ok().json(buckets),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch enrollment analytics: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch enrollment analytics"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT/PATCH a /courses/{id}
+/// Requiere la versión sobre la que se editó (cabecera `If-Match` o campo `expected_version`);
+/// si el curso ha cambiado desde entonces, responde 409 `version_conflict` sin sobrescribir nada.
+/// Las comprobaciones y la actualización (el curso, su categoría, sus etiquetas y la revisión) van
+/// en la transacción de la petición (`Tx`): si algo falla, no se aplica nada.
+async fn update_course_by_id(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    update_data: web::Json<UpdateCourse>,
+    mut tx: Tx,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    if update_data.max_students.is_some_and(|max| max.is_some_and(|max| max <= 0)) {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("must_be_positive", "max_students must be greater than 0").with_field("max_students"));
+    }
+    let limits = state.text_limits;
+    let new_title = match update_data.title.as_deref().map(|title| required_text("title", title, limits.title)) {
+        Some(Ok(title)) => Some(title),
+        Some(Err(error)) => return HttpResponse::BadRequest().json(error),
+        None => None,
+    };
+    let new_description = match &update_data.description {
+        Some(description) => match optional_text("description", description.as_deref(), limits.description) {
+            Ok(description) => Some(description),
+            Err(error) => return HttpResponse::BadRequest().json(error),
+        },
+        None => None,
+    };
+
+    let Some(expected_version) = if_match_version(&req).or(update_data.expected_version) else {
+        return HttpResponse::build(actix_web::http::StatusCode::PRECONDITION_REQUIRED).json(ApiError::new(
+            "version_required",
+            "Send the course version you edited in the If-Match header or as expected_version",
+        ));
+    };
+
+    // 1. Verificar permisos: solo el instructor que creó el curso o un admin pueden modificarlo.
+    if let Some(response) =
+        require_course_owner(&mut **tx, &auth_user, course_id, "You are not authorized to update this course").await
+    {
+        return response;
+    }
+
+    // Bloqueamos el curso: nadie puede modificarlo entre esta lectura y la actualización.
+    let course = match sqlx::query_as!(
+        Course,
+        r#"
+        SELECT id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        FROM courses
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        course_id
+    )
+    .fetch_optional(&mut **tx)
+    .await
+    {
+        Ok(Some(course)) => course,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+    };
+
+    // 2. Preparar los nuevos datos. Un campo ausente en la petición mantiene el valor antiguo.
+    let was_published = course.status == CourseStatus::Published;
+    // 3. Con `SLUG_FOLLOWS_TITLE`, un título nuevo regenera el slug; si no, el slug no cambia.
+    let slug = match &new_title {
+        Some(new_title) if state.slug_follows_title && *new_title != course.title => {
+            match unique_slug(&mut **tx, &slugify(new_title), Some(course_id)).await {
+                Ok(slug) => Some(slug),
+                Err(e) => {
+                    tracing::error!("Failed to generate course slug: {:?}", e);
+                    return HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to update course"));
+                }
+            }
+        }
+        _ => None,
+    };
+    // Solo los cambios del título o la descripción dejan una revisión (ver `course_revisions`).
+    let content_changed = new_title.as_ref().is_some_and(|title| *title != course.title)
+        || new_description.as_ref().is_some_and(|description| *description != course.description);
+    let title = new_title.unwrap_or(course.title);
+    let description = new_description.unwrap_or(course.description);
+    let publish_at = update_data.publish_at.unwrap_or(course.publish_at);
+    let max_students = update_data.max_students.unwrap_or(course.max_students);
+    let category_id = update_data.category_id.unwrap_or(course.category_id);
+    let status = update_data.status.unwrap_or(course.status);
+    let status_changed = status != course.status;
+    if status_changed && !course.status.can_transition_to(status) {
+        return HttpResponse::Conflict().json(
+            ApiError::new("invalid_transition", "The course cannot change from its current status to the requested one")
+                .with_field("status"),
+        );
+    }
+    let tags = update_data.tags.as_deref().map(normalize_tags);
+
+    // 4. Ejecutar la actualización. Si algo falla (por ejemplo, la categoría no existe), la
+    //    respuesta no es 2xx y la transacción se descarta entera.
+    if content_changed {
+        if let Err(e) = record_course_revision(&mut tx, course_id, auth_user.id).await {
+            tracing::error!("Failed to record course revision: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+    }
+
+    let updated_course = sqlx::query_as!(
+        Course,
+        r#"
+        UPDATE courses SET
+            title = $1,
+            description = $2,
+            status = $3,
+            -- La fecha de publicación se fija la primera vez que el curso pasa a `published`.
+            published_at = CASE
+                WHEN $3 = 'published'::course_status AND published_at IS NULL THEN NOW()
+                ELSE published_at
+            END,
+            publish_at = $6,
+            max_students = $7,
+            allow_reviews = COALESCE($8, allow_reviews),
+            slug = COALESCE($9, slug),
+            reserve_pending_slots = COALESCE($10, reserve_pending_slots),
+            issues_certificate = COALESCE($11, issues_certificate),
+            category_id = $12,
+            review_requires_completion = COALESCE($13, review_requires_completion),
+            version = version + 1,
+            updated_at = NOW()
+        WHERE id = $4 AND version = $5
+        RETURNING id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        "#,
+        title,
+        description,
+        status as CourseStatus,
+        course_id,
+        expected_version,
+        publish_at,
+        max_students,
+        update_data.allow_reviews,
+        slug,
+        update_data.reserve_pending_slots,
+        update_data.issues_certificate,
+        category_id,
+        update_data.review_requires_completion
+    )
+    .fetch_optional(&mut **tx)
+    .await;
+
+    let course = match updated_course {
+        Ok(Some(course)) => course,
+        // Ninguna fila coincide con la versión esperada: alguien modificó el curso antes.
+        Ok(None) => {
+            return HttpResponse::Conflict().json(ApiError::new(
+                "version_conflict",
+                "The course was modified by someone else; reload it and try again",
+            ))
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("slug_conflict", "Another course took the same slug; try again"))
+        }
+        // La categoría indicada no existe.
+        Err(e) if db::is_foreign_key_violation(&e) => return db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to update course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+    };
+
+    if let Some(tags) = &tags {
+        if let Err(e) = replace_course_tags(&mut tx, course_id, tags).await {
+            tracing::error!("Failed to replace course tags: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+    }
+
+    if course.status == CourseStatus::Archived && status_changed {
+        if let Err(e) = notify_course_archived(&mut tx, &[course_id]).await {
+            tracing::error!("Failed to notify students of archived course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+    }
+
+    // Si el curso acaba de publicarse, avisamos a los integradores mediante el webhook, con el
+    // cambio ya confirmado.
+    if !was_published && course.status == CourseStatus::Published {
+        if let Err(e) = tx.commit().await {
+            tracing::error!("Failed to commit course update: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to update course"));
+        }
+        notify_course_published(&state, &course);
+    }
+    HttpResponse::Ok().insert_header(course_etag(&course)).json(course)
+}
+
+/// Maneja las peticiones GET a /courses/{id}/revisions (propietario o admin)
+/// Devuelve las revisiones del contenido del curso, de la más reciente a la más antigua.
+async fn get_course_revisions(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    page: PageParams,
+) -> impl Responder {
+    let course_id = path.into_inner();
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to view this course's revisions",
+    )
+    .await
+    {
+        return response;
+    }
+
+    let revisions = sqlx::query_as!(
+        CourseRevision,
+        r#"
+        SELECT id, course_id, author_id, title, description, lessons, created_at
+        FROM course_revisions
+        WHERE course_id = $1
+        ORDER BY created_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        course_id,
+        page.limit(),
+        page.offset()
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match revisions {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch course revisions: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch course revisions"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /courses/{id}/revert/{revision_id} (propietario o admin)
+/// Devuelve el título, la descripción y las lecciones del curso al estado de la revisión, en una
+/// transacción. Las lecciones creadas después se borran (con el progreso de los estudiantes en
+/// ellas) y las borradas se recuperan con su mismo id. El estado anterior a revertir queda a su
+/// vez como revisión, así que se puede deshacer.
+async fn revert_course_revision(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let (course_id, revision_id) = path.into_inner();
+
+    if let Some(response) =
+        require_course_owner(&state.db_pool, &auth_user, course_id, "You are not authorized to update this course").await
+    {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+        }
+    };
+
+    // 1. Bloquear el curso para que nadie lo edite mientras se revierte.
+    match sqlx::query_scalar!("SELECT id FROM courses WHERE id = $1 AND deleted_at IS NULL FOR UPDATE", course_id)
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) => {
+            tracing::error!("Failed to lock course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+        }
+    }
+
+    let revision = sqlx::query!(
+        "SELECT title, description FROM course_revisions WHERE id = $1 AND course_id = $2",
+        revision_id,
+        course_id
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let revision = match revision {
+        Ok(Some(revision)) => revision,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("revision_not_found", "Revision not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch course revision: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+        }
+    };
+
+    // 2. Guardar el estado actual antes de sobrescribirlo.
+    if let Err(e) = record_course_revision(&mut tx, course_id, auth_user.id).await {
+        tracing::error!("Failed to record course revision: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+    }
+
+    // 3. Restaurar las lecciones: borrar las que no estaban y recuperar o sobrescribir el resto.
+    let lessons_restored = async {
+        sqlx::query!(
+            r#"
+            DELETE FROM lessons
+            WHERE course_id = $1
+              AND id NOT IN (
+                  SELECT (lesson->>'id')::uuid
+                  FROM course_revisions, jsonb_array_elements(lessons) AS lesson
+                  WHERE course_revisions.id = $2
+              )
+            "#,
+            course_id,
+            revision_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO lessons (id, course_id, title, content, position)
+            SELECT lesson.id, $1, lesson.title, lesson.content, lesson.position
+            FROM course_revisions,
+                 jsonb_to_recordset(lessons) AS lesson(id uuid, title text, content text, position int)
+            WHERE course_revisions.id = $2
+            ON CONFLICT (id) DO UPDATE SET
+                title = EXCLUDED.title,
+                content = EXCLUDED.content,
+                position = EXCLUDED.position,
+                updated_at = NOW()
+            "#,
+            course_id,
+            revision_id
+        )
+        .execute(&mut *tx)
+        .await
+    }
+    .await;
+
+    if let Err(e) = lessons_restored {
+        tracing::error!("Failed to restore course lessons: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+    }
+
+    // 4. Restaurar el título y la descripción.
+    let course = sqlx::query_as!(
+        Course,
+        r#"
+        UPDATE courses SET title = $2, description = $3, version = version + 1, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        "#,
+        course_id,
+        revision.title,
+        revision.description
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let course = match course {
+        Ok(course) => course,
+        Err(e) => {
+            tracing::error!("Failed to revert course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            info!("Course {} reverted to revision {} by {}", course_id, revision_id, auth_user.id);
+            HttpResponse::Ok().insert_header(course_etag(&course)).json(course)
+        }
+        Err(e) => {
+            tracing::error!("Failed to commit course revert: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to revert course"))
+        }
+    }
+}
+
+async fn delete_course_by_id(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    query: web::Query<DeleteCourseQuery>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    // Para eliminar (o simularlo), requerimos que sea el instructor propietario o un admin.
+    if let Some(response) =
+        require_course_owner(&state.db_pool, &auth_user, course_id, "You are not authorized to delete this course").await
+    {
+        return response;
+    }
+
+    if query.dry_run {
+        return course_deletion_summary(&state, course_id).await;
+    }
+
+    // Usamos `execute` para borrar, que devuelve el número de filas afectadas. El borrado lógico
+    // conserva el curso y todo lo que depende de él; solo deja de ser visible.
+    let result = if query.soft {
+        sqlx::query!(
+            "UPDATE courses SET deleted_at = NOW(), version = version + 1, updated_at = NOW() WHERE id = $1 AND deleted_at IS NULL",
+            course_id
+        )
+        .execute(&state.db_pool)
+        .await
+    } else {
+        sqlx::query!("DELETE FROM courses WHERE id = $1", course_id)
+            .execute(&state.db_pool)
+            .await
+    };
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::NoContent().finish(),
+        Ok(_) => HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) => {
+            tracing::error!("Failed to delete course: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Cuenta las filas dependientes que se borrarían con el curso, sin borrar nada.
+/// Quien la llama ya ha comprobado que el usuario puede borrar el curso.
+async fn course_deletion_summary(state: &AppState, course_id: Uuid) -> HttpResponse {
+    let summary = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM enrollments e WHERE e.course_id = c.id) as "enrollments!",
+            (SELECT COUNT(*) FROM enrollment_events ev JOIN enrollments e ON ev.enrollment_id = e.id WHERE e.course_id = c.id) as "enrollment_events!",
+            (SELECT COUNT(*) FROM course_offerings o WHERE o.course_id = c.id) as "offerings!",
+            (SELECT COUNT(*) FROM lessons l WHERE l.course_id = c.id) as "lessons!",
+            (SELECT COUNT(*) FROM lesson_progress lp JOIN lessons l ON lp.lesson_id = l.id WHERE l.course_id = c.id) as "lesson_progress!",
+            (SELECT COUNT(*) FROM course_materials m WHERE m.course_id = c.id) as "materials!",
+            (SELECT COUNT(*) FROM bookmarks b WHERE b.course_id = c.id) as "bookmarks!",
+            (SELECT COUNT(*) FROM course_waitlist w WHERE w.course_id = c.id) as "waitlist!",
+            (SELECT COUNT(*) FROM course_reviews r WHERE r.course_id = c.id) as "reviews!",
+            (SELECT COUNT(*) FROM review_reports rr JOIN course_reviews r ON rr.review_id = r.id WHERE r.course_id = c.id) as "review_reports!",
+            (SELECT COUNT(*) FROM certificates ce WHERE ce.course_id = c.id) as "certificates!",
+            (SELECT COUNT(*) FROM course_revisions rv WHERE rv.course_id = c.id) as "revisions!",
+            (SELECT COUNT(*) FROM course_tags t WHERE t.course_id = c.id) as "tags!",
+            (SELECT COUNT(*) FROM course_views v WHERE v.course_id = c.id) as "views!",
+            (SELECT COUNT(*) FROM activity a
+             WHERE a.course_id = c.id OR a.lesson_id IN (SELECT l.id FROM lessons l WHERE l.course_id = c.id)) as "activity!"
+        FROM courses c
+        WHERE c.id = $1
+        "#,
+        course_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match summary {
+        Ok(Some(row)) => {
+            HttpResponse::Ok().json(CourseDeletionSummary {
+                course_id,
+                enrollments: row.enrollments,
+                enrollment_events: row.enrollment_events,
+                offerings: row.offerings,
+                lessons: row.lessons,
+                lesson_progress: row.lesson_progress,
+                materials: row.materials,
+                bookmarks: row.bookmarks,
+                waitlist: row.waitlist,
+                reviews: row.reviews,
+                review_reports: row.review_reports,
+                certificates: row.certificates,
+                revisions: row.revisions,
+                tags: row.tags,
+                views: row.views,
+                activity: row.activity,
+            })
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) => {
+            tracing::error!("Failed to summarize course deletion: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// --- Materiales del Curso ---
+
+/// Maneja las peticiones POST a /courses/{id}/materials?file_name=...
+/// El cuerpo de la petición es el contenido del fichero; solo el propietario o un admin pueden subirlo.
+async fn upload_material(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<UploadMaterialQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to add materials to this course",
+    )
+    .await
+    {
+        return response;
+    }
+
+    // Evitamos caracteres que romperían la cabecera `Content-Disposition` al descargar.
+    let file_name: String = query
+        .file_name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"' && *c != '/' && *c != '\\')
+        .collect();
+    if file_name.trim().is_empty() || body.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_material", "A file name and a non-empty body are required"));
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    // El fichero se guarda con el ID del material como nombre, nunca con el nombre enviado por el cliente.
+    let material_id = Uuid::new_v4();
+    let file_path = state.materials_dir.join(material_id.to_string());
+    if let Err(e) = tokio::fs::write(&file_path, &body).await {
+        tracing::error!("Failed to store material file: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to store material"));
+    }
+
+    let material = sqlx::query_as!(
+        CourseMaterial,
+        r#"
+        INSERT INTO course_materials (id, course_id, file_name, content_type, size_bytes)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, course_id, file_name, content_type, size_bytes, created_at
+        "#,
+        material_id,
+        course_id,
+        file_name,
+        content_type,
+        body.len() as i64
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match material {
+        Ok(material) => HttpResponse::Created().json(material),
+        // El curso se borró mientras se subía el fichero.
+        Err(e) if db::is_foreign_key_violation(&e) => {
+            let _ = tokio::fs::remove_file(&file_path).await;
+            db::invalid_reference_response(&e)
+        }
+        Err(e) => {
+            tracing::error!("Failed to save material: {:?}", e);
+            let _ = tokio::fs::remove_file(&file_path).await;
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to store material"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /materials/{id}/download-token
+/// Emite un enlace firmado para estudiantes inscritos, el propietario del curso o un admin.
+async fn create_download_token(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let material_id = path.into_inner();
+
+    let course_id = sqlx::query_scalar!("SELECT course_id FROM course_materials WHERE id = $1", material_id)
+        .fetch_optional(&state.db_pool)
+        .await;
+
+    let course_id = match course_id {
+        Ok(Some(course_id)) => course_id,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("material_not_found", "Material not found")),
+        Err(e) => {
+            tracing::error!("Failed to check material access: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // Si el curso está oculto para el usuario, el material tampoco existe para él.
+    match course_access(&state.db_pool, Some(&auth_user), course_id).await {
+        Ok(access) if access.can_read_content() => {}
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("material_not_found", "Material not found"))
+        }
+        Ok(_) => {
+            return HttpResponse::Forbidden().json(ApiError::new(
+                "enrollment_required",
+                "You must be enrolled in this course to download its materials",
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check material access: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let expires_at = Utc::now() + chrono::Duration::seconds(DOWNLOAD_TOKEN_TTL_SECONDS);
+    let token = sign_download_token(&state.download_token_secret, material_id, expires_at);
+    let url = format!("/materials/download?token={}", token);
+
+    HttpResponse::Ok().json(DownloadTokenResponse { token, url, expires_at })
+}
+
+/// Parte de un material que pide la cabecera `Range`.
+enum ByteRange {
+    /// Sin `Range` (o con una que no se entiende o pide varios rangos): el fichero completo.
+    Full,
+    /// Rango satisfacible, con los extremos inclusivos `(inicio, fin)`.
+    Partial(u64, u64),
+    /// Ningún byte del rango pedido existe en el fichero.
+    Unsatisfiable,
+}
+
+/// Interpreta la cabecera `Range` para un fichero de `len` bytes. Solo se atiende un único
+/// rango de bytes; según la RFC 9110 el servidor puede ignorar el resto y servir el fichero entero.
+fn requested_range(req: &HttpRequest, len: u64) -> ByteRange {
+    let Some(range) = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<header::Range>().ok())
+    else {
+        return ByteRange::Full;
+    };
+    match range {
+        header::Range::Bytes(specs) if specs.len() == 1 => match specs[0].to_satisfiable_range(len) {
+            Some((start, end)) => ByteRange::Partial(start, end),
+            None => ByteRange::Unsatisfiable,
+        },
+        _ => ByteRange::Full,
+    }
+}
+
+/// Lee los bytes `start..=end` de un fichero.
+async fn read_file_range(path: &std::path::Path, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    let mut contents = vec![0; (end - start + 1) as usize];
+    file.read_exact(&mut contents).await?;
+    Ok(contents)
+}
+
+/// Maneja las peticiones GET a /materials/download?token=...
+/// No requiere autenticación: el token firmado es la autorización.
+async fn download_material(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<DownloadQuery>,
+) -> impl Responder {
+    let Some(material_id) = verify_download_token(&state.download_token_secret, &query.token) else {
+        return HttpResponse::Forbidden()
+            .json(ApiError::new("invalid_download_token", "Invalid or expired download token"));
+    };
+
+    let material = match sqlx::query_as!(
+        CourseMaterial,
+        "SELECT id, course_id, file_name, content_type, size_bytes, created_at FROM course_materials WHERE id = $1",
+        material_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    {
+        Ok(Some(material)) => material,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("material_not_found", "Material not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch material: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let path = state.materials_dir.join(material.id.to_string());
+    let len = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            tracing::error!("Failed to read material file: {:?}", e);
+            return HttpResponse::NotFound().json(ApiError::new("material_not_found", "Material file not found"));
+        }
+    };
+
+    // Con `Range` se sirve solo la parte pedida (206), para reanudar descargas o saltar en un vídeo.
+    let (mut response, contents) = match requested_range(&req, len) {
+        ByteRange::Unsatisfiable => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", len)))
+                .finish();
+        }
+        ByteRange::Partial(start, end) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)));
+            (response, read_file_range(&path, start, end).await)
+        }
+        ByteRange::Full => (HttpResponse::Ok(), tokio::fs::read(&path).await),
+    };
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::error!("Failed to read material file: {:?}", e);
+            return HttpResponse::NotFound().json(ApiError::new("material_not_found", "Material file not found"));
+        }
+    };
+
+    response
+        .content_type(material.content_type)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", material.file_name),
+        ))
+        .body(contents)
+}
+
+// --- Lecciones ---
+
+/// Maneja las peticiones GET a /courses/{id}/lessons
+/// Devuelve las lecciones del curso, con su contenido, a los estudiantes inscritos (inscripción
+/// activa o completada), a su propietario o a un admin.
+async fn get_course_lessons(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    // Como en `GET /lessons/{id}`: el contenido es solo para inscritos, el propietario o un admin.
+    match course_access(&state.db_pool, auth_user.as_ref(), course_id).await {
+        Ok(access) if access.can_read_content() => {}
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(_) => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("enrollment_required", "You must be enrolled in the course to read its lessons"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch lessons"));
+        }
+    }
+
+    let lessons = sqlx::query_as!(
+        Lesson,
+        r#"
+        SELECT id, course_id, title, content, position, created_at, updated_at
+        FROM lessons
+        WHERE course_id = $1
+        ORDER BY position, created_at
+        "#,
+        course_id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match lessons {
+        Ok(lessons) => HttpResponse::Ok().json(lessons),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch lessons: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch lessons"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /lessons/{id}
+/// Devuelve una sola lección. Solo pueden leerla los estudiantes inscritos en el curso
+/// (inscripción activa o completada), su propietario o un admin.
+async fn get_lesson_by_id(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let lesson_id = path.into_inner();
+
+    let lesson = sqlx::query_as!(
+        Lesson,
+        r#"
+        SELECT id, course_id, title, content, position, created_at, updated_at
+        FROM lessons
+        WHERE id = $1
+        "#,
+        lesson_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    let lesson = match lesson {
+        Ok(Some(lesson)) => lesson,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found")),
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch lesson: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch lesson"));
+        }
+    };
+
+    // Si el curso está oculto para el usuario, la lección tampoco existe para él.
+    match course_access(&state.db_pool, Some(&auth_user), lesson.course_id).await {
+        Ok(access) if access.can_read_content() => HttpResponse::Ok().json(lesson),
+        Ok(CourseAccess::Hidden) => {
+            HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found"))
+        }
+        Ok(_) => {
+            HttpResponse::Forbidden()
+                .json(ApiError::new("enrollment_required", "You must be enrolled in the course to read this lesson"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch lesson"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /lessons/{id}/complete
+/// Marca la lección como completada por el estudiante inscrito. Es idempotente: si ya estaba
+/// completada, devuelve la fecha original.
+async fn complete_lesson(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let lesson_id = path.into_inner();
+
+    let course_id = match sqlx::query_scalar!("SELECT course_id FROM lessons WHERE id = $1", lesson_id)
+        .fetch_optional(&state.db_pool)
+        .await
+    {
+        Ok(Some(course_id)) => course_id,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch lesson: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to complete lesson"));
+        }
+    };
+
+    match course_access(&state.db_pool, Some(&auth_user), course_id).await {
+        Ok(CourseAccess::Enrolled) => {}
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found"))
+        }
+        Ok(_) => {
+            return HttpResponse::Forbidden().json(ApiError::new(
+                "enrollment_required",
+                "You must be enrolled in the course to complete this lesson",
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to complete lesson"));
+        }
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to complete lesson"));
+        }
+    };
+
+    // Bloqueamos las inscripciones activas del estudiante en el curso: si completa dos lecciones a
+    // la vez, la segunda transacción espera y ve la primera, así que el curso no queda sin completar.
+    if let Err(e) = sqlx::query!(
+        "SELECT id FROM enrollments WHERE user_id = $1 AND course_id = $2 AND status = 'active' FOR UPDATE",
+        auth_user.id,
+        course_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    {
+        tracing::error!("Failed to lock enrollment: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to complete lesson"));
+    }
+
+    // El `DO UPDATE` sin cambios devuelve la fila existente; `xmax = 0` solo en una fila recién
+    // insertada, lo que distingue una lección recién completada de una que ya lo estaba.
+    let progress = match sqlx::query!(
+        r#"
+        INSERT INTO lesson_progress (user_id, lesson_id) VALUES ($1, $2)
+        ON CONFLICT (user_id, lesson_id) DO UPDATE SET completed_at = lesson_progress.completed_at
+        RETURNING completed_at, (xmax = 0) as "inserted!"
+        "#,
+        auth_user.id,
+        lesson_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(progress) => progress,
+        Err(e) if db::is_foreign_key_violation(&e) => return db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to complete lesson: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to complete lesson"));
+        }
+    };
+
+    // Solo una lección recién completada puede cerrar el curso.
+    let (course_completed, certificate_id) = if progress.inserted {
+        match complete_course_if_finished(&mut tx, auth_user.id, course_id).await {
+            Ok(completion) => completion,
+            Err(e) => {
+                tracing::error!("Failed to complete course: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to complete lesson"));
+            }
+        }
+    } else {
+        (false, None)
+    };
+
+    // El certificado queda en la actividad del estudiante en la misma transacción que lo emite.
+    if certificate_id.is_some() {
+        let earned = Activity {
+            user_id: auth_user.id,
+            kind: activity::CERTIFICATE_EARNED,
+            course_id: Some(course_id),
+            lesson_id: None,
+        };
+        if let Err(e) = activity::record(&mut *tx, &[earned]).await {
+            tracing::error!("Failed to record certificate activity: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to complete lesson"));
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit lesson completion: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to complete lesson"));
+    }
+
+    if progress.inserted {
+        activity::record_in_background(
+            &state.db_pool,
+            vec![Activity {
+                user_id: auth_user.id,
+                kind: activity::LESSON_COMPLETED,
+                course_id: Some(course_id),
+                lesson_id: Some(lesson_id),
+            }],
+        );
+    }
+    if let Some(certificate_id) = certificate_id {
+        info!("Certificate {} issued to user {} for course {}", certificate_id, auth_user.id, course_id);
+    }
+
+    HttpResponse::Ok().json(LessonCompletion {
+        lesson_id,
+        completed_at: progress.completed_at,
+        course_completed,
+        certificate_id,
+    })
+}
+
+/// Maneja las peticiones POST a /courses/{id}/lessons
+/// La nueva lección se añade al final del curso. Solo el propietario o un admin pueden crearla.
+async fn create_lesson(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    lesson_data: web::Json<CreateLesson>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+    let limits = state.text_limits;
+    let title = match required_text("title", &lesson_data.title, limits.title) {
+        Ok(title) => title,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+    let content = match optional_text("content", lesson_data.content.as_deref(), limits.lesson_content) {
+        Ok(content) => content,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to add lessons to this course",
+    )
+    .await
+    {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create lesson"));
+        }
+    };
+
+    if let Err(e) = record_course_revision(&mut tx, course_id, auth_user.id).await {
+        tracing::error!("Failed to record course revision: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create lesson"));
+    }
+
+    let new_lesson = sqlx::query_as!(
+        Lesson,
+        r#"
+        INSERT INTO lessons (course_id, title, content, position)
+        SELECT $1, $2, $3, COALESCE(MAX(position), 0) + 1 FROM lessons WHERE course_id = $1
+        RETURNING id, course_id, title, content, position, created_at, updated_at
+        "#,
+        course_id,
+        title,
+        content
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let new_lesson = match new_lesson {
+        Ok(lesson) => tx.commit().await.map(|_| lesson),
+        Err(e) => Err(e),
+    };
+
+    match new_lesson {
+        Ok(lesson) => HttpResponse::Created().json(lesson),
+        // El curso se borró mientras tanto.
+        Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to create lesson: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create lesson"))
+        }
+    }
+}
+
+/// Maneja las peticiones DELETE a /lessons/{id}
+/// Borra la lección (con el progreso de los estudiantes en ella) y desplaza las siguientes para
+/// que las posiciones sigan siendo contiguas. Solo el propietario o un admin pueden borrarla.
+async fn delete_lesson(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let lesson_id = path.into_inner();
+
+    let course_id = match sqlx::query_scalar!("SELECT course_id FROM lessons WHERE id = $1", lesson_id)
+        .fetch_optional(&state.db_pool)
+        .await
+    {
+        Ok(Some(course_id)) => course_id,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch lesson: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"));
+        }
+    };
+
+    if let Some(response) =
+        require_course_owner(&state.db_pool, &auth_user, course_id, "You are not authorized to delete this lesson").await
+    {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"));
+        }
+    };
+
+    if let Err(e) = record_course_revision(&mut tx, course_id, auth_user.id).await {
+        tracing::error!("Failed to record course revision: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"));
+    }
+
+    let position = match sqlx::query_scalar!("DELETE FROM lessons WHERE id = $1 RETURNING position", lesson_id)
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(Some(position)) => position,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("lesson_not_found", "Lesson not found")),
+        Err(e) => {
+            tracing::error!("Failed to delete lesson: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"));
+        }
+    };
+
+    let shifted = sqlx::query!(
+        "UPDATE lessons SET position = position - 1, updated_at = NOW() WHERE course_id = $1 AND position > $2",
+        course_id,
+        position
+    )
+    .execute(&mut *tx)
+    .await;
+    if let Err(e) = shifted {
+        tracing::error!("Failed to shift lesson positions: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"));
+    }
+
+    match tx.commit().await {
+        Ok(_) => {
+            info!("Lesson {} deleted by {}", lesson_id, auth_user.id);
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            tracing::error!("Failed to commit lesson deletion: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete lesson"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /courses/{id}/lessons/reorder
+/// Recibe la lista completa de lecciones del curso en el nuevo orden y reasigna todas las
+/// posiciones (1, 2, 3...) en una sola transacción, evitando posiciones duplicadas.
+async fn reorder_lessons(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    reorder_data: web::Json<ReorderLessons>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    // 1. Verificar que el curso existe y que el usuario puede modificarlo.
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to reorder this course's lessons",
+    )
+    .await
+    {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // 2. Bloquear las lecciones actuales para que nadie las modifique mientras reordenamos.
+    let mut current_ids = match sqlx::query_scalar!(
+        "SELECT id FROM lessons WHERE course_id = $1 FOR UPDATE",
+        course_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    {
+        Ok(ids) => ids,
+        Err(e) => {
+            tracing::error!("Failed to fetch lessons: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // 3. La lista recibida debe contener exactamente las lecciones del curso, sin repetir ninguna.
+    let mut requested_ids = reorder_data.lesson_ids.clone();
+    current_ids.sort();
+    requested_ids.sort();
+    if current_ids != requested_ids {
+        return HttpResponse::BadRequest().json(
+            ApiError::new(
+                "invalid_lesson_order",
+                "lesson_ids must contain exactly the course's lessons, each one once",
+            )
+            .with_field("lesson_ids"),
+        );
+    }
+
+    if let Err(e) = record_course_revision(&mut tx, course_id, auth_user.id).await {
+        tracing::error!("Failed to record course revision: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reorder lessons"));
+    }
+
+    // 4. Asignar posiciones contiguas según el orden recibido.
+    let updated = sqlx::query!(
+        r#"
+        UPDATE lessons SET position = ordered.position::int, updated_at = NOW()
+        FROM UNNEST($1::uuid[]) WITH ORDINALITY AS ordered(id, position)
+        WHERE lessons.id = ordered.id AND lessons.course_id = $2
+        "#,
+        &reorder_data.lesson_ids[..],
+        course_id
+    )
+    .execute(&mut *tx)
+    .await;
+
+    if let Err(e) = updated {
+        tracing::error!("Failed to reorder lessons: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reorder lessons"));
+    }
+
+    let lessons = sqlx::query_as!(
+        Lesson,
+        r#"
+        SELECT id, course_id, title, content, position, created_at, updated_at
+        FROM lessons
+        WHERE course_id = $1
+        ORDER BY position
+        "#,
+        course_id
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let lessons = match lessons {
+        Ok(lessons) => lessons,
+        Err(e) => {
+            tracing::error!("Failed to fetch reordered lessons: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to reorder lessons"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => HttpResponse::Ok().json(lessons),
+        Err(e) => {
+            tracing::error!("Failed to commit lesson reorder: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to reorder lessons"))
+        }
+    }
+}
+
+// --- Ediciones ---
+
+/// Maneja las peticiones GET a /courses/{id}/offerings
+/// Devuelve las ediciones del curso, de la más antigua a la más reciente.
+async fn get_course_offerings(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if !state.offerings_enabled {
+        return HttpResponse::NotFound().json(ApiError::new("offerings_disabled", "Course offerings are disabled"));
+    }
+    let course_id = path.into_inner();
+
+    match course_access(&state.db_pool, auth_user.as_ref(), course_id).await {
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch offerings"));
+        }
+    }
+
+    let offerings = sqlx::query_as!(
+        Offering,
+        r#"
+        SELECT id, course_id, name, starts_at, ends_at, created_at
+        FROM course_offerings
+        WHERE course_id = $1
+        ORDER BY starts_at NULLS LAST, created_at
+        "#,
+        course_id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match offerings {
+        Ok(offerings) => HttpResponse::Ok().json(offerings),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch offerings: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch offerings"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /courses/{id}/offerings (propietario o admin)
+/// Crea una nueva edición del curso. El nombre debe ser único dentro del curso.
+async fn create_offering(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    offering_data: web::Json<CreateOffering>,
+) -> impl Responder {
+    if !state.offerings_enabled {
+        return HttpResponse::NotFound().json(ApiError::new("offerings_disabled", "Course offerings are disabled"));
+    }
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    let name = offering_data.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "name must not be empty").with_field("name"));
+    }
+    if let (Some(starts_at), Some(ends_at)) = (offering_data.starts_at, offering_data.ends_at) {
+        if ends_at <= starts_at {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("invalid_date_range", "ends_at must be after starts_at").with_field("ends_at"));
+        }
+    }
+
+    if let Some(response) = require_course_owner(
+        &state.db_pool,
+        &auth_user,
+        course_id,
+        "You are not authorized to add offerings to this course",
+    )
+    .await
+    {
+        return response;
+    }
+
+    let offering = sqlx::query_as!(
+        Offering,
+        r#"
+        INSERT INTO course_offerings (course_id, name, starts_at, ends_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, course_id, name, starts_at, ends_at, created_at
+        "#,
+        course_id,
+        name,
+        offering_data.starts_at,
+        offering_data.ends_at
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match offering {
+        Ok(offering) => HttpResponse::Created().json(offering),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            HttpResponse::Conflict().json(
+                ApiError::new("offering_exists", "An offering with this name already exists in the course")
+                    .with_field("name"),
+            )
+        }
+        Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to create offering: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create offering"))
+        }
+    }
+}
+
+// --- Reseñas ---
+
+/// Maneja las peticiones GET a /courses/{id}/reviews
+/// Devuelve las reseñas del curso con el nombre y el avatar de sus autores, paginadas, filtradas
+/// por puntuación (`min_rating`, `max_rating`) y ordenadas por fecha (por defecto, la más reciente
+/// primero) o por puntuación. Las reseñas ocultas por acumular reportes no aparecen.
+async fn get_course_reviews(
+    state: web::Data<AppState>,
+    auth_user: Option<AuthenticatedUser>,
+    path: web::Path<Uuid>,
+    query: web::Query<ReviewsQuery>,
+    page: PageParams,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    let min_rating = query.min_rating.unwrap_or(1);
+    let max_rating = query.max_rating.unwrap_or(5);
+    if !(1..=5).contains(&min_rating) || !(1..=5).contains(&max_rating) {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_rating", "min_rating and max_rating must be between 1 and 5"));
+    }
+    if min_rating > max_rating {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_rating_range", "min_rating cannot be greater than max_rating"));
+    }
+    // Solo se permite ordenar por columnas conocidas: nunca se interpola el texto del cliente en el SQL.
+    let sort_column = match query.sort.as_deref().unwrap_or("recent") {
+        "recent" => "r.created_at",
+        "rating" => "r.rating",
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("invalid_sort", "Invalid sort field: use 'recent' or 'rating'").with_field("sort"))
+        }
+    };
+    let sort_direction = match query.order.as_deref().unwrap_or("desc") {
+        "asc" => "ASC",
+        "desc" => "DESC",
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("invalid_order", "Invalid order: use 'asc' or 'desc'").with_field("order"))
+        }
+    };
+
+    match course_access(&state.db_pool, auth_user.as_ref(), course_id).await {
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch reviews"));
+        }
+    }
+
+    let mut builder = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT
+            r.id, r.course_id, r.user_id,
+            u.display_username as reviewer_name,
+            u.avatar_url as reviewer_avatar_url,
+            r.rating, r.comment, r.created_at, r.updated_at
+        FROM course_reviews r
+        JOIN users u ON u.id = r.user_id
+        WHERE r.hidden_at IS NULL AND r.course_id = "#,
+    );
+    builder
+        .push_bind(course_id)
+        .push(" AND r.rating BETWEEN ")
+        .push_bind(min_rating)
+        .push(" AND ")
+        .push_bind(max_rating)
+        // A igual puntuación, las más recientes primero.
+        .push(format!(" ORDER BY {} {}, r.created_at DESC, r.id", sort_column, sort_direction))
+        .push(" LIMIT ")
+        .push_bind(page.limit())
+        .push(" OFFSET ")
+        .push_bind(page.offset());
+
+    let reviews = builder.build_query_as::<CourseReviewEntry>().fetch_all(&state.db_pool).await;
+
+    match reviews {
+        Ok(reviews) => HttpResponse::Ok().json(reviews),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch reviews: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch reviews"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /courses/{id}/reviews
+/// Solo los estudiantes inscritos pueden reseñar un curso, una vez cada uno, y solo si el curso
+/// admite reseñas (`allow_reviews`); si no, responde 403 `reviews_disabled`. Con
+/// `review_requires_completion`, la inscripción debe estar completada (403 `course_not_completed`).
+async fn create_review(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    review_data: web::Json<CreateReview>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    if !(1..=5).contains(&review_data.rating) {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_rating", "rating must be between 1 and 5").with_field("rating"));
+    }
+
+    match course_access(&state.db_pool, Some(&auth_user), course_id).await {
+        Ok(CourseAccess::Enrolled) => {}
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(_) => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("enrollment_required", "You must be enrolled in the course to review it"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create review"));
+        }
+    }
+
+    let policy = match sqlx::query!(
+        r#"
+        SELECT
+            allow_reviews,
+            review_requires_completion,
+            EXISTS(
+                SELECT 1 FROM enrollments
+                WHERE course_id = $1 AND user_id = $2 AND status = 'completed'
+            ) as "completed!"
+        FROM courses WHERE id = $1
+        "#,
+        course_id,
+        auth_user.id
+    )
+    .fetch_optional(&state.db_pool)
+    .await
+    {
+        Ok(Some(policy)) => policy,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create review"));
+        }
+    };
+
+    if !policy.allow_reviews {
+        return HttpResponse::Forbidden().json(ApiError::new(
+            "reviews_disabled",
+            "Reviews are disabled for this course",
+        ));
+    }
+    if policy.review_requires_completion && !policy.completed {
+        return HttpResponse::Forbidden().json(ApiError::new(
+            "course_not_completed",
+            "Only students who completed this course can review it",
+        ));
+    }
+
+    let review = sqlx::query_as!(
+        Review,
+        r#"
+        INSERT INTO course_reviews (course_id, user_id, rating, comment)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, course_id, user_id, rating, comment, created_at, updated_at
+        "#,
+        course_id,
+        auth_user.id,
+        review_data.rating,
+        review_data.comment
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match review {
+        Ok(review) => HttpResponse::Created().json(review),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            HttpResponse::Conflict().json(ApiError::new("already_reviewed", "You have already reviewed this course"))
+        }
+        Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to create review: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create review"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /reviews/{id}/report
+/// Cualquier usuario que pueda ver el curso puede reportar una reseña ajena, una vez. Al llegar
+/// a `REVIEW_REPORT_THRESHOLD` reportes, la reseña se oculta hasta que se modere.
+async fn report_review(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    report_data: web::Json<ReportReview>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let review_id = path.into_inner();
+    let reason = report_data.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "reason must not be empty").with_field("reason"));
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"));
+        }
+    };
+
+    // Se bloquea la reseña para que los reportes simultáneos se cuenten de uno en uno.
+    let review = match sqlx::query!(
+        "SELECT course_id, user_id FROM course_reviews WHERE id = $1 FOR UPDATE",
+        review_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(review)) => review,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("review_not_found", "Review not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch review: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"));
+        }
+    };
+
+    match course_access(&state.db_pool, Some(&auth_user), review.course_id).await {
+        Ok(CourseAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("review_not_found", "Review not found"))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Failed to check course access: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"));
+        }
+    }
+    if review.user_id == auth_user.id {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("cannot_report_own_review", "You cannot report your own review"));
+    }
+
+    let inserted = sqlx::query!(
+        "INSERT INTO review_reports (review_id, user_id, reason) VALUES ($1, $2, $3)",
+        review_id,
+        auth_user.id,
+        reason
+    )
+    .execute(&mut *tx)
+    .await;
+    match inserted {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return HttpResponse::Conflict()
+                .json(ApiError::new("already_reported", "You have already reported this review"))
+        }
+        Err(e) if db::is_foreign_key_violation(&e) => return db::invalid_reference_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to report review: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"));
+        }
+    }
+
+    // Una reseña ya oculta conserva su `hidden_at` original.
+    let result = sqlx::query_as!(
+        ReviewReportResult,
+        r#"
+        WITH reports AS (
+            SELECT COUNT(*) AS report_count FROM review_reports WHERE review_id = $1
+        )
+        UPDATE course_reviews
+        SET hidden_at = COALESCE(hidden_at, CASE WHEN reports.report_count >= $2 THEN NOW() END)
+        FROM reports
+        WHERE id = $1
+        RETURNING id as review_id, reports.report_count as "report_count!", hidden_at IS NOT NULL as "hidden!"
+        "#,
+        review_id,
+        state.review_report_threshold
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!("Failed to update review after report: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => {
+            if result.hidden && result.report_count == state.review_report_threshold {
+                info!("Review {} hidden after {} reports", review_id, result.report_count);
+            }
+            HttpResponse::Created().json(result)
+        }
+        Err(e) => {
+            tracing::error!("Failed to commit review report: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to report review"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /reviews/reported (instructores y admins)
+/// Devuelve las reseñas con reportes, primero las ocultas y después por reporte más reciente.
+/// Un admin ve las de todos los cursos; un instructor, solo las de sus cursos.
+async fn get_reported_reviews(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    page: PageParams,
+) -> impl Responder {
+    if auth_user.role == UserRole::Student {
+        return HttpResponse::Forbidden()
+            .json(ApiError::new("instructor_required", "Only instructors and admins can moderate reviews"));
+    }
+    let (limit, offset) = (page.limit(), page.offset());
+
+    let reviews = sqlx::query_as!(
+        ReportedReview,
+        r#"
+        SELECT
+            r.id, r.course_id, r.user_id, r.rating, r.comment, r.hidden_at, r.created_at,
+            COUNT(*) as "report_count!",
+            ARRAY_AGG(rr.reason ORDER BY rr.created_at) as "reasons!",
+            MAX(rr.created_at) as "last_reported_at!"
+        FROM course_reviews r
+        JOIN review_reports rr ON rr.review_id = r.id
+        JOIN courses c ON c.id = r.course_id
+        WHERE $1 OR c.instructor_id = $2
+        GROUP BY r.course_id, r.user_id
+        ORDER BY r.hidden_at IS NULL, MAX(rr.created_at) DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        auth_user.role == UserRole::Admin,
+        auth_user.id,
+        limit,
+        offset
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match reviews {
+        Ok(reviews) => HttpResponse::Ok().json(reviews),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch reported reviews: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to fetch reported reviews"))
+        }
+    }
+}
+
+/// Maneja las peticiones DELETE a /reviews/{id}
+/// Solo el propietario del curso o un admin pueden borrar una reseña; sus reportes se borran con ella.
+async fn delete_review(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    let review_id = path.into_inner();
+
+    let course_id = match sqlx::query_scalar!("SELECT course_id FROM course_reviews WHERE id = $1", review_id)
+        .fetch_optional(&state.db_pool)
+        .await
+    {
+        Ok(Some(course_id)) => course_id,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError::new("review_not_found", "Review not found")),
+        Err(e) => {
+            tracing::error!("Failed to fetch review: {:?}", e);
+            return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete review"));
+        }
+    };
+
+    if let Some(response) =
+        require_course_owner(&state.db_pool, &auth_user, course_id, "You are not authorized to moderate this review")
+            .await
+    {
+        return response;
+    }
+
+    match sqlx::query!("DELETE FROM course_reviews WHERE id = $1", review_id)
+        .execute(&state.db_pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            HttpResponse::NotFound().json(ApiError::new("review_not_found", "Review not found"))
+        }
+        Ok(_) => {
+            info!("Review {} deleted by {}", review_id, auth_user.id);
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete review: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to delete review"))
+        }
+    }
+}
+
+// --- Categorías ---
+
+/// Maneja las peticiones GET a /categories
+async fn get_categories(state: web::Data<AppState>) -> impl Responder {
+    let categories = sqlx::query_as!(Category, "SELECT id, name, created_at FROM categories ORDER BY name")
+        .fetch_all(&state.db_pool)
+        .await;
+
+    match categories {
+        Ok(categories) => HttpResponse::Ok().json(categories),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            tracing::error!("Failed to fetch categories: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to fetch categories"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /categories (solo administradores)
+async fn create_category(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    category_data: web::Json<CreateCategory>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can create categories"));
+    }
+
+    let name = category_data.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("field_required", "Category name must not be empty").with_field("name"));
+    }
+
+    let category = sqlx::query_as!(
+        Category,
+        "INSERT INTO categories (name) VALUES ($1) RETURNING id, name, created_at",
+        name
+    )
+    .fetch_one(&state.db_pool)
+    .await;
+
+    match category {
+        Ok(category) => HttpResponse::Created().json(category),
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            HttpResponse::Conflict()
+                .json(ApiError::new("category_exists", "Category already exists").with_field("name"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create category: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to create category"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /courses/categorize (solo administradores)
+/// Asigna la categoría a todos los cursos indicados en una transacción e informa de los IDs inexistentes.
+async fn categorize_courses(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    categorize_data: web::Json<CategorizeCourses>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    if auth_user.role != UserRole::Admin {
+        return HttpResponse::Forbidden().json(ApiError::new("admin_required", "Only admins can recategorize courses"));
+    }
+
+    if categorize_data.course_ids.len() > MAX_BATCH_IDS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("too_many_ids", format!("Too many ids: at most {} are allowed per request", MAX_BATCH_IDS))
+                .with_field("course_ids"),
+        );
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // 1. Validar que la categoría existe antes de tocar ningún curso.
+    let category_exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM categories WHERE id = $1) as "exists!""#,
+        categorize_data.category_id
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    match category_exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("category_not_found", "Category not found").with_field("category_id"))
+        }
+        Err(e) => {
+            tracing::error!("Failed to check category: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    // 2. Actualizar todos los cursos de una vez y recoger los que realmente existían.
+    let updated = sqlx::query_scalar!(
+        r#"
+        UPDATE courses SET category_id = $1, version = version + 1, updated_at = NOW()
+        WHERE id = ANY($2)
+        RETURNING id
+        "#,
+        categorize_data.category_id,
+        &categorize_data.course_ids[..]
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let updated = match updated {
+        Ok(updated) => updated,
+        Err(e) => {
+            tracing::error!("Failed to categorize courses: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to categorize courses"));
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit course categorization: {:?}", e);
+        return HttpResponse::InternalServerError()
+            .json(ApiError::new("internal_error", "Failed to categorize courses"));
+    }
+
+    let not_found = categorize_data
+        .course_ids
+        .iter()
+        .filter(|id| !updated.contains(id))
+        .copied()
+        .collect();
+
+    HttpResponse::Ok().json(CategorizeResult { updated, not_found })
+}
+
+/// Maneja las peticiones POST a /courses/status/bulk
+/// Cambia el estado de varios cursos en una sola transacción y devuelve el resultado de cada uno.
+/// Los cursos de otros instructores se informan como `forbidden` (salvo para un admin) y no se tocan.
+async fn bulk_update_course_status(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    bulk_data: web::Json<BulkStatusRequest>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::COURSES_WRITE) {
+        return response;
+    }
+    if bulk_data.course_ids.len() > MAX_BATCH_IDS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new("too_many_ids", format!("Too many ids: at most {} are allowed per request", MAX_BATCH_IDS))
+                .with_field("course_ids"),
+        );
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // 1. Bloquear los cursos pedidos para que nadie cambie su estado mientras validamos.
+    let current = sqlx::query!(
+        r#"
+        SELECT
+            c.id, c.instructor_id, c.status as "status: CourseStatus",
+            EXISTS (
+                SELECT 1 FROM enrollments e
+                WHERE e.course_id = c.id AND e.user_id = $2 AND e.status IN ('active', 'completed')
+            ) as "is_enrolled!"
+        FROM courses c
+        WHERE c.id = ANY($1)
+        FOR UPDATE OF c
+        "#,
+        &bulk_data.course_ids[..],
+        auth_user.id
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let current = match current {
+        Ok(current) => current,
+        Err(e) => {
+            tracing::error!("Failed to fetch courses for status change: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update course status"));
+        }
+    };
+
+    // 2. Decidir el resultado de cada curso, en el orden en que se pidieron.
+    let target = bulk_data.status;
+    let results: Vec<BulkStatusResult> = bulk_data
+        .course_ids
+        .iter()
+        .map(|&course_id| {
+            let result = match current.iter().find(|course| course.id == course_id) {
+                None => BulkStatusOutcome::NotFound,
+                // Misma política que `CourseAccess`: los cursos que el usuario no puede ver no existen para él.
+                Some(course) if course.instructor_id != auth_user.id && auth_user.role != UserRole::Admin => {
+                    if course.status == CourseStatus::Published || course.is_enrolled {
+                        BulkStatusOutcome::Forbidden
+                    } else {
+                        BulkStatusOutcome::NotFound
+                    }
+                }
+                Some(course) if course.status == target => BulkStatusOutcome::Unchanged,
+                Some(course) if !course.status.can_transition_to(target) => BulkStatusOutcome::InvalidTransition,
+                Some(_) => BulkStatusOutcome::Updated,
+            };
+            BulkStatusResult { course_id, result }
+        })
+        .collect();
+
+    let to_update: Vec<Uuid> = results
+        .iter()
+        .filter(|result| matches!(result.result, BulkStatusOutcome::Updated))
+        .map(|result| result.course_id)
+        .collect();
+
+    // 3. Aplicar el cambio a los cursos válidos de una sola vez.
+    let updated = sqlx::query_as!(
+        Course,
+        r#"
+        UPDATE courses SET
+            status = $1,
+            published_at = CASE
+                WHEN $1 = 'published'::course_status AND published_at IS NULL THEN NOW()
+                ELSE published_at
+            END,
+            version = version + 1,
+            updated_at = NOW()
+        WHERE id = ANY($2)
+        RETURNING id, title, description, instructor_id, category_id, status as "status: CourseStatus", published_at, publish_at, max_students, allow_reviews, review_requires_completion, reserve_pending_slots, issues_certificate, deleted_at, is_featured, featured_rank, slug, version, created_at, updated_at
+        "#,
+        target as CourseStatus,
+        &to_update[..]
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    let updated = match updated {
+        Ok(updated) => updated,
+        Err(e) => {
+            tracing::error!("Failed to update course status: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update course status"));
+        }
+    };
+
+    if target == CourseStatus::Archived {
+        if let Err(e) = notify_course_archived(&mut tx, &to_update).await {
+            tracing::error!("Failed to notify students of archived courses: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update course status"));
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit course status change: {:?}", e);
+        return HttpResponse::InternalServerError()
+            .json(ApiError::new("internal_error", "Failed to update course status"));
+    }
+
+    // Los cursos recién publicados se anuncian por el webhook, igual que al publicarlos uno a uno.
+    if target == CourseStatus::Published {
+        for course in &updated {
+            notify_course_published(&state, course);
+        }
+    }
+
+    HttpResponse::Ok().json(results)
+}
+
+/// Maneja las peticiones GET a /health/detail (solo uso interno, requiere `X-Api-Key`)
+/// Devuelve la versión del servicio, la de PostgreSQL y si el esquema tiene todas las migraciones.
+async fn get_health_detail(state: web::Data<AppState>, _api_key: ApiKeyGuard) -> impl Responder {
+    health::health_detail(&state.db_pool, "course-service", env!("CARGO_PKG_VERSION")).await
+}
+
+/// Registra las rutas del servicio. Lo usan `main` y las pruebas, para montar la misma aplicación.
+fn routes(cfg: &mut web::ServiceConfig) {
+    // Agrupamos las rutas bajo el scope "/courses"
+    cfg.service(
+        web::scope("/courses")
+            .route("", web::get().to(get_courses))
+            .route("", web::post().to(create_course).wrap(JwtMiddleware::new(vec![
+                AuthRole::Instructor,
+                AuthRole::Admin,
+            ])))
+            .route("/newest", web::get().to(get_newest_courses)) // GET /courses/newest
+            .route("/trending", web::get().to(get_trending_courses)) // GET /courses/trending
+            .route("/featured", web::get().to(get_featured_courses)) // GET /courses/featured
+            .route("/featured", web::put().to(set_featured_courses)) // PUT /courses/featured
+            .route("/batch", web::post().to(get_courses_batch)) // POST /courses/batch
+            .route("/export", web::get().to(export_courses)) // GET /courses/export
+            .route("/categorize", web::post().to(categorize_courses)) // POST /courses/categorize
+            .route("/status/bulk", web::post().to(bulk_update_course_status)) // POST /courses/status/bulk
+            .route("/by-slug/{slug}", web::get().to(get_course_by_slug)) // GET /courses/by-slug/{slug}
+            .route("/{id}", web::get().to(get_course_by_id)) // GET /courses/{id}
+            .route("/{id}", web::put().to(update_course_by_id)) // PUT /courses/{id}
+            .route("/{id}", web::patch().to(update_course_by_id)) // PATCH /courses/{id}
+            .route("/{id}", web::delete().to(delete_course_by_id)) // DELETE /courses/{id}
+            .route("/{id}/bookmark", web::post().to(bookmark_course)) // POST /courses/{id}/bookmark
+            .route("/{id}/bookmark", web::delete().to(remove_bookmark)) // DELETE /courses/{id}/bookmark
+            .route("/{id}/students/count", web::get().to(get_student_count)) // GET /courses/{id}/students/count
+            .route("/{id}/capacity", web::get().to(get_course_capacity)) // GET /courses/{id}/capacity
+            .route("/{id}/similar", web::get().to(get_similar_courses)) // GET /courses/{id}/similar
+            .route("/{id}/analytics/enrollments", web::get().to(get_enrollment_analytics)) // GET /courses/{id}/analytics/enrollments
+            .route("/{id}/lessons", web::get().to(get_course_lessons)) // GET /courses/{id}/lessons
+            .route("/{id}/lessons", web::post().to(create_lesson)) // POST /courses/{id}/lessons
+            .route("/{id}/offerings", web::get().to(get_course_offerings)) // GET /courses/{id}/offerings
+            .route("/{id}/offerings", web::post().to(create_offering)) // POST /courses/{id}/offerings
+            .route("/{id}/lessons/reorder", web::put().to(reorder_lessons)) // PUT /courses/{id}/lessons/reorder
+            .route("/{id}/revisions", web::get().to(get_course_revisions)) // GET /courses/{id}/revisions
+            .route("/{id}/revert/{revision_id}", web::post().to(revert_course_revision)) // POST /courses/{id}/revert/{revision_id}
+            .route("/{id}/reviews", web::get().to(get_course_reviews)) // GET /courses/{id}/reviews
+            .route("/{id}/reviews", web::post().to(create_review)) // POST /courses/{id}/reviews
+            .service(
+                web::resource("/{id}/materials")
+                    .app_data(web::PayloadConfig::new(MAX_MATERIAL_BYTES))
+                    .route(web::post().to(upload_material)), // POST /courses/{id}/materials
+            ),
+    )
+    .service(
+        web::scope("/reviews")
+            .route("/reported", web::get().to(get_reported_reviews)) // GET /reviews/reported
+            .route("/{id}/report", web::post().to(report_review)) // POST /reviews/{id}/report
+            .route("/{id}", web::delete().to(delete_review)), // DELETE /reviews/{id}
+    )
+    .service(
+        web::scope("/lessons")
+            .route("/{id}", web::get().to(get_lesson_by_id)) // GET /lessons/{id}
+            .route("/{id}", web::delete().to(delete_lesson)) // DELETE /lessons/{id}
+            .route("/{id}/complete", web::post().to(complete_lesson)), // POST /lessons/{id}/complete
+    )
+    .service(
+        web::scope("/materials")
+            .route("/download", web::get().to(download_material)) // GET /materials/download?token=...
+            .route("/{id}/download-token", web::post().to(create_download_token)), // POST /materials/{id}/download-token
+    )
+    .service(
+        web::scope("/me")
+            .route("/bookmarks", web::get().to(get_my_bookmarks)) // GET /me/bookmarks
+            .route("/recently-viewed", web::get().to(get_recently_viewed)), // GET /me/recently-viewed
+    )
+    .service(
+        web::scope("/categories")
+            .route("", web::get().to(get_categories)) // GET /categories
+            .route("", web::post().to(create_category)), // POST /categories
+    )
+    .service(
+        web::scope("/tags")
+            .route("/suggest", web::get().to(suggest_tags)), // GET /tags/suggest?q=ru
+    )
+    // Activa o desactiva el modo mantenimiento (solo administradores).
+    .route(maintenance::MAINTENANCE_PATH, web::put().to(maintenance::set_maintenance))
+    // Estado detallado para operaciones (requiere `X-Api-Key`).
+    .route("/health/detail", web::get().to(get_health_detail));
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenvy::dotenv().ok();
+    telemetry::init_logging();
+
+    // Carga y valida toda la configuración de una vez; si falta algo, se informa de todos los problemas.
+    let config = Config::from_env("COURSE").unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
+
+    let db_pool = db::connect(&config)
+        .await
+        .expect("Failed to create database pool.");
+
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to create HTTP client.");
+    std::fs::create_dir_all(&config.materials_dir)?;
+
+    // El estado se comparte entre todos los workers y la tarea de publicación programada.
+    let state = web::Data::new(AppState {
+        db_pool,
+        http_client,
+        // El webhook de publicación es opcional: solo se activa si `WEBHOOK_URL` está definida.
+        webhook: config.webhook.clone(),
+        materials_dir: config.materials_dir.clone(),
+        download_token_secret: config.download_token_secret.clone(),
+        // Por defecto el slug no cambia al editar el título, para no romper enlaces ya compartidos.
+        slug_follows_title: config.slug_follows_title,
+        review_report_threshold: config.review_report_threshold,
+        offerings_enabled: config.course_offerings_enabled,
+        text_limits: config.text_limits,
+    });
+    actix_web::rt::spawn(run_scheduled_publisher(state.clone()));
+
+    info!("🚀 Servidor de cursos iniciado en el puerto {}", config.port);
+    let port = config.port;
+    let workers = config.http_workers;
+    let keep_alive = config.keep_alive();
+    info!("HTTP workers: {}, keep-alive: {:?}", workers, config.http_keep_alive);
+    // El modo mantenimiento se comparte entre todos los workers y se puede cambiar en caliente.
+    let maintenance_flag = MaintenanceFlag::new(config.maintenance_mode);
+    // Los cubos del límite de escrituras también se comparten entre los workers.
+    let write_limiter = config.write_rate_limit.map(RateLimiter::new);
+
+    HttpServer::new(move || {
+        App::new()
+            // Confirma la transacción de los manejadores con `Tx` si la respuesta es 2xx; si no, la descarta.
+            .wrap(RequestTransactions)
+            // Limita las escrituras por usuario (o por IP) con `WRITE_RATE_LIMIT_*`; responde 429.
+            .wrap(WriteRateLimit::new(write_limiter.clone()))
+            // Con el modo mantenimiento activo, rechaza las escrituras con 503 y deja pasar las lecturas.
+            .wrap(MaintenanceMode::new(maintenance_flag.clone()))
+            // Traduce los mensajes de `ApiError` según `Accept-Language` (por defecto, inglés).
+            .wrap(LocalizedErrors)
+            // Middleware de CORS con la política de `COURSE_CORS_*` (o `CORS_*`); sin configurar permite cualquier origen.
+            // ¡IMPORTANTE! En producción, esto debería restringirse a dominios específicos.
+            .wrap(config.cors())
+            // Cabeceras de seguridad (`nosniff`, `X-Frame-Options`, HSTS y CSP) en todas las respuestas.
+            .wrap(config.security_headers.clone())
+            // Logs de acceso estructurados (método, ruta, estado, latencia, request_id y usuario).
+            .wrap(AccessLog)
+            // Claves para verificar los JWT de `AuthenticatedUser` (admite varias durante una rotación).
+            .app_data(web::Data::new(config.jwt_keys.clone()))
+            // Pool con el que `AuthenticatedUser` valida los tokens de acceso personal.
+            .app_data(web::Data::new(state.db_pool.clone()))
+            // Errores claros (415/400) para cuerpos JSON con otro `Content-Type`, vacíos o inválidos.
+            .app_data(ccb_common::error::json_config())
+            // Máximo de `per_page` de los listados paginados (`PageParams`).
+            .app_data(web::Data::new(config.pagination))
+            // API key de los endpoints internos como `/health/detail` (si está configurada).
+            .app_data(web::Data::new(config.internal_api_key.clone()))
+            // Si hay un proxy de confianza delante, la IP del cliente se toma de sus cabeceras.
+            .app_data(web::Data::new(config.trusted_proxy))
+            .app_data(web::Data::new(maintenance_flag.clone()))
+            .app_data(state.clone())
+            // Rutas del servicio.
+            .configure(routes)
+    })
+    .workers(workers)
+    .keep_alive(keep_alive)
+    .bind(("0.0.0.0", port))?
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, read_body_json, TestRequest};
+    use ccb_common::{api_key::InternalApiKey, jwt::JwtKeySet, Claims};
+    use sqlx::postgres::PgPoolOptions;
+
+    /// API key de los endpoints internos.
+    const API_KEY: &str = "internal-key";
+
+    /// Monta la aplicación con las rutas del servicio, la traducción de errores y el estado indicado
+    /// (`web::Data<AppState>`).
+    macro_rules! app {
+        ($state:expr) => {
+            init_service(
+                App::new()
+                    .wrap(RequestTransactions)
+                    .wrap(LocalizedErrors)
+                    .app_data(web::Data::new(keys()))
+                    .app_data(web::Data::new($state.db_pool.clone()))
+                    .app_data(ccb_common::error::json_config())
+                    .app_data(web::Data::new(InternalApiKey(Some(API_KEY.to_string()))))
+                    .app_data($state.clone())
+                    .configure(routes),
+            )
+            .await
+        };
+    }
+
+    /// Claves HMAC con las que se firman los tokens de los tests.
+    fn keys() -> JwtKeySet {
+        JwtKeySet::new("current".to_string(), "test-secret".to_string(), Vec::new())
+    }
+
+    /// Valor de la cabecera `Authorization` con un token de sesión del usuario.
+    fn bearer(id: Uuid, role: UserRole) -> String {
+        let claims = Claims {
+            sub: id.to_string(),
+            role,
+            exp: (Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
+            password_change_required: false,
+            scopes: None,
+            act_as: None,
+        };
+        format!("Bearer {}", keys().encode(&claims).unwrap())
+    }
+
+    /// Estado del servicio contra la base de datos de los tests (`DATABASE_URL`), con los límites
+    /// de texto por defecto, sin webhook y sin ediciones. Los tests lo envuelven en `web::Data`,
+    /// tras cambiar los campos que necesiten.
+    async fn state() -> AppState {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point to the test database");
+        // Cada test guarda sus materiales en un directorio propio.
+        let materials_dir = std::env::temp_dir().join(format!("ccb-materials-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&materials_dir).unwrap();
+        AppState {
+            db_pool: PgPoolOptions::new().max_connections(4).connect(&url).await.unwrap(),
+            http_client: reqwest::Client::new(),
+            webhook: None,
+            materials_dir,
+            download_token_secret: "download-secret".to_string(),
+            slug_follows_title: false,
+            review_report_threshold: 3,
+            offerings_enabled: false,
+            text_limits: TextLimits {
+                title: 200,
+                description: 10_000,
+                lesson_content: 100_000,
+            },
+        }
+    }
+
+    /// Crea un usuario con un nombre único y el rol indicado (`student`, `instructor`, `admin`) y
+    /// devuelve su id.
+    async fn insert_user(db_pool: &PgPool, role: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO users (username, display_username, password_hash, first_name, last_name, role)
+            VALUES ($1, $1, 'not-a-hash', 'Test', 'User', $2::user_role)
+            RETURNING id
+            "#,
+        )
+        .bind(format!("test_{}", Uuid::new_v4().simple()))
+        .bind(role)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+    }
+
+    /// Crea un curso del instructor con el título y el estado indicados (`draft`, `published`,
+    /// `archived`) y devuelve su id.
+    async fn insert_course(db_pool: &PgPool, instructor_id: Uuid, title: &str, status: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO courses (title, slug, instructor_id, status, published_at)
+            VALUES ($1, $2, $3, $4::course_status, CASE WHEN $4 = 'published' THEN NOW() END)
+            RETURNING id
+            "#,
+        )
+        .bind(title)
+        .bind(format!("test-{}", Uuid::new_v4().simple()))
+        .bind(instructor_id)
+        .bind(status)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+    }
+
+    /// Ids de una lista de cursos en JSON, ordenados.
+    fn sorted_ids(courses: &serde_json::Value) -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> =
+            courses.as_array().unwrap().iter().map(|course| course["id"].as_str().unwrap().parse().unwrap()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[actix_web::test]
+    async fn batch_lookup_returns_the_existing_courses() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let published = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let draft = insert_course(&state.db_pool, instructor, "Go", "draft").await;
+        let deleted = insert_course(&state.db_pool, instructor, "C", "published").await;
+        sqlx::query("UPDATE courses SET deleted_at = NOW() WHERE id = $1")
+            .bind(deleted)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let req = TestRequest::post()
+            .uri("/courses/batch")
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({ "ids": [published, draft, deleted, Uuid::new_v4()] }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let mut expected = vec![published, draft];
+        expected.sort();
+        assert_eq!(sorted_ids(&body), expected);
+    }
+
+    #[actix_web::test]
+    async fn course_lists_only_include_the_courses_the_caller_can_see() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let owner = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let enrolled = insert_user(&state.db_pool, "student").await;
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let published = insert_course(&state.db_pool, owner, "Published", "published").await;
+        let draft = insert_course(&state.db_pool, owner, "Draft", "draft").await;
+        let archived = insert_course(&state.db_pool, owner, "Archived", "archived").await;
+        insert_enrollment(&state.db_pool, enrolled, archived, "completed", 1).await;
+        let ids = [published, draft, archived];
+        let with_user = |req: TestRequest, user: Option<(Uuid, UserRole)>| match user {
+            Some((id, role)) => req.insert_header((header::AUTHORIZATION, bearer(id, role))).to_request(),
+            None => req.to_request(),
+        };
+        // Cursos de la prueba que aparecen en `GET /courses` y en `POST /courses/batch`.
+        let visible = |user: Option<(Uuid, UserRole)>| {
+            let app = &app;
+            async move {
+                let res = call_service(app, with_user(TestRequest::get().uri("/courses"), user.clone())).await;
+                let listed: serde_json::Value = read_body_json(res).await;
+                let listed: Vec<Uuid> = sorted_ids(&listed).into_iter().filter(|id| ids.contains(id)).collect();
+                let req = TestRequest::post().uri("/courses/batch").set_json(serde_json::json!({ "ids": ids }));
+                let batch: serde_json::Value = read_body_json(call_service(app, with_user(req, user)).await).await;
+                assert_eq!(listed, sorted_ids(&batch));
+                listed
+            }
+        };
+        let sorted = |mut ids: Vec<Uuid>| {
+            ids.sort();
+            ids
+        };
+
+        // Sin sesión o sin relación con el curso, solo los publicados.
+        assert_eq!(visible(None).await, [published]);
+        assert_eq!(visible(Some((student, UserRole::Student))).await, [published]);
+        // La inscripción activa o completada da acceso al curso aunque ya no esté publicado.
+        assert_eq!(visible(Some((enrolled, UserRole::Student))).await, sorted(vec![published, archived]));
+        assert_eq!(visible(Some((owner, UserRole::Instructor))).await, sorted(ids.to_vec()));
+        assert_eq!(visible(Some((admin, UserRole::Admin))).await, sorted(ids.to_vec()));
+    }
+
+    #[actix_web::test]
+    async fn batch_lookup_limits_the_ids() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+
+        let ids: Vec<Uuid> = (0..=MAX_BATCH_IDS).map(|_| Uuid::new_v4()).collect();
+        let req = TestRequest::post().uri("/courses/batch").set_json(serde_json::json!({ "ids": ids })).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+    }
+
+    /// Inscribe al usuario en el curso con el estado indicado (`pending`, `active`, `completed`,
+    /// `dropped`) y la fecha de hace `days_ago` días.
+    async fn insert_enrollment(db_pool: &PgPool, user_id: Uuid, course_id: Uuid, status: &str, days_ago: i32) {
+        sqlx::query(
+            r#"
+            INSERT INTO enrollments (user_id, course_id, status, enrollment_date)
+            VALUES ($1, $2, $3::enrollment_status, NOW() - make_interval(days => $4))
+            "#,
+        )
+        .bind(user_id)
+        .bind(course_id)
+        .bind(status)
+        .bind(days_ago)
+        .execute(db_pool)
+        .await
+        .unwrap();
+    }
+
+    /// Posición del curso en una lista de cursos en JSON, si aparece.
+    fn position(courses: &serde_json::Value, course_id: Uuid) -> Option<usize> {
+        courses.as_array().unwrap().iter().position(|course| course["id"] == course_id.to_string())
+    }
+
+    #[actix_web::test]
+    async fn newest_feed_lists_published_courses_by_publication_date() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let older = insert_course(&state.db_pool, instructor, "Older", "published").await;
+        let newer = insert_course(&state.db_pool, instructor, "Newer", "published").await;
+        sqlx::query("UPDATE courses SET published_at = NOW() - INTERVAL '1 hour' WHERE id = $1")
+            .bind(older)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let draft = insert_course(&state.db_pool, instructor, "Draft", "draft").await;
+
+        let req = TestRequest::get().uri("/courses/newest?per_page=100").to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert!(position(&body, newer).unwrap() < position(&body, older).unwrap());
+        assert_eq!(position(&body, draft), None);
+    }
+
+    #[actix_web::test]
+    async fn trending_feed_ranks_by_enrollments_within_the_window() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let popular = insert_course(&state.db_pool, instructor, "Popular", "published").await;
+        let rising = insert_course(&state.db_pool, instructor, "Rising", "published").await;
+        let former = insert_course(&state.db_pool, instructor, "Former", "published").await;
+        for (course_id, recent, old) in [(popular, 3, 0), (rising, 2, 0), (former, 0, 4)] {
+            for days_ago in std::iter::repeat_n(1, recent).chain(std::iter::repeat_n(30, old)) {
+                let student = insert_user(&state.db_pool, "student").await;
+                insert_enrollment(&state.db_pool, student, course_id, "active", days_ago).await;
+            }
+        }
+
+        // En la última semana (por defecto), las inscripciones antiguas no cuentan.
+        let req = TestRequest::get().uri("/courses/trending?per_page=100").to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let ranks = [popular, rising, former].map(|id| position(&body, id));
+        assert!(ranks[0].unwrap() < ranks[1].unwrap());
+        assert!(ranks[2].is_none_or(|former| former > ranks[1].unwrap()));
+
+        let req = TestRequest::get().uri("/courses/trending?days=60&per_page=100").to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert!(position(&body, former).unwrap() < position(&body, popular).unwrap());
+    }
+
+    /// Añade las etiquetas al curso.
+    async fn tag_course(db_pool: &PgPool, course_id: Uuid, tags: &[String]) {
+        let mut tx = db_pool.begin().await.unwrap();
+        attach_course_tags(&mut tx, course_id, tags).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn tag_suggestions_match_the_prefix_by_usage() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        // Un prefijo único separa las etiquetas de este test de las del resto.
+        let prefix = format!("t{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let [rust, ruby, go] = ["rust", "ruby", "go"].map(|name| format!("{}-{}", prefix, name));
+        let first = insert_course(&state.db_pool, instructor, "First", "published").await;
+        let second = insert_course(&state.db_pool, instructor, "Second", "published").await;
+        tag_course(&state.db_pool, first, &[rust.clone(), ruby.clone(), go.clone()]).await;
+        tag_course(&state.db_pool, second, std::slice::from_ref(&rust)).await;
+
+        let req = TestRequest::get().uri(&format!("/tags/suggest?q={}-RU", prefix.to_uppercase())).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(
+            body,
+            serde_json::json!([
+                { "name": rust, "usage_count": 2 },
+                { "name": ruby, "usage_count": 1 },
+            ])
+        );
+
+        // Los comodines de `LIKE` se buscan literalmente.
+        let req = TestRequest::get().uri(&format!("/tags/suggest?q={}_", prefix)).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body, serde_json::json!([]));
+    }
+
+    #[actix_web::test]
+    async fn bookmarks_are_idempotent_and_listed_with_the_course() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let token = bearer(student, UserRole::Student);
+        let bookmark = |method: TestRequest, course_id: Uuid| {
+            method
+                .uri(&format!("/courses/{}/bookmark", course_id))
+                .insert_header((header::AUTHORIZATION, token.clone()))
+                .to_request()
+        };
+
+        for course_id in [rust, go, rust] {
+            assert_eq!(call_service(&app, bookmark(TestRequest::post(), course_id)).await.status(), 204);
+        }
+        assert_eq!(call_service(&app, bookmark(TestRequest::post(), Uuid::new_v4())).await.status(), 404);
+
+        let list = || TestRequest::get().uri("/me/bookmarks").insert_header((header::AUTHORIZATION, token.clone()));
+        let body: serde_json::Value = read_body_json(call_service(&app, list().to_request()).await).await;
+        let bookmarks = body.as_array().unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        let rust_bookmark = bookmarks.iter().find(|bookmark| bookmark["course_id"] == rust.to_string()).unwrap();
+        assert_eq!(rust_bookmark["title"], "Rust");
+        assert_eq!(rust_bookmark["instructor_id"], instructor.to_string());
+
+        assert_eq!(call_service(&app, bookmark(TestRequest::delete(), go)).await.status(), 204);
+        let body: serde_json::Value = read_body_json(call_service(&app, list().to_request()).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["course_id"], rust.to_string());
+    }
+
+    /// Entregas recibidas por el receptor de webhooks de prueba: la firma y el cuerpo.
+    type Deliveries = tokio::sync::mpsc::UnboundedReceiver<(String, Vec<u8>)>;
+
+    /// Levanta un receptor de webhooks que rechaza la primera entrega con 503 y acepta las
+    /// siguientes. Devuelve la configuración que apunta a él y las entregas recibidas.
+    fn mock_webhook() -> (WebhookSettings, Deliveries) {
+        let (sender, deliveries) = tokio::sync::mpsc::unbounded_channel();
+        let attempts = web::Data::new(std::sync::atomic::AtomicUsize::new(0));
+
+        async fn receive(
+            req: HttpRequest,
+            body: web::Bytes,
+            sender: web::Data<tokio::sync::mpsc::UnboundedSender<(String, Vec<u8>)>>,
+            attempts: web::Data<std::sync::atomic::AtomicUsize>,
+        ) -> HttpResponse {
+            let signature = req.headers().get("X-CCB-Signature").unwrap().to_str().unwrap().to_string();
+            sender.send((signature, body.to_vec())).unwrap();
+            match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => HttpResponse::ServiceUnavailable().finish(),
+                _ => HttpResponse::Ok().finish(),
+            }
+        }
+
+        let sender = web::Data::new(sender);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(sender.clone())
+                .app_data(attempts.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        let settings = WebhookSettings {
+            url: format!("http://{}/hook", addr),
+            secret: "webhook-secret".to_string(),
+        };
+        (settings, deliveries)
+    }
+
+    #[actix_web::test]
+    async fn publishing_a_course_sends_a_signed_webhook_with_retries() {
+        let (settings, mut deliveries) = mock_webhook();
+        let state = web::Data::new(AppState { webhook: Some(settings), ..state().await });
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+
+        let req = TestRequest::patch()
+            .uri(&format!("/courses/{}", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({ "status": "published", "expected_version": 1 }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+
+        // El primer intento falla y el segundo, tras la espera, se entrega con el mismo cuerpo.
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let delivery = tokio::time::timeout(Duration::from_secs(10), deliveries.recv()).await.unwrap();
+            received.push(delivery.unwrap());
+        }
+        assert_eq!(received[0], received[1]);
+        let (signature, body) = &received[0];
+        assert_eq!(*signature, format!("sha256={}", hmac_sha256_hex("webhook-secret", body)));
+        let event: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(event["event"], "course.published");
+        assert_eq!(event["course_id"], course_id.to_string());
+        assert_eq!(event["title"], "Rust");
+        assert_eq!(event["instructor_id"], instructor.to_string());
+    }
+
+    #[actix_web::test]
+    async fn download_tokens_are_minted_for_enrolled_students_and_verified() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let outsider = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+
+        let req = TestRequest::post()
+            .uri(&format!("/courses/{}/materials?file_name=notes.txt", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .set_payload("lesson notes")
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let material: serde_json::Value = read_body_json(res).await;
+        let material_id: Uuid = material["id"].as_str().unwrap().parse().unwrap();
+
+        let mint = |user_id: Uuid| {
+            TestRequest::post()
+                .uri(&format!("/materials/{}/download-token", material_id))
+                .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student)))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, mint(outsider)).await.status(), 403);
+        let res = call_service(&app, mint(student)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let download =
+            |token: &str| TestRequest::get().uri(&format!("/materials/download?token={}", token)).to_request();
+        let res = call_service(&app, download(&token)).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(read_body(res).await, "lesson notes");
+
+        // Un token con otro material o con otra caducidad ya no coincide con la firma.
+        let (_, rest) = token.split_once('.').unwrap();
+        let tampered = format!("{}.{}", Uuid::new_v4(), rest);
+        assert_eq!(call_service(&app, download(&tampered)).await.status(), 403);
+        let (payload, signature) = token.rsplit_once('.').unwrap();
+        let tampered = format!("{}9.{}", payload, signature);
+        assert_eq!(call_service(&app, download(&tampered)).await.status(), 403);
+
+        let expired = sign_download_token("download-secret", material_id, Utc::now() - chrono::Duration::seconds(1));
+        assert_eq!(call_service(&app, download(&expired)).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn student_count_follows_active_enrollments_for_the_owner_only() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let other_instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let mut students = Vec::new();
+        for status in ["active", "active", "pending", "dropped"] {
+            let student = insert_user(&state.db_pool, "student").await;
+            insert_enrollment(&state.db_pool, student, course_id, status, 0).await;
+            students.push(student);
+        }
+        let count = |user_id: Uuid| {
+            TestRequest::get()
+                .uri(&format!("/courses/{}/students/count", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Instructor)))
+                .to_request()
+        };
+
+        let body: serde_json::Value = read_body_json(call_service(&app, count(instructor)).await).await;
+        assert_eq!(body, serde_json::json!({ "count": 2 }));
+
+        // Una baja deja de contar.
+        sqlx::query("UPDATE enrollments SET status = 'dropped' WHERE user_id = $1 AND course_id = $2")
+            .bind(students[0])
+            .bind(course_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let body: serde_json::Value = read_body_json(call_service(&app, count(instructor)).await).await;
+        assert_eq!(body, serde_json::json!({ "count": 1 }));
+
+        assert_eq!(call_service(&app, count(other_instructor)).await.status(), 403);
+    }
+
+    /// Crea una lección del curso en la posición indicada y devuelve su id.
+    async fn insert_lesson(db_pool: &PgPool, course_id: Uuid, title: &str, position: i32) -> Uuid {
+        sqlx::query_scalar("INSERT INTO lessons (course_id, title, position) VALUES ($1, $2, $3) RETURNING id")
+            .bind(course_id)
+            .bind(title)
+            .bind(position)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reordering_lessons_needs_every_lesson_and_leaves_contiguous_positions() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+        // Posiciones con huecos, como las que dejan los borrados.
+        let first = insert_lesson(&state.db_pool, course_id, "First", 1).await;
+        let second = insert_lesson(&state.db_pool, course_id, "Second", 5).await;
+        let third = insert_lesson(&state.db_pool, course_id, "Third", 9).await;
+        let reorder = |lesson_ids: &[Uuid]| {
+            TestRequest::put()
+                .uri(&format!("/courses/{}/lessons/reorder", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(serde_json::json!({ "lesson_ids": lesson_ids }))
+                .to_request()
+        };
+
+        assert_eq!(call_service(&app, reorder(&[third, first])).await.status(), 400);
+        assert_eq!(call_service(&app, reorder(&[third, first, first])).await.status(), 400);
+        assert_eq!(call_service(&app, reorder(&[third, first, Uuid::new_v4()])).await.status(), 400);
+
+        let res = call_service(&app, reorder(&[third, first, second])).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let order: Vec<(String, i64)> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|lesson| (lesson["id"].as_str().unwrap().to_string(), lesson["position"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(order, [(third.to_string(), 1), (first.to_string(), 2), (second.to_string(), 3)]);
+    }
+
+    #[actix_web::test]
+    async fn course_updates_need_the_current_version() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+        let update = |title: &str| {
+            TestRequest::patch()
+                .uri(&format!("/courses/{}", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(serde_json::json!({ "title": title }))
+        };
+
+        assert_eq!(call_service(&app, update("Rust 2").to_request()).await.status(), 428);
+
+        let req = update("Rust 2").insert_header((header::IF_MATCH, "\"1\"")).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), "\"2\"");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["version"], 2);
+
+        // Quien editó la versión 1 ya no puede sobrescribir el cambio.
+        let req = update("Rust 3").insert_header((header::IF_MATCH, "\"1\"")).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "version_conflict");
+        let title: String = sqlx::query_scalar("SELECT title FROM courses WHERE id = $1")
+            .bind(course_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(title, "Rust 2");
+
+        // La versión también puede ir en el cuerpo.
+        let req = TestRequest::put()
+            .uri(&format!("/courses/{}", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({ "title": "Rust 3", "expected_version": 2 }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+    }
+
+    /// Crea una categoría con un nombre único y devuelve su id.
+    async fn insert_category(db_pool: &PgPool) -> Uuid {
+        sqlx::query_scalar("INSERT INTO categories (name) VALUES ($1) RETURNING id")
+            .bind(format!("Category {}", Uuid::new_v4().simple()))
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn admins_categorize_courses_in_bulk() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let category_id = insert_category(&state.db_pool).await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "draft").await;
+        let missing = Uuid::new_v4();
+        let categorize = |user_id: Uuid, role: UserRole, category_id: Uuid| {
+            TestRequest::post()
+                .uri("/courses/categorize")
+                .insert_header((header::AUTHORIZATION, bearer(user_id, role)))
+                .set_json(serde_json::json!({ "category_id": category_id, "course_ids": [rust, go, missing] }))
+                .to_request()
+        };
+
+        let req = categorize(instructor, UserRole::Instructor, category_id);
+        assert_eq!(call_service(&app, req).await.status(), 403);
+        let req = categorize(admin, UserRole::Admin, Uuid::new_v4());
+        assert_eq!(call_service(&app, req).await.status(), 400);
+
+        let res = call_service(&app, categorize(admin, UserRole::Admin, category_id)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let mut updated: Vec<Uuid> = serde_json::from_value(body["updated"].clone()).unwrap();
+        updated.sort();
+        let mut expected = vec![rust, go];
+        expected.sort();
+        assert_eq!(updated, expected);
+        assert_eq!(body["not_found"], serde_json::json!([missing]));
+        let categorized: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM courses WHERE category_id = $1")
+            .bind(category_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(categorized, 2);
+    }
+
+    #[actix_web::test]
+    async fn scheduled_publish_only_publishes_due_drafts_once() {
+        let state = state().await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let due = insert_course(&state.db_pool, instructor, "Due", "draft").await;
+        let future = insert_course(&state.db_pool, instructor, "Future", "draft").await;
+        for (course_id, offset) in [(due, "-1 minute"), (future, "1 day")] {
+            sqlx::query("UPDATE courses SET publish_at = NOW() + $2::interval WHERE id = $1")
+                .bind(course_id)
+                .bind(offset)
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+        }
+
+        let published = publish_scheduled_courses(&state.db_pool).await.unwrap();
+        let due_course = published.iter().find(|course| course.id == due).unwrap();
+        assert_eq!(due_course.status, CourseStatus::Published);
+        assert!(due_course.published_at.is_some());
+        assert!(published.iter().all(|course| course.id != future));
+
+        // Otra réplica que ejecute la tarea después ya no lo encuentra en borrador.
+        let published = publish_scheduled_courses(&state.db_pool).await.unwrap();
+        assert!(published.iter().all(|course| course.id != due && course.id != future));
+        let status: String = sqlx::query_scalar("SELECT status::text FROM courses WHERE id = $1")
+            .bind(future)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "draft");
+    }
+
+    /// Espera a que se registre (en segundo plano) una visita del usuario al curso posterior a
+    /// `after`.
+    async fn wait_for_view(db_pool: &PgPool, user_id: Uuid, course_id: Uuid, after: Option<DateTime<Utc>>) {
+        for _ in 0..100 {
+            let viewed_at: Option<DateTime<Utc>> =
+                sqlx::query_scalar("SELECT viewed_at FROM course_views WHERE user_id = $1 AND course_id = $2")
+                    .bind(user_id)
+                    .bind(course_id)
+                    .fetch_optional(db_pool)
+                    .await
+                    .unwrap();
+            if viewed_at.is_some_and(|viewed_at| after.is_none_or(|after| viewed_at > after)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the view of course {} was never recorded", course_id);
+    }
+
+    #[actix_web::test]
+    async fn viewed_courses_are_listed_once_from_the_most_recent() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let token = bearer(student, UserRole::Student);
+
+        let mut last_view = None;
+        for course_id in [rust, go, rust] {
+            let req = TestRequest::get()
+                .uri(&format!("/courses/{}", course_id))
+                .insert_header((header::AUTHORIZATION, token.clone()))
+                .to_request();
+            assert_eq!(call_service(&app, req).await.status(), 200);
+            wait_for_view(&state.db_pool, student, course_id, last_view).await;
+            last_view = Some(Utc::now());
+        }
+
+        let list = |limit: usize| {
+            TestRequest::get()
+                .uri(&format!("/me/recently-viewed?limit={}", limit))
+                .insert_header((header::AUTHORIZATION, token.clone()))
+                .to_request()
+        };
+        let body: serde_json::Value = read_body_json(call_service(&app, list(10)).await).await;
+        let ids: Vec<&str> =
+            body.as_array().unwrap().iter().map(|course| course["course_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, [rust.to_string(), go.to_string()]);
+
+        let body: serde_json::Value = read_body_json(call_service(&app, list(1)).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["course_id"], rust.to_string());
+    }
+
+    #[actix_web::test]
+    async fn capacity_reports_seats_waitlist_and_the_callers_position() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        sqlx::query("UPDATE courses SET max_students = 2 WHERE id = $1")
+            .bind(course_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let capacity = |user_id: Option<Uuid>| {
+            let req = TestRequest::get().uri(&format!("/courses/{}/capacity", course_id));
+            match user_id {
+                Some(user_id) => req.insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student))),
+                None => req,
+            }
+            .to_request()
+        };
+
+        // Con plazas libres.
+        let student = insert_user(&state.db_pool, "student").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+        let body: serde_json::Value = read_body_json(call_service(&app, capacity(None)).await).await;
+        assert_eq!(body["max_students"], 2);
+        assert_eq!(body["active_count"], 1);
+        assert_eq!(body["waitlist_count"], 0);
+
+        // Lleno, con dos estudiantes en la lista de espera por orden de llegada.
+        let student = insert_user(&state.db_pool, "student").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+        let mut waiting = Vec::new();
+        for minutes_ago in [10, 5] {
+            let student = insert_user(&state.db_pool, "student").await;
+            sqlx::query(
+                r#"
+                INSERT INTO course_waitlist (course_id, user_id, created_at)
+                VALUES ($1, $2, NOW() - make_interval(mins => $3))
+                "#,
+            )
+            .bind(course_id)
+            .bind(student)
+            .bind(minutes_ago)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+            waiting.push(student);
+        }
+        let body: serde_json::Value = read_body_json(call_service(&app, capacity(None)).await).await;
+        assert_eq!(body["active_count"], 2);
+        assert_eq!(body["waitlist_count"], 2);
+        assert!(body.get("my_waitlist_position").is_none());
+
+        let body: serde_json::Value = read_body_json(call_service(&app, capacity(Some(waiting[1]))).await).await;
+        assert_eq!(body["my_waitlist_position"], 2);
+        let body: serde_json::Value = read_body_json(call_service(&app, capacity(Some(student))).await).await;
+        assert!(body.get("my_waitlist_position").is_none());
+    }
+
+    #[actix_web::test]
+    async fn similar_courses_rank_by_shared_tags() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let prefix = format!("s{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let [web, async_tag, cli] = ["web", "async", "cli"].map(|name| format!("{}-{}", prefix, name));
+        let base = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let close = insert_course(&state.db_pool, instructor, "Axum", "published").await;
+        let loose = insert_course(&state.db_pool, instructor, "Clap", "published").await;
+        let hidden = insert_course(&state.db_pool, instructor, "Draft", "draft").await;
+        tag_course(&state.db_pool, base, &[web.clone(), async_tag.clone(), cli.clone()]).await;
+        tag_course(&state.db_pool, close, &[web.clone(), async_tag.clone()]).await;
+        tag_course(&state.db_pool, loose, std::slice::from_ref(&cli)).await;
+        tag_course(&state.db_pool, hidden, &[web, async_tag, cli]).await;
+
+        let similar = |course_id: Uuid| TestRequest::get().uri(&format!("/courses/{}/similar", course_id)).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, similar(base)).await).await;
+        assert_eq!(position(&body, close), Some(0));
+        assert_eq!(position(&body, loose), Some(1));
+        assert_eq!(position(&body, hidden), None);
+
+        // Un curso sin etiquetas recibe otros cursos publicados, nunca a sí mismo.
+        let untagged = insert_course(&state.db_pool, instructor, "Untagged", "published").await;
+        let res = call_service(&app, similar(untagged)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(!body.as_array().unwrap().is_empty());
+        assert_eq!(position(&body, untagged), None);
+
+        assert_eq!(call_service(&app, similar(Uuid::new_v4())).await.status(), 404);
+    }
+
+    /// Publica una reseña del usuario en el curso con la nota indicada y devuelve su id.
+    async fn insert_review(db_pool: &PgPool, course_id: Uuid, user_id: Uuid, rating: i32) -> Uuid {
+        sqlx::query_scalar("INSERT INTO course_reviews (course_id, user_id, rating) VALUES ($1, $2, $3) RETURNING id")
+            .bind(course_id)
+            .bind(user_id)
+            .bind(rating)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn dry_run_deletion_counts_dependents_and_keeps_the_course() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        for position in 1..=3 {
+            insert_lesson(&state.db_pool, course_id, "Lesson", position).await;
+        }
+        for rating in [4, 5] {
+            let student = insert_user(&state.db_pool, "student").await;
+            insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+            insert_review(&state.db_pool, course_id, student, rating).await;
+        }
+        let delete = |query: &str| {
+            TestRequest::delete()
+                .uri(&format!("/courses/{}{}", course_id, query))
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .to_request()
+        };
+
+        let res = call_service(&app, delete("?dry_run=true")).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["course_id"], course_id.to_string());
+        assert_eq!(body["enrollments"], 2);
+        assert_eq!(body["lessons"], 3);
+        assert_eq!(body["reviews"], 2);
+        assert_eq!(body["materials"], 0);
+        let lessons: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lessons WHERE course_id = $1")
+            .bind(course_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(lessons, 3);
+
+        assert_eq!(call_service(&app, delete("")).await.status(), 204);
+        assert_eq!(call_service(&app, delete("?dry_run=true")).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn single_lessons_are_readable_by_enrolled_students_and_owners() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let outsider = insert_user(&state.db_pool, "student").await;
+        let published = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let draft = insert_course(&state.db_pool, instructor, "Go", "draft").await;
+        insert_enrollment(&state.db_pool, student, published, "active", 0).await;
+        let lesson = insert_lesson(&state.db_pool, published, "Ownership", 1).await;
+        let draft_lesson = insert_lesson(&state.db_pool, draft, "Goroutines", 1).await;
+        let read = |user_id: Uuid, role: UserRole, lesson_id: Uuid| {
+            TestRequest::get()
+                .uri(&format!("/lessons/{}", lesson_id))
+                .insert_header((header::AUTHORIZATION, bearer(user_id, role)))
+                .to_request()
+        };
+
+        let res = call_service(&app, read(student, UserRole::Student, lesson)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["title"], "Ownership");
+
+        assert_eq!(call_service(&app, read(outsider, UserRole::Student, lesson)).await.status(), 403);
+        let res = call_service(&app, read(instructor, UserRole::Instructor, draft_lesson)).await;
+        assert_eq!(res.status(), 200);
+        // El borrador no existe para quien no es su propietario.
+        assert_eq!(call_service(&app, read(student, UserRole::Student, draft_lesson)).await.status(), 404);
+    }
+
+    /// Estado actual del curso en la base de datos.
+    async fn course_status(db_pool: &PgPool, course_id: Uuid) -> String {
+        sqlx::query_scalar("SELECT status::text FROM courses WHERE id = $1")
+            .bind(course_id)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn bulk_status_changes_only_the_callers_courses() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let other_instructor = insert_user(&state.db_pool, "instructor").await;
+        let published = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let draft = insert_course(&state.db_pool, instructor, "Go", "draft").await;
+        let archived = insert_course(&state.db_pool, instructor, "C", "archived").await;
+        let foreign = insert_course(&state.db_pool, other_instructor, "Zig", "published").await;
+        let missing = Uuid::new_v4();
+
+        let req = TestRequest::post()
+            .uri("/courses/status/bulk")
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({
+                "status": "archived",
+                "course_ids": [published, draft, archived, foreign, missing],
+            }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(
+            body,
+            serde_json::json!([
+                { "course_id": published, "result": "updated" },
+                { "course_id": draft, "result": "updated" },
+                { "course_id": archived, "result": "unchanged" },
+                { "course_id": foreign, "result": "forbidden" },
+                { "course_id": missing, "result": "not_found" },
+            ])
+        );
+        for course_id in [published, draft, archived] {
+            assert_eq!(course_status(&state.db_pool, course_id).await, "archived");
+        }
+        assert_eq!(course_status(&state.db_pool, foreign).await, "published");
+    }
+
+    #[actix_web::test]
+    async fn course_detail_includes_content_only_for_enrolled_students_and_owners() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let outsider = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+        let lesson = insert_lesson(&state.db_pool, course_id, "Ownership", 1).await;
+        let detail = |user: Option<(Uuid, UserRole)>| {
+            let req = TestRequest::get().uri(&format!("/courses/{}", course_id));
+            match user {
+                Some((user_id, role)) => req.insert_header((header::AUTHORIZATION, bearer(user_id, role))),
+                None => req,
+            }
+            .to_request()
+        };
+
+        for user in [None, Some((outsider, UserRole::Student))] {
+            let body: serde_json::Value = read_body_json(call_service(&app, detail(user)).await).await;
+            assert_eq!(body["title"], "Rust");
+            assert!(body.get("lessons").is_none());
+            assert!(body.get("materials").is_none());
+        }
+
+        for user in [(student, UserRole::Student), (instructor, UserRole::Instructor)] {
+            let body: serde_json::Value = read_body_json(call_service(&app, detail(Some(user))).await).await;
+            assert_eq!(body["title"], "Rust");
+            assert_eq!(body["lessons"][0]["id"], lesson.to_string());
+            assert_eq!(body["materials"], serde_json::json!([]));
+        }
+    }
+
+    #[actix_web::test]
+    async fn export_streams_every_course_as_json_lines_for_admins() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let published = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let draft = insert_course(&state.db_pool, instructor, "Go", "draft").await;
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM courses").fetch_one(&state.db_pool).await.unwrap();
+        let export = |user_id: Uuid, role: UserRole| {
+            TestRequest::get()
+                .uri("/courses/export")
+                .insert_header((header::AUTHORIZATION, bearer(user_id, role)))
+                .to_request()
+        };
+
+        assert_eq!(call_service(&app, export(instructor, UserRole::Instructor)).await.status(), 403);
+
+        let res = call_service(&app, export(admin, UserRole::Admin)).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), "application/x-ndjson");
+        let body = read_body(res).await;
+        let courses: Vec<serde_json::Value> =
+            std::str::from_utf8(&body).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        // Otros tests pueden crear cursos a la vez: como mínimo están todos los que había.
+        assert!(courses.len() as i64 >= total);
+        let mine: Vec<&serde_json::Value> =
+            courses.iter().filter(|course| course["instructor_id"] == instructor.to_string()).collect();
+        assert_eq!(mine.len(), 2);
+        assert_eq!(mine[0]["id"], published.to_string());
+        assert_eq!(mine[0]["status"], "published");
+        assert_eq!(mine[1]["id"], draft.to_string());
+        assert_eq!(mine[1]["status"], "draft");
+    }
+
+    /// Petición de `user_id` para reseñar el curso con la nota indicada.
+    fn review_request(course_id: Uuid, user_id: Uuid, rating: i32) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/courses/{}/reviews", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student)))
+            .set_json(serde_json::json!({ "rating": rating, "comment": "Great course" }))
+    }
+
+    #[actix_web::test]
+    async fn reviews_are_rejected_when_the_course_disables_them() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let create = |fields: serde_json::Value| {
+            TestRequest::post()
+                .uri("/courses")
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(fields)
+                .to_request()
+        };
+        let req = create(serde_json::json!({ "title": "Rust" }));
+        let open: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let req = create(serde_json::json!({ "title": "Go", "allow_reviews": false }));
+        let closed: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(open["allow_reviews"], true);
+        assert_eq!(closed["allow_reviews"], false);
+        let [open, closed] = [open, closed].map(|course| course["id"].as_str().unwrap().parse::<Uuid>().unwrap());
+        for course_id in [open, closed] {
+            insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+        }
+
+        let res = call_service(&app, review_request(open, student, 5).to_request()).await;
+        assert_eq!(res.status(), 201);
+
+        let res = call_service(&app, review_request(closed, student, 5).to_request()).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "reviews_disabled");
+    }
+
+    #[actix_web::test]
+    async fn enrollment_analytics_fill_the_days_without_enrollments() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let other_instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        // Las inscripciones (también las abandonadas) empiezan antes que el propio curso.
+        for (days_ago, status) in [(4, "active"), (4, "dropped"), (2, "completed"), (0, "active")] {
+            let student = insert_user(&state.db_pool, "student").await;
+            insert_enrollment(&state.db_pool, student, course_id, status, days_ago).await;
+        }
+        let analytics = |user_id: Uuid| {
+            TestRequest::get()
+                .uri(&format!("/courses/{}/analytics/enrollments?granularity=day", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Instructor)))
+                .to_request()
+        };
+
+        let res = call_service(&app, analytics(instructor)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let counts: Vec<i64> =
+            body.as_array().unwrap().iter().map(|bucket| bucket["count"].as_i64().unwrap()).collect();
+        assert_eq!(counts, [2, 0, 1, 0, 1]);
+
+        assert_eq!(call_service(&app, analytics(other_instructor)).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn non_owners_get_404_for_hidden_courses_and_403_for_public_ones() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let owner = insert_user(&state.db_pool, "instructor").await;
+        let other = insert_user(&state.db_pool, "instructor").await;
+        let draft = insert_course(&state.db_pool, owner, "Draft", "draft").await;
+        let published = insert_course(&state.db_pool, owner, "Published", "published").await;
+        let token = bearer(other, UserRole::Instructor);
+        let statuses = |course_id: Uuid| {
+            let uri = format!("/courses/{}", course_id);
+            [
+                TestRequest::get().uri(&uri),
+                TestRequest::put().uri(&uri).set_json(serde_json::json!({ "title": "Mine", "expected_version": 1 })),
+                TestRequest::delete().uri(&uri),
+            ]
+            .map(|req| req.insert_header((header::AUTHORIZATION, token.clone())).to_request())
+        };
+
+        let mut codes = Vec::new();
+        for req in statuses(draft) {
+            codes.push(call_service(&app, req).await.status().as_u16());
+        }
+        assert_eq!(codes, [404, 404, 404]);
+
+        let mut codes = Vec::new();
+        for req in statuses(published) {
+            codes.push(call_service(&app, req).await.status().as_u16());
+        }
+        assert_eq!(codes, [200, 403, 403]);
+    }
+
+    #[actix_web::test]
+    async fn lesson_list_is_only_for_enrolled_students_and_owners() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let owner = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let enrolled = insert_user(&state.db_pool, "student").await;
+        let published = insert_course(&state.db_pool, owner, "Published", "published").await;
+        let draft = insert_course(&state.db_pool, owner, "Draft", "draft").await;
+        insert_lesson(&state.db_pool, published, "Intro", 1).await;
+        insert_enrollment(&state.db_pool, enrolled, published, "active", 1).await;
+        let list = |course_id: Uuid, user: Option<(Uuid, UserRole)>| {
+            let req = TestRequest::get().uri(&format!("/courses/{}/lessons", course_id));
+            match user {
+                Some((id, role)) => req.insert_header((header::AUTHORIZATION, bearer(id, role))).to_request(),
+                None => req.to_request(),
+            }
+        };
+
+        // Anónimos y usuarios sin inscripción ven el curso publicado, pero no el contenido de sus lecciones.
+        assert_eq!(call_service(&app, list(published, None)).await.status(), 403);
+        assert_eq!(call_service(&app, list(published, Some((student, UserRole::Student)))).await.status(), 403);
+        assert_eq!(call_service(&app, list(draft, Some((student, UserRole::Student)))).await.status(), 404);
+
+        for user in [(enrolled, UserRole::Student), (owner, UserRole::Instructor)] {
+            let res = call_service(&app, list(published, Some(user))).await;
+            assert_eq!(res.status(), 200);
+            let body: serde_json::Value = read_body_json(res).await;
+            assert_eq!(body[0]["title"], "Intro");
+        }
+    }
+
+    #[test]
+    fn slugify_keeps_lowercase_ascii_words() {
+        assert_eq!(slugify("Intro to Rust"), "intro-to-rust");
+        assert_eq!(slugify("  Programación en Español: ¡Año 2!  "), "programacion-en-espanol-ano-2");
+        assert_eq!(slugify("C++ & C#"), "c-c");
+        assert_eq!(slugify("¿?"), "course");
+        assert_eq!(slugify(&"a".repeat(200)).len(), MAX_SLUG_LENGTH);
+    }
+
+    #[actix_web::test]
+    async fn created_courses_get_unique_slugs_that_resolve() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let marker = Uuid::new_v4().simple().to_string();
+        let create = || {
+            TestRequest::post()
+                .uri("/courses")
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(serde_json::json!({ "title": format!("Intro to Rust {}", marker) }))
+                .to_request()
+        };
+
+        let first: serde_json::Value = read_body_json(call_service(&app, create()).await).await;
+        let second: serde_json::Value = read_body_json(call_service(&app, create()).await).await;
+        let slug = format!("intro-to-rust-{}", marker);
+        assert_eq!(first["slug"], slug);
+        assert_eq!(second["slug"], format!("{}-2", slug));
+
+        let req = TestRequest::get()
+            .uri(&format!("/courses/by-slug/{}-2", slug))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["id"], second["id"]);
+
+        let req = TestRequest::get().uri(&format!("/courses/by-slug/{}-3", slug)).to_request();
+        assert_eq!(call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn slugs_follow_title_changes_only_when_configured() {
+        for follows in [false, true] {
+            let state = web::Data::new(AppState { slug_follows_title: follows, ..state().await });
+            let app = app!(state);
+            let instructor = insert_user(&state.db_pool, "instructor").await;
+            let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+            let old_slug: String = sqlx::query_scalar("SELECT slug FROM courses WHERE id = $1")
+                .bind(course_id)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+            let title = format!("Advanced Rust {}", Uuid::new_v4().simple());
+
+            let req = TestRequest::patch()
+                .uri(&format!("/courses/{}", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(serde_json::json!({ "title": title, "expected_version": 1 }))
+                .to_request();
+            let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+            let expected = if follows { slugify(&title) } else { old_slug };
+            assert_eq!(body["slug"], expected);
+        }
+    }
+
+    /// Guarda un material del curso con el contenido indicado y devuelve su id.
+    async fn insert_material(state: &AppState, course_id: Uuid, contents: &[u8]) -> Uuid {
+        let material_id = Uuid::new_v4();
+        std::fs::write(state.materials_dir.join(material_id.to_string()), contents).unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO course_materials (id, course_id, file_name, content_type, size_bytes)
+            VALUES ($1, $2, 'video.bin', 'application/octet-stream', $3)
+            "#,
+        )
+        .bind(material_id)
+        .bind(course_id)
+        .bind(contents.len() as i64)
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+        material_id
+    }
+
+    #[actix_web::test]
+    async fn downloads_honor_single_byte_ranges() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let material_id = insert_material(&state, course_id, b"0123456789").await;
+        let token = sign_download_token("download-secret", material_id, Utc::now() + chrono::Duration::minutes(5));
+        let download = |range: Option<&str>| {
+            let req = TestRequest::get().uri(&format!("/materials/download?token={}", token));
+            match range {
+                Some(range) => req.insert_header((header::RANGE, range.to_string())),
+                None => req,
+            }
+            .to_request()
+        };
+
+        let res = call_service(&app, download(None)).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+        assert_eq!(read_body(res).await, "0123456789");
+
+        let res = call_service(&app, download(Some("bytes=2-5"))).await;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.headers().get(header::CONTENT_RANGE).unwrap(), "bytes 2-5/10");
+        assert_eq!(read_body(res).await, "2345");
+
+        let res = call_service(&app, download(Some("bytes=-3"))).await;
+        assert_eq!(res.status(), 206);
+        assert_eq!(read_body(res).await, "789");
+
+        let res = call_service(&app, download(Some("bytes=20-30"))).await;
+        assert_eq!(res.status(), 416);
+        assert_eq!(res.headers().get(header::CONTENT_RANGE).unwrap(), "bytes */10");
+    }
+
+    #[actix_web::test]
+    async fn reported_reviews_are_hidden_at_the_threshold_and_moderated() {
+        let state = web::Data::new(AppState { review_report_threshold: 2, ..state().await });
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let author = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let review_id = insert_review(&state.db_pool, course_id, author, 1).await;
+        let report = |user_id: Uuid| {
+            TestRequest::post()
+                .uri(&format!("/reviews/{}/report", review_id))
+                .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student)))
+                .set_json(serde_json::json!({ "reason": "spam" }))
+                .to_request()
+        };
+        let public_reviews = || TestRequest::get().uri(&format!("/courses/{}/reviews", course_id)).to_request();
+
+        assert_eq!(call_service(&app, report(author)).await.status(), 400);
+        let first = insert_user(&state.db_pool, "student").await;
+        let res = call_service(&app, report(first)).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["report_count"], 1);
+        assert_eq!(body["hidden"], false);
+        assert_eq!(call_service(&app, report(first)).await.status(), 409);
+        let body: serde_json::Value = read_body_json(call_service(&app, public_reviews()).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        // El segundo reporte alcanza el umbral y la reseña deja de mostrarse.
+        let res = call_service(&app, report(insert_user(&state.db_pool, "student").await)).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["report_count"], 2);
+        assert_eq!(body["hidden"], true);
+        let body: serde_json::Value = read_body_json(call_service(&app, public_reviews()).await).await;
+        assert_eq!(body, serde_json::json!([]));
+
+        let req = TestRequest::get()
+            .uri("/reviews/reported?per_page=100")
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["id"], review_id.to_string());
+        assert_eq!(body[0]["reasons"], serde_json::json!(["spam", "spam"]));
+
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let delete = || {
+            TestRequest::delete()
+                .uri(&format!("/reviews/{}", review_id))
+                .insert_header((header::AUTHORIZATION, bearer(admin, UserRole::Admin)))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, delete()).await.status(), 204);
+        assert_eq!(call_service(&app, delete()).await.status(), 404);
+    }
+
+    /// Petición del estudiante para completar la lección.
+    fn complete_request(lesson_id: Uuid, user_id: Uuid) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/lessons/{}/complete", lesson_id))
+            .insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student)))
+    }
+
+    /// Espera a que el usuario tenga `count` entradas de actividad del tipo indicado (algunas se
+    /// guardan en segundo plano) y devuelve sus lecciones.
+    async fn wait_for_activity(db_pool: &PgPool, user_id: Uuid, kind: &str, count: usize) -> Vec<Option<Uuid>> {
+        for _ in 0..100 {
+            let lessons: Vec<Option<Uuid>> = sqlx::query_scalar(
+                "SELECT lesson_id FROM activity WHERE user_id = $1 AND kind = $2 ORDER BY created_at",
+            )
+            .bind(user_id)
+            .bind(kind)
+            .fetch_all(db_pool)
+            .await
+            .unwrap();
+            if lessons.len() >= count {
+                return lessons;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("user {} never got {} '{}' activity entries", user_id, count, kind);
+    }
+
+    #[actix_web::test]
+    async fn completing_a_lesson_records_activity_once() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+        let lesson = insert_lesson(&state.db_pool, course_id, "Ownership", 1).await;
+        insert_lesson(&state.db_pool, course_id, "Borrowing", 2).await;
+
+        let res = call_service(&app, complete_request(lesson, student).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let first: serde_json::Value = read_body_json(res).await;
+        assert_eq!(first["course_completed"], false);
+        assert_eq!(wait_for_activity(&state.db_pool, student, activity::LESSON_COMPLETED, 1).await, [Some(lesson)]);
+
+        // Repetirla devuelve la misma fecha y no añade actividad.
+        let again: serde_json::Value =
+            read_body_json(call_service(&app, complete_request(lesson, student).to_request()).await).await;
+        assert_eq!(again["completed_at"], first["completed_at"]);
+        assert_eq!(wait_for_activity(&state.db_pool, student, activity::LESSON_COMPLETED, 1).await.len(), 1);
+
+        let outsider = insert_user(&state.db_pool, "student").await;
+        assert_eq!(call_service(&app, complete_request(lesson, outsider).to_request()).await.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn course_list_can_exclude_the_students_enrollments() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let enrolled = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let dropped = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        insert_enrollment(&state.db_pool, student, enrolled, "active", 0).await;
+        insert_enrollment(&state.db_pool, student, dropped, "dropped", 0).await;
+        let list = |uri: &str, user_id: Option<Uuid>| {
+            let req = TestRequest::get().uri(uri);
+            match user_id {
+                Some(user_id) => req.insert_header((header::AUTHORIZATION, bearer(user_id, UserRole::Student))),
+                None => req,
+            }
+            .to_request()
+        };
+
+        let req = list("/courses?exclude_enrolled=true", Some(student));
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(position(&body, enrolled), None);
+        // Una inscripción abandonada no impide volver a inscribirse.
+        assert!(position(&body, dropped).is_some());
+
+        for req in [list("/courses", Some(student)), list("/courses?exclude_enrolled=true", None)] {
+            let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+            assert!(position(&body, enrolled).is_some());
+        }
+    }
+
+    #[actix_web::test]
+    async fn completing_the_last_lesson_issues_a_certificate_if_enabled() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        for issues_certificate in [true, false] {
+            let student = insert_user(&state.db_pool, "student").await;
+            let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+            sqlx::query("UPDATE courses SET issues_certificate = $2 WHERE id = $1")
+                .bind(course_id)
+                .bind(issues_certificate)
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+            insert_enrollment(&state.db_pool, student, course_id, "active", 0).await;
+            let lessons = [
+                insert_lesson(&state.db_pool, course_id, "Ownership", 1).await,
+                insert_lesson(&state.db_pool, course_id, "Borrowing", 2).await,
+            ];
+
+            let res = call_service(&app, complete_request(lessons[0], student).to_request()).await;
+            let body: serde_json::Value = read_body_json(res).await;
+            assert_eq!(body["course_completed"], false);
+            assert!(body.get("certificate_id").is_none());
+
+            let res = call_service(&app, complete_request(lessons[1], student).to_request()).await;
+            let body: serde_json::Value = read_body_json(res).await;
+            assert_eq!(body["course_completed"], true);
+            let certificate: Option<Uuid> =
+                sqlx::query_scalar("SELECT id FROM certificates WHERE user_id = $1 AND course_id = $2")
+                    .bind(student)
+                    .bind(course_id)
+                    .fetch_optional(&state.db_pool)
+                    .await
+                    .unwrap();
+            assert_eq!(certificate.is_some(), issues_certificate);
+            let issued = body.get("certificate_id").map(|id| id.as_str().unwrap().parse::<Uuid>().unwrap());
+            assert_eq!(issued, certificate);
+            let status: String =
+                sqlx::query_scalar("SELECT status::text FROM enrollments WHERE user_id = $1 AND course_id = $2")
+                    .bind(student)
+                    .bind(course_id)
+                    .fetch_one(&state.db_pool)
+                    .await
+                    .unwrap();
+            assert_eq!(status, "completed");
+        }
+    }
+
+    /// Etiquetas del curso, ordenadas.
+    async fn course_tags(db_pool: &PgPool, course_id: Uuid) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT t.name FROM course_tags ct JOIN tags t ON t.id = ct.tag_id WHERE ct.course_id = $1 ORDER BY t.name",
+        )
+        .bind(course_id)
+        .fetch_all(db_pool)
+        .await
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn patching_taxonomy_replaces_tags_and_rolls_back_on_a_missing_category() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+        let category_id = insert_category(&state.db_pool).await;
+        let prefix = format!("p{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let [web, cli, async_tag] = ["web", "cli", "async"].map(|name| format!("{}-{}", prefix, name));
+        tag_course(&state.db_pool, course_id, &[web.clone(), cli.clone()]).await;
+        let patch = |fields: serde_json::Value| {
+            TestRequest::patch()
+                .uri(&format!("/courses/{}", course_id))
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .set_json(fields)
+                .to_request()
+        };
+
+        let req = patch(serde_json::json!({
+            "category_id": category_id,
+            "tags": [cli, async_tag],
+            "expected_version": 1,
+        }));
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["category_id"], category_id.to_string());
+        assert_eq!(course_tags(&state.db_pool, course_id).await, [async_tag.clone(), cli.clone()]);
+
+        // Con una categoría inexistente no se aplica nada: ni el título ni las etiquetas.
+        let req = patch(serde_json::json!({
+            "title": "Rust 2",
+            "category_id": Uuid::new_v4(),
+            "tags": [web],
+            "expected_version": 2,
+        }));
+        assert_eq!(call_service(&app, req).await.status(), 400);
+        let (title, version): (String, i32) = sqlx::query_as("SELECT title, version FROM courses WHERE id = $1")
+            .bind(course_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!((title.as_str(), version), ("Rust", 2));
+        assert_eq!(course_tags(&state.db_pool, course_id).await, [async_tag, cli]);
+    }
+
+    #[actix_web::test]
+    async fn only_admins_see_soft_deleted_courses_with_include_deleted() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let req = TestRequest::delete()
+            .uri(&format!("/courses/{}?soft=true", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 204);
+        let get = |uri: String, user_id: Uuid, role: UserRole| {
+            TestRequest::get().uri(&uri).insert_header((header::AUTHORIZATION, bearer(user_id, role))).to_request()
+        };
+        let detail = format!("/courses/{}?include_deleted=true", course_id);
+
+        let req = get("/courses?include_deleted=true".to_string(), admin, UserRole::Admin);
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let listed = &body[position(&body, course_id).unwrap()];
+        assert!(listed["deleted_at"].is_string());
+        let res = call_service(&app, get(detail.clone(), admin, UserRole::Admin)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert!(body["deleted_at"].is_string());
+
+        // Sin el parámetro, ni siquiera un admin lo ve.
+        let req = get("/courses".to_string(), admin, UserRole::Admin);
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(position(&body, course_id), None);
+
+        let req = get("/courses?include_deleted=true".to_string(), instructor, UserRole::Instructor);
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(position(&body, course_id), None);
+        let res = call_service(&app, get(detail, instructor, UserRole::Instructor)).await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn delete_endpoints_answer_204_without_a_body() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let lesson_id = insert_lesson(&state.db_pool, course_id, "Ownership", 1).await;
+        let review_id = insert_review(&state.db_pool, course_id, student, 4).await;
+
+        for uri in [
+            format!("/lessons/{}", lesson_id),
+            format!("/reviews/{}", review_id),
+            format!("/courses/{}", course_id),
+        ] {
+            let req = TestRequest::delete()
+                .uri(&uri)
+                .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+                .to_request();
+            let res = call_service(&app, req).await;
+            assert_eq!(res.status(), 204, "{}", uri);
+            assert!(res.headers().get(header::CONTENT_TYPE).is_none(), "{}", uri);
+            assert!(read_body(res).await.is_empty(), "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn featured_feed_follows_the_admin_ranking_of_published_courses() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let draft = insert_course(&state.db_pool, instructor, "Draft", "draft").await;
+        let feature = |user_id: Uuid, role: UserRole, course_ids: &[Uuid]| {
+            TestRequest::put()
+                .uri("/courses/featured")
+                .insert_header((header::AUTHORIZATION, bearer(user_id, role)))
+                .set_json(serde_json::json!({ "course_ids": course_ids }))
+                .to_request()
+        };
+        let featured_ids = || async {
+            let req = TestRequest::get().uri("/courses/featured").to_request();
+            let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+            let ids: Vec<Uuid> =
+                body.as_array().unwrap().iter().map(|course| course["id"].as_str().unwrap().parse().unwrap()).collect();
+            ids
+        };
+
+        let req = feature(instructor, UserRole::Instructor, &[rust]);
+        assert_eq!(call_service(&app, req).await.status(), 403);
+
+        // El borrador queda destacado, pero no aparece hasta que se publique.
+        let req = feature(admin, UserRole::Admin, &[go, draft, rust]);
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        assert_eq!(featured_ids().await, [go, rust]);
+
+        let req = feature(admin, UserRole::Admin, &[rust, go, Uuid::new_v4()]);
+        assert_eq!(call_service(&app, req).await.status(), 400);
+        assert_eq!(featured_ids().await, [go, rust]);
+
+        let req = feature(admin, UserRole::Admin, &[rust, go]);
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        assert_eq!(featured_ids().await, [rust, go]);
+
+        let req = feature(admin, UserRole::Admin, &[]);
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        assert!(featured_ids().await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn text_fields_are_trimmed_and_limited() {
+        let limits = TextLimits {
+            title: 10,
+            description: 20,
+            lesson_content: 5,
+        };
+        let state = web::Data::new(AppState { text_limits: limits, ..state().await });
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let token = bearer(instructor, UserRole::Instructor);
+        let post = |uri: String, fields: serde_json::Value| {
+            TestRequest::post()
+                .uri(&uri)
+                .insert_header((header::AUTHORIZATION, token.clone()))
+                .set_json(fields)
+                .to_request()
+        };
+
+        let req = post("/courses".to_string(), serde_json::json!({ "title": "A".repeat(11) }));
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 400);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "field_too_long");
+        assert_eq!(body["field"], "title");
+
+        // Los espacios alrededor no cuentan para el límite y no se guardan.
+        let req = post(
+            "/courses".to_string(),
+            serde_json::json!({ "title": format!("  {}  ", "A".repeat(10)), "description": "   " }),
+        );
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let course: serde_json::Value = read_body_json(res).await;
+        assert_eq!(course["title"], "A".repeat(10));
+        assert_eq!(course["description"], serde_json::Value::Null);
+
+        let lessons = format!("/courses/{}/lessons", course["id"].as_str().unwrap());
+        let req = post(lessons.clone(), serde_json::json!({ "title": "Intro", "content": "123456" }));
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 400);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["field"], "content");
+        let req = post(lessons, serde_json::json!({ "title": " Intro ", "content": " 12345 " }));
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let lesson: serde_json::Value = read_body_json(res).await;
+        assert_eq!(lesson["title"], "Intro");
+        assert_eq!(lesson["content"], "12345");
+    }
+
+    #[actix_web::test]
+    async fn course_reviews_filter_by_rating_and_sort_by_recency() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        // (nota, hace cuántos días)
+        let mut reviews = Vec::new();
+        for (rating, days_ago) in [(5, 3), (2, 2), (4, 1), (1, 0)] {
+            let student = insert_user(&state.db_pool, "student").await;
+            let review_id = insert_review(&state.db_pool, course_id, student, rating).await;
+            sqlx::query("UPDATE course_reviews SET created_at = NOW() - make_interval(days => $2) WHERE id = $1")
+                .bind(review_id)
+                .bind(days_ago)
+                .execute(&state.db_pool)
+                .await
+                .unwrap();
+            reviews.push(review_id.to_string());
+        }
+        // Índices (en `reviews`) de las reseñas devueltas, en orden.
+        let order = |query: &str| {
+            let req = TestRequest::get().uri(&format!("/courses/{}/reviews?{}", course_id, query)).to_request();
+            let (app, reviews) = (&app, &reviews);
+            async move {
+                let res = call_service(app, req).await;
+                assert_eq!(res.status(), 200);
+                let body: serde_json::Value = read_body_json(res).await;
+                let ids = body.as_array().unwrap().iter().map(|review| review["id"].as_str().unwrap());
+                ids.map(|id| reviews.iter().position(|review| review == id).unwrap()).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(order("").await, [3, 2, 1, 0]);
+        assert_eq!(order("min_rating=4").await, [2, 0]);
+        assert_eq!(order("min_rating=2&max_rating=4&order=asc").await, [1, 2]);
+        assert_eq!(order("sort=rating").await, [0, 2, 1, 3]);
+        assert_eq!(order("per_page=2&page=2").await, [1, 0]);
+
+        let req = TestRequest::get().uri(&format!("/courses/{}/reviews?per_page=1", course_id)).to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert!(body[0]["reviewer_name"].as_str().unwrap().starts_with("test_"));
+
+        for query in ["min_rating=0", "min_rating=4&max_rating=2", "sort=comment"] {
+            let req = TestRequest::get().uri(&format!("/courses/{}/reviews?{}", course_id, query)).to_request();
+            assert_eq!(call_service(&app, req).await.status(), 400, "{}", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn edits_accumulate_revisions_that_can_be_reverted() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "draft").await;
+        let first = insert_lesson(&state.db_pool, course_id, "Ownership", 1).await;
+        let second = insert_lesson(&state.db_pool, course_id, "Borrowing", 2).await;
+        let token = bearer(instructor, UserRole::Instructor);
+        let authorized = |req: TestRequest| req.insert_header((header::AUTHORIZATION, token.clone())).to_request();
+        let revisions = || authorized(TestRequest::get().uri(&format!("/courses/{}/revisions", course_id)));
+
+        let req = TestRequest::patch()
+            .uri(&format!("/courses/{}", course_id))
+            .set_json(serde_json::json!({ "title": "Rust 2", "expected_version": 1 }));
+        assert_eq!(call_service(&app, authorized(req)).await.status(), 200);
+        let req = TestRequest::put()
+            .uri(&format!("/courses/{}/lessons/reorder", course_id))
+            .set_json(serde_json::json!({ "lesson_ids": [second, first] }));
+        assert_eq!(call_service(&app, authorized(req)).await.status(), 200);
+
+        // Cada cambio guarda el contenido que había antes, de la revisión más reciente a la más antigua.
+        let body: serde_json::Value = read_body_json(call_service(&app, revisions()).await).await;
+        let titles: Vec<&str> =
+            body.as_array().unwrap().iter().map(|revision| revision["title"].as_str().unwrap()).collect();
+        assert_eq!(titles, ["Rust 2", "Rust"]);
+        assert_eq!(body[1]["lessons"][0]["id"], first.to_string());
+        let original = body[1]["id"].as_str().unwrap().to_string();
+
+        let req = TestRequest::post().uri(&format!("/courses/{}/revert/{}", course_id, original));
+        let res = call_service(&app, authorized(req)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["title"], "Rust");
+        let positions: Vec<(Uuid, i32)> =
+            sqlx::query_as("SELECT id, position FROM lessons WHERE course_id = $1 ORDER BY position")
+                .bind(course_id)
+                .fetch_all(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(positions, [(first, 1), (second, 2)]);
+
+        // La reversión también deja su revisión, así que se puede deshacer.
+        let body: serde_json::Value = read_body_json(call_service(&app, revisions()).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[0]["title"], "Rust 2");
+
+        let req = TestRequest::post().uri(&format!("/courses/{}/revert/{}", course_id, Uuid::new_v4()));
+        assert_eq!(call_service(&app, authorized(req)).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn sparse_fieldsets_project_courses_and_reject_unknown_fields() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_lesson(&state.db_pool, course_id, "Ownership", 1).await;
+
+        let req = TestRequest::get().uri("/courses?fields=id,title").to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body[position(&body, course_id).unwrap()], serde_json::json!({ "id": course_id, "title": "Rust" }));
+        assert!(body.as_array().unwrap().iter().all(|course| course.as_object().unwrap().len() == 2));
+
+        let req = TestRequest::get()
+            .uri(&format!("/courses/{}?fields=title,lessons", course_id))
+            .insert_header((header::AUTHORIZATION, bearer(instructor, UserRole::Instructor)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        let keys: Vec<&String> = body.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["lessons", "title"]);
+
+        for uri in ["/courses?fields=id,password".to_string(), format!("/courses/{}?fields=id,secret", course_id)] {
+            let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(res.status(), 400, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn courses_requiring_completion_only_accept_reviews_from_graduates() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        sqlx::query("UPDATE courses SET review_requires_completion = TRUE WHERE id = $1")
+            .bind(course_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        let active = insert_user(&state.db_pool, "student").await;
+        let graduate = insert_user(&state.db_pool, "student").await;
+        insert_enrollment(&state.db_pool, active, course_id, "active", 0).await;
+        insert_enrollment(&state.db_pool, graduate, course_id, "completed", 0).await;
+
+        let res = call_service(&app, review_request(course_id, active, 3).to_request()).await;
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "course_not_completed");
+
+        let res = call_service(&app, review_request(course_id, graduate, 5).to_request()).await;
+        assert_eq!(res.status(), 201);
+    }
+
+    #[actix_web::test]
+    async fn errors_carry_a_code_and_follow_accept_language() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let student = insert_user(&state.db_pool, "student").await;
+
+        let req = TestRequest::get()
+            .uri(&format!("/courses/{}", Uuid::new_v4()))
+            .insert_header((header::ACCEPT_LANGUAGE, "es"))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 404);
+        assert_eq!(res.headers().get(header::CONTENT_LANGUAGE).unwrap(), "es");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "course_not_found");
+        assert_eq!(body["message"], "El curso no existe");
+
+        // Sin `Accept-Language` el mensaje sigue en inglés, con el mismo `code`.
+        let req = TestRequest::post()
+            .uri("/categories")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .set_json(serde_json::json!({ "name": "Programación" }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 403);
+        assert!(res.headers().get(header::CONTENT_LANGUAGE).is_none());
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "admin_required");
+        assert_eq!(body["message"], "Only admins can create categories");
+    }
 }
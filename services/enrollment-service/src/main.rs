@@ -1,10 +1,10 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use ccb_common::AuthenticatedUser;
-use actix_cors::Cors;
+use ccb_common::{activity::{self, Activity}, notifications::{self, Notification}, api_key::{ApiKeyGuard, InternalApiKey}, i18n::LocalizedErrors, maintenance::{self, MaintenanceFlag, MaintenanceMode}, rate_limit::{RateLimiter, WriteRateLimit}, health, config::Config, db, error::ApiError, pagination::PageParams, scope, telemetry::{self, AccessLog}, timestamp, AuthenticatedUser, UserRole};
 use serde::{Deserialize, Serialize}; 
-use sqlx::{postgres::PgPoolOptions, FromRow, PgPool};
-use std::env;
+use sqlx::{FromRow, PgExecutor, PgPool, Postgres, QueryBuilder};
 use tracing::{error, info};
+use std::collections::HashMap;
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -13,76 +13,1574 @@ use chrono::{DateTime, Utc};
 #[derive(Deserialize)]
 struct EnrollmentRequest {
     course_id: Uuid,
+    /// Edición del curso en la que inscribirse (solo con `COURSE_OFFERINGS_ENABLED`).
+    offering_id: Option<Uuid>,
+}
+
+/// Estructura para inscribirse en varios cursos en una sola petición.
+#[derive(Deserialize)]
+struct BatchEnrollmentRequest {
+    course_ids: Vec<Uuid>,
+}
+
+/// Resultado de la inscripción en un curso dentro de una petición por lotes.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchEnrollmentOutcome {
+    Enrolled,
+    AlreadyEnrolled,
+    NotFound,
+    /// El curso está completo: el estudiante pasa a la lista de espera.
+    Waitlisted,
+    /// El estudiante alcanzó el máximo de inscripciones activas.
+    LimitReached,
+    /// Es el instructor del curso: no puede inscribirse como estudiante.
+    OwnCourse,
+}
+
+#[derive(Serialize)]
+struct BatchEnrollmentResult {
+    course_id: Uuid,
+    result: BatchEnrollmentOutcome,
+}
+
+/// Número máximo de cursos aceptados en una sola petición a `POST /enrollments/batch`.
+const MAX_BATCH_ENROLLMENTS: usize = 50;
+/// Longitud máxima (en caracteres) de la nota privada de una inscripción.
+const MAX_ENROLLMENT_NOTES_CHARS: usize = 2000;
+
+/// Entrada de la línea de tiempo del usuario.
+#[derive(Serialize, FromRow)]
+struct ActivityEntry {
+    id: Uuid,
+    /// Tipo de actividad (`enrolled`, `lesson_completed`, ...).
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    course_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    course_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lesson_id: Option<Uuid>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    created_at: DateTime<Utc>,
+}
+
+/// Estado de una inscripción. Solo las `active` cuentan como inscripciones en curso.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "enrollment_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+enum EnrollmentStatus {
+    Pending,
+    Active,
+    Completed,
+    Dropped,
 }
 
 #[derive(Serialize, FromRow)]
 struct Enrollment {
     user_id: Uuid,
     course_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offering_id: Option<Uuid>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    enrollment_date: DateTime<Utc>,
+    status: EnrollmentStatus,
+    /// Último cambio de la inscripción (lo actualiza un trigger en cada `UPDATE`).
+    #[serde(serialize_with = "timestamp::serialize")]
+    updated_at: DateTime<Utc>,
+}
+
+/// Estado de la inscripción del usuario en un curso concreto.
+/// `status` solo se incluye si existe una inscripción (en cualquier estado).
+#[derive(Serialize)]
+struct EnrollmentStatusResponse {
+    /// `true` solo si la inscripción está `active`.
+    enrolled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<EnrollmentStatus>,
+}
+
+/// Cambio de estado de una inscripción, tal como lo registra `enrollment_events`.
+#[derive(Serialize, FromRow)]
+struct EnrollmentEvent {
+    #[serde(skip_serializing)]
+    enrollment_id: Uuid,
+    status: EnrollmentStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    occurred_at: DateTime<Utc>,
+}
+
+/// Inscripción del historial del estudiante, con todos sus cambios de estado.
+#[derive(Serialize)]
+struct EnrollmentHistoryEntry {
+    course_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offering_id: Option<Uuid>,
+    title: String,
+    status: EnrollmentStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    enrollment_date: DateTime<Utc>,
+    /// Cambios de estado en orden cronológico (el primero es el alta).
+    events: Vec<EnrollmentEvent>,
+}
+
+/// Fila intermedia del historial antes de añadirle los eventos.
+#[derive(FromRow)]
+struct EnrollmentHistoryRow {
+    id: Uuid,
+    course_id: Uuid,
+    offering_id: Option<Uuid>,
+    title: String,
+    status: EnrollmentStatus,
     enrollment_date: DateTime<Utc>,
 }
 
-/// Estructura para devolver los detalles de un curso en el que el usuario está inscrito.
-#[derive(Serialize, FromRow)]
-struct EnrolledCourseDetails {
-    course_id: Uuid,
-    title: String,
-    description: Option<String>,
-    enrollment_date: DateTime<Utc>,
-}
+/// Parámetros de `POST /enrollments/{course_id}/drop`.
+#[derive(Deserialize)]
+struct DropQuery {
+    /// Edición que se abandona. Sin ella se abandonan todas las inscripciones en el curso.
+    offering_id: Option<Uuid>,
+}
+
+/// Parámetros del listado de mis inscripciones: filtro por estado y ordenación (la paginación va en `PageParams`).
+#[derive(Deserialize)]
+struct MyEnrollmentsQuery {
+    status: Option<EnrollmentStatus>,
+    /// Campo de ordenación: `enrollment_date` (por defecto), `updated_at` o `title`.
+    sort: Option<String>,
+    /// Dirección de ordenación: `asc` o `desc` (por defecto).
+    order: Option<String>,
+}
+
+/// Parámetros del listado de estudiantes de un curso: búsqueda (la paginación va en `PageParams`).
+#[derive(Deserialize)]
+struct RosterQuery {
+    /// Texto a buscar (parcialmente) en el nombre de usuario o el email.
+    q: Option<String>,
+}
+
+/// Estudiante inscrito en un curso, tal como lo ve su instructor.
+#[derive(Serialize, FromRow)]
+struct RosterStudent {
+    user_id: Uuid,
+    username: String,
+    /// `None` si el estudiante aún no ha indicado su email.
+    email: Option<String>,
+    first_name: String,
+    last_name: String,
+    status: EnrollmentStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    enrollment_date: DateTime<Utc>,
+    /// Nota privada del instructor sobre la inscripción (el estudiante no la ve).
+    notes: Option<String>,
+    /// Metadatos libres del instructor (un objeto JSON; `{}` si no hay).
+    metadata: serde_json::Value,
+}
+
+/// Cuerpo de `PUT /enrollments/course/{course_id}/student/{user_id}/notes`. Sustituye la nota y
+/// los metadatos actuales: `notes` ausente o `null` borra la nota y `metadata` ausente la deja en `{}`.
+#[derive(Deserialize)]
+struct UpdateEnrollmentNotes {
+    notes: Option<String>,
+    metadata: Option<serde_json::Value>,
+}
+
+/// Nota y metadatos de una inscripción, tal como quedan tras actualizarlos.
+#[derive(Serialize)]
+struct EnrollmentNotes {
+    user_id: Uuid,
+    course_id: Uuid,
+    notes: Option<String>,
+    metadata: serde_json::Value,
+}
+
+/// Respuesta paginada del listado de estudiantes, con el total de resultados del filtro.
+#[derive(Serialize)]
+struct RosterResponse {
+    students: Vec<RosterStudent>,
+    total: i64,
+    page: i64,
+    per_page: i64,
+}
+
+/// Estado de una dependencia en `GET /ready`.
+#[derive(Serialize)]
+struct DependencyStatus {
+    name: &'static str,
+    up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Respuesta de `GET /ready`: `ready` es `false` si alguna dependencia obligatoria está caída.
+#[derive(Serialize)]
+struct ReadinessResponse {
+    ready: bool,
+    dependencies: Vec<DependencyStatus>,
+}
+
+/// Tiempo máximo de espera de cada comprobación de `GET /ready`.
+const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Estructura para devolver los detalles de un curso en el que el usuario está inscrito.
+#[derive(Serialize, FromRow)]
+struct EnrolledCourseDetails {
+    course_id: Uuid,
+    title: String,
+    description: Option<String>,
+    #[serde(serialize_with = "timestamp::serialize")]
+    enrollment_date: DateTime<Utc>,
+    status: EnrollmentStatus,
+    #[serde(serialize_with = "timestamp::serialize")]
+    updated_at: DateTime<Utc>,
+}
+
+/// Fila intermedia del panel del estudiante: totales de lecciones por curso inscrito.
+#[derive(FromRow)]
+struct DashboardRow {
+    course_id: Uuid,
+    title: String,
+    enrollment_date: DateTime<Utc>,
+    total_lessons: i64,
+    completed_lessons: i64,
+    next_lesson_id: Option<Uuid>,
+}
+
+/// Entrada del panel del estudiante con el progreso en un curso.
+#[derive(Serialize)]
+struct DashboardEntry {
+    course_id: Uuid,
+    title: String,
+    #[serde(serialize_with = "timestamp::serialize")]
+    enrollment_date: DateTime<Utc>,
+    total_lessons: i64,
+    completed_lessons: i64,
+    completion_percentage: f64,
+    /// Primera lección (por posición) que el estudiante aún no ha completado.
+    next_lesson_id: Option<Uuid>,
+}
+
+impl From<DashboardRow> for DashboardEntry {
+    fn from(row: DashboardRow) -> Self {
+        let completion_percentage = if row.total_lessons > 0 {
+            (row.completed_lessons as f64 * 100.0 / row.total_lessons as f64 * 10.0).round() / 10.0
+        } else {
+            0.0
+        };
+
+        Self {
+            course_id: row.course_id,
+            title: row.title,
+            enrollment_date: row.enrollment_date,
+            total_lessons: row.total_lessons,
+            completed_lessons: row.completed_lessons,
+            completion_percentage,
+            next_lesson_id: row.next_lesson_id,
+        }
+    }
+}
+
+struct AppState {
+    db_pool: PgPool,
+    /// Máximo de inscripciones activas por estudiante (`MAX_ACTIVE_ENROLLMENTS_PER_USER`).
+    /// `None` significa sin límite.
+    max_active_enrollments: Option<i64>,
+    http_client: reqwest::Client,
+    /// Endpoint de salud de course-service (`COURSE_SERVICE_HEALTH_URL`). Si está definido,
+    /// `GET /ready` lo comprueba y course-service pasa a ser una dependencia obligatoria.
+    course_service_health_url: Option<String>,
+    /// Ediciones de los cursos activadas (`COURSE_OFFERINGS_ENABLED`).
+    offerings_enabled: bool,
+    /// Para inscribirse hace falta el email verificado, no solo indicado
+    /// (`ENROLLMENT_REQUIRES_VERIFIED_EMAIL`).
+    require_verified_email: bool,
+}
+
+// --- Funciones Auxiliares ---
+
+/// Bloquea la fila del usuario hasta el final de la transacción y devuelve cuántas
+/// inscripciones activas tiene (las de cursos borrados no cuentan). El bloqueo serializa las
+/// inscripciones concurrentes del mismo estudiante, así que dos peticiones simultáneas no pueden
+/// superar el límite.
+async fn lock_and_count_active_enrollments(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM enrollments e
+        JOIN courses c ON c.id = e.course_id
+        WHERE e.user_id = $1 AND e.status = 'active' AND c.deleted_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+/// Bloquea la fila del curso hasta el final de la transacción e indica si el estudiante debe ir
+/// a la lista de espera: el curso no tiene plazas libres (inscripciones `active` iguales o por
+/// encima de `max_students`) y el estudiante aún no tiene inscripción en él (una abandonada no cuenta). Un curso sin
+/// límite o inexistente nunca manda a nadie a la lista de espera. Con `reserve_pending_slots`,
+/// las inscripciones `pending` también ocupan plaza. Con ediciones, solo cuenta como inscripción
+/// previa la de la misma edición (`offering_id`).
+async fn lock_and_check_waitlist(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    course_id: Uuid,
+    offering_id: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    let course = sqlx::query!(
+        "SELECT max_students, reserve_pending_slots FROM courses WHERE id = $1 FOR UPDATE",
+        course_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let Some((max_students, reserve_pending_slots)) =
+        course.and_then(|course| Some((course.max_students?, course.reserve_pending_slots)))
+    else {
+        return Ok(false);
+    };
+
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'active' OR ($3 AND status = 'pending')) as "taken!",
+            COUNT(*) FILTER (
+                WHERE user_id = $2 AND status <> 'dropped' AND offering_id IS NOT DISTINCT FROM $4
+            ) > 0 as "already_enrolled!"
+        FROM enrollments
+        WHERE course_id = $1
+        "#,
+        course_id,
+        user_id,
+        reserve_pending_slots,
+        offering_id as Option<Uuid>
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(!row.already_enrolled && row.taken >= i64::from(max_students))
+}
+
+/// Añade al estudiante a la lista de espera del curso (si ya estaba, conserva su posición).
+async fn join_waitlist(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    course_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO course_waitlist (course_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        course_id,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Inscribe al estudiante en el curso o, con `offering_id`, en esa edición del curso. Si ya la
+/// había abandonado, reactiva esa inscripción en lugar de fallar por la restricción única; con
+/// cualquier otro estado devuelve `None`: es un duplicado. Al inscribirse, el estudiante sale
+/// de la lista de espera del curso si estaba en ella.
+async fn upsert_enrollment(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    course_id: Uuid,
+    offering_id: Option<Uuid>,
+) -> Result<Option<Enrollment>, sqlx::Error> {
+    match offering_id {
+        None => {
+            sqlx::query_as!(
+                Enrollment,
+                r#"
+                WITH left_waitlist AS (DELETE FROM course_waitlist WHERE course_id = $2 AND user_id = $1)
+                INSERT INTO enrollments (user_id, course_id) VALUES ($1, $2)
+                ON CONFLICT (user_id, course_id) WHERE offering_id IS NULL
+                DO UPDATE SET status = 'active', enrollment_date = NOW()
+                WHERE enrollments.status = 'dropped'
+                RETURNING user_id, course_id, offering_id, enrollment_date, status as "status: EnrollmentStatus", updated_at
+                "#,
+                user_id,
+                course_id
+            )
+            .fetch_optional(&mut **tx)
+            .await
+        }
+        Some(offering_id) => {
+            sqlx::query_as!(
+                Enrollment,
+                r#"
+                WITH left_waitlist AS (DELETE FROM course_waitlist WHERE course_id = $2 AND user_id = $1)
+                INSERT INTO enrollments (user_id, course_id, offering_id) VALUES ($1, $2, $3)
+                ON CONFLICT (user_id, offering_id) WHERE offering_id IS NOT NULL
+                DO UPDATE SET status = 'active', enrollment_date = NOW()
+                WHERE enrollments.status = 'dropped'
+                RETURNING user_id, course_id, offering_id, enrollment_date, status as "status: EnrollmentStatus", updated_at
+                "#,
+                user_id,
+                course_id,
+                offering_id
+            )
+            .fetch_optional(&mut **tx)
+            .await
+        }
+    }
+}
+
+/// Entrada de actividad de una inscripción nueva (o reactivada).
+fn enrolled_activity(user_id: Uuid, course_id: Uuid) -> Activity {
+    Activity {
+        user_id,
+        kind: activity::ENROLLED,
+        course_id: Some(course_id),
+        lesson_id: None,
+    }
+}
+
+fn enrollment_limit_error(limit: i64) -> ApiError {
+    ApiError::new(
+        "enrollment_limit_reached",
+        format!("You already have the maximum of {} active enrollments", limit),
+    )
+}
+
+/// Escapa los comodines de `LIKE` (`%`, `_` y `\`) para buscar un texto literal.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Añade al `QueryBuilder` las condiciones del listado de estudiantes de un curso.
+/// Las inscripciones abandonadas no forman parte del listado.
+fn push_roster_filters(builder: &mut QueryBuilder<'_, Postgres>, course_id: Uuid, query: &RosterQuery) {
+    builder
+        .push(" WHERE e.course_id = ")
+        .push_bind(course_id)
+        .push(" AND e.status <> 'dropped'");
+
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", escape_like(q));
+        builder
+            .push(" AND (u.username ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR u.email ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+}
+
+/// Comprueba que la base de datos responde.
+async fn check_database(pool: &PgPool) -> DependencyStatus {
+    let result = sqlx::query("SELECT 1").execute(pool).await;
+    if let Err(e) = &result {
+        error!("Readiness check failed to reach the database: {:?}", e);
+    }
+    DependencyStatus {
+        name: "database",
+        up: result.is_ok(),
+        error: result.err().map(|_| "unreachable".to_string()),
+    }
+}
+
+/// Comprueba que el endpoint de salud de course-service responde con un estado 2xx.
+/// Envía la API key interna, ya que `/health/detail` la exige.
+async fn check_course_service(client: &reqwest::Client, url: &str, api_key: Option<&str>) -> DependencyStatus {
+    let mut request = client.get(url).timeout(READINESS_CHECK_TIMEOUT);
+    if let Some(api_key) = api_key {
+        request = request.header("X-Api-Key", api_key);
+    }
+
+    let error = match request.send().await {
+        Ok(response) if response.status().is_success() => None,
+        Ok(response) => Some(format!("unexpected status {}", response.status().as_u16())),
+        Err(e) => {
+            error!("Readiness check failed to reach course-service: {:?}", e);
+            Some("unreachable".to_string())
+        }
+    };
+    DependencyStatus {
+        name: "course-service",
+        up: error.is_none(),
+        error,
+    }
+}
+
+// --- Manejadores de Endpoints ---
+
+/// Comprueba que la cuenta tiene email (las que se crean sin él deben completarlo con
+/// `PUT /me/email` antes de inscribirse) y, con `ENROLLMENT_REQUIRES_VERIFIED_EMAIL`, que está
+/// verificado. Responde `403` con el código `email_required` o `email_unverified`.
+async fn email_requirement_error(state: &AppState, user_id: Uuid) -> Option<HttpResponse> {
+    let email = sqlx::query!(
+        r#"SELECT email IS NOT NULL as "has_email!", email_verified_at IS NOT NULL as "verified!" FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match email {
+        Ok(Some(email)) if !email.has_email => Some(HttpResponse::Forbidden().json(ApiError::new(
+            "email_required",
+            "Set the account email with PUT /me/email before enrolling",
+        ))),
+        Ok(Some(email)) if state.require_verified_email && !email.verified => Some(HttpResponse::Forbidden().json(
+            ApiError::new("email_unverified", "Verify the account email before enrolling"),
+        )),
+        Ok(_) => None,
+        Err(e) => {
+            error!("Failed to fetch user email status: {:?}", e);
+            Some(HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course")))
+        }
+    }
+}
+
+/// Permiso de un usuario para gestionar los estudiantes de un curso, según `roster_access`.
+enum RosterAccess {
+    /// El curso no existe o el usuario no puede verlo.
+    Hidden,
+    /// El usuario ve el curso, pero no es su propietario ni admin.
+    Forbidden,
+    /// Propietario del curso o admin; incluye el título del curso.
+    Manager { title: String },
+}
+
+/// Comprueba si el usuario puede gestionar los estudiantes del curso. Como en course-service
+/// (`course_access`), a quien no puede ver el curso (borrado, o sin publicar y sin inscripción
+/// suya) se le responde 404, como si no existiera; 403 solo cuando ya puede saber que existe.
+/// Acepta el pool o una transacción (`&mut *tx`).
+async fn roster_access<'e>(
+    executor: impl PgExecutor<'e>,
+    auth_user: &AuthenticatedUser,
+    course_id: Uuid,
+) -> Result<RosterAccess, sqlx::Error> {
+    let course = sqlx::query!(
+        r#"
+        SELECT
+            c.instructor_id, c.title,
+            (c.deleted_at IS NULL AND (c.status = 'published' OR EXISTS (
+                SELECT 1 FROM enrollments e
+                WHERE e.course_id = c.id AND e.user_id = $2 AND e.status IN ('active', 'completed')
+            ))) as "is_visible!"
+        FROM courses c
+        WHERE c.id = $1
+        "#,
+        course_id,
+        auth_user.id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(match course {
+        None => RosterAccess::Hidden,
+        Some(course) if course.instructor_id == auth_user.id || auth_user.role == UserRole::Admin => {
+            RosterAccess::Manager { title: course.title }
+        }
+        Some(course) if course.is_visible => RosterAccess::Forbidden,
+        Some(_) => RosterAccess::Hidden,
+    })
+}
+
+/// Si el usuario puede inscribirse en un curso, según `course_eligibility`.
+enum CourseEligibility {
+    Eligible,
+    /// El curso no existe o está borrado.
+    NotFound,
+    /// Es el instructor del curso (y no es admin).
+    OwnCourse,
+}
+
+/// Comprueba que el usuario puede inscribirse en el curso: el curso no puede estar borrado, y un
+/// instructor no puede inscribirse como estudiante en su propio curso (los admins sí).
+async fn course_eligibility<'e>(
+    executor: impl PgExecutor<'e>,
+    auth_user: &AuthenticatedUser,
+    course_id: Uuid,
+) -> Result<CourseEligibility, sqlx::Error> {
+    let instructor_id = sqlx::query_scalar!(
+        "SELECT instructor_id FROM courses WHERE id = $1 AND deleted_at IS NULL",
+        course_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(match instructor_id {
+        None => CourseEligibility::NotFound,
+        Some(instructor_id) if instructor_id == auth_user.id && auth_user.role != UserRole::Admin => {
+            CourseEligibility::OwnCourse
+        }
+        Some(_) => CourseEligibility::Eligible,
+    })
+}
+
+/// Respuesta de error de `course_eligibility` para las inscripciones de un solo curso.
+async fn course_enrollment_error(
+    state: &AppState,
+    auth_user: &AuthenticatedUser,
+    course_id: Uuid,
+) -> Option<HttpResponse> {
+    match course_eligibility(&state.db_pool, auth_user, course_id).await {
+        Ok(CourseEligibility::NotFound) => Some(HttpResponse::BadRequest().json(
+            ApiError::new("invalid_reference", "The referenced course_id does not exist").with_field("course_id"),
+        )),
+        Ok(CourseEligibility::OwnCourse) => Some(HttpResponse::Conflict().json(ApiError::new(
+            "cannot_enroll_own_course",
+            "Instructors cannot enroll in their own course",
+        ))),
+        Ok(CourseEligibility::Eligible) => None,
+        Err(e) => {
+            error!("Failed to fetch course owner: {:?}", e);
+            Some(HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course")))
+        }
+    }
+}
+
+/// Inscripción vigente (no abandonada) del usuario en el curso, sin edición.
+async fn find_current_enrollment(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    course_id: Uuid,
+) -> Result<Option<Enrollment>, sqlx::Error> {
+    sqlx::query_as!(
+        Enrollment,
+        r#"
+        SELECT user_id, course_id, offering_id, enrollment_date, status as "status: EnrollmentStatus", updated_at
+        FROM enrollments
+        WHERE user_id = $1 AND course_id = $2 AND offering_id IS NULL AND status <> 'dropped'
+        "#,
+        user_id,
+        course_id
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+async fn enroll_in_course(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    enrollment_data: web::Json<EnrollmentRequest>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let user_id = auth_user.id;
+    let course_id = enrollment_data.course_id;
+    let offering_id = enrollment_data.offering_id;
+
+    if let Some(response) = email_requirement_error(&state, user_id).await {
+        return response;
+    }
+
+    if let Some(offering_id) = offering_id {
+        if !state.offerings_enabled {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("offerings_disabled", "Course offerings are disabled"));
+        }
+        let offering_exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM course_offerings WHERE id = $1 AND course_id = $2) as "exists!""#,
+            offering_id,
+            course_id
+        )
+        .fetch_one(&state.db_pool)
+        .await;
+
+        match offering_exists {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::BadRequest().json(
+                    ApiError::new("invalid_reference", "The referenced offering does not exist in this course")
+                        .with_field("offering_id"),
+                );
+            }
+            Err(e) => {
+                error!("Failed to fetch course offering: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to enroll in course"));
+            }
+        }
+    }
+
+    if let Some(response) = course_enrollment_error(&state, &auth_user, course_id).await {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course"));
+        }
+    };
+
+    if let Some(limit) = state.max_active_enrollments {
+        match lock_and_count_active_enrollments(&mut tx, user_id).await {
+            Ok(active) if active >= limit => {
+                return HttpResponse::Conflict().json(enrollment_limit_error(limit));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to count active enrollments: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to enroll in course"));
+            }
+        }
+    }
+
+    // Con el curso completo, el estudiante pasa a la lista de espera en lugar de inscribirse.
+    match lock_and_check_waitlist(&mut tx, user_id, course_id, offering_id).await {
+        Ok(true) => {
+            let joined = match join_waitlist(&mut tx, user_id, course_id).await {
+                Ok(_) => tx.commit().await,
+                Err(e) => Err(e),
+            };
+            return match joined {
+                Ok(_) => HttpResponse::Conflict().json(ApiError::new(
+                    "course_full",
+                    "The course is full; you have been added to its waitlist",
+                )),
+                Err(e) => {
+                    error!("Failed to join course waitlist: {:?}", e);
+                    HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to enroll in course"))
+                }
+            };
+        }
+        Ok(false) => {}
+        Err(e) => {
+            error!("Failed to check course capacity: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course"));
+        }
+    }
+
+    let new_enrollment = upsert_enrollment(&mut tx, user_id, course_id, offering_id).await;
+
+    match new_enrollment {
+        Ok(Some(enrollment)) => match tx.commit().await {
+            Ok(_) => {
+                activity::record_in_background(&state.db_pool, vec![enrolled_activity(user_id, course_id)]);
+                HttpResponse::Created().json(enrollment)
+            }
+            Err(e) => {
+                error!("Failed to commit enrollment: {:?}", e);
+                HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to enroll in course"))
+            }
+        },
+        Ok(None) if offering_id.is_some() => {
+            HttpResponse::Conflict()
+                .json(ApiError::new("already_enrolled", "User is already enrolled in this offering"))
+        }
+        Ok(None) => {
+            HttpResponse::Conflict().json(ApiError::new("already_enrolled", "User is already enrolled in this course"))
+        }
+        // El curso no existe (o se borró mientras tanto).
+        Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),
+        Err(e) => {
+            error!("Failed to enroll user in course: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to enroll in course"))
+        }
+    }
+}
+
+/// Maneja las peticiones PUT a /enrollments/{course_id}
+/// Inscribe al usuario en el curso si aún no lo está y, si ya lo estaba, devuelve su inscripción
+/// actual. Pensado para las integraciones que sincronizan listas de estudiantes: responde `200` en
+/// ambos casos, sin el `409` de `POST /enrollments`.
+async fn ensure_enrollment(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let user_id = auth_user.id;
+    let course_id = path.into_inner();
+
+    if let Some(response) = email_requirement_error(&state, user_id).await {
+        return response;
+    }
+    if let Some(response) = course_enrollment_error(&state, &auth_user, course_id).await {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course"));
+        }
+    };
+
+    // Si ya está inscrito no se aplican el límite de inscripciones ni el cupo del curso.
+    match find_current_enrollment(&mut tx, user_id, course_id).await {
+        Ok(Some(enrollment)) => return HttpResponse::Ok().json(enrollment),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Failed to fetch enrollment: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course"));
+        }
+    }
+
+    if let Some(limit) = state.max_active_enrollments {
+        match lock_and_count_active_enrollments(&mut tx, user_id).await {
+            Ok(active) if active >= limit => {
+                return HttpResponse::Conflict().json(enrollment_limit_error(limit));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to count active enrollments: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to enroll in course"));
+            }
+        }
+    }
+
+    match lock_and_check_waitlist(&mut tx, user_id, course_id, None).await {
+        Ok(true) => {
+            let joined = match join_waitlist(&mut tx, user_id, course_id).await {
+                Ok(_) => tx.commit().await,
+                Err(e) => Err(e),
+            };
+            return match joined {
+                Ok(_) => HttpResponse::Conflict().json(ApiError::new(
+                    "course_full",
+                    "The course is full; you have been added to its waitlist",
+                )),
+                Err(e) => {
+                    error!("Failed to join course waitlist: {:?}", e);
+                    HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to enroll in course"))
+                }
+            };
+        }
+        Ok(false) => {}
+        Err(e) => {
+            error!("Failed to check course capacity: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in course"));
+        }
+    }
+
+    // Si otra petición lo inscribió mientras tanto, el `INSERT` no hace nada (`ON CONFLICT`) y
+    // devolvemos esa inscripción.
+    let enrollment = match upsert_enrollment(&mut tx, user_id, course_id, None).await {
+        Ok(Some(enrollment)) => Ok((enrollment, true)),
+        Ok(None) => match find_current_enrollment(&mut tx, user_id, course_id).await {
+            Ok(Some(enrollment)) => Ok((enrollment, false)),
+            Ok(None) => Err(sqlx::Error::RowNotFound),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+
+    match enrollment {
+        Ok((enrollment, created)) => match tx.commit().await {
+            Ok(_) => {
+                if created {
+                    activity::record_in_background(&state.db_pool, vec![enrolled_activity(user_id, course_id)]);
+                }
+                HttpResponse::Ok().json(enrollment)
+            }
+            Err(e) => {
+                error!("Failed to commit enrollment: {:?}", e);
+                HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to enroll in course"))
+            }
+        },
+        Err(e) if db::is_foreign_key_violation(&e) => db::invalid_reference_response(&e),
+        Err(e) => {
+            error!("Failed to ensure enrollment: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to enroll in course"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /enrollments/batch
+/// Inscribe al usuario en cada curso dentro de una transacción y devuelve el resultado de cada uno.
+async fn enroll_in_courses_batch(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    batch_data: web::Json<BatchEnrollmentRequest>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    if batch_data.course_ids.len() > MAX_BATCH_ENROLLMENTS {
+        return HttpResponse::BadRequest().json(
+            ApiError::new(
+                "too_many_ids",
+                format!("Too many courses: at most {} are allowed per request", MAX_BATCH_ENROLLMENTS),
+            )
+            .with_field("course_ids"),
+        );
+    }
+    if let Some(response) = email_requirement_error(&state, auth_user.id).await {
+        return response;
+    }
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+        }
+    };
+
+    // Con límite configurado, llevamos la cuenta de inscripciones activas mientras avanzamos.
+    let mut active_enrollments = match state.max_active_enrollments {
+        Some(_) => match lock_and_count_active_enrollments(&mut tx, auth_user.id).await {
+            Ok(count) => count,
+            Err(e) => {
+                error!("Failed to count active enrollments: {:?}", e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+            }
+        },
+        None => 0,
+    };
+
+    let mut results = Vec::with_capacity(batch_data.course_ids.len());
+    for &course_id in &batch_data.course_ids {
+        // Comprobamos el curso antes de insertar (las mismas reglas que `POST /enrollments`), para
+        // no abortar la transacción con una violación de clave foránea.
+        let eligibility = course_eligibility(&mut *tx, &auth_user, course_id).await;
+
+        let outcome = match eligibility {
+            Ok(CourseEligibility::NotFound) => BatchEnrollmentOutcome::NotFound,
+            Ok(CourseEligibility::OwnCourse) => BatchEnrollmentOutcome::OwnCourse,
+            Ok(CourseEligibility::Eligible)
+                if state.max_active_enrollments.is_some_and(|limit| active_enrollments >= limit) =>
+            {
+                BatchEnrollmentOutcome::LimitReached
+            }
+            Ok(CourseEligibility::Eligible) => match lock_and_check_waitlist(&mut tx, auth_user.id, course_id, None).await {
+                Ok(true) => match join_waitlist(&mut tx, auth_user.id, course_id).await {
+                    Ok(_) => BatchEnrollmentOutcome::Waitlisted,
+                    Err(e) => {
+                        error!("Failed to join waitlist of course {}: {:?}", course_id, e);
+                        return HttpResponse::InternalServerError()
+                            .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+                    }
+                },
+                // `upsert_enrollment` reactiva una inscripción abandonada y detecta el duplicado sin
+                // abortar la transacción.
+                Ok(false) => match upsert_enrollment(&mut tx, auth_user.id, course_id, None).await {
+                    Ok(Some(_)) => {
+                        active_enrollments += 1;
+                        BatchEnrollmentOutcome::Enrolled
+                    }
+                    Ok(None) => BatchEnrollmentOutcome::AlreadyEnrolled,
+                    Err(e) => {
+                        error!("Failed to enroll user in course {}: {:?}", course_id, e);
+                        return HttpResponse::InternalServerError()
+                            .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to check capacity of course {}: {:?}", course_id, e);
+                    return HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+                }
+            },
+            Err(e) => {
+                error!("Failed to check course {}: {:?}", course_id, e);
+                return HttpResponse::InternalServerError()
+                    .json(ApiError::new("internal_error", "Failed to enroll in courses"));
+            }
+        };
+
+        results.push(BatchEnrollmentResult { course_id, result: outcome });
+    }
+
+    match tx.commit().await {
+        Ok(_) => {
+            let activities = results
+                .iter()
+                .filter(|r| matches!(r.result, BatchEnrollmentOutcome::Enrolled))
+                .map(|r| enrolled_activity(auth_user.id, r.course_id))
+                .collect();
+            activity::record_in_background(&state.db_pool, activities);
+            HttpResponse::Ok().json(results)
+        }
+        Err(e) => {
+            error!("Failed to commit batch enrollment: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to enroll in courses"))
+        }
+    }
+}
+
+/// Maneja las peticiones POST a /enrollments/{course_id}/drop
+/// El estudiante abandona un curso: su inscripción activa o pendiente pasa a `dropped` (con
+/// `?offering_id=`, solo la de esa edición). Si está inscrito en varias ediciones, `offering_id`
+/// es obligatorio: responde `409` con `offering_required` en lugar de abandonarlas todas.
+async fn drop_enrollment(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    query: web::Query<DropQuery>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let course_id = path.into_inner();
+
+    let dropped = sqlx::query_as!(
+        Enrollment,
+        r#"
+        UPDATE enrollments SET status = 'dropped'
+        WHERE user_id = $1 AND course_id = $2 AND status IN ('active', 'pending')
+          AND ($3::uuid IS NULL OR offering_id = $3)
+          -- Sin edición, solo si la inscripción es única: nunca se abandonan varias a la vez.
+          AND ($3::uuid IS NOT NULL OR (
+              SELECT COUNT(*) FROM enrollments
+              WHERE user_id = $1 AND course_id = $2 AND status IN ('active', 'pending')
+          ) = 1)
+        RETURNING user_id, course_id, offering_id, enrollment_date, status as "status: EnrollmentStatus", updated_at
+        "#,
+        auth_user.id,
+        course_id,
+        query.offering_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match dropped {
+        Ok(Some(enrollment)) => HttpResponse::Ok().json(enrollment),
+        Ok(None) if query.offering_id.is_none() => {
+            let active = sqlx::query_scalar!(
+                r#"
+                SELECT COUNT(*) as "count!" FROM enrollments
+                WHERE user_id = $1 AND course_id = $2 AND status IN ('active', 'pending')
+                "#,
+                auth_user.id,
+                course_id
+            )
+            .fetch_one(&state.db_pool)
+            .await;
+            match active {
+                Ok(count) if count > 1 => HttpResponse::Conflict().json(
+                    ApiError::new(
+                        "offering_required",
+                        "You are enrolled in several offerings of this course; choose one with offering_id",
+                    )
+                    .with_field("offering_id"),
+                ),
+                Ok(_) => {
+                    HttpResponse::NotFound()
+                        .json(ApiError::new("enrollment_not_found", "No active enrollment in this course"))
+                }
+                Err(e) => {
+                    error!("Failed to count enrollments: {:?}", e);
+                    HttpResponse::InternalServerError()
+                        .json(ApiError::new("internal_error", "Failed to drop enrollment"))
+                }
+            }
+        }
+        Ok(None) => {
+            HttpResponse::NotFound().json(ApiError::new("enrollment_not_found", "No active enrollment in this course"))
+        }
+        Err(e) => {
+            error!("Failed to drop enrollment: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to drop enrollment"))
+        }
+    }
+}
+
+/// Maneja las peticiones DELETE a /enrollments/course/{course_id}/student/{user_id}
+/// El propietario del curso o un admin dan de baja a un estudiante: su inscripción pasa a
+/// `dropped` (se conserva el historial) y el estudiante recibe una notificación.
+async fn remove_student_from_course(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let (course_id, student_id) = path.into_inner();
+
+    let mut tx = match db::begin(&state.db_pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to remove student"));
+        }
+    };
+
+    let course_title = match roster_access(&mut *tx, &auth_user, course_id).await {
+        Ok(RosterAccess::Manager { title }) => title,
+        Ok(RosterAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(RosterAccess::Forbidden) => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("not_course_owner", "You are not authorized to manage this course's students"))
+        }
+        Err(e) => {
+            error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to remove student"));
+        }
+    };
+
+    let removed = sqlx::query!(
+        "UPDATE enrollments SET status = 'dropped' WHERE user_id = $1 AND course_id = $2 AND status <> 'dropped'",
+        student_id,
+        course_id
+    )
+    .execute(&mut *tx)
+    .await;
+
+    match removed {
+        Ok(result) if result.rows_affected() == 0 => {
+            return HttpResponse::NotFound()
+                .json(ApiError::new("enrollment_not_found", "The student is not enrolled in this course"));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Failed to remove student from course: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to remove student"));
+        }
+    }
+
+    let notification = Notification {
+        user_id: student_id,
+        kind: "enrollment_removed",
+        message: format!("You have been removed from the course \"{}\"", course_title),
+    };
+    if let Err(e) = notifications::create_notification(&mut tx, &notification).await {
+        error!("Failed to notify removed student: {:?}", e);
+        return HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to remove student"));
+    }
+
+    match tx.commit().await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Failed to commit student removal: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to remove student"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /me/enrollments/history
+/// Devuelve todas las inscripciones del usuario, sea cual sea su estado, con su historial de cambios.
+async fn get_my_enrollment_history(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+) -> impl Responder {
+    let rows = sqlx::query_as!(
+        EnrollmentHistoryRow,
+        r#"
+        SELECT e.id, c.id as course_id, e.offering_id, c.title, e.status as "status: EnrollmentStatus", e.enrollment_date
+        FROM enrollments e
+        JOIN courses c ON e.course_id = c.id
+        WHERE e.user_id = $1
+        ORDER BY e.enrollment_date DESC, c.id
+        "#,
+        auth_user.id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch enrollment history: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve your enrollment history"));
+        }
+    };
+
+    let events = sqlx::query_as!(
+        EnrollmentEvent,
+        r#"
+        SELECT enrollment_id, status as "status: EnrollmentStatus", occurred_at
+        FROM enrollment_events
+        WHERE user_id = $1
+        ORDER BY occurred_at, id
+        "#,
+        auth_user.id
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    // Agrupamos los eventos (ya en orden cronológico) por inscripción.
+    let mut events_by_enrollment: HashMap<Uuid, Vec<EnrollmentEvent>> = HashMap::new();
+    match events {
+        Ok(events) => {
+            for event in events {
+                events_by_enrollment.entry(event.enrollment_id).or_default().push(event);
+            }
+        }
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch enrollment events: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve your enrollment history"));
+        }
+    }
+
+    let history: Vec<EnrollmentHistoryEntry> = rows
+        .into_iter()
+        .map(|row| EnrollmentHistoryEntry {
+            events: events_by_enrollment.remove(&row.id).unwrap_or_default(),
+            course_id: row.course_id,
+            offering_id: row.offering_id,
+            title: row.title,
+            status: row.status,
+            enrollment_date: row.enrollment_date,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(history)
+}
+
+/// Maneja las peticiones GET a /me/activity
+/// Devuelve la actividad del usuario autenticado, de la más reciente a la más antigua, paginada.
+async fn get_my_activity(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    pagination: PageParams,
+) -> impl Responder {
+    let entries = sqlx::query_as!(
+        ActivityEntry,
+        r#"
+        SELECT a.id, a.kind, a.course_id, c.title as "course_title?", a.lesson_id, a.created_at
+        FROM activity a
+        LEFT JOIN courses c ON c.id = a.course_id
+        WHERE a.user_id = $1
+        ORDER BY a.created_at DESC, a.id
+        LIMIT $2 OFFSET $3
+        "#,
+        auth_user.id,
+        pagination.limit(),
+        pagination.offset()
+    )
+    .fetch_all(&state.db_pool)
+    .await;
+
+    match entries {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch activity: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve your activity"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /enrollments/status/{course_id}
+/// Indica si el usuario autenticado está inscrito en el curso (con ediciones, en alguna de ellas).
+async fn get_enrollment_status(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let course_id = path.into_inner();
+
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT status as "status: EnrollmentStatus"
+        FROM enrollments
+        WHERE user_id = $1 AND course_id = $2
+        -- Con varias ediciones, prima la inscripción activa y, después, la más reciente.
+        ORDER BY status = 'active' DESC, enrollment_date DESC
+        LIMIT 1
+        "#,
+        auth_user.id,
+        course_id
+    )
+    .fetch_optional(&state.db_pool)
+    .await;
+
+    match status {
+        Ok(status) => HttpResponse::Ok().json(EnrollmentStatusResponse {
+            enrolled: status == Some(EnrollmentStatus::Active),
+            status,
+        }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch enrollment status: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve enrollment status"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /enrollments/my-courses
+/// Los cursos borrados (`deleted_at`) no aparecen.
+async fn get_my_enrollments(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    query: web::Query<MyEnrollmentsQuery>,
+    pagination: PageParams,
+) -> impl Responder {
+    let user_id = auth_user.id;
 
-struct AppState {
-    db_pool: PgPool,
+    // Solo se permite ordenar por columnas conocidas: nunca se interpola el texto del cliente en el SQL.
+    let sort_column = match query.sort.as_deref().unwrap_or("enrollment_date") {
+        "enrollment_date" => "e.enrollment_date",
+        "updated_at" => "e.updated_at",
+        "title" => "c.title",
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ApiError::new("invalid_sort", "Invalid sort field: use 'enrollment_date', 'updated_at' or 'title'")
+                    .with_field("sort"),
+            )
+        }
+    };
+    let sort_direction = match query.order.as_deref().unwrap_or("desc") {
+        "asc" => "ASC",
+        "desc" => "DESC",
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(ApiError::new("invalid_order", "Invalid order: use 'asc' or 'desc'").with_field("order"))
+        }
+    };
+
+    // Hacemos un JOIN entre las tablas `enrollments` y `courses` para obtener los detalles.
+    let mut builder = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT
+            c.id as course_id,
+            c.title,
+            c.description,
+            e.enrollment_date,
+            e.status,
+            e.updated_at
+        FROM enrollments e
+        JOIN courses c ON e.course_id = c.id
+        WHERE c.deleted_at IS NULL AND e.user_id = "#,
+    );
+    builder.push_bind(user_id);
+    if let Some(status) = query.status {
+        builder.push(" AND e.status = ").push_bind(status);
+    }
+    builder
+        .push(format!(" ORDER BY {} {}, e.course_id", sort_column, sort_direction))
+        .push(" LIMIT ")
+        .push_bind(pagination.limit())
+        .push(" OFFSET ")
+        .push_bind(pagination.offset());
+
+    let enrolled_courses = builder
+        .build_query_as::<EnrolledCourseDetails>()
+        .fetch_all(&state.db_pool)
+        .await;
+
+    match enrolled_courses {
+        Ok(courses) => HttpResponse::Ok().json(courses),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch user enrollments: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve your enrollments"))
+        }
+    }
 }
 
-async fn enroll_in_course(
+/// Maneja las peticiones PUT a /enrollments/course/{course_id}/student/{user_id}/notes
+/// El propietario del curso o un admin guardan una nota privada y metadatos en la inscripción
+/// de un estudiante. Solo aparecen en el listado de estudiantes del curso.
+async fn update_enrollment_notes(
     state: web::Data<AppState>,
     auth_user: AuthenticatedUser,
-    enrollment_data: web::Json<EnrollmentRequest>,
+    path: web::Path<(Uuid, Uuid)>,
+    notes_data: web::Json<UpdateEnrollmentNotes>,
 ) -> impl Responder {
-    let user_id = auth_user.id;
-    let course_id = enrollment_data.course_id;
+    if let Some(response) = auth_user.missing_scope(scope::ENROLLMENTS_WRITE) {
+        return response;
+    }
+    let (course_id, student_id) = path.into_inner();
+    let UpdateEnrollmentNotes { notes, metadata } = notes_data.into_inner();
 
-    let new_enrollment = sqlx::query_as!(
-        Enrollment,
-        "INSERT INTO enrollments (user_id, course_id) VALUES ($1, $2) RETURNING user_id, course_id, enrollment_date",
-        user_id,
-        course_id
+    let notes = notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty());
+    if notes.as_ref().is_some_and(|notes| notes.chars().count() > MAX_ENROLLMENT_NOTES_CHARS) {
+        return HttpResponse::BadRequest().json(
+            ApiError::new(
+                "field_too_long",
+                format!("notes must be at most {} characters long", MAX_ENROLLMENT_NOTES_CHARS),
+            )
+            .with_field("notes"),
+        );
+    }
+    let metadata = metadata.unwrap_or_else(|| serde_json::json!({}));
+    if !metadata.is_object() {
+        return HttpResponse::BadRequest()
+            .json(ApiError::new("invalid_metadata", "metadata must be a JSON object").with_field("metadata"));
+    }
+
+    match roster_access(&state.db_pool, &auth_user, course_id).await {
+        Ok(RosterAccess::Manager { .. }) => {}
+        Ok(RosterAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(RosterAccess::Forbidden) => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("not_course_owner", "You are not authorized to manage this course's students"))
+        }
+        Err(e) => {
+            error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update enrollment notes"));
+        }
+    }
+
+    let updated = sqlx::query!(
+        "UPDATE enrollments SET notes = $3, metadata = $4 WHERE user_id = $1 AND course_id = $2",
+        student_id,
+        course_id,
+        notes,
+        metadata
     )
-    .fetch_one(&state.db_pool)
+    .execute(&state.db_pool)
     .await;
 
-    match new_enrollment {
-        Ok(enrollment) => HttpResponse::Created().json(enrollment),
-        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
-            HttpResponse::Conflict().body("User is already enrolled in this course")
+    match updated {
+        Ok(result) if result.rows_affected() == 0 => {
+            HttpResponse::NotFound()
+                .json(ApiError::new("enrollment_not_found", "The student is not enrolled in this course"))
         }
+        Ok(_) => HttpResponse::Ok().json(EnrollmentNotes {
+            user_id: student_id,
+            course_id,
+            notes,
+            metadata,
+        }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
         Err(e) => {
-            error!("Failed to enroll user in course: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to enroll in course")
+            error!("Failed to update enrollment notes: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to update enrollment notes"))
         }
     }
 }
 
-/// Maneja las peticiones GET a /enrollments/my-courses
-async fn get_my_enrollments(
+/// Maneja las peticiones GET a /enrollments/course/{course_id}/students
+/// Devuelve los estudiantes del curso paginados (solo su propietario o un admin). Admite buscar
+/// con `q` en el nombre de usuario o el email, y devuelve el total de estudiantes del filtro.
+async fn get_course_students(
+    state: web::Data<AppState>,
+    auth_user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    query: web::Query<RosterQuery>,
+    pagination: PageParams,
+) -> impl Responder {
+    let course_id = path.into_inner();
+    let PageParams { page, per_page } = pagination;
+
+    match roster_access(&state.db_pool, &auth_user, course_id).await {
+        Ok(RosterAccess::Manager { .. }) => {}
+        Ok(RosterAccess::Hidden) => {
+            return HttpResponse::NotFound().json(ApiError::new("course_not_found", "Course not found"))
+        }
+        Ok(RosterAccess::Forbidden) => {
+            return HttpResponse::Forbidden()
+                .json(ApiError::new("not_course_owner", "You are not authorized to view this course's students"))
+        }
+        Err(e) => {
+            error!("Failed to fetch course: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve students"));
+        }
+    }
+
+    // 1. Contar el total de estudiantes que cumplen el filtro.
+    let mut count_builder = QueryBuilder::<Postgres>::new(
+        "SELECT COUNT(*) FROM enrollments e JOIN users u ON u.id = e.user_id",
+    );
+    push_roster_filters(&mut count_builder, course_id, &query);
+
+    let total: i64 = match count_builder.build_query_scalar().fetch_one(&state.db_pool).await {
+        Ok(total) => total,
+        Err(e) if db::is_timeout(&e) => return db::timeout_response(),
+        Err(e) => {
+            error!("Failed to count course students: {:?}", e);
+            return HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve students"));
+        }
+    };
+
+    // 2. Obtener la página pedida, ordenada por nombre de usuario.
+    let mut builder = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT
+            e.user_id,
+            u.display_username as username,
+            u.email,
+            u.first_name,
+            u.last_name,
+            e.status,
+            e.enrollment_date,
+            e.notes,
+            e.metadata
+        FROM enrollments e
+        JOIN users u ON u.id = e.user_id"#,
+    );
+    push_roster_filters(&mut builder, course_id, &query);
+    builder
+        .push(" ORDER BY u.username, e.user_id LIMIT ")
+        .push_bind(per_page)
+        .push(" OFFSET ")
+        .push_bind(pagination.offset());
+
+    match builder.build_query_as::<RosterStudent>().fetch_all(&state.db_pool).await {
+        Ok(students) => HttpResponse::Ok().json(RosterResponse { students, total, page, per_page }),
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
+        Err(e) => {
+            error!("Failed to fetch course students: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiError::new("internal_error", "Failed to retrieve students"))
+        }
+    }
+}
+
+/// Maneja las peticiones GET a /me/dashboard
+/// Devuelve cada curso inscrito con su porcentaje de avance y la siguiente lección pendiente.
+async fn get_my_dashboard(
     state: web::Data<AppState>,
     auth_user: AuthenticatedUser,
 ) -> impl Responder {
     let user_id = auth_user.id;
 
-    // Hacemos un JOIN entre las tablas `enrollments` y `courses` para obtener los detalles.
-    let enrolled_courses = sqlx::query_as!(
-        EnrolledCourseDetails,
+    // Combinamos inscripciones, lecciones y progreso en una sola consulta agregada.
+    let rows = sqlx::query_as!(
+        DashboardRow,
         r#"
-        SELECT 
+        SELECT
             c.id as "course_id!",
             c.title as "title!",
-            c.description,
-            e.enrollment_date as "enrollment_date!"
+            e.enrollment_date as "enrollment_date!",
+            COUNT(l.id) as "total_lessons!",
+            COUNT(lp.lesson_id) as "completed_lessons!",
+            (
+                SELECT nl.id
+                FROM lessons nl
+                LEFT JOIN lesson_progress nlp ON nlp.lesson_id = nl.id AND nlp.user_id = $1
+                WHERE nl.course_id = c.id AND nlp.lesson_id IS NULL
+                ORDER BY nl.position, nl.created_at
+                LIMIT 1
+            ) as next_lesson_id
         FROM enrollments e
         JOIN courses c ON e.course_id = c.id
-        WHERE e.user_id = $1
+        LEFT JOIN lessons l ON l.course_id = c.id
+        LEFT JOIN lesson_progress lp ON lp.lesson_id = l.id AND lp.user_id = e.user_id
+        WHERE e.user_id = $1 AND e.status = 'active'
+        GROUP BY c.id, c.title, e.enrollment_date
         ORDER BY e.enrollment_date DESC
         "#,
         user_id
@@ -90,48 +1588,1204 @@ async fn get_my_enrollments(
     .fetch_all(&state.db_pool)
     .await;
 
-    match enrolled_courses {
-        Ok(courses) => HttpResponse::Ok().json(courses),
+    match rows {
+        Ok(rows) => {
+            let dashboard: Vec<DashboardEntry> = rows.into_iter().map(DashboardEntry::from).collect();
+            HttpResponse::Ok().json(dashboard)
+        }
+        Err(e) if db::is_timeout(&e) => db::timeout_response(),
         Err(e) => {
-            error!("Failed to fetch user enrollments: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to retrieve your enrollments")
+            error!("Failed to build dashboard: {:?}", e);
+            HttpResponse::InternalServerError()
+                .json(ApiError::new("internal_error", "Failed to retrieve your dashboard"))
         }
     }
 }
 
+/// Maneja las peticiones GET a /health/detail (solo uso interno, requiere `X-Api-Key`)
+/// Devuelve la versión del servicio, la de PostgreSQL y si el esquema tiene todas las migraciones.
+async fn get_health_detail(state: web::Data<AppState>, _api_key: ApiKeyGuard) -> impl Responder {
+    health::health_detail(&state.db_pool, "enrollment-service", env!("CARGO_PKG_VERSION")).await
+}
+
+/// Maneja las peticiones GET a /ready
+/// Indica si el servicio puede atender peticiones: comprueba la base de datos y, si se configuró
+/// `COURSE_SERVICE_HEALTH_URL`, course-service. Responde 503 si alguna dependencia está caída.
+async fn get_readiness(state: web::Data<AppState>, api_key: web::Data<InternalApiKey>) -> impl Responder {
+    let mut dependencies = vec![check_database(&state.db_pool).await];
+    if let Some(url) = &state.course_service_health_url {
+        dependencies.push(check_course_service(&state.http_client, url, api_key.0.as_deref()).await);
+    }
+
+    let ready = dependencies.iter().all(|dependency| dependency.up);
+    let mut response = if ready { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+    response.json(ReadinessResponse { ready, dependencies })
+}
+
+/// Registra las rutas del servicio. Lo usan `main` y las pruebas, para montar la misma aplicación.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/enrollments")
+            .route("", web::post().to(enroll_in_course))
+            .route("/batch", web::post().to(enroll_in_courses_batch))
+            .route("/my-courses", web::get().to(get_my_enrollments))
+            .route("/status/{course_id}", web::get().to(get_enrollment_status))
+            .route("/{course_id}", web::put().to(ensure_enrollment))
+            .route("/{course_id}/drop", web::post().to(drop_enrollment))
+            .route("/course/{course_id}/students", web::get().to(get_course_students))
+            .route(
+                "/course/{course_id}/student/{user_id}",
+                web::delete().to(remove_student_from_course),
+            )
+            .route(
+                "/course/{course_id}/student/{user_id}/notes",
+                web::put().to(update_enrollment_notes),
+            ),
+    )
+    .service(
+        web::scope("/me")
+            .route("/dashboard", web::get().to(get_my_dashboard))
+            .route("/enrollments/history", web::get().to(get_my_enrollment_history))
+            .route("/activity", web::get().to(get_my_activity)),
+    )
+    // Activa o desactiva el modo mantenimiento (solo administradores).
+    .route(maintenance::MAINTENANCE_PATH, web::put().to(maintenance::set_maintenance))
+    // Estado detallado para operaciones (requiere `X-Api-Key`).
+    .route("/health/detail", web::get().to(get_health_detail))
+    // Disponibilidad del servicio y de sus dependencias (sin autenticación).
+    .route("/ready", web::get().to(get_readiness));
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
     dotenvy::dotenv().ok();
+    telemetry::init_logging();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+    // Carga y valida toda la configuración de una vez; si falta algo, se informa de todos los problemas.
+    let config = Config::from_env("ENROLLMENT").unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
+
+    let db_pool = db::connect(&config)
         .await
         .expect("Failed to create database pool.");
 
-    info!("🚀 Servidor de inscripciones iniciado en http://localhost:8083");
+    let http_client = reqwest::Client::builder()
+        .timeout(READINESS_CHECK_TIMEOUT)
+        .build()
+        .expect("Failed to create HTTP client.");
+
+    info!("🚀 Servidor de inscripciones iniciado en el puerto {}", config.port);
+    let port = config.port;
+    let workers = config.http_workers;
+    let keep_alive = config.keep_alive();
+    info!("HTTP workers: {}, keep-alive: {:?}", workers, config.http_keep_alive);
+    // El modo mantenimiento se comparte entre todos los workers y se puede cambiar en caliente.
+    let maintenance_flag = MaintenanceFlag::new(config.maintenance_mode);
+    // Los cubos del límite de escrituras también se comparten entre los workers.
+    let write_limiter = config.write_rate_limit.map(RateLimiter::new);
 
     HttpServer::new(move || {
         App::new()
-            // Middleware de CORS: permite peticiones desde cualquier origen.
+            // Limita las escrituras por usuario (o por IP) con `WRITE_RATE_LIMIT_*`; responde 429.
+            .wrap(WriteRateLimit::new(write_limiter.clone()))
+            // Con el modo mantenimiento activo, rechaza las escrituras con 503 y deja pasar las lecturas.
+            .wrap(MaintenanceMode::new(maintenance_flag.clone()))
+            // Traduce los mensajes de `ApiError` según `Accept-Language` (por defecto, inglés).
+            .wrap(LocalizedErrors)
+            // Middleware de CORS con la política de `ENROLLMENT_CORS_*` (o `CORS_*`); sin configurar permite cualquier origen.
             // ¡IMPORTANTE! En producción, esto debería restringirse a dominios específicos.
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header(),
-            )
-            .wrap(actix_web::middleware::Logger::default())
-            .app_data(web::Data::new(AppState { db_pool: db_pool.clone() }))
-            .service(
-                web::scope("/enrollments")
-                    .route("", web::post().to(enroll_in_course))
-                    .route("/my-courses", web::get().to(get_my_enrollments)),
-            )
+            .wrap(config.cors())
+            // Cabeceras de seguridad (`nosniff`, `X-Frame-Options`, HSTS y CSP) en todas las respuestas.
+            .wrap(config.security_headers.clone())
+            // Logs de acceso estructurados (método, ruta, estado, latencia, request_id y usuario).
+            .wrap(AccessLog)
+            // Claves para verificar los JWT de `AuthenticatedUser` (admite varias durante una rotación).
+            .app_data(web::Data::new(config.jwt_keys.clone()))
+            // Pool con el que `AuthenticatedUser` valida los tokens de acceso personal.
+            .app_data(web::Data::new(db_pool.clone()))
+            // Errores claros (415/400) para cuerpos JSON con otro `Content-Type`, vacíos o inválidos.
+            .app_data(ccb_common::error::json_config())
+            // Máximo de `per_page` de los listados paginados (`PageParams`).
+            .app_data(web::Data::new(config.pagination))
+            // API key de los endpoints internos como `/health/detail` (si está configurada).
+            .app_data(web::Data::new(config.internal_api_key.clone()))
+            // Si hay un proxy de confianza delante, la IP del cliente se toma de sus cabeceras.
+            .app_data(web::Data::new(config.trusted_proxy))
+            .app_data(web::Data::new(maintenance_flag.clone()))
+            .app_data(web::Data::new(AppState {
+                db_pool: db_pool.clone(),
+                max_active_enrollments: config.max_active_enrollments,
+                http_client: http_client.clone(),
+                course_service_health_url: config.course_service_health_url.clone(),
+                offerings_enabled: config.course_offerings_enabled,
+                require_verified_email: config.enrollment_requires_verified_email,
+            }))
+            // Rutas del servicio.
+            .configure(routes)
     })
-    .bind(("0.0.0.0", 8080))?
+    .workers(workers)
+    .keep_alive(keep_alive)
+    .bind(("0.0.0.0", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, read_body_json, TestRequest};
+    use ccb_common::{jwt::JwtKeySet, Claims};
+    use sqlx::postgres::PgPoolOptions;
+
+    /// API key de los endpoints internos.
+    const API_KEY: &str = "internal-key";
+
+    /// Monta la aplicación con las rutas del servicio, la traducción de errores y el estado indicado
+    /// (`web::Data<AppState>`).
+    macro_rules! app {
+        ($state:expr) => {
+            init_service(
+                App::new()
+                    .wrap(LocalizedErrors)
+                    .app_data(web::Data::new(keys()))
+                    .app_data(web::Data::new($state.db_pool.clone()))
+                    .app_data(ccb_common::error::json_config())
+                    .app_data(web::Data::new(InternalApiKey(Some(API_KEY.to_string()))))
+                    .app_data($state.clone())
+                    .configure(routes),
+            )
+            .await
+        };
+    }
+
+    /// Claves HMAC con las que se firman los tokens de los tests.
+    fn keys() -> JwtKeySet {
+        JwtKeySet::new("current".to_string(), "test-secret".to_string(), Vec::new())
+    }
+
+    /// Valor de la cabecera `Authorization` con un token de sesión del usuario.
+    fn bearer(id: Uuid, role: UserRole) -> String {
+        let claims = Claims {
+            sub: id.to_string(),
+            role,
+            exp: (Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
+            password_change_required: false,
+            scopes: None,
+            act_as: None,
+        };
+        format!("Bearer {}", keys().encode(&claims).unwrap())
+    }
+
+    /// Estado del servicio contra la base de datos de los tests (`DATABASE_URL`), sin límite de
+    /// inscripciones, sin ediciones y sin exigir el email verificado. Los tests lo envuelven en
+    /// `web::Data`, tras cambiar los campos que necesiten.
+    async fn state() -> AppState {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point to the test database");
+        AppState {
+            db_pool: PgPoolOptions::new().max_connections(4).connect(&url).await.unwrap(),
+            max_active_enrollments: None,
+            http_client: reqwest::Client::new(),
+            course_service_health_url: None,
+            offerings_enabled: false,
+            require_verified_email: false,
+        }
+    }
+
+    /// Crea un usuario con un nombre único, email (sin verificar) y el rol indicado (`student`,
+    /// `instructor`, `admin`) y devuelve su id.
+    async fn insert_user(db_pool: &PgPool, role: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO users (username, display_username, email, password_hash, first_name, last_name, role)
+            VALUES ($1, $1, $1 || '@example.com', 'not-a-hash', 'Test', 'User', $2::user_role)
+            RETURNING id
+            "#,
+        )
+        .bind(format!("test_{}", Uuid::new_v4().simple()))
+        .bind(role)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+    }
+
+    /// Crea un curso del instructor con el título y el estado indicados (`draft`, `published`,
+    /// `archived`) y devuelve su id.
+    async fn insert_course(db_pool: &PgPool, instructor_id: Uuid, title: &str, status: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO courses (title, slug, instructor_id, status, published_at)
+            VALUES ($1, $2, $3, $4::course_status, CASE WHEN $4 = 'published' THEN NOW() END)
+            RETURNING id
+            "#,
+        )
+        .bind(title)
+        .bind(format!("test-{}", Uuid::new_v4().simple()))
+        .bind(instructor_id)
+        .bind(status)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+    }
+
+    /// Crea una lección del curso en la posición indicada y devuelve su id.
+    async fn insert_lesson(db_pool: &PgPool, course_id: Uuid, title: &str, position: i32) -> Uuid {
+        sqlx::query_scalar("INSERT INTO lessons (course_id, title, position) VALUES ($1, $2, $3) RETURNING id")
+            .bind(course_id)
+            .bind(title)
+            .bind(position)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    /// Inscribe al usuario en el curso con el estado indicado (`pending`, `active`, `completed`,
+    /// `dropped`) y la fecha de hace `days_ago` días.
+    async fn insert_enrollment(db_pool: &PgPool, user_id: Uuid, course_id: Uuid, status: &str, days_ago: i32) {
+        sqlx::query(
+            r#"
+            INSERT INTO enrollments (user_id, course_id, status, enrollment_date)
+            VALUES ($1, $2, $3::enrollment_status, NOW() - make_interval(days => $4))
+            "#,
+        )
+        .bind(user_id)
+        .bind(course_id)
+        .bind(status)
+        .bind(days_ago)
+        .execute(db_pool)
+        .await
+        .unwrap();
+    }
+
+    /// Marca la lección como completada por el usuario.
+    async fn complete_lesson(db_pool: &PgPool, user_id: Uuid, lesson_id: Uuid) {
+        sqlx::query("INSERT INTO lesson_progress (user_id, lesson_id) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(lesson_id)
+            .execute(db_pool)
+            .await
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn dashboard_reports_progress_and_the_next_lesson() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let started = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let empty = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let dropped = insert_course(&state.db_pool, instructor, "C", "published").await;
+        // Las posiciones no siguen el orden de creación: la siguiente lección se elige por posición.
+        let third = insert_lesson(&state.db_pool, started, "Third", 3).await;
+        let first = insert_lesson(&state.db_pool, started, "First", 1).await;
+        let second = insert_lesson(&state.db_pool, started, "Second", 2).await;
+        insert_enrollment(&state.db_pool, student, started, "active", 1).await;
+        insert_enrollment(&state.db_pool, student, empty, "active", 2).await;
+        insert_enrollment(&state.db_pool, student, dropped, "dropped", 3).await;
+        // Completar la tercera no cuenta como siguiente: la segunda sigue pendiente.
+        complete_lesson(&state.db_pool, student, first).await;
+        complete_lesson(&state.db_pool, student, third).await;
+
+        let req = TestRequest::get()
+            .uri("/me/dashboard")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let entries = body.as_array().unwrap();
+        // Solo las inscripciones activas, de la más reciente a la más antigua.
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["course_id"], started.to_string());
+        assert_eq!(entries[0]["title"], "Rust");
+        assert_eq!(entries[0]["total_lessons"], 3);
+        assert_eq!(entries[0]["completed_lessons"], 2);
+        assert_eq!(entries[0]["completion_percentage"], 66.7);
+        assert_eq!(entries[0]["next_lesson_id"], second.to_string());
+        // Un curso sin lecciones aparece al 0 % y sin siguiente lección.
+        assert_eq!(entries[1]["course_id"], empty.to_string());
+        assert_eq!(entries[1]["total_lessons"], 0);
+        assert_eq!(entries[1]["completion_percentage"], 0.0);
+        assert!(entries[1]["next_lesson_id"].is_null());
+
+        // Con todas las lecciones completadas, el curso queda al 100 % y sin siguiente lección.
+        complete_lesson(&state.db_pool, student, second).await;
+        let req = TestRequest::get()
+            .uri("/me/dashboard")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body[0]["completion_percentage"], 100.0);
+        assert!(body[0]["next_lesson_id"].is_null());
+    }
+
+    /// Estado de la inscripción vigente (no abandonada) del usuario en el curso, si la hay.
+    async fn enrollment_status(db_pool: &PgPool, user_id: Uuid, course_id: Uuid) -> Option<String> {
+        sqlx::query_scalar(
+            "SELECT status::text FROM enrollments WHERE user_id = $1 AND course_id = $2 AND status <> 'dropped'",
+        )
+        .bind(user_id)
+        .bind(course_id)
+        .fetch_optional(db_pool)
+        .await
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn batch_enrollment_reports_a_result_per_course() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let new = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let duplicate = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let missing = Uuid::new_v4();
+        insert_enrollment(&state.db_pool, student, duplicate, "active", 1).await;
+
+        let req = TestRequest::post()
+            .uri("/enrollments/batch")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .set_json(serde_json::json!({ "course_ids": [new, duplicate, missing] }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        // Un resultado por curso, en el orden de la petición.
+        assert_eq!(
+            body,
+            serde_json::json!([
+                { "course_id": new, "result": "enrolled" },
+                { "course_id": duplicate, "result": "already_enrolled" },
+                { "course_id": missing, "result": "not_found" },
+            ])
+        );
+        // El curso inexistente no impide el resto: la transacción se confirma.
+        assert_eq!(enrollment_status(&state.db_pool, student, new).await.as_deref(), Some("active"));
+        assert_eq!(enrollment_status(&state.db_pool, student, duplicate).await.as_deref(), Some("active"));
+    }
+
+    #[actix_web::test]
+    async fn batch_enrollment_limits_the_courses() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let student = insert_user(&state.db_pool, "student").await;
+
+        let course_ids: Vec<Uuid> = (0..=MAX_BATCH_ENROLLMENTS).map(|_| Uuid::new_v4()).collect();
+        let req = TestRequest::post()
+            .uri("/enrollments/batch")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .set_json(serde_json::json!({ "course_ids": course_ids }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+    }
+
+    /// Títulos de una lista de inscripciones en JSON, en el orden recibido.
+    fn titles(enrollments: &serde_json::Value) -> Vec<&str> {
+        enrollments.as_array().unwrap().iter().map(|enrollment| enrollment["title"].as_str().unwrap()).collect()
+    }
+
+    #[actix_web::test]
+    async fn my_enrollments_filter_by_status_and_sort_by_title() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let alpha = insert_course(&state.db_pool, instructor, "Alpha", "published").await;
+        let beta = insert_course(&state.db_pool, instructor, "Beta", "published").await;
+        let gamma = insert_course(&state.db_pool, instructor, "Gamma", "published").await;
+        insert_enrollment(&state.db_pool, student, beta, "completed", 1).await;
+        insert_enrollment(&state.db_pool, student, alpha, "active", 2).await;
+        insert_enrollment(&state.db_pool, student, gamma, "completed", 3).await;
+        let list = |query: &str| {
+            TestRequest::get()
+                .uri(&format!("/enrollments/my-courses{}", query))
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .to_request()
+        };
+
+        // Por defecto, de la inscripción más reciente a la más antigua.
+        let body: serde_json::Value = read_body_json(call_service(&app, list("")).await).await;
+        assert_eq!(titles(&body), ["Beta", "Alpha", "Gamma"]);
+
+        let body: serde_json::Value = read_body_json(call_service(&app, list("?status=completed")).await).await;
+        assert_eq!(titles(&body), ["Beta", "Gamma"]);
+        assert!(body.as_array().unwrap().iter().all(|enrollment| enrollment["status"] == "completed"));
+
+        let body: serde_json::Value = read_body_json(call_service(&app, list("?sort=title&order=asc")).await).await;
+        assert_eq!(titles(&body), ["Alpha", "Beta", "Gamma"]);
+        let body: serde_json::Value = read_body_json(call_service(&app, list("?sort=title")).await).await;
+        assert_eq!(titles(&body), ["Gamma", "Beta", "Alpha"]);
+
+        // La paginación se aplica sobre el orden pedido.
+        let res = call_service(&app, list("?sort=title&order=asc&per_page=1&page=2")).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(titles(&body), ["Beta"]);
+    }
+
+    #[actix_web::test]
+    async fn my_enrollments_reject_unknown_sort_fields() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let student = insert_user(&state.db_pool, "student").await;
+        let list = |query: &str| {
+            TestRequest::get()
+                .uri(&format!("/enrollments/my-courses{}", query))
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .to_request()
+        };
+
+        assert_eq!(call_service(&app, list("?sort=password_hash")).await.status(), 400);
+        assert_eq!(call_service(&app, list("?sort=title&order=sideways")).await.status(), 400);
+        assert_eq!(call_service(&app, list("?status=unknown")).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn enrollment_cap_counts_only_active_enrollments() {
+        let mut state = state().await;
+        state.max_active_enrollments = Some(2);
+        let state = web::Data::new(state);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let mut courses = Vec::new();
+        for title in ["Rust", "Go", "C", "Zig", "Odin"] {
+            courses.push(insert_course(&state.db_pool, instructor, title, "published").await);
+        }
+        // Las inscripciones completadas o abandonadas no cuentan para el límite.
+        insert_enrollment(&state.db_pool, student, courses[0], "completed", 2).await;
+        insert_enrollment(&state.db_pool, student, courses[1], "dropped", 1).await;
+        let enroll = |course_id: Uuid| {
+            TestRequest::post()
+                .uri("/enrollments")
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .set_json(serde_json::json!({ "course_id": course_id }))
+                .to_request()
+        };
+
+        assert_eq!(call_service(&app, enroll(courses[2])).await.status(), 201);
+        assert_eq!(call_service(&app, enroll(courses[3])).await.status(), 201);
+        let res = call_service(&app, enroll(courses[4])).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "enrollment_limit_reached");
+        assert_eq!(enrollment_status(&state.db_pool, student, courses[4]).await, None);
+
+        // Al abandonar un curso se libera una plaza.
+        sqlx::query("UPDATE enrollments SET status = 'dropped' WHERE user_id = $1 AND course_id = $2")
+            .bind(student)
+            .bind(courses[2])
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(call_service(&app, enroll(courses[4])).await.status(), 201);
+    }
+
+    #[actix_web::test]
+    async fn enrollment_status_reflects_the_current_user() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let enrolled = insert_user(&state.db_pool, "student").await;
+        let pending = insert_user(&state.db_pool, "student").await;
+        let outsider = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, enrolled, course_id, "active", 1).await;
+        insert_enrollment(&state.db_pool, pending, course_id, "pending", 1).await;
+        let status = |user_id: Uuid| {
+            TestRequest::get()
+                .uri(&format!("/enrollments/status/{}", course_id))
+                .insert_header(("Authorization", bearer(user_id, UserRole::Student)))
+                .to_request()
+        };
+
+        let res = call_service(&app, status(enrolled)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body, serde_json::json!({ "enrolled": true, "status": "active" }));
+
+        // Una inscripción pendiente aún no cuenta como inscrito.
+        let body: serde_json::Value = read_body_json(call_service(&app, status(pending)).await).await;
+        assert_eq!(body, serde_json::json!({ "enrolled": false, "status": "pending" }));
+
+        // Sin inscripción no hay `status`.
+        let body: serde_json::Value = read_body_json(call_service(&app, status(outsider)).await).await;
+        assert_eq!(body, serde_json::json!({ "enrolled": false }));
+    }
+
+    #[actix_web::test]
+    async fn instructors_cannot_enroll_in_their_own_course() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let admin_course = insert_course(&state.db_pool, admin, "Go", "published").await;
+        let enroll = |user_id: Uuid, role: UserRole, course_id: Uuid| {
+            TestRequest::post()
+                .uri("/enrollments")
+                .insert_header(("Authorization", bearer(user_id, role)))
+                .set_json(serde_json::json!({ "course_id": course_id }))
+                .to_request()
+        };
+
+        let res = call_service(&app, enroll(instructor, UserRole::Instructor, course_id)).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "cannot_enroll_own_course");
+        assert_eq!(enrollment_status(&state.db_pool, instructor, course_id).await, None);
+
+        assert_eq!(call_service(&app, enroll(student, UserRole::Student, course_id)).await.status(), 201);
+        // Los admins sí pueden inscribirse en sus propios cursos.
+        assert_eq!(call_service(&app, enroll(admin, UserRole::Admin, admin_course)).await.status(), 201);
+
+        // En lote, el curso propio aparece como `own_course` sin impedir el resto.
+        let other_course = insert_course(&state.db_pool, admin, "C", "published").await;
+        let req = TestRequest::post()
+            .uri("/enrollments/batch")
+            .insert_header(("Authorization", bearer(instructor, UserRole::Instructor)))
+            .set_json(serde_json::json!({ "course_ids": [course_id, other_course] }))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(
+            body,
+            serde_json::json!([
+                { "course_id": course_id, "result": "own_course" },
+                { "course_id": other_course, "result": "enrolled" },
+            ])
+        );
+    }
+
+    /// Petición de `POST /enrollments` del estudiante en el curso.
+    fn enroll_request(user_id: Uuid, course_id: Uuid) -> TestRequest {
+        TestRequest::post()
+            .uri("/enrollments")
+            .insert_header(("Authorization", bearer(user_id, UserRole::Student)))
+            .set_json(serde_json::json!({ "course_id": course_id }))
+    }
+
+    /// Petición de `POST /enrollments/{course_id}/drop` del estudiante.
+    fn drop_request(user_id: Uuid, course_id: Uuid) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/enrollments/{}/drop", course_id))
+            .insert_header(("Authorization", bearer(user_id, UserRole::Student)))
+    }
+
+    #[actix_web::test]
+    async fn history_records_a_drop_and_a_re_enrollment() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let completed = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        insert_enrollment(&state.db_pool, student, completed, "completed", 30).await;
+
+        assert_eq!(call_service(&app, enroll_request(student, course_id).to_request()).await.status(), 201);
+        assert_eq!(call_service(&app, drop_request(student, course_id).to_request()).await.status(), 200);
+        assert_eq!(call_service(&app, enroll_request(student, course_id).to_request()).await.status(), 201);
+
+        let req = TestRequest::get()
+            .uri("/me/enrollments/history")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        let history = body.as_array().unwrap();
+        // Todas las inscripciones, sea cual sea su estado, de la más reciente a la más antigua.
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["course_id"], course_id.to_string());
+        assert_eq!(history[0]["status"], "active");
+        assert_eq!(history[1]["course_id"], completed.to_string());
+        assert_eq!(history[1]["status"], "completed");
+
+        // La reinscripción reactiva la misma inscripción: sus eventos recogen cada cambio, en orden.
+        let events = history[0]["events"].as_array().unwrap();
+        let statuses: Vec<&str> = events.iter().map(|event| event["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, ["active", "dropped", "active"]);
+        let times: Vec<DateTime<Utc>> =
+            events.iter().map(|event| event["occurred_at"].as_str().unwrap().parse().unwrap()).collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[actix_web::test]
+    async fn re_enrolling_after_a_drop_reactivates_the_enrollment() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "dropped", 10).await;
+
+        let res = call_service(&app, enroll_request(student, course_id).to_request()).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["status"], "active");
+        // La fecha de inscripción pasa a ser la de la reactivación.
+        let enrollment_date: DateTime<Utc> = body["enrollment_date"].as_str().unwrap().parse().unwrap();
+        assert!(Utc::now() - enrollment_date < chrono::Duration::minutes(1));
+
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM enrollments WHERE user_id = $1 AND course_id = $2")
+            .bind(student)
+            .bind(course_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        // Con la inscripción activa, repetirla sigue siendo un duplicado.
+        assert_eq!(call_service(&app, enroll_request(student, course_id).to_request()).await.status(), 409);
+    }
+
+    #[actix_web::test]
+    async fn course_owner_removes_a_student() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let other_instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let outsider = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 1).await;
+        let remove = |user_id: Uuid, student_id: Uuid| {
+            TestRequest::delete()
+                .uri(&format!("/enrollments/course/{}/student/{}", course_id, student_id))
+                .insert_header(("Authorization", bearer(user_id, UserRole::Instructor)))
+                .to_request()
+        };
+
+        // Otro instructor no puede dar de baja a los estudiantes del curso.
+        assert_eq!(call_service(&app, remove(other_instructor, student)).await.status(), 403);
+        assert_eq!(enrollment_status(&state.db_pool, student, course_id).await.as_deref(), Some("active"));
+
+        assert_eq!(call_service(&app, remove(instructor, student)).await.status(), 204);
+        // La inscripción se conserva como abandonada y el estudiante recibe una notificación.
+        let status: String =
+            sqlx::query_scalar("SELECT status::text FROM enrollments WHERE user_id = $1 AND course_id = $2")
+                .bind(student)
+                .bind(course_id)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "dropped");
+        let messages: Vec<String> =
+            sqlx::query_scalar("SELECT message FROM notifications WHERE user_id = $1 AND kind = 'enrollment_removed'")
+                .bind(student)
+                .fetch_all(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(messages, ["You have been removed from the course \"Rust\""]);
+
+        // Un estudiante no inscrito (o ya dado de baja) no tiene inscripción que quitar.
+        assert_eq!(call_service(&app, remove(instructor, outsider)).await.status(), 404);
+        assert_eq!(call_service(&app, remove(instructor, student)).await.status(), 404);
+    }
+
+    /// Crea un estudiante con el nombre de usuario indicado (y su email) y devuelve su id.
+    async fn insert_student_named(db_pool: &PgPool, username: &str) -> Uuid {
+        let user_id = insert_user(db_pool, "student").await;
+        sqlx::query("UPDATE users SET username = $2, display_username = $2, email = $2 || '@example.com' WHERE id = $1")
+            .bind(user_id)
+            .bind(username)
+            .execute(db_pool)
+            .await
+            .unwrap();
+        user_id
+    }
+
+    #[actix_web::test]
+    async fn roster_pages_and_searches_the_students() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let other_instructor = insert_user(&state.db_pool, "instructor").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let marker = Uuid::new_v4().simple().to_string();
+        for name in ["alice", "bob", "carol", "dave", "erin"] {
+            let student = insert_student_named(&state.db_pool, &format!("{}_{}", name, marker)).await;
+            insert_enrollment(&state.db_pool, student, course_id, "active", 1).await;
+        }
+        // Los estudiantes que abandonaron el curso no aparecen.
+        let dropped = insert_student_named(&state.db_pool, &format!("aaron_{}", marker)).await;
+        insert_enrollment(&state.db_pool, dropped, course_id, "dropped", 1).await;
+        let roster = |user_id: Uuid, query: &str| {
+            TestRequest::get()
+                .uri(&format!("/enrollments/course/{}/students{}", course_id, query))
+                .insert_header(("Authorization", bearer(user_id, UserRole::Instructor)))
+                .to_request()
+        };
+        // Nombres de usuario de la página, sin el sufijo común.
+        let suffix = format!("_{}", marker);
+        let usernames = |body: &serde_json::Value| -> Vec<String> {
+            let students = body["students"].as_array().unwrap();
+            students.iter().map(|student| student["username"].as_str().unwrap().replace(&suffix, "")).collect()
+        };
+
+        let res = call_service(&app, roster(instructor, "?per_page=2&page=2")).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(usernames(&body), ["carol", "dave"]);
+        assert_eq!(body["total"], 5);
+        assert_eq!(body["page"], 2);
+        assert_eq!(body["per_page"], 2);
+        let res = call_service(&app, roster(instructor, "?per_page=2&page=3")).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(usernames(&body), ["erin"]);
+
+        // La búsqueda es parcial, sin distinguir mayúsculas, y el total cuenta solo los resultados.
+        let body: serde_json::Value = read_body_json(call_service(&app, roster(instructor, "?q=CAR")).await).await;
+        assert_eq!(usernames(&body), ["carol"]);
+        assert_eq!(body["total"], 1);
+        // `_` es literal, no un comodín de `LIKE`.
+        let body: serde_json::Value = read_body_json(call_service(&app, roster(instructor, "?q=b_")).await).await;
+        assert_eq!(usernames(&body), ["bob"]);
+
+        assert_eq!(call_service(&app, roster(other_instructor, "")).await.status(), 403);
+    }
+
+    /// Levanta un course-service de prueba: `/up` responde 200 si recibe la API key interna (401 si
+    /// no) y `/down` responde siempre 503. Devuelve su dirección base.
+    fn mock_course_service() -> String {
+        async fn up(req: actix_web::HttpRequest) -> HttpResponse {
+            match req.headers().get("X-Api-Key") {
+                Some(key) if key == API_KEY => HttpResponse::Ok().finish(),
+                _ => HttpResponse::Unauthorized().finish(),
+            }
+        }
+
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/up", web::get().to(up))
+                .route("/down", web::get().to(HttpResponse::ServiceUnavailable))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}", addr)
+    }
+
+    #[actix_web::test]
+    async fn readiness_reports_each_dependency() {
+        let base_url = mock_course_service();
+        let ready = |state: AppState| async move {
+            let state = web::Data::new(state);
+            let app = app!(state);
+            let res = call_service(&app, TestRequest::get().uri("/ready").to_request()).await;
+            let status = res.status();
+            let body: serde_json::Value = read_body_json(res).await;
+            (status, body)
+        };
+
+        // Sin URL configurada, course-service no es una dependencia.
+        let (status, body) = ready(state().await).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, serde_json::json!({ "ready": true, "dependencies": [{ "name": "database", "up": true }] }));
+
+        let up = AppState { course_service_health_url: Some(format!("{}/up", base_url)), ..state().await };
+        let (status, body) = ready(up).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["dependencies"][1], serde_json::json!({ "name": "course-service", "up": true }));
+
+        let down = AppState { course_service_health_url: Some(format!("{}/down", base_url)), ..state().await };
+        let (status, body) = ready(down).await;
+        assert_eq!(status, 503);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["dependencies"][0]["up"], true);
+        assert_eq!(
+            body["dependencies"][1],
+            serde_json::json!({ "name": "course-service", "up": false, "error": "unexpected status 503" })
+        );
+
+        // Un servicio que no responde también cuenta como caído.
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let closed_url = format!("http://{}/up", listener.local_addr().unwrap());
+        drop(listener);
+        let (status, body) = ready(AppState { course_service_health_url: Some(closed_url), ..state().await }).await;
+        assert_eq!(status, 503);
+        assert_eq!(body["dependencies"][1]["error"], "unreachable");
+    }
+
+    /// Limita las plazas del curso y, con `reserve_pending_slots`, hace que las inscripciones
+    /// pendientes también ocupen plaza.
+    async fn set_capacity(db_pool: &PgPool, course_id: Uuid, max_students: i32, reserve_pending_slots: bool) {
+        sqlx::query("UPDATE courses SET max_students = $2, reserve_pending_slots = $3 WHERE id = $1")
+            .bind(course_id)
+            .bind(max_students)
+            .bind(reserve_pending_slots)
+            .execute(db_pool)
+            .await
+            .unwrap();
+    }
+
+    /// Indica si el usuario está en la lista de espera del curso.
+    async fn is_waitlisted(db_pool: &PgPool, user_id: Uuid, course_id: Uuid) -> bool {
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM course_waitlist WHERE course_id = $1 AND user_id = $2)")
+            .bind(course_id)
+            .bind(user_id)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn pending_enrollments_take_a_slot_only_when_the_course_reserves_them() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let reserved = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let unreserved = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        set_capacity(&state.db_pool, reserved, 2, true).await;
+        set_capacity(&state.db_pool, unreserved, 2, false).await;
+        // En ambos cursos, una inscripción activa y otra pendiente de aprobación.
+        for course_id in [reserved, unreserved] {
+            let active = insert_user(&state.db_pool, "student").await;
+            let pending = insert_user(&state.db_pool, "student").await;
+            insert_enrollment(&state.db_pool, active, course_id, "active", 2).await;
+            insert_enrollment(&state.db_pool, pending, course_id, "pending", 1).await;
+        }
+        let student = insert_user(&state.db_pool, "student").await;
+
+        // La pendiente completa el curso que reserva plazas: el estudiante pasa a la lista de espera.
+        let res = call_service(&app, enroll_request(student, reserved).to_request()).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "course_full");
+        assert!(is_waitlisted(&state.db_pool, student, reserved).await);
+        assert_eq!(enrollment_status(&state.db_pool, student, reserved).await, None);
+
+        // Sin reserva, la pendiente no ocupa plaza y aún queda una libre.
+        assert_eq!(call_service(&app, enroll_request(student, unreserved).to_request()).await.status(), 201);
+        assert!(!is_waitlisted(&state.db_pool, student, unreserved).await);
+    }
+
+    /// Espera (hasta ~2 s) a que se registren en segundo plano `count` entradas de actividad del
+    /// usuario.
+    async fn wait_for_activity(db_pool: &PgPool, user_id: Uuid, count: i64) {
+        for _ in 0..100 {
+            let recorded: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activity WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(db_pool)
+                .await
+                .unwrap();
+            if recorded >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the activity of user {} was not recorded", user_id);
+    }
+
+    #[actix_web::test]
+    async fn enrolling_adds_an_entry_to_the_activity_feed() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let lesson_id = insert_lesson(&state.db_pool, rust, "Intro", 1).await;
+
+        assert_eq!(call_service(&app, enroll_request(student, rust).to_request()).await.status(), 201);
+        wait_for_activity(&state.db_pool, student, 1).await;
+        // La lección completada la registra course-service; aquí basta con la misma escritura.
+        let completed = Activity {
+            user_id: student,
+            kind: activity::LESSON_COMPLETED,
+            course_id: Some(rust),
+            lesson_id: Some(lesson_id),
+        };
+        activity::record(&state.db_pool, &[completed]).await.unwrap();
+        let req = TestRequest::post()
+            .uri("/enrollments/batch")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .set_json(serde_json::json!({ "course_ids": [go] }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        wait_for_activity(&state.db_pool, student, 3).await;
+
+        let feed = |query: &str| {
+            TestRequest::get()
+                .uri(&format!("/me/activity{}", query))
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .to_request()
+        };
+        let res = call_service(&app, feed("")).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        // De la más reciente a la más antigua, con el título del curso.
+        let entries = body.as_array().unwrap();
+        let kinds: Vec<&str> = entries.iter().map(|entry| entry["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["enrolled", "lesson_completed", "enrolled"]);
+        assert_eq!(entries[0]["course_title"], "Go");
+        assert_eq!(entries[1]["lesson_id"], lesson_id.to_string());
+        assert_eq!(entries[2]["course_id"], rust.to_string());
+        assert_eq!(entries[2]["course_title"], "Rust");
+
+        let body: serde_json::Value = read_body_json(call_service(&app, feed("?per_page=1&page=2")).await).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["kind"], "lesson_completed");
+    }
+
+    /// Crea una edición del curso con el nombre indicado y devuelve su id.
+    async fn insert_offering(db_pool: &PgPool, course_id: Uuid, name: &str) -> Uuid {
+        sqlx::query_scalar("INSERT INTO course_offerings (course_id, name) VALUES ($1, $2) RETURNING id")
+            .bind(course_id)
+            .bind(name)
+            .fetch_one(db_pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn offerings_allow_one_enrollment_per_term() {
+        let state = web::Data::new(AppState { offerings_enabled: true, ..state().await });
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let other_course = insert_course(&state.db_pool, instructor, "Go", "published").await;
+        let spring = insert_offering(&state.db_pool, course_id, "Spring").await;
+        let autumn = insert_offering(&state.db_pool, course_id, "Autumn").await;
+        let elsewhere = insert_offering(&state.db_pool, other_course, "Spring").await;
+        let enroll = |offering_id: Uuid| {
+            TestRequest::post()
+                .uri("/enrollments")
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .set_json(serde_json::json!({ "course_id": course_id, "offering_id": offering_id }))
+                .to_request()
+        };
+
+        let res = call_service(&app, enroll(spring)).await;
+        assert_eq!(res.status(), 201);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["offering_id"], spring.to_string());
+        // El mismo curso en otra edición es otra inscripción; en la misma, un duplicado.
+        assert_eq!(call_service(&app, enroll(autumn)).await.status(), 201);
+        assert_eq!(call_service(&app, enroll(spring)).await.status(), 409);
+        // La edición tiene que ser del curso.
+        assert_eq!(call_service(&app, enroll(elsewhere)).await.status(), 400);
+
+        // Con dos ediciones, abandonar el curso exige elegir una.
+        let res = call_service(&app, drop_request(student, course_id).to_request()).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "offering_required");
+        let req = drop_request(student, course_id)
+            .uri(&format!("/enrollments/{}/drop?offering_id={}", course_id, spring))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        let statuses: Vec<String> = sqlx::query_scalar(
+            "SELECT status::text FROM enrollments WHERE user_id = $1 AND course_id = $2 ORDER BY offering_id = $3",
+        )
+        .bind(student)
+        .bind(course_id)
+        .bind(spring)
+        .fetch_all(&state.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(statuses, ["active", "dropped"]);
+    }
+
+    #[actix_web::test]
+    async fn offerings_are_rejected_while_disabled() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let spring = insert_offering(&state.db_pool, course_id, "Spring").await;
+
+        let req = TestRequest::post()
+            .uri("/enrollments")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .set_json(serde_json::json!({ "course_id": course_id, "offering_id": spring }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+        assert_eq!(enrollment_status(&state.db_pool, student, course_id).await, None);
+    }
+
+    #[actix_web::test]
+    async fn removing_a_student_answers_an_empty_204() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let admin = insert_user(&state.db_pool, "admin").await;
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 1).await;
+
+        // Como el resto de borrados: 204 sin cuerpo, también para un admin que no es el propietario.
+        let req = TestRequest::delete()
+            .uri(&format!("/enrollments/course/{}/student/{}", course_id, student))
+            .insert_header(("Authorization", bearer(admin, UserRole::Admin)))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 204);
+        assert!(read_body(res).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn instructors_keep_private_notes_on_an_enrollment() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        insert_enrollment(&state.db_pool, student, course_id, "active", 1).await;
+        let set_notes = |user_id: Uuid, role: UserRole, body: serde_json::Value| {
+            TestRequest::put()
+                .uri(&format!("/enrollments/course/{}/student/{}/notes", course_id, student))
+                .insert_header(("Authorization", bearer(user_id, role)))
+                .set_json(body)
+                .to_request()
+        };
+
+        let notes = serde_json::json!({ "notes": "  transfer student ", "metadata": { "accommodation": true } });
+        let res = call_service(&app, set_notes(instructor, UserRole::Instructor, notes)).await;
+        assert_eq!(res.status(), 200);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["notes"], "transfer student");
+        assert_eq!(body["metadata"], serde_json::json!({ "accommodation": true }));
+
+        // El instructor las ve en el listado de estudiantes.
+        let req = TestRequest::get()
+            .uri(&format!("/enrollments/course/{}/students", course_id))
+            .insert_header(("Authorization", bearer(instructor, UserRole::Instructor)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(body["students"][0]["notes"], "transfer student");
+        assert_eq!(body["students"][0]["metadata"], serde_json::json!({ "accommodation": true }));
+
+        // El estudiante no puede escribirlas ni las ve en sus propias inscripciones.
+        let own_notes = serde_json::json!({ "notes": "nothing to see" });
+        assert_eq!(call_service(&app, set_notes(student, UserRole::Student, own_notes)).await.status(), 403);
+        for uri in ["/enrollments/my-courses", "/me/enrollments/history"] {
+            let req = TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .to_request();
+            let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+            let enrollment = body[0].as_object().unwrap();
+            assert!(!enrollment.contains_key("notes") && !enrollment.contains_key("metadata"), "{}", uri);
+        }
+
+        // Los metadatos tienen que ser un objeto JSON.
+        let invalid = serde_json::json!({ "metadata": ["accommodation"] });
+        assert_eq!(call_service(&app, set_notes(instructor, UserRole::Instructor, invalid)).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn ensuring_an_enrollment_twice_returns_the_same_one() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let ensure = |course_id: Uuid| {
+            TestRequest::put()
+                .uri(&format!("/enrollments/{}", course_id))
+                .insert_header(("Authorization", bearer(student, UserRole::Student)))
+                .to_request()
+        };
+
+        let res = call_service(&app, ensure(course_id)).await;
+        assert_eq!(res.status(), 200);
+        let first: serde_json::Value = read_body_json(res).await;
+        assert_eq!(first["status"], "active");
+        let res = call_service(&app, ensure(course_id)).await;
+        assert_eq!(res.status(), 200);
+        let second: serde_json::Value = read_body_json(res).await;
+        assert_eq!(first, second);
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM enrollments WHERE user_id = $1 AND course_id = $2")
+            .bind(student)
+            .bind(course_id)
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        // Las mismas comprobaciones del curso que `POST /enrollments`.
+        assert_eq!(call_service(&app, ensure(Uuid::new_v4())).await.status(), 400);
+    }
+
+    /// Fecha en formato RFC 3339 de un campo de una respuesta JSON.
+    fn timestamp(value: &serde_json::Value) -> DateTime<Utc> {
+        value.as_str().unwrap().parse().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn status_changes_bump_the_enrollment_updated_at() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let rust = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+        let go = insert_course(&state.db_pool, instructor, "Go", "published").await;
+
+        // La fecha de inscripción la fija el servidor, aunque el cliente envíe otra.
+        let req = enroll_request(student, rust)
+            .set_json(serde_json::json!({ "course_id": rust, "enrollment_date": "2000-01-01T00:00:00Z" }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 201);
+        let enrolled: serde_json::Value = read_body_json(res).await;
+        let enrollment_date = timestamp(&enrolled["enrollment_date"]);
+        assert!(Utc::now() - enrollment_date < chrono::Duration::minutes(1));
+        assert_eq!(timestamp(&enrolled["updated_at"]), enrollment_date);
+        assert_eq!(call_service(&app, enroll_request(student, go).to_request()).await.status(), 201);
+
+        let res = call_service(&app, drop_request(student, rust).to_request()).await;
+        assert_eq!(res.status(), 200);
+        let dropped: serde_json::Value = read_body_json(res).await;
+        assert_eq!(dropped["status"], "dropped");
+        assert!(timestamp(&dropped["updated_at"]) > timestamp(&enrolled["updated_at"]));
+        assert_eq!(timestamp(&dropped["enrollment_date"]), enrollment_date);
+
+        // El cambio más reciente va primero al ordenar por `updated_at`.
+        let req = TestRequest::get()
+            .uri("/enrollments/my-courses?sort=updated_at")
+            .insert_header(("Authorization", bearer(student, UserRole::Student)))
+            .to_request();
+        let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(titles(&body), ["Rust", "Go"]);
+        assert_eq!(body[0]["updated_at"], dropped["updated_at"]);
+    }
+
+    #[actix_web::test]
+    async fn errors_carry_a_code_and_follow_accept_language() {
+        let state = web::Data::new(state().await);
+        let app = app!(state);
+        let instructor = insert_user(&state.db_pool, "instructor").await;
+        let student = insert_user(&state.db_pool, "student").await;
+        let course_id = insert_course(&state.db_pool, instructor, "Rust", "published").await;
+
+        // Sin inscripción no hay nada que abandonar.
+        let req = drop_request(student, course_id).insert_header(("Accept-Language", "es")).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 404);
+        assert_eq!(res.headers().get("Content-Language").unwrap(), "es");
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "enrollment_not_found");
+        assert_eq!(body["message"], "No hay una inscripción activa en este curso");
+
+        assert_eq!(call_service(&app, enroll_request(student, course_id).to_request()).await.status(), 201);
+        let req = enroll_request(student, course_id).insert_header(("Accept-Language", "es")).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 409);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "already_enrolled");
+        assert_eq!(body["message"], "Ya estás inscrito en este curso");
+
+        // Sin `Accept-Language` el mensaje sigue en inglés, con el mismo `code`.
+        let res = call_service(&app, enroll_request(student, course_id).to_request()).await;
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "already_enrolled");
+        assert_eq!(body["message"], "User is already enrolled in this course");
+    }
+}